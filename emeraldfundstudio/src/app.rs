@@ -1,10 +1,35 @@
-use eframe::egui::{self};
-use egui_snarl::Snarl;
+use std::time::Duration;
+
+use eframe::egui::{self, Key, Modifiers};
+use egui_snarl::{NodeId, Snarl};
+use log::error;
 
 use crate::{
+    backtest::panel::BacktestPanel,
     candles::chart::candlestick_chart,
-    node_editor::{node_trait::EFNodeFNSerialized, style::default_style, EFViewer},
-    node_runners::realtime::run_nodes,
+    node_editor::{
+        clipboard::{copy_nodes, is_copied_nodes, paste_nodes},
+        composite_panel::CompositePanel,
+        history::History,
+        inspector::InspectorPanel,
+        load_snarl,
+        node_trait::EFNodeFNSerialized,
+        palette::NodePaletteWindow,
+        profiler_panel::ProfilerPanel,
+        style::default_style,
+        EFViewer,
+    },
+    node_runners::realtime::{forget_node, is_running, run_nodes},
+    optimization::{
+        optimizer_panel::OptimizerPanel, sweep_panel::SweepPanel,
+        walk_forward_panel::WalkForwardPanel,
+    },
+    types::live_trade::LiveTrade,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    data_store::panel::DataStorePanel, hummingbot_api::panel::HummingbotPanel,
+    paper::panel::PaperTradingPanel, replay::panel::ReplayPanel,
 };
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
@@ -12,12 +37,68 @@ use crate::{
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct EmeraldFundStudioApp<'a> {
     snarl: Snarl<EFNodeFNSerialized<'a>>,
+    /// Keeps the library of composite nodes
+    composite_panel: CompositePanel,
+    /// Undo and redo of the edits of `snarl`
+    #[serde(skip)]
+    history: History,
+    #[serde(skip)]
+    palette_window: NodePaletteWindow,
+    /// Nodes selected in the graph in the last frame
+    #[serde(skip)]
+    selected_nodes: Vec<NodeId>,
+    #[serde(skip)]
+    inspector_panel: InspectorPanel,
+    #[serde(skip)]
+    profiler_panel: ProfilerPanel,
+    #[serde(skip)]
+    sweep_panel: SweepPanel,
+    #[serde(skip)]
+    walk_forward_panel: WalkForwardPanel,
+    #[serde(skip)]
+    optimizer_panel: OptimizerPanel,
+    #[serde(skip)]
+    backtest_panel: BacktestPanel,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    data_store_panel: DataStorePanel,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    replay_panel: ReplayPanel,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    paper_trading_panel: PaperTradingPanel,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    hummingbot_panel: HummingbotPanel,
+    /// Fills of a live bot, shown on the chart next to the graph's signals
+    #[serde(skip)]
+    live_trades: Vec<LiveTrade>,
 }
 
 impl Default for EmeraldFundStudioApp<'_> {
     fn default() -> Self {
         Self {
             snarl: Snarl::new(),
+            composite_panel: Default::default(),
+            history: Default::default(),
+            palette_window: Default::default(),
+            selected_nodes: Vec::new(),
+            inspector_panel: Default::default(),
+            profiler_panel: Default::default(),
+            sweep_panel: Default::default(),
+            walk_forward_panel: Default::default(),
+            optimizer_panel: Default::default(),
+            backtest_panel: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            data_store_panel: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            replay_panel: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            paper_trading_panel: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            hummingbot_panel: Default::default(),
+            live_trades: Vec::new(),
         }
     }
 }
@@ -32,9 +113,7 @@ impl EmeraldFundStudioApp<'_> {
         // Note that you must enable the `persistence` feature for this to work.
         if let Some(storage) = cc.storage {
             let mut result: Self = eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
-            for node in result.snarl.nodes_mut() {
-                node.load_node().unwrap();
-            }
+            load_snarl(&mut result.snarl);
             run_nodes(&result.snarl);
             return result;
         }
@@ -43,6 +122,39 @@ impl EmeraldFundStudioApp<'_> {
     }
 }
 
+impl EmeraldFundStudioApp<'_> {
+    /// Ctrl+C copies the selected nodes, Ctrl+V pastes nodes copied from any graph
+    fn clipboard_shortcuts(&mut self, ctx: &egui::Context) {
+        let events = ctx.input(|i| i.events.clone());
+        for event in events {
+            match event {
+                egui::Event::Copy if !self.selected_nodes.is_empty() => {
+                    match copy_nodes(&self.snarl, &self.selected_nodes) {
+                        Ok(text) => ctx.copy_text(text),
+                        Err(e) => error!("Can't copy: {}", e),
+                    }
+                }
+                egui::Event::Paste(text) if is_copied_nodes(&text) => {
+                    let pasted = self
+                        .history
+                        .record(&mut self.snarl, |snarl| paste_nodes(snarl, &text));
+                    match pasted {
+                        Ok(ids) => {
+                            // Ids of removed nodes are reused, their results must not be
+                            for id in &ids {
+                                forget_node(id);
+                            }
+                            run_nodes(&self.snarl);
+                        }
+                        Err(e) => error!("Can't paste: {}", e),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
 impl eframe::App for EmeraldFundStudioApp<'_> {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
@@ -58,6 +170,23 @@ impl eframe::App for EmeraldFundStudioApp<'_> {
         // Put your widgets into a `SidePanel`, `TopBottomPanel`, `CentralPanel`, `Window` or `Area`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
 
+        // New bars of a feed go through the graph before anything is drawn
+        #[cfg(not(target_arch = "wasm32"))]
+        self.replay_panel.poll(ctx, &self.snarl);
+
+        // Text fields have an undo of their own
+        if !ctx.wants_keyboard_input() {
+            // Ctrl+Z matches Ctrl+Shift+Z too, so redo goes first
+            if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z)) {
+                self.history.redo(&mut self.snarl);
+            } else if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::Z)) {
+                self.history.undo(&mut self.snarl);
+            }
+            if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::K)) {
+                self.palette_window.open = true;
+            }
+        }
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
 
@@ -71,14 +200,92 @@ impl eframe::App for EmeraldFundStudioApp<'_> {
                         }
                         if ui.button("Reset").clicked() {
                             self.snarl = Default::default();
+                            self.history.clear();
                             ctx.memory_mut(|mem| *mem = Default::default());
                         }
                     });
                     ui.add_space(16.0);
                 }
+                ui.menu_button("Edit", |ui| {
+                    let undo = egui::Button::new("Undo").shortcut_text("Ctrl+Z");
+                    if ui.add_enabled(self.history.can_undo(), undo).clicked() {
+                        self.history.undo(&mut self.snarl);
+                        ui.close_menu();
+                    }
+                    let redo = egui::Button::new("Redo").shortcut_text("Ctrl+Shift+Z");
+                    if ui.add_enabled(self.history.can_redo(), redo).clicked() {
+                        self.history.redo(&mut self.snarl);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    let add = egui::Button::new("Add node…").shortcut_text("Ctrl+K");
+                    if ui.add(add).clicked() {
+                        self.palette_window.open = true;
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Tools", |ui| {
+                    ui.checkbox(&mut self.backtest_panel.open, "Backtest");
+                    ui.checkbox(&mut self.inspector_panel.open, "Inspector");
+                    if ui.button("Profiler").clicked() {
+                        self.profiler_panel.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Parameter Sweep").clicked() {
+                        self.sweep_panel.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Walk-Forward").clicked() {
+                        self.walk_forward_panel.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Composites").clicked() {
+                        self.composite_panel.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Optimiser").clicked() {
+                        self.optimizer_panel.open = true;
+                        ui.close_menu();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Data Store").clicked() {
+                        self.data_store_panel.open = true;
+                        ui.close_menu();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Replay").clicked() {
+                        self.replay_panel.open = true;
+                        ui.close_menu();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Paper Trading").clicked() {
+                        self.paper_trading_panel.open = true;
+                        ui.close_menu();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Hummingbot Deploy").clicked() {
+                        self.hummingbot_panel.open = true;
+                        ui.close_menu();
+                    }
+                });
             });
         });
 
+        if self.backtest_panel.open {
+            egui::TopBottomPanel::bottom("backtest")
+                .resizable(true)
+                .min_height(200.0)
+                .show(ctx, |ui| {
+                    self.backtest_panel.show(ui, &self.snarl);
+                });
+        }
+
+        if self.inspector_panel.open {
+            // Side panels go before the central one, so this is last frame's selection
+            self.inspector_panel
+                .show(ctx, &self.snarl, &self.selected_nodes);
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
             ui.heading("Emerald Fund Studio");
@@ -86,12 +293,72 @@ impl eframe::App for EmeraldFundStudioApp<'_> {
                 .resizable(true)
                 .min_height(256.0)
                 .show(ctx, |ui| {
-                    candlestick_chart(ui, &self.snarl);
+                    candlestick_chart(ui, &self.snarl, &self.live_trades);
                 });
             egui::CentralPanel::default().show(ctx, |ui| {
-                self.snarl
-                    .show(&mut EFViewer, &default_style(), "snarl", ui);
+                let snarl_id = ui.make_persistent_id("snarl");
+                self.selected_nodes =
+                    Snarl::<EFNodeFNSerialized<'_>>::get_selected_nodes(snarl_id, ctx);
+                let mut viewer = EFViewer {
+                    history: Some(&mut self.history),
+                    selected: self.selected_nodes.clone(),
+                    timings: self.profiler_panel.overlay,
+                    ..Default::default()
+                };
+                self.snarl.show(&mut viewer, &default_style(), "snarl", ui);
             });
         });
+        if !ctx.wants_keyboard_input() {
+            self.clipboard_shortcuts(ctx);
+        }
+
+        if self.palette_window.open {
+            self.palette_window
+                .show(ctx, &mut self.snarl, &mut self.history);
+        }
+        if self.profiler_panel.open {
+            self.profiler_panel.show(ctx, &self.snarl);
+        }
+        self.sweep_panel.show(ctx, &self.snarl);
+        if self.composite_panel.open || self.composite_panel.is_editing() {
+            self.composite_panel.show(
+                ctx,
+                &mut self.snarl,
+                &self.selected_nodes,
+                &mut self.history,
+            );
+        }
+        if self.walk_forward_panel.open {
+            let ranges = self.sweep_panel.collect_ranges(&self.snarl);
+            self.walk_forward_panel.show(ctx, &self.snarl, ranges);
+        }
+        if self.optimizer_panel.open {
+            let ranges = self.sweep_panel.collect_ranges(&self.snarl);
+            self.optimizer_panel
+                .show(ctx, &mut self.snarl, ranges, &mut self.history);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.replay_panel.open {
+            self.replay_panel.show(ctx);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.paper_trading_panel.open {
+            self.paper_trading_panel.show(ctx);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.hummingbot_panel.open {
+            self.hummingbot_panel.show(ctx, &self.snarl);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.data_store_panel.open {
+            self.data_store_panel
+                .show(ctx, &self.snarl, &mut self.live_trades);
+        }
+
+        self.history.end_frame(ctx, &self.snarl);
+        if is_running() {
+            // Nodes show their results as the background run finishes them
+            ctx.request_repaint_after(Duration::from_millis(50));
+        }
     }
 }
@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+use egui_snarl::{InPinId, NodeId, Snarl};
+use polars::frame::DataFrame;
+use serde::{Deserialize, Serialize};
+
+use super::metrics::BacktestMetrics;
+use crate::{
+    node_editor::node_trait::{EFNodeFNSerialized, NodeDataTypeWithValue},
+    node_runners::{
+        graph::{ComputeCache, GraphSnapshot},
+        realtime::NODE_COMPUTE_CACHE,
+    },
+    types::{
+        candles::Candles,
+        signal::Signal,
+        time_index::{same_index, Indexed},
+    },
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BacktestConfig {
+    pub initial_balance: f64,
+    /// Fee per fill, as a fraction of the traded value
+    pub fee: f64,
+    /// Price slippage per fill, as a fraction of the price
+    pub slippage: f64,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            initial_balance: 1000.0,
+            fee: 0.001,
+            slippage: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Trade {
+    pub entry_index: usize,
+    pub exit_index: usize,
+    /// Candle close prices, before slippage
+    pub entry_price: f64,
+    pub exit_price: f64,
+    /// Return of the trade after fees and slippage, 0.01 = +1%
+    pub pnl: f64,
+}
+
+impl Trade {
+    pub fn net_return(entry_price: f64, exit_price: f64, config: &BacktestConfig) -> f64 {
+        let entry = entry_price * (1.0 + config.slippage);
+        let exit = exit_price * (1.0 - config.slippage);
+        (exit / entry) * (1.0 - config.fee).powi(2) - 1.0
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BacktestResult {
+    /// Account value at every candle close
+    pub equity: Vec<f64>,
+    pub trades: Vec<Trade>,
+    pub metrics: BacktestMetrics,
+}
+
+/// Long-only backtest: a buy signal opens a position with the full balance when flat,
+/// a sell signal closes it. A position still open at the end is closed on the last candle.
+pub fn run_backtest(
+    closes: &[f64],
+    timestamps: &[u64],
+    signal: &[i8],
+    config: &BacktestConfig,
+) -> BacktestResult {
+    let len = closes.len().min(signal.len());
+    let mut equity = Vec::with_capacity(len);
+    let mut trades = vec![];
+    let mut balance = config.initial_balance;
+    let mut entry: Option<usize> = None;
+
+    for idx in 0..len {
+        match (signal[idx], entry) {
+            (1, None) => entry = Some(idx),
+            (-1, Some(entry_index)) => {
+                let pnl = Trade::net_return(closes[entry_index], closes[idx], config);
+                balance *= 1.0 + pnl;
+                trades.push(Trade {
+                    entry_index,
+                    exit_index: idx,
+                    entry_price: closes[entry_index],
+                    exit_price: closes[idx],
+                    pnl,
+                });
+                entry = None;
+            }
+            _ => {}
+        }
+        match entry {
+            Some(entry_index) => equity.push(
+                balance * (1.0 + Trade::net_return(closes[entry_index], closes[idx], config)),
+            ),
+            None => equity.push(balance),
+        }
+    }
+
+    if let Some(entry_index) = entry {
+        let exit_index = len - 1;
+        trades.push(Trade {
+            entry_index,
+            exit_index,
+            entry_price: closes[entry_index],
+            exit_price: closes[exit_index],
+            pnl: Trade::net_return(closes[entry_index], closes[exit_index], config),
+        });
+    }
+
+    let bar_seconds = match timestamps {
+        [first, second, ..] => second.saturating_sub(*first),
+        _ => 0,
+    };
+    let metrics = BacktestMetrics::new(&equity, &trades, bar_seconds);
+    BacktestResult {
+        equity,
+        trades,
+        metrics,
+    }
+}
+
+pub fn backtest_candles(
+    candles: &DataFrame,
+    signal: &[i8],
+    config: &BacktestConfig,
+) -> Result<BacktestResult> {
+    let closes: Vec<f64> = candles
+        .column("close")?
+        .f64()?
+        .into_iter()
+        .map(|x| x.unwrap_or(f64::NAN))
+        .collect();
+    let timestamps: Vec<u64> = candles
+        .column("timestamp")?
+        .u64()?
+        .into_iter()
+        .map(|x| x.unwrap_or_default())
+        .collect();
+    Ok(run_backtest(&closes, &timestamps, signal, config))
+}
+
+/// The backtest fills at the closes of `candles`, so the signal has to be computed on them
+fn check_signal_index(candles: &Candles, signal: &Indexed<Signal>) -> Result<()> {
+    if !same_index(&candles.time_index()?, &signal.index) {
+        return Err(anyhow!(
+            "The signal was not computed on the candles of the first market ({}), \
+             join it back onto them first",
+            candles.info
+        ));
+    }
+    Ok(())
+}
+
+pub fn market_data_node(graph: &GraphSnapshot) -> Result<usize> {
+    graph
+        .find_nodes("MarketDataNode")
+        .next()
+        .ok_or_else(|| anyhow!("Graph has no MarketDataNode"))
+}
+
+/// The candles of the first `MarketDataNode` and the signal going into the first
+/// `ExecutePositionNode`, taken from the results of a finished graph run.
+pub fn graph_candles_and_signal(
+    graph: &GraphSnapshot,
+    cache: &ComputeCache,
+) -> Result<(Candles, Signal)> {
+    let execute_node_id = graph
+        .find_nodes("ExecutePositionNode")
+        .next()
+        .ok_or_else(|| anyhow!("Graph has no ExecutePositionNode"))?;
+    let signal = graph
+        .input_value(cache, execute_node_id, 0)
+        .ok_or_else(|| anyhow!("ExecutePositionNode has no computed signal"))?;
+    let candles = cache
+        .get(&market_data_node(graph)?)
+        .and_then(|outputs| outputs.first().cloned())
+        .ok_or_else(|| anyhow!("MarketDataNode has no computed candles"))?;
+
+    if let NodeDataTypeWithValue::Signal(signal) = &*signal {
+        if let NodeDataTypeWithValue::Candles(candles) = &*candles {
+            check_signal_index(candles, signal)?;
+            return Ok((candles.clone(), signal.values.clone()));
+        }
+    }
+    Err(anyhow!("Unexpected input types for backtest"))
+}
+
+pub fn backtest_graph(
+    graph: &GraphSnapshot,
+    cache: &ComputeCache,
+    config: &BacktestConfig,
+) -> Result<BacktestResult> {
+    let (candles, signal) = graph_candles_and_signal(graph, cache)?;
+    backtest_candles(&candles.df, &signal, config)
+}
+
+/// Backtests the graph as it is shown in the editor, using the results in `NODE_COMPUTE_CACHE`
+pub fn backtest_snarl(
+    snarl: &Snarl<EFNodeFNSerialized<'_>>,
+    config: &BacktestConfig,
+) -> Result<BacktestResult> {
+    let find_node = |node_name: &str| {
+        snarl
+            .node_ids()
+            .find(|(_, node)| node.node_name == node_name)
+            .map(|(id, _)| id)
+    };
+    let cached_input = |node_id: NodeId| {
+        let remote = *snarl
+            .in_pin(InPinId {
+                node: node_id,
+                input: 0,
+            })
+            .remotes
+            .first()?;
+        NODE_COMPUTE_CACHE
+            .get(&remote.node.0)?
+            .get(remote.output)
+            .cloned()
+    };
+
+    let execute_node_id = find_node("ExecutePositionNode")
+        .ok_or_else(|| anyhow!("Graph has no ExecutePositionNode"))?;
+    let market_data_node_id =
+        find_node("MarketDataNode").ok_or_else(|| anyhow!("Graph has no MarketDataNode"))?;
+    let signal = cached_input(execute_node_id)
+        .ok_or_else(|| anyhow!("ExecutePositionNode has no computed signal"))?;
+    let candles = NODE_COMPUTE_CACHE
+        .get(&market_data_node_id.0)
+        .and_then(|outputs| outputs.first().cloned())
+        .ok_or_else(|| anyhow!("MarketDataNode has no computed candles"))?;
+
+    if let NodeDataTypeWithValue::Signal(signal) = &*signal {
+        if let NodeDataTypeWithValue::Candles(candles) = &*candles {
+            check_signal_index(candles, signal)?;
+            return backtest_candles(&candles.df, signal, config);
+        }
+    }
+    Err(anyhow!("Unexpected input types for backtest"))
+}
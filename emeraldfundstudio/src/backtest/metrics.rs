@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+
+use super::engine::Trade;
+use crate::types::timestamp::SECONDS_PER_YEAR;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BacktestMetrics {
+    /// Final equity relative to the starting balance, 0.1 = +10%
+    pub total_return: f64,
+    /// Largest peak-to-trough equity drop as a positive fraction
+    pub max_drawdown: f64,
+    /// Annualized, based on per-bar returns
+    pub sharpe: f64,
+    pub trade_count: usize,
+    pub win_rate: f64,
+}
+
+pub fn max_drawdown(equity: &[f64]) -> f64 {
+    let mut peak = f64::MIN;
+    let mut result: f64 = 0.0;
+    for value in equity {
+        peak = peak.max(*value);
+        if peak > 0.0 {
+            result = result.max((peak - value) / peak);
+        }
+    }
+    result
+}
+
+pub fn sharpe(equity: &[f64], bar_seconds: u64) -> f64 {
+    if equity.len() < 3 || bar_seconds == 0 {
+        return 0.0;
+    }
+    let returns: Vec<f64> = equity
+        .windows(2)
+        .map(|w| if w[0] > 0.0 { w[1] / w[0] - 1.0 } else { 0.0 })
+        .collect();
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance =
+        returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+    let std = variance.sqrt();
+    if std == 0.0 {
+        return 0.0;
+    }
+    let bars_per_year = SECONDS_PER_YEAR / bar_seconds as f64;
+    mean / std * bars_per_year.sqrt()
+}
+
+impl BacktestMetrics {
+    pub fn new(equity: &[f64], trades: &[Trade], bar_seconds: u64) -> Self {
+        let total_return = match (equity.first(), equity.last()) {
+            (Some(first), Some(last)) if *first > 0.0 => last / first - 1.0,
+            _ => 0.0,
+        };
+        let winning_trades = trades.iter().filter(|trade| trade.pnl > 0.0).count();
+        Self {
+            total_return,
+            max_drawdown: max_drawdown(equity),
+            sharpe: sharpe(equity, bar_seconds),
+            trade_count: trades.len(),
+            win_rate: if trades.is_empty() {
+                0.0
+            } else {
+                winning_trades as f64 / trades.len() as f64
+            },
+        }
+    }
+}
+
+/// A single number picked out of `BacktestMetrics`, used as objective or to color heatmaps
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum Metric {
+    #[default]
+    Sharpe,
+    #[strum(serialize = "Total Return")]
+    TotalReturn,
+    #[strum(serialize = "Max Drawdown")]
+    MaxDrawdown,
+    #[strum(serialize = "Return / Drawdown")]
+    ReturnOverDrawdown,
+    #[strum(serialize = "Trade Count")]
+    TradeCount,
+    #[strum(serialize = "Win Rate")]
+    WinRate,
+}
+
+impl Metric {
+    pub fn value(&self, metrics: &BacktestMetrics) -> f64 {
+        match self {
+            Metric::Sharpe => metrics.sharpe,
+            Metric::TotalReturn => metrics.total_return,
+            Metric::MaxDrawdown => metrics.max_drawdown,
+            Metric::ReturnOverDrawdown => {
+                if metrics.max_drawdown > 0.0 {
+                    metrics.total_return / metrics.max_drawdown
+                } else {
+                    metrics.total_return
+                }
+            }
+            Metric::TradeCount => metrics.trade_count as f64,
+            Metric::WinRate => metrics.win_rate,
+        }
+    }
+
+    /// Same as `value`, but flipped for metrics where lower is better so it can always be maximised
+    pub fn score(&self, metrics: &BacktestMetrics) -> f64 {
+        match self {
+            Metric::MaxDrawdown => -self.value(metrics),
+            _ => self.value(metrics),
+        }
+    }
+}
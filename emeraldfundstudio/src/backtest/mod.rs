@@ -0,0 +1,4 @@
+pub mod engine;
+pub mod metrics;
+pub mod monte_carlo;
+pub mod panel;
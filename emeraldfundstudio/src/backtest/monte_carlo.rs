@@ -0,0 +1,220 @@
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+
+use super::engine::{BacktestConfig, Trade};
+
+pub const PERCENTILES: [f64; 5] = [0.05, 0.25, 0.5, 0.75, 0.95];
+
+/// Runs whose equity curves are kept for the bands, the others only keep their final equity
+/// and drawdown so memory doesn't grow with runs × trades
+const BAND_RUNS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum ResampleMode {
+    /// Same trades in a random order
+    #[default]
+    Shuffle,
+    /// Trades drawn with replacement, some appear several times and some not at all
+    Bootstrap,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonteCarloConfig {
+    pub runs: usize,
+    pub mode: ResampleMode,
+    pub seed: u64,
+    /// Fees are scaled by a random factor in `1 ± fee_jitter`
+    pub fee_jitter: f64,
+    /// Up to this much extra slippage (fraction of the price) is added to every fill
+    pub slippage_jitter: f64,
+    /// A run is ruined once its drawdown reaches this fraction
+    pub ruin_drawdown: f64,
+}
+
+impl Default for MonteCarloConfig {
+    fn default() -> Self {
+        Self {
+            runs: 2000,
+            mode: ResampleMode::Shuffle,
+            seed: 21,
+            fee_jitter: 0.5,
+            slippage_jitter: 0.0005,
+            ruin_drawdown: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MonteCarloResult {
+    /// Sorted ascending
+    pub final_equity: Vec<f64>,
+    /// Sorted ascending
+    pub max_drawdowns: Vec<f64>,
+    /// Fraction of the runs that hit `ruin_drawdown`
+    pub risk_of_ruin: f64,
+    /// Equity after each trade at every value of `PERCENTILES`, starting with the initial balance.
+    /// Taken from the first `BAND_RUNS` runs, which are as random as any others.
+    pub bands: Vec<[f64; 5]>,
+}
+
+/// Value at `p` (0..=1) of an ascending slice, using the nearest rank
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let idx = ((sorted.len() - 1) as f64 * p.clamp(0.0, 1.0)).round() as usize;
+    sorted[idx]
+}
+
+/// What's kept of a single run
+struct Run {
+    final_equity: f64,
+    max_drawdown: f64,
+    /// Only for the runs that make up the bands
+    equity: Option<Vec<f64>>,
+}
+
+fn simulate(
+    trades: &[Trade],
+    backtest_config: &BacktestConfig,
+    config: &MonteCarloConfig,
+    rng: &mut ChaCha8Rng,
+    keep_equity: bool,
+) -> Run {
+    let sequence: Vec<&Trade> = match config.mode {
+        ResampleMode::Shuffle => {
+            let mut sequence: Vec<&Trade> = trades.iter().collect();
+            sequence.shuffle(rng);
+            sequence
+        }
+        ResampleMode::Bootstrap => (0..trades.len())
+            .map(|_| &trades[rng.gen_range(0..trades.len())])
+            .collect(),
+    };
+
+    let mut equity = keep_equity.then(|| Vec::with_capacity(sequence.len() + 1));
+    let mut balance = backtest_config.initial_balance;
+    // Same as `metrics::max_drawdown`, without needing the whole curve
+    let mut peak = balance;
+    let mut max_drawdown: f64 = 0.0;
+    if let Some(equity) = &mut equity {
+        equity.push(balance);
+    }
+    for trade in sequence {
+        let perturbed = BacktestConfig {
+            fee: backtest_config.fee * (1.0 + config.fee_jitter * rng.gen_range(-1.0..=1.0)),
+            slippage: backtest_config.slippage + config.slippage_jitter * rng.gen::<f64>(),
+            ..backtest_config.clone()
+        };
+        balance *= 1.0 + Trade::net_return(trade.entry_price, trade.exit_price, &perturbed);
+        peak = peak.max(balance);
+        if peak > 0.0 {
+            max_drawdown = max_drawdown.max((peak - balance) / peak);
+        }
+        if let Some(equity) = &mut equity {
+            equity.push(balance);
+        }
+    }
+    Run {
+        final_equity: balance,
+        max_drawdown,
+        equity,
+    }
+}
+
+/// Replays the trades of a finished backtest `config.runs` times in random orders and with
+/// randomised costs, to see how much of the result was luck. Deterministic for a given seed.
+pub fn run_monte_carlo(
+    trades: &[Trade],
+    backtest_config: &BacktestConfig,
+    config: &MonteCarloConfig,
+) -> MonteCarloResult {
+    if trades.is_empty() || config.runs == 0 {
+        return MonteCarloResult::default();
+    }
+
+    let runs: Vec<Run> = (0..config.runs)
+        .into_par_iter()
+        .map(|run| {
+            // Every run gets its own stream so the result doesn't depend on thread scheduling
+            let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+            rng.set_stream(run as u64);
+            simulate(trades, backtest_config, config, &mut rng, run < BAND_RUNS)
+        })
+        .collect();
+
+    let mut final_equity: Vec<f64> = runs.iter().map(|run| run.final_equity).collect();
+    let mut max_drawdowns: Vec<f64> = runs.iter().map(|run| run.max_drawdown).collect();
+    final_equity.sort_by(f64::total_cmp);
+    max_drawdowns.sort_by(f64::total_cmp);
+    let ruined = max_drawdowns
+        .iter()
+        .filter(|drawdown| **drawdown >= config.ruin_drawdown)
+        .count();
+
+    let curves: Vec<&Vec<f64>> = runs.iter().filter_map(|run| run.equity.as_ref()).collect();
+    let bands = (0..=trades.len())
+        .map(|step| {
+            let mut values: Vec<f64> = curves.iter().map(|curve| curve[step]).collect();
+            values.sort_by(f64::total_cmp);
+            PERCENTILES.map(|p| percentile(&values, p))
+        })
+        .collect();
+
+    MonteCarloResult {
+        final_equity,
+        max_drawdowns,
+        risk_of_ruin: ruined as f64 / config.runs as f64,
+        bands,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    fn trades() -> Vec<Trade> {
+        (0..40)
+            .map(|i| {
+                let entry_price = 100.0 + i as f64;
+                let change = if i % 3 == 0 { -0.04 } else { 0.03 };
+                Trade {
+                    entry_index: i * 10,
+                    exit_index: i * 10 + 5,
+                    entry_price,
+                    exit_price: entry_price * (1.0 + change),
+                    pnl: change,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn monte_carlo_is_deterministic_by_seed() {
+        let trades = trades();
+        let backtest_config = BacktestConfig::default();
+        for mode in ResampleMode::iter() {
+            let config = MonteCarloConfig {
+                runs: 300,
+                mode,
+                ..Default::default()
+            };
+            let first = run_monte_carlo(&trades, &backtest_config, &config);
+            let second = run_monte_carlo(&trades, &backtest_config, &config);
+            assert_eq!(first.final_equity, second.final_equity, "{}", mode);
+            assert_eq!(first.max_drawdowns, second.max_drawdowns, "{}", mode);
+            assert_eq!(first.bands, second.bands, "{}", mode);
+            assert_eq!(first.bands.len(), trades.len() + 1);
+
+            let other = MonteCarloConfig { seed: 22, ..config };
+            let other = run_monte_carlo(&trades, &backtest_config, &other);
+            assert_ne!(first.max_drawdowns, other.max_drawdowns, "{}", mode);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use egui::{Button, Color32, ComboBox, DragValue, Grid, RichText, Spinner, Ui};
+use egui_plot::{Legend, Line, Plot, PlotPoints, Polygon};
+use egui_snarl::Snarl;
+use parking_lot::Mutex;
+use strum::IntoEnumIterator;
+
+use super::{
+    engine::{backtest_snarl, BacktestConfig, BacktestResult, Trade},
+    monte_carlo::{
+        percentile, run_monte_carlo, MonteCarloConfig, MonteCarloResult, ResampleMode, PERCENTILES,
+    },
+};
+use crate::node_editor::node_trait::EFNodeFNSerialized;
+
+const EQUITY_COLOR: Color32 = Color32::from_rgb(0x00, 0xb0, 0x00);
+const BAND_OUTER_COLOR: Color32 = Color32::from_rgba_premultiplied(0x00, 0x40, 0x80, 0x30);
+const BAND_INNER_COLOR: Color32 = Color32::from_rgba_premultiplied(0x00, 0x60, 0xb0, 0x50);
+const MEDIAN_COLOR: Color32 = Color32::from_rgb(0x40, 0x90, 0xe0);
+
+/// Same x scale as the candlestick chart, so both plots can share their x axis
+fn candle_x(idx: usize) -> f64 {
+    idx as f64 * 0.01
+}
+
+/// Polygon between two percentile curves
+fn band<'a>(xs: &[f64], bands: &[[f64; 5]], lower: usize, upper: usize) -> PlotPoints<'a> {
+    let upper_points = xs.iter().zip(bands).map(|(x, band)| [*x, band[upper]]);
+    let lower_points = xs
+        .iter()
+        .zip(bands)
+        .rev()
+        .map(|(x, band)| [*x, band[lower]]);
+    upper_points.chain(lower_points).collect()
+}
+
+#[derive(Default)]
+struct SharedState {
+    /// A Monte Carlo analysis that finished and the trade count it was made for, until the
+    /// panel takes it
+    finished: Option<(usize, MonteCarloResult)>,
+    running: bool,
+}
+
+/// Equity curve of the graph's backtest, with optional Monte Carlo robustness analysis on a
+/// worker thread
+#[derive(Default)]
+pub struct BacktestPanel {
+    pub open: bool,
+    config: BacktestConfig,
+    monte_carlo_config: MonteCarloConfig,
+    /// The trade count the Monte Carlo result was made for, to warn when it's outdated
+    monte_carlo: Option<(usize, MonteCarloResult)>,
+    state: Arc<Mutex<SharedState>>,
+}
+
+impl BacktestPanel {
+    pub fn show(&mut self, ui: &mut Ui, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+        if let Some(finished) = self.state.lock().finished.take() {
+            self.monte_carlo = Some(finished);
+        }
+        let backtest = match backtest_snarl(snarl, &self.config) {
+            Ok(backtest) => backtest,
+            Err(e) => {
+                ui.label(format!("No backtest: {}", e));
+                return;
+            }
+        };
+
+        ui.horizontal(|ui| {
+            let metrics = &backtest.metrics;
+            ui.label(format!(
+                "Return {:+.2}% | Max DD {:.2}% | Sharpe {:.2} | {} trades | Win rate {:.0}%",
+                metrics.total_return * 100.0,
+                metrics.max_drawdown * 100.0,
+                metrics.sharpe,
+                metrics.trade_count,
+                metrics.win_rate * 100.0
+            ));
+            ui.separator();
+            ui.label("Fee");
+            ui.add(
+                DragValue::new(&mut self.config.fee)
+                    .speed(0.0001)
+                    .range(0.0..=0.1),
+            );
+            ui.label("Slippage");
+            ui.add(
+                DragValue::new(&mut self.config.slippage)
+                    .speed(0.0001)
+                    .range(0.0..=0.1),
+            );
+        });
+
+        ui.columns(2, |columns| {
+            self.show_equity_chart(&mut columns[0], &backtest);
+            self.show_monte_carlo(&mut columns[1], &backtest);
+        });
+    }
+
+    fn show_equity_chart(&self, ui: &mut Ui, backtest: &BacktestResult) {
+        let equity = PlotPoints::from_iter(
+            backtest
+                .equity
+                .iter()
+                .enumerate()
+                .map(|(idx, value)| [candle_x(idx), *value]),
+        );
+
+        let mut bands = vec![];
+        let mut median = None;
+        if let Some((_, monte_carlo)) = &self.monte_carlo {
+            // Step 0 is the starting balance, step n is the equity after the n-th trade exit
+            let xs: Vec<f64> = backtest
+                .trades
+                .first()
+                .map(|trade| candle_x(trade.entry_index))
+                .into_iter()
+                .chain(
+                    backtest
+                        .trades
+                        .iter()
+                        .map(|trade| candle_x(trade.exit_index)),
+                )
+                .collect();
+            let n = xs.len().min(monte_carlo.bands.len());
+            let (xs, monte_carlo_bands) = (&xs[..n], &monte_carlo.bands[..n]);
+            bands.push(
+                Polygon::new(band(xs, monte_carlo_bands, 0, 4))
+                    .name("5-95%")
+                    .fill_color(BAND_OUTER_COLOR),
+            );
+            bands.push(
+                Polygon::new(band(xs, monte_carlo_bands, 1, 3))
+                    .name("25-75%")
+                    .fill_color(BAND_INNER_COLOR),
+            );
+            let points = xs
+                .iter()
+                .zip(monte_carlo_bands)
+                .map(|(x, band)| [*x, band[2]])
+                .collect::<PlotPoints<'_>>();
+            median = Some(Line::new(points).name("Median").color(MEDIAN_COLOR));
+        }
+
+        Plot::new("equity chart")
+            .legend(Legend::default())
+            .link_axis("candles", [true, false])
+            .show(ui, |plot_ui| {
+                for band in bands {
+                    plot_ui.polygon(band);
+                }
+                if let Some(median) = median {
+                    plot_ui.line(median);
+                }
+                plot_ui.line(Line::new(equity).name("Equity").color(EQUITY_COLOR));
+            });
+    }
+
+    fn start_monte_carlo(&mut self, ctx: egui::Context, trades: Vec<Trade>) {
+        self.state.lock().running = true;
+        let state = self.state.clone();
+        let backtest_config = self.config.clone();
+        let config = self.monte_carlo_config.clone();
+        let monte_carlo = move || {
+            let result = run_monte_carlo(&trades, &backtest_config, &config);
+            let mut state = state.lock();
+            state.finished = Some((trades.len(), result));
+            state.running = false;
+            ctx.request_repaint();
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(monte_carlo);
+        // There are no threads on the web, the analysis runs before the next frame
+        #[cfg(target_arch = "wasm32")]
+        monte_carlo();
+    }
+
+    fn show_monte_carlo(&mut self, ui: &mut Ui, backtest: &BacktestResult) {
+        ui.heading("Monte Carlo");
+        Grid::new("monte_carlo_config").show(ui, |ui| {
+            ui.label("Runs");
+            ui.add(DragValue::new(&mut self.monte_carlo_config.runs).range(1..=100_000));
+            ui.label("Mode");
+            ComboBox::from_id_salt("monte_carlo_mode")
+                .selected_text(self.monte_carlo_config.mode.to_string())
+                .show_ui(ui, |ui| {
+                    for mode in ResampleMode::iter() {
+                        ui.selectable_value(
+                            &mut self.monte_carlo_config.mode,
+                            mode,
+                            mode.to_string(),
+                        );
+                    }
+                });
+            ui.end_row();
+            ui.label("Fee jitter");
+            ui.add(
+                DragValue::new(&mut self.monte_carlo_config.fee_jitter)
+                    .speed(0.01)
+                    .range(0.0..=1.0),
+            );
+            ui.label("Extra slippage");
+            ui.add(
+                DragValue::new(&mut self.monte_carlo_config.slippage_jitter)
+                    .speed(0.0001)
+                    .range(0.0..=0.1),
+            );
+            ui.end_row();
+            ui.label("Ruin at drawdown");
+            ui.add(
+                DragValue::new(&mut self.monte_carlo_config.ruin_drawdown)
+                    .speed(0.01)
+                    .range(0.01..=1.0),
+            );
+            ui.label("Seed");
+            ui.add(DragValue::new(&mut self.monte_carlo_config.seed));
+            ui.end_row();
+        });
+
+        ui.horizontal(|ui| {
+            let running = self.state.lock().running;
+            if ui.add_enabled(!running, Button::new("Run")).clicked() {
+                self.start_monte_carlo(ui.ctx().clone(), backtest.trades.clone());
+            }
+            if running {
+                ui.add(Spinner::new());
+            }
+            if self.monte_carlo.is_some() && ui.button("Clear").clicked() {
+                self.monte_carlo = None;
+            }
+        });
+
+        let Some((n_trades, result)) = &self.monte_carlo else {
+            return;
+        };
+        if *n_trades != backtest.trades.len() {
+            ui.colored_label(Color32::YELLOW, "The backtest changed, run again");
+        }
+        if result.final_equity.is_empty() {
+            ui.label("The backtest has no trades to resample");
+            return;
+        }
+        ui.label(format!("Risk of ruin: {:.1}%", result.risk_of_ruin * 100.0));
+        Grid::new("monte_carlo_result")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("");
+                for p in PERCENTILES {
+                    ui.label(RichText::new(format!("P{:.0}", p * 100.0)).strong());
+                }
+                ui.end_row();
+                ui.label(RichText::new("Final equity").strong());
+                for p in PERCENTILES {
+                    ui.label(format!("{:.2}", percentile(&result.final_equity, p)));
+                }
+                ui.end_row();
+                ui.label(RichText::new("Max drawdown").strong());
+                for p in PERCENTILES {
+                    ui.label(format!(
+                        "{:.2}%",
+                        percentile(&result.max_drawdowns, p) * 100.0
+                    ));
+                }
+                ui.end_row();
+            });
+    }
+}
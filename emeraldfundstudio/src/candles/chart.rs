@@ -3,10 +3,11 @@ use crate::{
         node_trait::{
             CheapCloneNodeDataTypeWithValue, EFNodeFNSerialized, EFNodeFn, NodeDataTypeWithValue,
         },
-        nodes::preview::PreviewNode,
+        nodes::{market_data::MarketDataNode, preview::PreviewNode},
+        OrderDirection,
     },
     node_runners::realtime::NODE_COMPUTE_CACHE,
-    types::candles::generate_candles,
+    types::{candles::Candles, live_trade::LiveTrade, time_index::TimeIndex, timestamp::TimeStamp},
 };
 use chrono::{DateTime, Utc};
 use ecolor::Color32;
@@ -21,6 +22,8 @@ const CANDLE_GREEN: Color32 = Color32::from_rgb(0, 255, 0);
 
 const MARKER_BUY: Color32 = Color32::from_rgb(12, 116, 169);
 const MARKER_SELL: Color32 = Color32::from_rgb(163, 43, 138);
+const MARKER_LIVE_BUY: Color32 = Color32::from_rgb(64, 200, 255);
+const MARKER_LIVE_SELL: Color32 = Color32::from_rgb(255, 140, 0);
 
 pub fn candles_to_box_chart(df: &DataFrame) -> Vec<BoxElem> {
     let opens = df.column("open").unwrap().f64().unwrap();
@@ -61,9 +64,16 @@ pub fn candles_to_box_chart(df: &DataFrame) -> Vec<BoxElem> {
     .collect()
 }
 
+/// Candle of the chart that closes at `timestamp`, values of other timeframes only show up
+/// on the candles where they are known
+fn chart_position(chart_index: &TimeIndex, timestamp: &TimeStamp) -> Option<usize> {
+    chart_index.binary_search(timestamp).ok()
+}
+
 pub fn signals_as_markers<'a>(
     snarl: &Snarl<EFNodeFNSerialized<'_>>,
     box_chart: &[BoxElem],
+    chart_index: &TimeIndex,
 ) -> Vec<Points<'a>> {
     let mut result: Vec<Points<'a>> = Vec::new();
     snarl.node_ids().for_each(|(id, node)| {
@@ -86,17 +96,25 @@ pub fn signals_as_markers<'a>(
                 if let NodeDataTypeWithValue::Signal(signal) = &**cached_result {
                     let mut pt_sell = vec![];
                     let mut pt_buy = vec![];
-                    signal.iter().enumerate().for_each(|(idx, signal)| {
-                        match *signal {
-                            -1 => {
-                                pt_sell.push([(idx as f64) * 0.01, box_chart[idx].spread.median]);
-                            }
-                            1 => {
-                                pt_buy.push([(idx as f64) * 0.01, box_chart[idx].spread.median]);
-                            }
-                            _ => {}
-                        };
-                    });
+                    signal
+                        .iter()
+                        .zip(signal.index.iter())
+                        .for_each(|(signal, timestamp)| {
+                            let Some(idx) = chart_position(chart_index, timestamp) else {
+                                return;
+                            };
+                            match *signal {
+                                -1 => {
+                                    pt_sell
+                                        .push([(idx as f64) * 0.01, box_chart[idx].spread.median]);
+                                }
+                                1 => {
+                                    pt_buy
+                                        .push([(idx as f64) * 0.01, box_chart[idx].spread.median]);
+                                }
+                                _ => {}
+                            };
+                        });
                     if !pt_sell.is_empty() {
                         result.push(
                             Points::new(pt_sell)
@@ -125,6 +143,53 @@ pub fn signals_as_markers<'a>(
     return result;
 }
 
+/// Fills of a live bot on the charted pair, at their price on the candle they happened in
+pub fn live_trades_as_markers<'a>(
+    trades: &[LiveTrade],
+    pair: &str,
+    chart_timestamps: &[TimeStamp],
+    chart_index: &TimeIndex,
+) -> Vec<Points<'a>> {
+    let mut pt_buy = vec![];
+    let mut pt_sell = vec![];
+    for trade in trades.iter().filter(|trade| trade.is_pair(pair)) {
+        let Some(idx) = chart_timestamps
+            .partition_point(|open| *open <= trade.timestamp)
+            .checked_sub(1)
+        else {
+            continue;
+        };
+        if trade.timestamp >= chart_index[idx] {
+            continue;
+        }
+        match trade.direction {
+            OrderDirection::Buy => pt_buy.push([(idx as f64) * 0.01, trade.price]),
+            OrderDirection::Sell => pt_sell.push([(idx as f64) * 0.01, trade.price]),
+        }
+    }
+
+    let mut result: Vec<Points<'a>> = Vec::new();
+    if !pt_buy.is_empty() {
+        result.push(
+            Points::new(pt_buy)
+                .name("Live buy")
+                .color(MARKER_LIVE_BUY)
+                .radius(5.0)
+                .shape(MarkerShape::Up),
+        );
+    }
+    if !pt_sell.is_empty() {
+        result.push(
+            Points::new(pt_sell)
+                .name("Live sell")
+                .color(MARKER_LIVE_SELL)
+                .radius(5.0)
+                .shape(MarkerShape::Down),
+        );
+    }
+    return result;
+}
+
 fn get_preview_outputs<'a>(
     snarl: &'a Snarl<EFNodeFNSerialized<'a>>,
 ) -> impl Iterator<Item = ([u8; 3], CheapCloneNodeDataTypeWithValue)> + use<'a> {
@@ -153,18 +218,52 @@ fn get_preview_outputs<'a>(
     })
 }
 
-pub fn candlestick_chart(ui: &mut eframe::egui::Ui, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
-    let candles = generate_candles(21, 500).unwrap();
-    let first_timestamp = candles
-        .column("timestamp")
-        .unwrap()
-        .u64()
-        .unwrap()
-        .get(0)
-        .unwrap() as u64;
+/// First output of the first `MarketDataNode`, or the default synthetic market when it hasn't run yet
+fn chart_candles(snarl: &Snarl<EFNodeFNSerialized<'_>>) -> Candles {
+    let computed = snarl.node_ids().find_map(|(id, node)| {
+        if node.get_node().get_name() != "MarketDataNode" {
+            return None;
+        }
+        let cached_result = NODE_COMPUTE_CACHE.get(&id.0)?;
+        if let NodeDataTypeWithValue::Candles(candles) = &**cached_result.first()? {
+            return Some(candles.clone());
+        }
+        None
+    });
+    return computed
+        .unwrap_or_else(|| MarketDataNode::default().generate().unwrap().swap_remove(0));
+}
+
+pub fn candlestick_chart(
+    ui: &mut eframe::egui::Ui,
+    snarl: &Snarl<EFNodeFNSerialized<'_>>,
+    live_trades: &[LiveTrade],
+) {
+    let chart_candles = chart_candles(snarl);
+    let Ok(chart_index) = chart_candles.time_index() else {
+        return;
+    };
+    let Candles { info, df: candles } = chart_candles;
+    if candles.height() == 0 {
+        return;
+    }
+    let timestamps = candles.column("timestamp").unwrap().u64().unwrap();
+    let first_timestamp = timestamps.get(0).unwrap();
+    let bar_seconds = match timestamps.get(1) {
+        Some(second_timestamp) => second_timestamp - first_timestamp,
+        None => 60,
+    };
     let box_chart = candles_to_box_chart(&candles);
-    let markers = signals_as_markers(snarl, &box_chart);
+    let mut markers = signals_as_markers(snarl, &box_chart, &chart_index);
+    let chart_timestamps: Vec<TimeStamp> = timestamps.into_no_null_iter().collect();
+    markers.extend(live_trades_as_markers(
+        live_trades,
+        &info.pair,
+        &chart_timestamps,
+        &chart_index,
+    ));
     let data = BoxPlot::new(box_chart)
+        .name(info.to_string())
         // TODO: finish this formatter
         .element_formatter(Box::new(|elm, _| {
             format!(
@@ -182,8 +281,9 @@ pub fn candlestick_chart(ui: &mut eframe::egui::Ui, snarl: &Snarl<EFNodeFNSerial
 
     let plot = Plot::new("candlestick chart")
         .legend(Legend::default())
+        .link_axis("candles", [true, false])
         .x_axis_formatter(|grid, _| {
-            let d = (first_timestamp + ((grid.value * 100.0 * 60.0) as u64)) as i64;
+            let d = (first_timestamp + ((grid.value * 100.0 * bar_seconds as f64) as u64)) as i64;
             let datetime = DateTime::<Utc>::from_timestamp(d, 0).unwrap();
             datetime.format("%Y-%m-%d %H:%M").to_string()
         });
@@ -197,12 +297,16 @@ pub fn candlestick_chart(ui: &mut eframe::egui::Ui, snarl: &Snarl<EFNodeFNSerial
                 }
                 for output in get_preview_outputs(&snarl) {
                     if let NodeDataTypeWithValue::DecimalSequence(seq) = &*output.1 {
-                        let f64_iter = seq.iter().enumerate().filter_map(|(i, x)| {
-                            if let Some(x) = x {
-                                return Some([i as f64 * 0.01, x]);
-                            }
-                            None
-                        });
+                        let f64_iter =
+                            seq.iter()
+                                .zip(seq.index.iter())
+                                .filter_map(|(x, timestamp)| {
+                                    let i = chart_position(&chart_index, timestamp)?;
+                                    if let Some(x) = x {
+                                        return Some([i as f64 * 0.01, x]);
+                                    }
+                                    None
+                                });
                         let line_points = PlotPoints::from_iter(f64_iter);
                         let line = Line::new(line_points)
                             .color(Color32::from_rgb(output.0[0], output.0[1], output.0[2]))
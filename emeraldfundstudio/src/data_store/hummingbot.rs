@@ -0,0 +1,84 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use rusqlite::{types::ValueRef, Connection, OpenFlags};
+
+use super::store::read_ohlcv_csv;
+use crate::{
+    node_editor::OrderDirection,
+    types::{
+        candles::{Candles, MarketInfo},
+        live_trade::LiveTrade,
+        timeframe::parse_timeframe_label,
+        timestamp::TimeStamp,
+    },
+};
+
+/// Hummingbot stores prices and amounts as integers with 6 decimals (`SqliteDecimal(6)`)
+const SQLITE_DECIMAL_SCALE: f64 = 1_000_000.0;
+
+/// Market of a candles feed file, which Hummingbot names
+/// `candles_{connector}_{trading_pair}_{interval}.csv`
+pub fn candles_file_info(path: &Path) -> Option<MarketInfo> {
+    let name = path.file_stem()?.to_str()?.strip_prefix("candles_")?;
+    let mut parts = name.rsplitn(3, '_');
+    let interval = parts.next()?;
+    let pair = parts.next()?;
+    let exchange = parts.next()?;
+    Some(MarketInfo {
+        exchange: exchange.to_owned(),
+        pair: pair.to_owned(),
+        timeframe_seconds: parse_timeframe_label(interval)?,
+    })
+}
+
+/// Reads a Hummingbot candles feed CSV. The market comes from the file name unless `info` is
+/// given.
+pub fn read_candles(path: &Path, info: Option<MarketInfo>) -> Result<Candles> {
+    let info = info.or_else(|| candles_file_info(path)).ok_or_else(|| {
+        anyhow!(
+            "Can't tell the market of {}, expected a name like candles_binance_BTC-USDT_1m.csv",
+            path.display()
+        )
+    })?;
+    Ok(Candles::new(info, read_ohlcv_csv(path)?))
+}
+
+fn decimal(value: ValueRef<'_>) -> Result<f64> {
+    match value {
+        ValueRef::Integer(value) => Ok(value as f64 / SQLITE_DECIMAL_SCALE),
+        ValueRef::Real(value) => Ok(value),
+        ValueRef::Text(text) => Ok(std::str::from_utf8(text)?.trim().parse()?),
+        value => Err(anyhow!("Expected a number, got {:?}", value.data_type())),
+    }
+}
+
+/// Fills of the `TradeFill` table of a Hummingbot trades database, oldest first
+pub fn read_trades(path: &Path) -> Result<Vec<LiveTrade>> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare(
+        "SELECT timestamp, market, symbol, trade_type, price, amount \
+         FROM TradeFill ORDER BY timestamp",
+    )?;
+    let mut rows = statement.query([])?;
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        let timestamp: i64 = row.get(0)?;
+        let trade_type: String = row.get(3)?;
+        let direction = match trade_type.to_uppercase().as_str() {
+            "BUY" => OrderDirection::Buy,
+            "SELL" => OrderDirection::Sell,
+            other => return Err(anyhow!("Unknown trade type \"{}\"", other)),
+        };
+        result.push(LiveTrade {
+            // Milliseconds
+            timestamp: (timestamp.max(0) / 1000) as TimeStamp,
+            exchange: row.get(1)?,
+            pair: row.get(2)?,
+            direction,
+            price: decimal(row.get_ref(4)?)?,
+            amount: decimal(row.get_ref(5)?)?,
+        });
+    }
+    Ok(result)
+}
@@ -0,0 +1,8 @@
+pub mod quality;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hummingbot;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod panel;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod store;
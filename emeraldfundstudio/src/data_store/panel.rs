@@ -0,0 +1,346 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use chrono::DateTime;
+use egui::{Color32, ComboBox, DragValue, Grid, ScrollArea, TextEdit, Ui};
+use egui_snarl::Snarl;
+use strum::IntoEnumIterator;
+
+use super::{
+    hummingbot::{candles_file_info, read_candles, read_trades},
+    quality::{check_quality, repair, QualityReport, RepairMode, DEFAULT_OUTLIER_THRESHOLD},
+    store::{CandleStore, CANDLE_STORE},
+};
+use crate::{
+    node_editor::node_trait::EFNodeFNSerialized,
+    node_runners::realtime::{clear_cache, run_nodes},
+    types::{
+        candles::MarketInfo, live_trade::LiveTrade, timeframe::Timeframe, timestamp::TimeStamp,
+    },
+};
+
+pub fn format_date(timestamp: TimeStamp) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|datetime| datetime.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+enum Action {
+    Check,
+    Repair(RepairMode),
+    Remove,
+}
+
+/// Imports candles into the local store and checks what is stored
+pub struct DataStorePanel {
+    pub open: bool,
+    csv_path: String,
+    exchange: String,
+    pair: String,
+    timeframe: Timeframe,
+    outlier_threshold: f64,
+    reports: HashMap<MarketInfo, QualityReport>,
+    message: Option<Result<String, String>>,
+    hummingbot_candles_path: String,
+    hummingbot_trades_path: String,
+}
+
+impl Default for DataStorePanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            csv_path: String::new(),
+            exchange: String::new(),
+            pair: String::new(),
+            timeframe: Timeframe::M1,
+            outlier_threshold: DEFAULT_OUTLIER_THRESHOLD,
+            reports: HashMap::new(),
+            message: None,
+            hummingbot_candles_path: String::new(),
+            hummingbot_trades_path: String::new(),
+        }
+    }
+}
+
+impl DataStorePanel {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        live_trades: &mut Vec<LiveTrade>,
+    ) {
+        let mut open = self.open;
+        egui::Window::new("Data Store")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    let mut store = CANDLE_STORE.lock();
+                    ui.label(format!("Location: {}", store.root().display()));
+                    ui.separator();
+                    let mut changed = self.show_import(ui, &mut store);
+                    ui.separator();
+                    changed |= self.show_hummingbot(ui, &mut store, live_trades);
+                    ui.separator();
+                    changed |= self.show_entries(ui, &mut store);
+                    if let Some(message) = &self.message {
+                        match message {
+                            Ok(message) => ui.label(message),
+                            Err(error) => ui.colored_label(Color32::RED, error),
+                        };
+                    }
+                    drop(store);
+                    if changed {
+                        // Market data nodes might read the candles that changed
+                        clear_cache();
+                        run_nodes(snarl);
+                    }
+                });
+            });
+        self.open = open;
+    }
+
+    fn show_import(&mut self, ui: &mut Ui, store: &mut CandleStore) -> bool {
+        ui.heading("Import CSV");
+        Grid::new("data_store_import").show(ui, |ui| {
+            ui.label("File");
+            ui.add(
+                TextEdit::singleline(&mut self.csv_path)
+                    .hint_text("/path/to/candles.csv")
+                    .desired_width(320.0),
+            );
+            ui.end_row();
+            ui.label("Exchange");
+            ui.text_edit_singleline(&mut self.exchange);
+            ui.end_row();
+            ui.label("Pair");
+            ui.text_edit_singleline(&mut self.pair);
+            ui.end_row();
+            ui.label("Timeframe");
+            ComboBox::from_id_salt("data_store_timeframe")
+                .selected_text(self.timeframe.to_string())
+                .show_ui(ui, |ui| {
+                    for timeframe in Timeframe::iter() {
+                        ui.selectable_value(&mut self.timeframe, timeframe, timeframe.to_string());
+                    }
+                });
+            ui.end_row();
+        });
+        ui.label("Needs a header with timestamp, open, high, low, close and volume columns.");
+        if !ui.button("Import").clicked() {
+            return false;
+        }
+        let info = MarketInfo {
+            exchange: self.exchange.trim().to_owned(),
+            pair: self.pair.trim().to_owned(),
+            timeframe_seconds: self.timeframe.seconds(),
+        };
+        let path = PathBuf::from(self.csv_path.trim());
+        self.message = Some(
+            store
+                .import_csv(&path, &info)
+                .map(|entry| format!("{} now has {} candles", info, entry.rows()))
+                .map_err(|e| e.to_string()),
+        );
+        self.reports.remove(&info);
+        self.message.as_ref().is_some_and(|message| message.is_ok())
+    }
+
+    fn show_hummingbot(
+        &mut self,
+        ui: &mut Ui,
+        store: &mut CandleStore,
+        live_trades: &mut Vec<LiveTrade>,
+    ) -> bool {
+        ui.heading("Hummingbot");
+        let mut changed = false;
+        Grid::new("data_store_hummingbot").show(ui, |ui| {
+            ui.label("Candles feed CSV");
+            ui.add(
+                TextEdit::singleline(&mut self.hummingbot_candles_path)
+                    .hint_text("candles_binance_BTC-USDT_1m.csv")
+                    .desired_width(320.0),
+            );
+            if ui.button("Import").clicked() {
+                let path = PathBuf::from(self.hummingbot_candles_path.trim());
+                let result = read_candles(&path, None).and_then(|candles| {
+                    let entry = store.import(&candles.info, &candles.df)?;
+                    Ok(format!("{} now has {} candles", entry.info, entry.rows()))
+                });
+                if let Some(info) = candles_file_info(&path) {
+                    self.reports.remove(&info);
+                }
+                changed = result.is_ok();
+                self.message = Some(result.map_err(|e| e.to_string()));
+            }
+            ui.end_row();
+
+            ui.label("Trades database");
+            ui.add(
+                TextEdit::singleline(&mut self.hummingbot_trades_path)
+                    .hint_text("data/conf_pure_mm_1.sqlite")
+                    .desired_width(320.0),
+            );
+            if ui.button("Load").clicked() {
+                let path = PathBuf::from(self.hummingbot_trades_path.trim());
+                self.message = Some(match read_trades(&path) {
+                    Ok(trades) => {
+                        *live_trades = trades;
+                        Ok(format!("Loaded {} live trades", live_trades.len()))
+                    }
+                    Err(e) => Err(e.to_string()),
+                });
+            }
+            ui.end_row();
+        });
+        if let (Some(first), Some(last)) = (
+            live_trades.first().map(|trade| trade.timestamp),
+            live_trades.last().map(|trade| trade.timestamp),
+        ) {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} live trades from {} to {}, shown on the chart when their pair is charted",
+                    live_trades.len(),
+                    format_date(first),
+                    format_date(last)
+                ));
+                if ui.button("Clear").clicked() {
+                    live_trades.clear();
+                }
+            });
+            let mut markets: BTreeMap<(&str, &str), (usize, f64)> = BTreeMap::new();
+            for trade in live_trades.iter() {
+                let market = markets.entry((&trade.exchange, &trade.pair)).or_default();
+                market.0 += 1;
+                market.1 += trade.amount;
+            }
+            Grid::new("data_store_live_markets")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Exchange");
+                    ui.label("Pair");
+                    ui.label("Trades");
+                    ui.label("Traded amount");
+                    ui.end_row();
+                    for ((exchange, pair), (trades, amount)) in markets {
+                        ui.label(exchange);
+                        ui.label(pair);
+                        ui.label(trades.to_string());
+                        ui.label(format!("{:.6}", amount));
+                        ui.end_row();
+                    }
+                });
+        }
+        changed
+    }
+
+    fn show_entries(&mut self, ui: &mut Ui, store: &mut CandleStore) -> bool {
+        ui.heading("Stored candles");
+        if store.entries().is_empty() {
+            ui.label("Nothing stored yet");
+            return false;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Outlier threshold (MADs)");
+            ui.add(
+                DragValue::new(&mut self.outlier_threshold)
+                    .speed(0.1)
+                    .range(1.0..=100.0),
+            );
+        });
+
+        let mut action = None;
+        Grid::new("data_store_entries")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Market");
+                ui.label("From");
+                ui.label("To");
+                ui.label("Candles");
+                ui.label("Months");
+                ui.end_row();
+                for entry in store.entries() {
+                    ui.label(entry.info.to_string());
+                    ui.label(format_date(entry.first));
+                    ui.label(format_date(entry.last));
+                    ui.label(entry.rows().to_string());
+                    ui.label(entry.months.len().to_string());
+                    ui.horizontal(|ui| {
+                        if ui.button("Check").clicked() {
+                            action = Some((entry.info.clone(), Action::Check));
+                        }
+                        if ui.button("Fill gaps").clicked() {
+                            action =
+                                Some((entry.info.clone(), Action::Repair(RepairMode::ForwardFill)));
+                        }
+                        if ui.button("Drop outliers").clicked() {
+                            action = Some((entry.info.clone(), Action::Repair(RepairMode::Drop)));
+                        }
+                        if ui.button("Delete").clicked() {
+                            action = Some((entry.info.clone(), Action::Remove));
+                        }
+                    });
+                    ui.end_row();
+                    if let Some(report) = self.reports.get(&entry.info) {
+                        ui.label("");
+                        let color = match report.is_clean() {
+                            true => Color32::GREEN,
+                            false => Color32::YELLOW,
+                        };
+                        ui.colored_label(color, report.to_string());
+                        ui.end_row();
+                    }
+                }
+            });
+
+        let Some((info, action)) = action else {
+            return false;
+        };
+        let modifies_store = !matches!(action, Action::Check);
+        let result = self.run(store, &info, action);
+        let changed = modifies_store && result.is_ok();
+        self.message = Some(result.map_err(|e| e.to_string()));
+        changed
+    }
+
+    fn run(
+        &mut self,
+        store: &mut CandleStore,
+        info: &MarketInfo,
+        action: Action,
+    ) -> Result<String> {
+        match action {
+            Action::Check => {
+                let candles = store.query(info, None, None)?;
+                let report =
+                    check_quality(&candles.df, info.timeframe_seconds, self.outlier_threshold)?;
+                let message = format!("{}: {}", info, report);
+                self.reports.insert(info.clone(), report);
+                Ok(message)
+            }
+            Action::Repair(mode) => {
+                let candles = store.query(info, None, None)?;
+                let repaired = repair(
+                    &candles.df,
+                    info.timeframe_seconds,
+                    mode,
+                    self.outlier_threshold,
+                )?;
+                store.replace(info, &repaired)?;
+                let report =
+                    check_quality(&repaired, info.timeframe_seconds, self.outlier_threshold)?;
+                let message = format!("{} after {}: {}", info, mode, report);
+                self.reports.insert(info.clone(), report);
+                Ok(message)
+            }
+            Action::Remove => {
+                store.remove(info)?;
+                self.reports.remove(info);
+                Ok(format!("Deleted {}", info))
+            }
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use anyhow::{anyhow, Result};
+use polars::{df, frame::DataFrame};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+
+use crate::types::timestamp::TimeStamp;
+
+/// Median absolute deviations a close-to-close return has to be away from the median to count
+/// as an outlier
+pub const DEFAULT_OUTLIER_THRESHOLD: f64 = 12.0;
+
+/// Scales the median absolute deviation to a standard deviation for normal returns
+const MAD_TO_STD: f64 = 1.4826;
+
+/// One row of an OHLCV DataFrame
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Bar {
+    timestamp: TimeStamp,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+}
+
+fn f64_values(df: &DataFrame, column: &str) -> Result<Vec<f64>> {
+    Ok(df
+        .column(column)?
+        .f64()?
+        .into_iter()
+        .map(|x| x.unwrap_or(f64::NAN))
+        .collect())
+}
+
+/// Rows sorted by timestamp, in their original order where timestamps are equal
+fn sorted_bars(df: &DataFrame) -> Result<Vec<Bar>> {
+    let timestamps: Vec<TimeStamp> = df.column("timestamp")?.u64()?.into_no_null_iter().collect();
+    let opens = f64_values(df, "open")?;
+    let highs = f64_values(df, "high")?;
+    let lows = f64_values(df, "low")?;
+    let closes = f64_values(df, "close")?;
+    let volumes = f64_values(df, "volume")?;
+    let mut bars: Vec<Bar> = (0..timestamps.len())
+        .map(|idx| Bar {
+            timestamp: timestamps[idx],
+            open: opens[idx],
+            high: highs[idx],
+            low: lows[idx],
+            close: closes[idx],
+            volume: volumes[idx],
+        })
+        .collect();
+    bars.sort_by_key(|bar| bar.timestamp);
+    Ok(bars)
+}
+
+fn bars_to_df(bars: &[Bar]) -> Result<DataFrame> {
+    let df = df![
+        "open" => bars.iter().map(|bar| bar.open).collect::<Vec<_>>(),
+        "high" => bars.iter().map(|bar| bar.high).collect::<Vec<_>>(),
+        "low" => bars.iter().map(|bar| bar.low).collect::<Vec<_>>(),
+        "close" => bars.iter().map(|bar| bar.close).collect::<Vec<_>>(),
+        "volume" => bars.iter().map(|bar| bar.volume).collect::<Vec<_>>(),
+        "timestamp" => bars.iter().map(|bar| bar.timestamp).collect::<Vec<_>>(),
+    ]?;
+    Ok(df)
+}
+
+/// Keeps the last of every run of bars with the same timestamp, a later import wins
+fn dedupe(bars: Vec<Bar>) -> (Vec<Bar>, Vec<TimeStamp>) {
+    let mut result: Vec<Bar> = Vec::with_capacity(bars.len());
+    let mut duplicates = vec![];
+    for bar in bars {
+        match result.last_mut() {
+            Some(last) if last.timestamp == bar.timestamp => {
+                if duplicates.last() != Some(&bar.timestamp) {
+                    duplicates.push(bar.timestamp);
+                }
+                *last = bar;
+            }
+            _ => result.push(bar),
+        }
+    }
+    (result, duplicates)
+}
+
+/// Smallest distance between two bars, for candles without a known timeframe
+fn infer_timeframe(bars: &[Bar]) -> u64 {
+    bars.windows(2)
+        .map(|pair| pair[1].timestamp - pair[0].timestamp)
+        .filter(|distance| *distance > 0)
+        .min()
+        .unwrap_or(0)
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[middle]
+    } else {
+        (values[middle - 1] + values[middle]) / 2.0
+    }
+}
+
+/// Positions of bars whose close is a spike: the return into the bar and the return out of it
+/// are both extreme and point in opposite directions. A real level shift only has one extreme
+/// return, so it isn't flagged. The last bar only needs an extreme return into it.
+fn outlier_positions(bars: &[Bar], threshold: f64) -> Vec<usize> {
+    let returns: Vec<f64> = bars
+        .windows(2)
+        .map(|pair| (pair[1].close / pair[0].close).ln())
+        .collect();
+    let mut finite: Vec<f64> = returns.iter().copied().filter(|r| r.is_finite()).collect();
+    let center = median(&mut finite);
+    let mut deviations: Vec<f64> = finite.iter().map(|r| (r - center).abs()).collect();
+    let scale = median(&mut deviations) * MAD_TO_STD;
+    if scale <= 0.0 {
+        return vec![];
+    }
+    let extreme = |r: f64| !r.is_finite() || (r - center).abs() > threshold * scale;
+
+    (1..bars.len())
+        .filter(|idx| {
+            let into = returns[idx - 1];
+            match returns.get(*idx) {
+                Some(out) => extreme(into) && extreme(*out) && into.signum() != out.signum(),
+                None => extreme(into),
+            }
+        })
+        .collect()
+}
+
+/// `missing` bars are absent after the bar at `after`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gap {
+    pub after: TimeStamp,
+    pub missing: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QualityReport {
+    pub rows: usize,
+    pub gaps: Vec<Gap>,
+    /// Timestamps that occur more than once
+    pub duplicates: Vec<TimeStamp>,
+    /// Timestamps of bars with a spike in their close
+    pub outliers: Vec<TimeStamp>,
+}
+
+impl QualityReport {
+    pub fn missing_bars(&self) -> usize {
+        self.gaps.iter().map(|gap| gap.missing).sum()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.gaps.is_empty() && self.duplicates.is_empty() && self.outliers.is_empty()
+    }
+}
+
+impl std::fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} rows, {} missing bars in {} gaps, {} duplicates, {} outliers",
+            self.rows,
+            self.missing_bars(),
+            self.gaps.len(),
+            self.duplicates.len(),
+            self.outliers.len()
+        )
+    }
+}
+
+/// Finds missing bars, duplicate timestamps and price spikes. `timeframe_seconds` of 0 infers
+/// the timeframe from the data.
+pub fn check_quality(
+    df: &DataFrame,
+    timeframe_seconds: u64,
+    outlier_threshold: f64,
+) -> Result<QualityReport> {
+    let (bars, duplicates) = dedupe(sorted_bars(df)?);
+    let timeframe_seconds = match timeframe_seconds {
+        0 => infer_timeframe(&bars),
+        timeframe_seconds => timeframe_seconds,
+    };
+    let gaps = match timeframe_seconds {
+        0 => vec![],
+        _ => bars
+            .windows(2)
+            .filter_map(|pair| {
+                let distance = pair[1].timestamp - pair[0].timestamp;
+                let missing = (distance / timeframe_seconds).saturating_sub(1) as usize;
+                (missing > 0).then_some(Gap {
+                    after: pair[0].timestamp,
+                    missing,
+                })
+            })
+            .collect(),
+    };
+    let outliers = outlier_positions(&bars, outlier_threshold)
+        .into_iter()
+        .map(|idx| bars[idx].timestamp)
+        .collect();
+    Ok(QualityReport {
+        rows: df.height(),
+        gaps,
+        duplicates,
+        outliers,
+    })
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum RepairMode {
+    /// Leave the candles as they are
+    #[default]
+    #[strum(serialize = "Keep")]
+    Keep,
+    /// Insert missing bars and flatten outliers at the previous close, with no volume
+    #[strum(serialize = "Forward-fill")]
+    ForwardFill,
+    /// Remove outliers, missing bars stay missing
+    #[strum(serialize = "Drop")]
+    Drop,
+}
+
+fn flat_bar(timestamp: TimeStamp, close: f64) -> Bar {
+    Bar {
+        timestamp,
+        open: close,
+        high: close,
+        low: close,
+        close,
+        volume: 0.0,
+    }
+}
+
+/// Sorted and deduplicated candles, with gaps and outliers handled according to `mode`
+pub fn repair(
+    df: &DataFrame,
+    timeframe_seconds: u64,
+    mode: RepairMode,
+    outlier_threshold: f64,
+) -> Result<DataFrame> {
+    if mode == RepairMode::Keep {
+        return Ok(df.clone());
+    }
+    let (mut bars, _) = dedupe(sorted_bars(df)?);
+    let outliers = outlier_positions(&bars, outlier_threshold);
+    let timeframe_seconds = match timeframe_seconds {
+        0 => infer_timeframe(&bars),
+        timeframe_seconds => timeframe_seconds,
+    };
+
+    let result = match mode {
+        RepairMode::Keep => unreachable!(),
+        RepairMode::Drop => {
+            let mut outliers = outliers.into_iter().peekable();
+            bars.into_iter()
+                .enumerate()
+                .filter(|(idx, _)| outliers.next_if_eq(idx).is_none())
+                .map(|(_, bar)| bar)
+                .collect::<Vec<_>>()
+        }
+        RepairMode::ForwardFill => {
+            if timeframe_seconds == 0 {
+                return Err(anyhow!("Can't fill gaps without knowing the timeframe"));
+            }
+            for idx in outliers {
+                bars[idx] = flat_bar(bars[idx].timestamp, bars[idx - 1].close);
+            }
+            let mut result: Vec<Bar> = Vec::with_capacity(bars.len());
+            for bar in bars {
+                if let Some(last) = result.last().copied() {
+                    let mut timestamp = last.timestamp + timeframe_seconds;
+                    while timestamp < bar.timestamp {
+                        result.push(flat_bar(timestamp, last.close));
+                        timestamp += timeframe_seconds;
+                    }
+                }
+                result.push(bar);
+            }
+            result
+        }
+    };
+    bars_to_df(&result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candles(closes: &[(TimeStamp, f64)]) -> DataFrame {
+        let bars: Vec<Bar> = closes
+            .iter()
+            .map(|(timestamp, close)| flat_bar(*timestamp, *close))
+            .collect();
+        bars_to_df(&bars).unwrap()
+    }
+
+    fn closes(df: &DataFrame) -> Vec<(TimeStamp, f64)> {
+        sorted_bars(df)
+            .unwrap()
+            .iter()
+            .map(|bar| (bar.timestamp, bar.close))
+            .collect()
+    }
+
+    /// Minute bars wiggling around 100, then 1.5 times as high from bar 20 on, with a spike
+    /// to twice the price at bar 10
+    fn spiky() -> Vec<(TimeStamp, f64)> {
+        (0..30)
+            .map(|i| {
+                let mut close = 100.0 * (1.0 + 0.01 * (i as f64 * 1.3).sin());
+                if i >= 20 {
+                    close *= 1.5;
+                }
+                if i == 10 {
+                    close *= 2.0;
+                }
+                (60 + i * 60, close)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_gaps_and_duplicates() {
+        let df = candles(&[
+            (60, 100.0),
+            (120, 100.0),
+            (120, 100.0),
+            (300, 100.0),
+            (360, 100.0),
+        ]);
+        for timeframe_seconds in [60, 0] {
+            let report = check_quality(&df, timeframe_seconds, DEFAULT_OUTLIER_THRESHOLD).unwrap();
+            assert_eq!(report.rows, 5);
+            assert_eq!(
+                report.gaps,
+                vec![Gap {
+                    after: 120,
+                    missing: 2
+                }]
+            );
+            assert_eq!(report.duplicates, vec![120]);
+            assert!(report.outliers.is_empty());
+            assert!(!report.is_clean());
+        }
+    }
+
+    #[test]
+    fn flags_spikes_but_not_level_shifts() {
+        let report = check_quality(&candles(&spiky()), 60, DEFAULT_OUTLIER_THRESHOLD).unwrap();
+        assert_eq!(report.outliers, vec![660]);
+        assert!(report.gaps.is_empty());
+    }
+
+    #[test]
+    fn forward_fill_inserts_flat_bars_and_flattens_spikes() {
+        // The later of the two bars at 120 wins
+        let df = candles(&[(60, 100.0), (120, 101.0), (120, 102.0), (240, 103.0)]);
+        let repaired = repair(&df, 60, RepairMode::ForwardFill, DEFAULT_OUTLIER_THRESHOLD).unwrap();
+        assert_eq!(
+            closes(&repaired),
+            vec![(60, 100.0), (120, 102.0), (180, 102.0), (240, 103.0)]
+        );
+        assert_eq!(
+            repaired.column("volume").unwrap().f64().unwrap().get(2),
+            Some(0.0)
+        );
+
+        let bars = spiky();
+        let repaired = repair(
+            &candles(&bars),
+            60,
+            RepairMode::ForwardFill,
+            DEFAULT_OUTLIER_THRESHOLD,
+        )
+        .unwrap();
+        let repaired = closes(&repaired);
+        assert_eq!(repaired.len(), bars.len());
+        assert_eq!(repaired[10], (660, bars[9].1));
+        assert_eq!(repaired[20], bars[20]);
+    }
+
+    #[test]
+    fn drop_removes_spikes_and_leaves_gaps() {
+        let bars = spiky();
+        let repaired = repair(
+            &candles(&bars),
+            60,
+            RepairMode::Drop,
+            DEFAULT_OUTLIER_THRESHOLD,
+        )
+        .unwrap();
+        let mut expected = bars.clone();
+        expected.remove(10);
+        assert_eq!(closes(&repaired), expected);
+    }
+
+    #[test]
+    fn forward_fill_needs_a_timeframe() {
+        let df = candles(&[(60, 100.0)]);
+        assert!(repair(&df, 0, RepairMode::ForwardFill, DEFAULT_OUTLIER_THRESHOLD).is_err());
+        let kept = repair(&df, 0, RepairMode::Keep, DEFAULT_OUTLIER_THRESHOLD).unwrap();
+        assert_eq!(closes(&kept), vec![(60, 100.0)]);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::DateTime;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use polars::{
+    frame::{DataFrame, UniqueKeepStrategy},
+    prelude::{
+        col, concat, lit, BooleanChunked, Column, CsvReadOptions, DataType, IntoLazy, LazyFrame,
+        NewChunkedArray, ParquetReader, ParquetWriter, SerReader, SortMultipleOptions, UnionArgs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{
+    candles::{Candles, MarketInfo},
+    timeframe::timeframe_label,
+    timestamp::TimeStamp,
+};
+
+const APP_NAME: &str = "Emerald Fund Studio";
+const INDEX_FILE: &str = "index.json";
+const OHLCV_COLUMNS: [&str; 5] = ["open", "high", "low", "close", "volume"];
+/// Timestamps above this are taken as milliseconds (it's in the year 5138 in seconds)
+const MAX_SECONDS_TIMESTAMP: f64 = 1e11;
+/// Everything but letters, digits, '-' and '_', '.' too so a name can't leave its directory
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_');
+
+/// The store shared by the market data nodes and the data store window
+pub static CANDLE_STORE: Lazy<Mutex<CandleStore>> =
+    Lazy::new(|| Mutex::new(CandleStore::open(default_root())));
+
+/// Next to the app's saved state, or in the working directory when there is no storage dir
+pub fn default_root() -> PathBuf {
+    eframe::storage_dir(APP_NAME)
+        .map(|dir| dir.join("candles"))
+        .unwrap_or_else(|| PathBuf::from("candles"))
+}
+
+/// What the store holds for one market
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoreEntry {
+    pub info: MarketInfo,
+    pub first: TimeStamp,
+    pub last: TimeStamp,
+    /// Rows of every month file, keyed by "YYYY-MM"
+    pub months: BTreeMap<String, usize>,
+}
+
+impl StoreEntry {
+    pub fn rows(&self) -> usize {
+        self.months.values().sum()
+    }
+}
+
+/// Candles on disk, one Parquet file per `exchange/pair/timeframe/month`, with an index of
+/// what is stored so listing and range queries don't have to open every file
+pub struct CandleStore {
+    root: PathBuf,
+    entries: Vec<StoreEntry>,
+    /// Month files that were read before, with the modification time they were read at
+    cache: HashMap<PathBuf, (SystemTime, DataFrame)>,
+}
+
+/// `name` as one directory name, pairs are often written like "BTC/USDT". Distinct names
+/// stay distinct and none can be "." or "..".
+fn path_segment(name: &str) -> Result<String> {
+    if name.is_empty() {
+        return Err(anyhow!("Exchange and pair names can't be empty"));
+    }
+    Ok(utf8_percent_encode(name, PATH_SEGMENT).to_string())
+}
+
+fn month_key(timestamp: TimeStamp) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|datetime| datetime.format("%Y-%m").to_string())
+        .unwrap_or_else(|| "invalid".to_owned())
+}
+
+fn timestamps(df: &DataFrame) -> Result<Vec<TimeStamp>> {
+    Ok(df.column("timestamp")?.u64()?.into_no_null_iter().collect())
+}
+
+/// OHLCV columns as f64 and timestamps as seconds, sorted, later rows win on duplicates
+fn normalize(df: LazyFrame) -> LazyFrame {
+    let mut columns: Vec<_> = OHLCV_COLUMNS
+        .iter()
+        .map(|column| col(*column).cast(DataType::Float64))
+        .collect();
+    columns.push(col("timestamp").cast(DataType::UInt64));
+    df.select(columns)
+        .unique_stable(Some(vec!["timestamp".into()]), UniqueKeepStrategy::Last)
+        .sort(["timestamp"], SortMultipleOptions::default())
+}
+
+/// Reads a CSV file with a header and `timestamp`, `open`, `high`, `low`, `close` and `volume`
+/// columns, other columns are left out. Timestamps can be in seconds or milliseconds.
+pub fn read_ohlcv_csv(path: &Path) -> Result<DataFrame> {
+    let mut df = CsvReadOptions::default()
+        .with_has_header(true)
+        .try_into_reader_with_file_path(Some(path.to_owned()))?
+        .finish()?;
+    for column in OHLCV_COLUMNS.iter().chain(&["timestamp"]) {
+        if df.column(column).is_err() {
+            return Err(anyhow!("{} has no \"{}\" column", path.display(), column));
+        }
+    }
+    let raw_timestamps: Vec<f64> = df
+        .column("timestamp")?
+        .cast(&DataType::Float64)?
+        .f64()?
+        .into_no_null_iter()
+        .collect();
+    let in_millis = raw_timestamps
+        .iter()
+        .any(|timestamp| *timestamp > MAX_SECONDS_TIMESTAMP);
+    let seconds: Vec<TimeStamp> = raw_timestamps
+        .iter()
+        .map(|timestamp| match in_millis {
+            true => (timestamp / 1000.0) as TimeStamp,
+            false => *timestamp as TimeStamp,
+        })
+        .collect();
+    df.with_column(Column::new("timestamp".into(), seconds))?;
+    Ok(normalize(df.lazy()).collect()?)
+}
+
+impl CandleStore {
+    /// Opens the store at `root`. A missing or unreadable index starts an empty store.
+    pub fn open(root: PathBuf) -> Self {
+        let entries = fs::read_to_string(root.join(INDEX_FILE))
+            .ok()
+            .and_then(|index| match serde_json::from_str(&index) {
+                Ok(entries) => Some(entries),
+                Err(e) => {
+                    log::warn!("Ignoring broken candle store index: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            root,
+            entries,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn entries(&self) -> &[StoreEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, info: &MarketInfo) -> Option<&StoreEntry> {
+        self.entries.iter().find(|entry| entry.info == *info)
+    }
+
+    fn market_dir(&self, info: &MarketInfo) -> Result<PathBuf> {
+        Ok(self
+            .root
+            .join(path_segment(&info.exchange)?)
+            .join(path_segment(&info.pair)?)
+            .join(timeframe_label(info.timeframe_seconds)))
+    }
+
+    fn month_path(&self, info: &MarketInfo, month: &str) -> Result<PathBuf> {
+        Ok(self.market_dir(info)?.join(format!("{}.parquet", month)))
+    }
+
+    fn save_index(&self) -> Result<()> {
+        fs::create_dir_all(&self.root)?;
+        let temp = self.root.join(format!("{}.tmp", INDEX_FILE));
+        fs::write(&temp, serde_json::to_string_pretty(&self.entries)?)?;
+        fs::rename(temp, self.root.join(INDEX_FILE))?;
+        Ok(())
+    }
+
+    fn read_month(&mut self, path: &Path) -> Result<DataFrame> {
+        let modified = fs::metadata(path)?.modified()?;
+        if let Some((cached_at, df)) = self.cache.get(path) {
+            if *cached_at == modified {
+                return Ok(df.clone());
+            }
+        }
+        let df = ParquetReader::new(File::open(path)?).finish()?;
+        self.cache.insert(path.to_owned(), (modified, df.clone()));
+        Ok(df)
+    }
+
+    fn write_month(&mut self, path: &Path, df: &mut DataFrame) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("parquet.tmp");
+        ParquetWriter::new(File::create(&temp)?).finish(df)?;
+        fs::rename(&temp, path)?;
+        self.cache.insert(
+            path.to_owned(),
+            (fs::metadata(path)?.modified()?, df.clone()),
+        );
+        Ok(())
+    }
+
+    /// Merges `df` into the stored candles of `info`. Rows with a timestamp that is already
+    /// stored replace the stored row.
+    pub fn import(&mut self, info: &MarketInfo, df: &DataFrame) -> Result<&StoreEntry> {
+        if info.exchange.is_empty() || info.pair.is_empty() || info.timeframe_seconds == 0 {
+            return Err(anyhow!("Candles need an exchange, a pair and a timeframe"));
+        }
+        let df = normalize(df.clone().lazy()).collect()?;
+        let all_timestamps = timestamps(&df)?;
+        if all_timestamps.is_empty() {
+            return Err(anyhow!("No candles to import"));
+        }
+
+        let mut months: BTreeMap<String, Vec<bool>> = BTreeMap::new();
+        for (idx, timestamp) in all_timestamps.iter().enumerate() {
+            months
+                .entry(month_key(*timestamp))
+                .or_insert_with(|| vec![false; all_timestamps.len()])[idx] = true;
+        }
+
+        let mut month_rows = BTreeMap::new();
+        for (month, rows) in months {
+            let path = self.month_path(info, &month)?;
+            let new_rows = df.filter(&BooleanChunked::from_slice("month".into(), &rows))?;
+            let mut merged = if path.exists() {
+                let stored = self.read_month(&path)?;
+                normalize(concat(
+                    [stored.lazy(), new_rows.lazy()],
+                    UnionArgs::default(),
+                )?)
+                .collect()?
+            } else {
+                new_rows
+            };
+            self.write_month(&path, &mut merged)?;
+            month_rows.insert(month, merged.height());
+        }
+
+        let (first, last) = (all_timestamps[0], all_timestamps[all_timestamps.len() - 1]);
+        let position = match self.entries.iter().position(|entry| entry.info == *info) {
+            Some(position) => {
+                let entry = &mut self.entries[position];
+                entry.first = entry.first.min(first);
+                entry.last = entry.last.max(last);
+                entry.months.extend(month_rows);
+                position
+            }
+            None => {
+                self.entries.push(StoreEntry {
+                    info: info.clone(),
+                    first,
+                    last,
+                    months: month_rows,
+                });
+                self.entries.len() - 1
+            }
+        };
+        self.save_index()?;
+        Ok(&self.entries[position])
+    }
+
+    /// Imports a CSV file, see `read_ohlcv_csv`
+    pub fn import_csv(&mut self, path: &Path, info: &MarketInfo) -> Result<&StoreEntry> {
+        let df = read_ohlcv_csv(path)?;
+        self.import(info, &df)
+    }
+
+    /// Stored candles of `info` from `start` (inclusive) to `end` (exclusive)
+    pub fn query(
+        &mut self,
+        info: &MarketInfo,
+        start: Option<TimeStamp>,
+        end: Option<TimeStamp>,
+    ) -> Result<Candles> {
+        let entry = self.entry(info).ok_or_else(|| {
+            anyhow!(
+                "No stored candles for {}, import them in Tools > Data Store",
+                info
+            )
+        })?;
+        let first_month = start.map(month_key);
+        let last_month = end.map(|end| month_key(end.saturating_sub(1)));
+        let months: Vec<String> = entry
+            .months
+            .keys()
+            .filter(|month| first_month.as_ref().is_none_or(|first| *month >= first))
+            .filter(|month| last_month.as_ref().is_none_or(|last| *month <= last))
+            .cloned()
+            .collect();
+
+        let mut frames = Vec::with_capacity(months.len());
+        for month in months {
+            let path = self.month_path(info, &month)?;
+            frames.push(self.read_month(&path)?.lazy());
+        }
+        if frames.is_empty() {
+            return Err(anyhow!("No stored candles for {} in that range", info));
+        }
+        let mut df = concat(frames, UnionArgs::default())?;
+        if let Some(start) = start {
+            df = df.filter(col("timestamp").gt_eq(lit(start)));
+        }
+        if let Some(end) = end {
+            df = df.filter(col("timestamp").lt(lit(end)));
+        }
+        let df = df
+            .sort(["timestamp"], SortMultipleOptions::default())
+            .collect()?;
+        if df.height() == 0 {
+            return Err(anyhow!("No stored candles for {} in that range", info));
+        }
+        Ok(Candles::new(info.clone(), df))
+    }
+
+    /// Deletes every stored candle of `info`
+    pub fn remove(&mut self, info: &MarketInfo) -> Result<()> {
+        let dir = self.market_dir(info)?;
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        self.cache.retain(|path, _| !path.starts_with(&dir));
+        self.entries.retain(|entry| entry.info != *info);
+        self.save_index()
+    }
+
+    /// Swaps the stored candles of `info` for `df`, e.g. after repairing them
+    pub fn replace(&mut self, info: &MarketInfo, df: &DataFrame) -> Result<&StoreEntry> {
+        self.remove(info)?;
+        self.import(info, df)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_stay_one_distinct_directory() {
+        let names = [
+            "BTC/USDT",
+            "BTC_USDT",
+            "BTC%2FUSDT",
+            "..",
+            ".",
+            "a\\b",
+            "ETH-USDT",
+        ];
+        let segments: Vec<String> = names
+            .iter()
+            .map(|name| path_segment(name).unwrap())
+            .collect();
+        for (idx, segment) in segments.iter().enumerate() {
+            assert!(!segment.contains(['/', '\\', '.']), "{}", segment);
+            assert!(!segments[idx + 1..].contains(segment), "{}", segment);
+        }
+        assert_eq!(segments[6], "ETH-USDT");
+        assert!(path_segment("").is_err());
+    }
+}
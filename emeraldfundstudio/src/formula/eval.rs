@@ -0,0 +1,295 @@
+use anyhow::{anyhow, Result};
+use polars::prelude::{
+    arity::binary_elementwise_values, BooleanChunked, ChunkApply, ChunkCompareEq, ChunkCompareIneq,
+    ChunkFull, ChunkShift, ChunkTake, ChunkZip, Float64Chunked, IdxCa, IdxSize, NewChunkedArray,
+};
+
+use super::{
+    parser::{BinaryOp, Expr, ExprKind, Function, UnaryOp},
+    Formula,
+};
+use crate::{
+    node_editor::node_trait::{
+        CheapCloneNodeDataTypeWithValue, NodeDataType, NodeDataTypeWithValue,
+    },
+    types::{
+        candles::Candles,
+        decimal_sequence::DecimalSequence,
+        time_index::{join, same_index, Indexed, JoinMode, TimeIndex},
+    },
+};
+
+enum Value {
+    Number(Float64Chunked),
+    Bool(BooleanChunked),
+}
+
+/// An input lined up with the rows of the result
+enum Column<'a> {
+    Number(Float64Chunked),
+    Bool(BooleanChunked),
+    /// Fields are only lined up when the formula uses them
+    Candles(&'a Candles, Option<IdxCa>),
+}
+
+/// Positions of the rows of `index` in `input`, `None` if they are the same rows
+fn positions(index: &TimeIndex, input: &TimeIndex) -> Result<Option<IdxCa>> {
+    if same_index(index, input) {
+        return Ok(None);
+    }
+    let joined = join(index, input, JoinMode::Left)?;
+    Ok(Some(
+        joined
+            .right
+            .iter()
+            .map(|position| position.map(|position| position as IdxSize))
+            .collect(),
+    ))
+}
+
+fn line_up<T>(values: T, positions: &Option<IdxCa>) -> Result<T>
+where
+    T: ChunkTake<IdxCa>,
+{
+    match positions {
+        Some(positions) => Ok(values.take(positions)?),
+        None => Ok(values),
+    }
+}
+
+struct Evaluation<'a> {
+    columns: Vec<Column<'a>>,
+    len: usize,
+}
+
+impl Evaluation<'_> {
+    fn number(&self, expr: &Expr) -> Result<Float64Chunked> {
+        match self.value(expr)? {
+            Value::Number(values) => Ok(values),
+            Value::Bool(_) => Err(anyhow!("Expected a number at {}", expr.position + 1)),
+        }
+    }
+
+    fn bool(&self, expr: &Expr) -> Result<BooleanChunked> {
+        match self.value(expr)? {
+            Value::Bool(values) => Ok(values),
+            Value::Number(_) => Err(anyhow!("Expected a mask at {}", expr.position + 1)),
+        }
+    }
+
+    fn value(&self, expr: &Expr) -> Result<Value> {
+        Ok(match &expr.kind {
+            ExprKind::Number(value) => {
+                Value::Number(Float64Chunked::full("".into(), *value, self.len))
+            }
+            ExprKind::Bool(value) => Value::Bool(BooleanChunked::full("".into(), *value, self.len)),
+            ExprKind::Variable(index) => match &self.columns[*index] {
+                Column::Number(values) => Value::Number(values.clone()),
+                Column::Bool(values) => Value::Bool(values.clone()),
+                Column::Candles(..) => return Err(anyhow!("Candles need a field")),
+            },
+            ExprKind::Field(index, field) => match &self.columns[*index] {
+                Column::Candles(candles, positions) => {
+                    let column = candles.df.column(field.as_ref())?.f64()?.clone();
+                    Value::Number(line_up(column, positions)?)
+                }
+                _ => return Err(anyhow!("Only candles have a {}", field.as_ref())),
+            },
+            ExprKind::Unary(UnaryOp::Neg, operand) => {
+                Value::Number(self.number(operand)?.apply_values(|value| -value))
+            }
+            ExprKind::Unary(UnaryOp::Not, operand) => Value::Bool(!&self.bool(operand)?),
+            ExprKind::Binary(op, left, right) => self.binary(*op, left, right)?,
+            ExprKind::Call(function, arguments) => self.call(*function, arguments)?,
+        })
+    }
+
+    fn binary(&self, op: BinaryOp, left: &Expr, right: &Expr) -> Result<Value> {
+        if matches!(op, BinaryOp::And | BinaryOp::Or) {
+            let (left, right) = (self.bool(left)?, self.bool(right)?);
+            return Ok(Value::Bool(match op {
+                BinaryOp::And => &left & &right,
+                _ => &left | &right,
+            }));
+        }
+        let (left, right) = match (self.value(left)?, self.value(right)?) {
+            (Value::Bool(left), Value::Bool(right)) => {
+                return match op {
+                    BinaryOp::Eq => Ok(Value::Bool(left.equal(&right))),
+                    BinaryOp::Ne => Ok(Value::Bool(left.not_equal(&right))),
+                    _ => Err(anyhow!("Masks can only be compared for equality")),
+                };
+            }
+            (Value::Number(left), Value::Number(right)) => (left, right),
+            _ => return Err(anyhow!("Can't combine a number with a mask")),
+        };
+        Ok(match op {
+            BinaryOp::Add => Value::Number(&left + &right),
+            BinaryOp::Sub => Value::Number(&left - &right),
+            BinaryOp::Mul => Value::Number(&left * &right),
+            BinaryOp::Div => Value::Number(&left / &right),
+            BinaryOp::Rem => Value::Number(&left % &right),
+            BinaryOp::Pow => Value::Number(binary_elementwise_values(
+                &left,
+                &right,
+                |base: f64, exponent: f64| base.powf(exponent),
+            )),
+            BinaryOp::Gt => Value::Bool(left.gt(&right)),
+            BinaryOp::Lt => Value::Bool(left.lt(&right)),
+            BinaryOp::Ge => Value::Bool(left.gt_eq(&right)),
+            BinaryOp::Le => Value::Bool(left.lt_eq(&right)),
+            BinaryOp::Eq => Value::Bool(left.equal(&right)),
+            BinaryOp::Ne => Value::Bool(left.not_equal(&right)),
+            BinaryOp::And | BinaryOp::Or => unreachable!(),
+        })
+    }
+
+    fn call(&self, function: Function, arguments: &[Expr]) -> Result<Value> {
+        let unary = |f: fn(f64) -> f64| -> Result<Value> {
+            Ok(Value::Number(self.number(&arguments[0])?.apply_values(f)))
+        };
+        let binary = |f: fn(f64, f64) -> f64| -> Result<Value> {
+            let (left, right) = (self.number(&arguments[0])?, self.number(&arguments[1])?);
+            Ok(Value::Number(binary_elementwise_values(
+                &left,
+                &right,
+                |left: f64, right: f64| f(left, right),
+            )))
+        };
+        match function {
+            Function::Abs => unary(f64::abs),
+            Function::Sqrt => unary(f64::sqrt),
+            Function::Ln => unary(f64::ln),
+            Function::Exp => unary(f64::exp),
+            Function::Min => binary(f64::min),
+            Function::Max => binary(f64::max),
+            Function::If => {
+                // Rows where the condition is missing take the else branch
+                let condition = self.bool(&arguments[0])?;
+                Ok(
+                    match (self.value(&arguments[1])?, self.value(&arguments[2])?) {
+                        (Value::Number(then), Value::Number(otherwise)) => {
+                            Value::Number(then.zip_with(&condition, &otherwise)?)
+                        }
+                        (Value::Bool(then), Value::Bool(otherwise)) => {
+                            Value::Bool(then.zip_with(&condition, &otherwise)?)
+                        }
+                        _ => return Err(anyhow!("Both branches of if must have the same type")),
+                    },
+                )
+            }
+            Function::Lag => {
+                let ExprKind::Number(bars) = arguments[1].kind else {
+                    return Err(anyhow!("The lag must be a number"));
+                };
+                Ok(match self.value(&arguments[0])? {
+                    Value::Number(values) => Value::Number(values.shift(bars as i64)),
+                    Value::Bool(values) => Value::Bool(values.shift(bars as i64)),
+                })
+            }
+            Function::Signal => {
+                let (buy, sell) = (self.bool(&arguments[0])?, self.bool(&arguments[1])?);
+                Ok(Value::Number(Float64Chunked::from_iter_values(
+                    "".into(),
+                    buy.into_iter()
+                        .zip(&sell)
+                        .map(|(buy, sell)| match (buy, sell) {
+                            (Some(true), _) => 1.0,
+                            (_, Some(true)) => -1.0,
+                            _ => 0.0,
+                        }),
+                )))
+            }
+        }
+    }
+}
+
+impl Formula {
+    /// Evaluates the formula over whole sequences. With several inputs the rows are those of
+    /// the inputs joined one after the other with `mode`.
+    pub fn evaluate(
+        &self,
+        input_args: &[CheapCloneNodeDataTypeWithValue],
+        mode: JoinMode,
+        name: &str,
+    ) -> Result<NodeDataTypeWithValue> {
+        if input_args.len() != self.variables.len() {
+            return Err(anyhow!("should have {} inputs!", self.variables.len()));
+        }
+        let indexes = input_args
+            .iter()
+            .map(|input| input.time_index())
+            .collect::<Result<Vec<TimeIndex>>>()?;
+        let mut index = indexes[0].clone();
+        for other in &indexes[1..] {
+            index = join(&index, other, mode)?.index;
+        }
+
+        let mut columns = vec![];
+        for ((input, input_index), variable) in input_args.iter().zip(&indexes).zip(&self.variables)
+        {
+            let positions = positions(&index, input_index)?;
+            let column = match (&**input, variable.data_type) {
+                (NodeDataTypeWithValue::DecimalSequence(seq), NodeDataType::DecimalSequence) => {
+                    Column::Number(line_up(seq.values.clone(), &positions)?)
+                }
+                (NodeDataTypeWithValue::Mask(mask), NodeDataType::Mask) => Column::Bool(line_up(
+                    BooleanChunked::from_slice("".into(), &mask.values),
+                    &positions,
+                )?),
+                (NodeDataTypeWithValue::Signal(signal), NodeDataType::Signal) => {
+                    let values = Float64Chunked::from_iter_values(
+                        "".into(),
+                        signal.values.iter().map(|value| *value as f64),
+                    );
+                    Column::Number(line_up(values, &positions)?)
+                }
+                (NodeDataTypeWithValue::Candles(candles), NodeDataType::Candles) => {
+                    Column::Candles(candles, positions)
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "{} should be {:?}",
+                        variable.name,
+                        variable.data_type
+                    ))
+                }
+            };
+            columns.push(column);
+        }
+
+        let evaluation = Evaluation {
+            columns,
+            len: index.len(),
+        };
+        let value = evaluation.value(&self.expr)?;
+        Ok(match (value, self.output) {
+            (Value::Number(values), NodeDataType::Signal) => {
+                let signal = values
+                    .into_iter()
+                    .map(|value| match value {
+                        Some(value) if value > 0.0 => 1,
+                        Some(value) if value < 0.0 => -1,
+                        _ => 0,
+                    })
+                    .collect();
+                NodeDataTypeWithValue::Signal(Indexed::new(index, signal))
+            }
+            (Value::Number(values), _) => {
+                // Like the other nodes, values that can't be computed are missing
+                let values: DecimalSequence = values
+                    .apply(|value| value.filter(|value| value.is_finite()))
+                    .with_name(name.into());
+                NodeDataTypeWithValue::DecimalSequence(Indexed::new(index, values))
+            }
+            (Value::Bool(values), _) => {
+                // Missing values never match, like in the compare node
+                let mask = values
+                    .into_iter()
+                    .map(|value| value == Some(true))
+                    .collect();
+                NodeDataTypeWithValue::Mask(Indexed::new(index, mask))
+            }
+        })
+    }
+}
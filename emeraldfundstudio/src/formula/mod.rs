@@ -0,0 +1,317 @@
+pub mod eval;
+pub mod parser;
+
+use parser::{parse, BinaryOp, Expr, ExprKind, FormulaError, Function, UnaryOp};
+
+use crate::node_editor::node_trait::NodeDataType;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub data_type: NodeDataType,
+}
+
+/// A parsed and type checked formula
+#[derive(Clone, Debug, PartialEq)]
+pub struct Formula {
+    pub expr: Expr,
+    pub variables: Vec<Variable>,
+    pub output: NodeDataType,
+}
+
+fn is_number(data_type: NodeDataType) -> bool {
+    matches!(
+        data_type,
+        NodeDataType::DecimalSequence | NodeDataType::Signal
+    )
+}
+
+fn describe(data_type: NodeDataType) -> &'static str {
+    match data_type {
+        NodeDataType::DecimalSequence => "a number",
+        NodeDataType::Mask => "a mask",
+        NodeDataType::Signal => "a signal",
+        NodeDataType::Candles => "candles",
+    }
+}
+
+/// Variables without an annotation are masks where a mask is expected and candles when a
+/// field is taken from them, everything else is a decimal sequence
+fn infer(expr: &Expr, boolean: bool, types: &mut [Option<NodeDataType>]) {
+    let mut visit = |expr: &Expr, boolean: bool| infer(expr, boolean, types);
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Bool(_) => {}
+        ExprKind::Variable(index) => {
+            if boolean {
+                types[*index].get_or_insert(NodeDataType::Mask);
+            }
+        }
+        ExprKind::Field(index, _) => {
+            types[*index].get_or_insert(NodeDataType::Candles);
+        }
+        ExprKind::Unary(op, operand) => visit(operand, *op == UnaryOp::Not),
+        ExprKind::Binary(op, left, right) => {
+            let boolean = matches!(op, BinaryOp::And | BinaryOp::Or);
+            visit(left, boolean);
+            visit(right, boolean);
+        }
+        ExprKind::Call(function, arguments) => {
+            for (position, argument) in arguments.iter().enumerate() {
+                let boolean = match function {
+                    Function::If => position == 0 || boolean,
+                    Function::Lag => position == 0 && boolean,
+                    Function::Signal => true,
+                    _ => false,
+                };
+                visit(argument, boolean);
+            }
+        }
+    }
+}
+
+fn check(expr: &Expr, variables: &[Variable]) -> Result<NodeDataType, FormulaError> {
+    let error = |message: String| Err(FormulaError::new(expr.position, message));
+    let expect = |operand: &Expr, wanted: &str, number: bool| {
+        let data_type = check(operand, variables)?;
+        let fits = if number {
+            is_number(data_type)
+        } else {
+            data_type == NodeDataType::Mask
+        };
+        if fits {
+            return Ok(data_type);
+        }
+        Err(FormulaError::new(
+            operand.position,
+            format!("Expected {} but this is {}", wanted, describe(data_type)),
+        ))
+    };
+    match &expr.kind {
+        ExprKind::Number(_) => Ok(NodeDataType::DecimalSequence),
+        ExprKind::Bool(_) => Ok(NodeDataType::Mask),
+        ExprKind::Variable(index) => {
+            let variable = &variables[*index];
+            if variable.data_type == NodeDataType::Candles {
+                return error(format!(
+                    "{} are candles, take a field like {}.close",
+                    variable.name, variable.name
+                ));
+            }
+            Ok(variable.data_type)
+        }
+        ExprKind::Field(index, field) => {
+            let variable = &variables[*index];
+            if variable.data_type != NodeDataType::Candles {
+                return error(format!(
+                    "{} is {}, only candles have a {}",
+                    variable.name,
+                    describe(variable.data_type),
+                    field.as_ref()
+                ));
+            }
+            Ok(NodeDataType::DecimalSequence)
+        }
+        ExprKind::Unary(UnaryOp::Neg, operand) => {
+            expect(operand, "a number", true)?;
+            Ok(NodeDataType::DecimalSequence)
+        }
+        ExprKind::Unary(UnaryOp::Not, operand) => expect(operand, "a mask", false),
+        ExprKind::Binary(op, left, right) => match op {
+            BinaryOp::And | BinaryOp::Or => {
+                expect(left, "a mask", false)?;
+                expect(right, "a mask", false)
+            }
+            BinaryOp::Eq | BinaryOp::Ne => {
+                let left_type = check(left, variables)?;
+                let right_type = check(right, variables)?;
+                if is_number(left_type) && is_number(right_type)
+                    || left_type == NodeDataType::Mask && right_type == NodeDataType::Mask
+                {
+                    return Ok(NodeDataType::Mask);
+                }
+                error(format!(
+                    "Can't compare {} with {}",
+                    describe(left_type),
+                    describe(right_type)
+                ))
+            }
+            BinaryOp::Gt | BinaryOp::Lt | BinaryOp::Ge | BinaryOp::Le => {
+                expect(left, "a number", true)?;
+                expect(right, "a number", true)?;
+                Ok(NodeDataType::Mask)
+            }
+            _ => {
+                expect(left, "a number", true)?;
+                expect(right, "a number", true)?;
+                Ok(NodeDataType::DecimalSequence)
+            }
+        },
+        ExprKind::Call(function, arguments) => match function {
+            Function::If => {
+                expect(&arguments[0], "a mask", false)?;
+                let then_type = check(&arguments[1], variables)?;
+                let else_type = check(&arguments[2], variables)?;
+                match (then_type, else_type) {
+                    _ if then_type == else_type => Ok(then_type),
+                    _ if is_number(then_type) && is_number(else_type) => {
+                        Ok(NodeDataType::DecimalSequence)
+                    }
+                    _ => error(format!(
+                        "Both branches of if must have the same type, not {} and {}",
+                        describe(then_type),
+                        describe(else_type)
+                    )),
+                }
+            }
+            Function::Lag => {
+                // Negative lags would look into the future
+                match arguments[1].kind {
+                    ExprKind::Number(bars) if bars >= 0.0 && bars.fract() == 0.0 => {}
+                    _ => {
+                        return Err(FormulaError::new(
+                            arguments[1].position,
+                            "The lag must be a whole number of bars, 0 or more",
+                        ))
+                    }
+                }
+                check(&arguments[0], variables)
+            }
+            Function::Signal => {
+                expect(&arguments[0], "a mask", false)?;
+                expect(&arguments[1], "a mask", false)?;
+                Ok(NodeDataType::Signal)
+            }
+            _ => {
+                for argument in arguments {
+                    expect(argument, "a number", true)?;
+                }
+                Ok(NodeDataType::DecimalSequence)
+            }
+        },
+    }
+}
+
+impl Formula {
+    pub fn compile(text: &str) -> Result<Self, FormulaError> {
+        let (expr, parsed) = parse(text)?;
+        let mut types: Vec<Option<NodeDataType>> =
+            parsed.iter().map(|variable| variable.annotation).collect();
+        infer(&expr, false, &mut types);
+        let variables: Vec<Variable> = parsed
+            .into_iter()
+            .zip(types)
+            .map(|(variable, data_type)| Variable {
+                name: variable.name,
+                data_type: data_type.unwrap_or(NodeDataType::DecimalSequence),
+            })
+            .collect();
+        if variables.is_empty() {
+            return Err(FormulaError::new(
+                0,
+                "The formula needs at least one input, like close",
+            ));
+        }
+        let output = check(&expr, &variables)?;
+        Ok(Self {
+            expr,
+            variables,
+            output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(text: &str) -> Vec<(String, NodeDataType)> {
+        Formula::compile(text)
+            .unwrap()
+            .variables
+            .into_iter()
+            .map(|variable| (variable.name, variable.data_type))
+            .collect()
+    }
+
+    fn error(text: &str) -> String {
+        Formula::compile(text).unwrap_err().message
+    }
+
+    #[test]
+    fn infers_variable_types_from_their_use() {
+        use NodeDataType::*;
+        let named = |pairs: &[(&str, NodeDataType)]| -> Vec<(String, NodeDataType)> {
+            pairs
+                .iter()
+                .map(|(name, data_type)| (name.to_string(), *data_type))
+                .collect()
+        };
+        assert_eq!(
+            types("trend and close > sma"),
+            named(&[
+                ("trend", Mask),
+                ("close", DecimalSequence),
+                ("sma", DecimalSequence)
+            ])
+        );
+        assert_eq!(
+            types("btc.close / eth.close"),
+            named(&[("btc", Candles), ("eth", Candles)])
+        );
+        assert_eq!(
+            types("if(up, fast, slow)"),
+            named(&[
+                ("up", Mask),
+                ("fast", DecimalSequence),
+                ("slow", DecimalSequence)
+            ])
+        );
+        assert_eq!(types("not lag(up, 1)"), named(&[("up", Mask)]));
+        assert_eq!(types("s:signal * 2"), named(&[("s", Signal)]));
+    }
+
+    #[test]
+    fn output_type_follows_the_expression() {
+        let output = |text| Formula::compile(text).unwrap().output;
+        assert_eq!(output("close - sma"), NodeDataType::DecimalSequence);
+        assert_eq!(output("close > sma"), NodeDataType::Mask);
+        assert_eq!(output("signal(buy, sell)"), NodeDataType::Signal);
+        assert_eq!(output("if(up, s:signal, 0)"), NodeDataType::DecimalSequence);
+    }
+
+    #[test]
+    fn reports_type_errors() {
+        assert_eq!(error("up:mask + 1"), "Expected a number but this is a mask");
+        assert_eq!(
+            error("close:num and up"),
+            "Expected a mask but this is a number"
+        );
+        assert_eq!(
+            error("btc:candles * 2"),
+            "btc are candles, take a field like btc.close"
+        );
+        assert_eq!(
+            error("x:num.close"),
+            "x is a number, only candles have a close"
+        );
+        assert_eq!(error("up:mask == 1"), "Can't compare a mask with a number");
+        assert_eq!(
+            error("if(up, close, down:mask)"),
+            "Both branches of if must have the same type, not a number and a mask"
+        );
+        assert_eq!(
+            error("lag(close, -1)"),
+            "The lag must be a whole number of bars, 0 or more"
+        );
+        assert_eq!(
+            error("1 + 2"),
+            "The formula needs at least one input, like close"
+        );
+    }
+
+    #[test]
+    fn type_errors_point_at_the_operand() {
+        let error = Formula::compile("close + up:mask").unwrap_err();
+        assert_eq!(error.position, 8);
+    }
+}
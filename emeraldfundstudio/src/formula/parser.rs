@@ -0,0 +1,598 @@
+use std::fmt;
+
+use strum::{AsRefStr, EnumIter, IntoEnumIterator};
+
+use crate::node_editor::node_trait::NodeDataType;
+
+/// What went wrong and where, `position` is a character offset into the formula
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormulaError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl FormulaError {
+    pub fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (column {})", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for FormulaError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum CandleField {
+    Open,
+    High,
+    Low,
+    Close,
+    Volume,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum Function {
+    Abs,
+    Sqrt,
+    Ln,
+    Exp,
+    Min,
+    Max,
+    /// `if(condition, then, else)`
+    If,
+    /// `lag(x, n)`, the value `n` bars ago
+    Lag,
+    /// `signal(buy, sell)`, 1 where `buy`, else -1 where `sell`, else 0
+    Signal,
+}
+
+impl Function {
+    pub fn arity(self) -> usize {
+        match self {
+            Function::Abs | Function::Sqrt | Function::Ln | Function::Exp => 1,
+            Function::Min | Function::Max | Function::Lag | Function::Signal => 2,
+            Function::If => 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    Number(f64),
+    Bool(bool),
+    /// Index into the variables of the formula
+    Variable(usize),
+    /// A column of a candles variable, like `btc.close`
+    Field(usize, CandleField),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub position: usize,
+}
+
+/// A name the formula refers to, each one becomes an input of the node
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedVariable {
+    pub name: String,
+    /// From `name:type`, for types that can't be told from how the variable is used
+    pub annotation: Option<NodeDataType>,
+    pub position: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(&'static str),
+}
+
+/// How deep parentheses, function calls and operators in front of values can nest. Parsing
+/// recurses for each level, a formula like "((((…" mustn't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Longest first, so `>=` isn't read as `>`
+const SYMBOLS: [&str; 20] = [
+    ">=", "<=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "^", ">", "<", "!", "(", ")", ",",
+    ".", ":",
+];
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, FormulaError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // Exponent, like 1e-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut end = i + 1;
+                if end < chars.len() && (chars[end] == '+' || chars[end] == '-') {
+                    end += 1;
+                }
+                if end < chars.len() && chars[end].is_ascii_digit() {
+                    i = end;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let value = literal
+                .parse()
+                .map_err(|_| FormulaError::new(start, format!("Invalid number {}", literal)))?;
+            tokens.push((Token::Number(value), start));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), start));
+        } else {
+            let symbol = SYMBOLS.iter().find(|symbol| {
+                symbol
+                    .chars()
+                    .enumerate()
+                    .all(|(offset, s)| chars.get(i + offset) == Some(&s))
+            });
+            match symbol {
+                Some(symbol) => {
+                    tokens.push((Token::Symbol(symbol), i));
+                    i += symbol.len();
+                }
+                None => return Err(FormulaError::new(i, format!("Unexpected '{}'", c))),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Position of the end of the formula, for errors there
+    end: usize,
+    variables: Vec<ParsedVariable>,
+    /// Levels of `unary` being parsed, see `MAX_DEPTH`
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map(|(_, position)| *position)
+            .unwrap_or(self.end)
+    }
+
+    fn describe_next(&self) -> String {
+        match self.peek() {
+            Some(Token::Number(value)) => value.to_string(),
+            Some(Token::Ident(name)) => name.clone(),
+            Some(Token::Symbol(symbol)) => symbol.to_string(),
+            None => "the end".to_owned(),
+        }
+    }
+
+    /// Consumes the next token if it is one of `symbols` or a keyword among them
+    fn eat(&mut self, symbols: &[&'static str]) -> Option<&'static str> {
+        let found = match self.peek()? {
+            Token::Symbol(symbol) => symbols.iter().find(|s| *s == symbol),
+            Token::Ident(name) => symbols.iter().find(|s| *s == name),
+            Token::Number(_) => None,
+        }
+        .copied()?;
+        self.next += 1;
+        Some(found)
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), FormulaError> {
+        match self.eat(&[symbol]) {
+            Some(_) => Ok(()),
+            None => Err(FormulaError::new(
+                self.position(),
+                format!("Expected '{}' but found {}", symbol, self.describe_next()),
+            )),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        symbols: &[&'static str],
+        operand: fn(&mut Self) -> Result<Expr, FormulaError>,
+        op: fn(&str) -> BinaryOp,
+    ) -> Result<Expr, FormulaError> {
+        let mut left = operand(self)?;
+        loop {
+            let position = self.position();
+            let Some(symbol) = self.eat(symbols) else {
+                return Ok(left);
+            };
+            let right = operand(self)?;
+            left = Expr {
+                kind: ExprKind::Binary(op(symbol), Box::new(left), Box::new(right)),
+                position,
+            };
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, FormulaError> {
+        self.binary(&["or", "||"], Self::and, |_| BinaryOp::Or)
+    }
+
+    fn and(&mut self) -> Result<Expr, FormulaError> {
+        self.binary(&["and", "&&"], Self::comparison, |_| BinaryOp::And)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, FormulaError> {
+        const COMPARISONS: [&str; 6] = [">=", "<=", "==", "!=", ">", "<"];
+        let left = self.sum()?;
+        let position = self.position();
+        let Some(symbol) = self.eat(&COMPARISONS) else {
+            return Ok(left);
+        };
+        let right = self.sum()?;
+        if self.eat(&COMPARISONS).is_some() {
+            return Err(FormulaError::new(
+                self.position(),
+                "Comparisons can't be chained, combine them with 'and'",
+            ));
+        }
+        let op = match symbol {
+            ">=" => BinaryOp::Ge,
+            "<=" => BinaryOp::Le,
+            "==" => BinaryOp::Eq,
+            "!=" => BinaryOp::Ne,
+            ">" => BinaryOp::Gt,
+            _ => BinaryOp::Lt,
+        };
+        Ok(Expr {
+            kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+            position,
+        })
+    }
+
+    fn sum(&mut self) -> Result<Expr, FormulaError> {
+        self.binary(&["+", "-"], Self::product, |symbol| match symbol {
+            "+" => BinaryOp::Add,
+            _ => BinaryOp::Sub,
+        })
+    }
+
+    fn product(&mut self) -> Result<Expr, FormulaError> {
+        self.binary(&["*", "/", "%"], Self::unary, |symbol| match symbol {
+            "*" => BinaryOp::Mul,
+            "/" => BinaryOp::Div,
+            _ => BinaryOp::Rem,
+        })
+    }
+
+    /// Every nested part of a formula is parsed through here, which is where the depth is
+    /// limited
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        if self.depth == MAX_DEPTH {
+            return Err(FormulaError::new(
+                self.position(),
+                "The formula is nested too deeply",
+            ));
+        }
+        self.depth += 1;
+        let expr = self.prefixed();
+        self.depth -= 1;
+        expr
+    }
+
+    fn prefixed(&mut self) -> Result<Expr, FormulaError> {
+        let position = self.position();
+        let op = match self.eat(&["-", "!", "not"]) {
+            Some("-") => UnaryOp::Neg,
+            Some(_) => UnaryOp::Not,
+            None => return self.power(),
+        };
+        Ok(Expr {
+            kind: ExprKind::Unary(op, Box::new(self.unary()?)),
+            position,
+        })
+    }
+
+    /// Binds tighter than a minus in front, `-x^2` is `-(x^2)`
+    fn power(&mut self) -> Result<Expr, FormulaError> {
+        let base = self.primary()?;
+        let position = self.position();
+        if self.eat(&["^"]).is_none() {
+            return Ok(base);
+        }
+        let exponent = self.unary()?;
+        Ok(Expr {
+            kind: ExprKind::Binary(BinaryOp::Pow, Box::new(base), Box::new(exponent)),
+            position,
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, FormulaError> {
+        let position = self.position();
+        let token = self.peek().cloned();
+        let kind = match token {
+            Some(Token::Number(value)) => {
+                self.next += 1;
+                ExprKind::Number(value)
+            }
+            Some(Token::Symbol("(")) => {
+                self.next += 1;
+                let expr = self.or()?;
+                self.expect(")")?;
+                return Ok(expr);
+            }
+            Some(Token::Ident(name)) => {
+                self.next += 1;
+                match name.as_str() {
+                    "true" => ExprKind::Bool(true),
+                    "false" => ExprKind::Bool(false),
+                    "and" | "or" | "not" => {
+                        return Err(FormulaError::new(
+                            position,
+                            format!("Expected a value but found {}", name),
+                        ))
+                    }
+                    _ if self.peek() == Some(&Token::Symbol("(")) => self.call(&name, position)?,
+                    _ => self.variable(name, position)?,
+                }
+            }
+            _ => {
+                return Err(FormulaError::new(
+                    position,
+                    format!("Expected a value but found {}", self.describe_next()),
+                ))
+            }
+        };
+        Ok(Expr { kind, position })
+    }
+
+    fn call(&mut self, name: &str, position: usize) -> Result<ExprKind, FormulaError> {
+        let function = Function::iter()
+            .find(|function| function.as_ref() == name)
+            .ok_or_else(|| FormulaError::new(position, format!("Unknown function {}", name)))?;
+        self.expect("(")?;
+        let mut arguments = vec![];
+        if self.eat(&[")"]).is_none() {
+            loop {
+                arguments.push(self.or()?);
+                if self.eat(&[")"]).is_some() {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        if arguments.len() != function.arity() {
+            return Err(FormulaError::new(
+                position,
+                format!(
+                    "{} takes {} arguments, not {}",
+                    name,
+                    function.arity(),
+                    arguments.len()
+                ),
+            ));
+        }
+        Ok(ExprKind::Call(function, arguments))
+    }
+
+    fn variable(&mut self, name: String, position: usize) -> Result<ExprKind, FormulaError> {
+        let annotation = if self.eat(&[":"]).is_some() {
+            let type_position = self.position();
+            let data_type = match self.peek() {
+                Some(Token::Ident(type_name)) => match type_name.as_str() {
+                    "num" => Some(NodeDataType::DecimalSequence),
+                    "mask" => Some(NodeDataType::Mask),
+                    "signal" => Some(NodeDataType::Signal),
+                    "candles" => Some(NodeDataType::Candles),
+                    _ => None,
+                },
+                _ => None,
+            }
+            .ok_or_else(|| {
+                FormulaError::new(type_position, "Expected num, mask, signal or candles")
+            })?;
+            self.next += 1;
+            Some(data_type)
+        } else {
+            None
+        };
+        let index = match self.variables.iter().position(|v| v.name == name) {
+            Some(index) => {
+                let variable = &mut self.variables[index];
+                match (variable.annotation, annotation) {
+                    (Some(old), Some(new)) if old != new => {
+                        return Err(FormulaError::new(
+                            position,
+                            format!("{} was declared as {:?} before", name, old),
+                        ))
+                    }
+                    (None, Some(new)) => variable.annotation = Some(new),
+                    _ => {}
+                }
+                index
+            }
+            None => {
+                self.variables.push(ParsedVariable {
+                    name,
+                    annotation,
+                    position,
+                });
+                self.variables.len() - 1
+            }
+        };
+        if self.eat(&["."]).is_none() {
+            return Ok(ExprKind::Variable(index));
+        }
+        let field_position = self.position();
+        let field = match self.peek() {
+            Some(Token::Ident(field)) => CandleField::iter().find(|f| f.as_ref() == field),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            FormulaError::new(field_position, "Expected open, high, low, close or volume")
+        })?;
+        self.next += 1;
+        Ok(ExprKind::Field(index, field))
+    }
+}
+
+/// Parses a whole formula, variables are listed in the order they first appear
+pub fn parse(text: &str) -> Result<(Expr, Vec<ParsedVariable>), FormulaError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        next: 0,
+        end: text.chars().count(),
+        variables: vec![],
+        depth: 0,
+    };
+    if parser.tokens.is_empty() {
+        return Err(FormulaError::new(0, "The formula is empty"));
+    }
+    let expr = parser.or()?;
+    if parser.next < parser.tokens.len() {
+        return Err(FormulaError::new(
+            parser.position(),
+            format!("Unexpected {}", parser.describe_next()),
+        ));
+    }
+    Ok((expr, parser.variables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The parsed formula as an S-expression, like "(neg (pow x 2))"
+    fn tree(text: &str) -> String {
+        fn show(expr: &Expr, variables: &[ParsedVariable]) -> String {
+            match &expr.kind {
+                ExprKind::Number(value) => value.to_string(),
+                ExprKind::Bool(value) => value.to_string(),
+                ExprKind::Variable(index) => variables[*index].name.clone(),
+                ExprKind::Field(index, field) => {
+                    format!("{}.{}", variables[*index].name, field.as_ref())
+                }
+                ExprKind::Unary(op, operand) => {
+                    format!(
+                        "({} {})",
+                        format!("{:?}", op).to_lowercase(),
+                        show(operand, variables)
+                    )
+                }
+                ExprKind::Binary(op, left, right) => format!(
+                    "({} {} {})",
+                    format!("{:?}", op).to_lowercase(),
+                    show(left, variables),
+                    show(right, variables)
+                ),
+                ExprKind::Call(function, arguments) => format!(
+                    "({} {})",
+                    function.as_ref(),
+                    arguments
+                        .iter()
+                        .map(|argument| show(argument, variables))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+            }
+        }
+        let (expr, variables) = parse(text).unwrap();
+        show(&expr, &variables)
+    }
+
+    fn error(text: &str) -> FormulaError {
+        parse(text).unwrap_err()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(tree("-x^2"), "(neg (pow x 2))");
+        assert_eq!(tree("2^-x"), "(pow 2 (neg x))");
+        assert_eq!(tree("a + b * c"), "(add a (mul b c))");
+        assert_eq!(tree("a - b - c"), "(sub (sub a b) c)");
+        assert_eq!(tree("(a - b) / c"), "(div (sub a b) c)");
+        assert_eq!(
+            tree("not a and b > 1 or c"),
+            "(or (and (not a) (gt b 1)) c)"
+        );
+        assert_eq!(tree("lag(btc.close, 1)"), "(lag btc.close 1)");
+    }
+
+    #[test]
+    fn comparisons_dont_chain() {
+        let error = error("0 < x < 1");
+        assert!(error.message.contains("chained"), "{}", error);
+        assert_eq!(error.position, 8);
+        assert_eq!(tree("0 < x and x < 1"), "(and (lt 0 x) (lt x 1))");
+    }
+
+    #[test]
+    fn variables_keep_their_annotation() {
+        let (_, variables) = parse("s:signal > 0 or s == 1").unwrap();
+        assert_eq!(variables.len(), 1);
+        assert_eq!(variables[0].annotation, Some(NodeDataType::Signal));
+        let error = error("s:signal + s:mask");
+        assert!(error.message.contains("declared"), "{}", error);
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(tree(&nested(MAX_DEPTH - 1)), "x");
+        let error = error(&nested(10_000));
+        assert!(error.message.contains("nested too deeply"), "{}", error);
+        assert!(parse(&format!("{}x", "-".repeat(10_000))).is_err());
+        assert!(parse(&format!("{}x", "abs(".repeat(10_000))).is_err());
+    }
+}
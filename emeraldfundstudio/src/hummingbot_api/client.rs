@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::config::StrategyConfig;
+
+pub const DEFAULT_URL: &str = "http://localhost:8000";
+pub const DEFAULT_IMAGE: &str = "hummingbot/hummingbot:latest";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Everything but the unreserved characters of RFC 3986
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// `name` as one segment of a URL path, a config id like "a/b" must not add a segment
+fn path_segment(name: &str) -> String {
+    utf8_percent_encode(name, PATH_SEGMENT).to_string()
+}
+
+/// Results of a controller so far, in the quote asset
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Performance {
+    pub realized_pnl_quote: f64,
+    pub unrealized_pnl_quote: f64,
+    pub global_pnl_quote: f64,
+    pub global_pnl_pct: f64,
+    pub volume_traded: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ControllerStatus {
+    pub status: String,
+    pub performance: Performance,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BotStatus {
+    /// "running" or "stopped"
+    pub status: String,
+    /// By controller config id
+    pub performance: BTreeMap<String, ControllerStatus>,
+    pub error_logs: Vec<Value>,
+    pub general_logs: Vec<Value>,
+}
+
+/// Client of the Hummingbot backend API, which stores controller configs and runs bots in
+/// containers. Requests block, with a timeout.
+pub struct BackendApi {
+    pub url: String,
+    authorization: String,
+    agent: ureq::Agent,
+}
+
+impl BackendApi {
+    pub fn new(url: &str, username: &str, password: &str) -> Self {
+        Self {
+            url: url.trim().trim_end_matches('/').to_owned(),
+            authorization: format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", username, password))
+            ),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    fn send(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value> {
+        let request = self
+            .agent
+            .request(method, &format!("{}{}", self.url, path))
+            .set("Authorization", &self.authorization);
+        let response = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+        match response {
+            Ok(response) => Ok(response.into_json().unwrap_or(Value::Null)),
+            Err(ureq::Error::Status(code, response)) => {
+                // FastAPI puts the reason into "detail"
+                let body: Value = response.into_json().unwrap_or(Value::Null);
+                let detail = match &body["detail"] {
+                    Value::String(detail) => detail.clone(),
+                    Value::Null => String::new(),
+                    detail => detail.to_string(),
+                };
+                Err(anyhow!(
+                    "{} {} failed with {}: {}",
+                    method,
+                    path,
+                    code,
+                    detail
+                ))
+            }
+            Err(e) => Err(anyhow!("{} {} failed: {}", method, path, e)),
+        }
+    }
+
+    /// Stores `config` under its id, replacing a config with the same id
+    pub fn add_controller_config(&self, config: &StrategyConfig) -> Result<()> {
+        self.send(
+            "POST",
+            &format!("/controllers/configs/{}", path_segment(&config.id)),
+            Some(&serde_json::to_value(config)?),
+        )?;
+        Ok(())
+    }
+
+    pub fn controller_configs(&self) -> Result<Vec<StrategyConfig>> {
+        Ok(serde_json::from_value(self.send(
+            "GET",
+            "/controllers/configs/",
+            None,
+        )?)?)
+    }
+
+    /// Starts a bot container running the given controller configs
+    pub fn deploy(&self, instance_name: &str, config_ids: &[String], image: &str) -> Result<()> {
+        self.send(
+            "POST",
+            "/bot-orchestration/deploy-v2-controllers",
+            Some(&json!({
+                "instance_name": instance_name,
+                "credentials_profile": "master_account",
+                "controllers_config": config_ids,
+                "image": image,
+            })),
+        )?;
+        Ok(())
+    }
+
+    pub fn start_bot(&self, bot_name: &str) -> Result<()> {
+        self.send(
+            "POST",
+            "/bot-orchestration/start-bot",
+            Some(&json!({ "bot_name": bot_name, "async_backend": false })),
+        )?;
+        Ok(())
+    }
+
+    pub fn stop_bot(&self, bot_name: &str) -> Result<()> {
+        self.send(
+            "POST",
+            "/bot-orchestration/stop-bot",
+            Some(&json!({
+                "bot_name": bot_name,
+                "skip_order_cancellation": false,
+                "async_backend": false,
+            })),
+        )?;
+        Ok(())
+    }
+
+    pub fn bot_status(&self, bot_name: &str) -> Result<BotStatus> {
+        let response = self.send(
+            "GET",
+            &format!("/bot-orchestration/{}/status", path_segment(bot_name)),
+            None,
+        )?;
+        if response["status"] != "success" {
+            return Err(anyhow!(
+                "No status of {}: {}",
+                bot_name,
+                response["data"].as_str().unwrap_or("unknown error")
+            ));
+        }
+        Ok(serde_json::from_value(response["data"].clone())?)
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Result};
+use egui_snarl::Snarl;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    node_editor::{
+        node_trait::EFNodeFNSerialized,
+        nodes::{execute_position::ExecutePositionNode, market_data::MarketDataNode},
+        registry::registry,
+    },
+    node_runners::graph::GraphSnapshot,
+    types::timeframe::timeframe_label,
+};
+
+/// Controller that runs exported graphs inside Hummingbot
+pub const GRAPH_CONTROLLER_NAME: &str = "emerald_fund_graph";
+
+/// A V2 controller config as the backend API stores it. The graph travels along so the
+/// controller evaluates the same nodes as the studio.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StrategyConfig {
+    pub id: String,
+    pub controller_name: String,
+    pub controller_type: String,
+    pub connector_name: String,
+    pub trading_pair: String,
+    pub candles_interval: String,
+    pub total_amount_quote: f64,
+    pub graph: Value,
+}
+
+/// The nodes with their arguments and, for every input, the output it is connected to. The
+/// node types used are described too, so the controller can tell which ones it lacks.
+fn graph_json(snarl: &Snarl<EFNodeFNSerialized<'_>>) -> Result<Value> {
+    let graph = GraphSnapshot::from_snarl(snarl)?;
+    let registry = registry();
+    let mut node_types = Map::new();
+    let nodes: Vec<Value> = graph
+        .nodes
+        .iter()
+        .map(|(id, graph_node)| {
+            let node_name = graph_node.node.node_name.as_ref();
+            if let Some(registration) = registry.get(node_name) {
+                node_types.insert(
+                    node_name.to_owned(),
+                    json!({
+                        "title": registration.title,
+                        "category": registration.category,
+                        "description": registration.description,
+                    }),
+                );
+            }
+            let inputs: Vec<Value> = graph_node
+                .inputs
+                .iter()
+                .map(|input| match input {
+                    Some(pin) => json!({ "node": pin.node.0, "output": pin.output }),
+                    None => Value::Null,
+                })
+                .collect();
+            json!({
+                "id": id,
+                "node_name": graph_node.node.node_name,
+                "arguments": graph_node.node.arguments,
+                "version": graph_node.node.version,
+                "inputs": inputs,
+            })
+        })
+        .collect();
+    Ok(json!({ "nodes": nodes, "node_types": node_types }))
+}
+
+/// Exports the graph as a controller config. The market comes from the first symbol of the
+/// first `MarketDataNode`, the amount from the order size of the first `ExecutePositionNode`.
+pub fn export_strategy(snarl: &Snarl<EFNodeFNSerialized<'_>>, id: &str) -> Result<StrategyConfig> {
+    let find_node = |node_name: &str| {
+        snarl
+            .nodes()
+            .find(|node| node.get_node().get_name() == node_name)
+            .map(|node| node.get_node().as_any())
+    };
+    let market_data = find_node("MarketDataNode")
+        .and_then(|node| node.downcast_ref::<MarketDataNode>())
+        .ok_or_else(|| anyhow!("Graph has no MarketDataNode"))?;
+    let symbol = market_data
+        .symbols
+        .first()
+        .ok_or_else(|| anyhow!("MarketDataNode needs at least one symbol"))?;
+    let execute = find_node("ExecutePositionNode")
+        .and_then(|node| node.downcast_ref::<ExecutePositionNode>())
+        .ok_or_else(|| anyhow!("Graph has no ExecutePositionNode"))?;
+    if id.trim().is_empty() {
+        return Err(anyhow!("The config needs a name"));
+    }
+    Ok(StrategyConfig {
+        id: id.trim().to_owned(),
+        controller_name: GRAPH_CONTROLLER_NAME.to_owned(),
+        controller_type: "directional_trading".to_owned(),
+        connector_name: symbol.exchange.clone(),
+        trading_pair: symbol.pair.clone(),
+        candles_interval: timeframe_label(market_data.generator.timeframe.seconds()),
+        total_amount_quote: execute.order_size,
+        graph: graph_json(snarl)?,
+    })
+}
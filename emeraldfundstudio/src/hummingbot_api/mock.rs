@@ -0,0 +1,369 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use parking_lot::Mutex;
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// How long the server waits for a request before it checks whether it should stop
+const TICK: Duration = Duration::from_millis(50);
+
+/// What every status request adds to the results of a running controller
+const PNL_PER_STATUS: f64 = 0.5;
+const VOLUME_PER_STATUS: f64 = 100.0;
+
+#[derive(Default)]
+struct MockBot {
+    controllers: Vec<String>,
+    running: bool,
+    /// Status requests while running, the made up results grow with them
+    ticks: u32,
+}
+
+#[derive(Default)]
+pub struct MockState {
+    pub configs: BTreeMap<String, Value>,
+    bots: BTreeMap<String, MockBot>,
+    /// Method and path of every request, oldest first
+    pub requests: Vec<(String, String)>,
+}
+
+impl MockState {
+    #[cfg(test)]
+    pub fn is_running(&self, bot_name: &str) -> Option<bool> {
+        self.bots.get(bot_name).map(|bot| bot.running)
+    }
+}
+
+/// A stand-in for the Hummingbot backend API on localhost: it keeps controller configs and
+/// bots in memory, checks basic auth like the real one and reports made up performance, so
+/// deploying can be tried without Docker or exchange keys.
+pub struct MockBackend {
+    pub address: SocketAddr,
+    pub state: Arc<Mutex<MockState>>,
+    server: Arc<Server>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockBackend {
+    /// Port 0 picks a free port
+    pub fn start(port: u16, username: &str, password: &str) -> Result<Self> {
+        let server = Arc::new(Server::http(("127.0.0.1", port)).map_err(|e| anyhow!("{}", e))?);
+        let address = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| anyhow!("Mock backend isn't listening on a TCP port"))?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let authorization = format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", username, password))
+        );
+        let handle = {
+            let (server, state, stop) = (server.clone(), state.clone(), stop.clone());
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match server.recv_timeout(TICK) {
+                        Ok(Some(request)) => handle_request(request, &state, &authorization),
+                        Ok(None) => {}
+                        Err(e) => {
+                            log::warn!("Mock backend stopped: {}", e);
+                            break;
+                        }
+                    }
+                }
+            })
+        };
+        Ok(Self {
+            address,
+            state,
+            server,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+}
+
+impl Drop for MockBackend {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn handle_request(mut request: Request, state: &Mutex<MockState>, authorization: &str) {
+    let method = request.method().clone();
+    let path = request.url().to_owned();
+    let authorized = request.headers().iter().any(|header| {
+        header.field.equiv("Authorization") && header.value.as_str() == authorization
+    });
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let (code, response) = if authorized {
+        let mut state = state.lock();
+        state.requests.push((method.to_string(), path.clone()));
+        route(&mut state, &method, &path, &body)
+    } else {
+        (401, json!({ "detail": "Incorrect username or password" }))
+    };
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(response.to_string())
+        .with_status_code(code)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        log::warn!("Mock backend couldn't answer {} {}: {}", method, path, e);
+    }
+}
+
+fn detail(code: u16, detail: impl Into<String>) -> (u16, Value) {
+    (code, json!({ "detail": detail.into() }))
+}
+
+fn bot_name(body: &Value) -> Result<String, (u16, Value)> {
+    body["bot_name"]
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| detail(422, "bot_name is required"))
+}
+
+fn route(state: &mut MockState, method: &Method, path: &str, body: &str) -> (u16, Value) {
+    let body: Value = match body.trim() {
+        "" => Value::Null,
+        body => match serde_json::from_str(body) {
+            Ok(body) => body,
+            Err(e) => return detail(422, e.to_string()),
+        },
+    };
+    // Decoded like FastAPI does, after splitting so an encoded "/" stays in its segment
+    let segments: Vec<String> = path
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let result = match (method, segments.as_slice()) {
+        (Method::Get, []) => Ok((200, json!({ "status": "running" }))),
+        (Method::Get, ["controllers", "configs"]) => {
+            Ok((200, Value::Array(state.configs.values().cloned().collect())))
+        }
+        (Method::Post, ["controllers", "configs", name]) => {
+            state.configs.insert(name.to_string(), body);
+            Ok((200, json!({ "message": format!("Config {} saved", name) })))
+        }
+        (Method::Post, ["bot-orchestration", "deploy-v2-controllers"]) => deploy(state, &body),
+        (Method::Post, ["bot-orchestration", action @ ("start-bot" | "stop-bot")]) => {
+            bot_name(&body).and_then(|name| match state.bots.get_mut(&name) {
+                Some(bot) => {
+                    bot.running = *action == "start-bot";
+                    Ok((
+                        200,
+                        json!({ "status": "success", "response": { "success": true } }),
+                    ))
+                }
+                None => Err(detail(404, format!("Bot {} not found", name))),
+            })
+        }
+        (Method::Get, ["bot-orchestration", name, "status"]) => Ok(status(state, name)),
+        _ => Err(detail(404, "Not Found")),
+    };
+    result.unwrap_or_else(|error| error)
+}
+
+fn deploy(state: &mut MockState, body: &Value) -> Result<(u16, Value), (u16, Value)> {
+    let name = body["instance_name"]
+        .as_str()
+        .ok_or_else(|| detail(422, "instance_name is required"))?;
+    let controllers: Vec<String> = body["controllers_config"]
+        .as_array()
+        .ok_or_else(|| detail(422, "controllers_config is required"))?
+        .iter()
+        .filter_map(|config| config.as_str().map(str::to_owned))
+        .collect();
+    if let Some(missing) = controllers
+        .iter()
+        .find(|id| !state.configs.contains_key(*id))
+    {
+        return Err(detail(
+            400,
+            format!("Controller config {} not found", missing),
+        ));
+    }
+    // A new container runs its controllers right away
+    state.bots.insert(
+        name.to_owned(),
+        MockBot {
+            controllers,
+            running: true,
+            ticks: 0,
+        },
+    );
+    Ok((
+        200,
+        json!({
+            "success": true,
+            "message": "Instance created successfully.",
+            "unique_instance_name": name,
+        }),
+    ))
+}
+
+fn status(state: &mut MockState, name: &str) -> (u16, Value) {
+    let Some(bot) = state.bots.get_mut(name) else {
+        return (200, json!({ "status": "error", "data": "Bot not found" }));
+    };
+    if bot.running {
+        bot.ticks += 1;
+    }
+    let pnl = bot.ticks as f64 * PNL_PER_STATUS;
+    let run_status = if bot.running { "running" } else { "stopped" };
+    let performance: serde_json::Map<String, Value> = bot
+        .controllers
+        .iter()
+        .map(|controller| {
+            let performance = json!({
+                "status": run_status,
+                "performance": {
+                    "realized_pnl_quote": pnl,
+                    "unrealized_pnl_quote": 0.0,
+                    "global_pnl_quote": pnl,
+                    "global_pnl_pct": pnl / 10.0,
+                    "volume_traded": bot.ticks as f64 * VOLUME_PER_STATUS,
+                },
+            });
+            (controller.clone(), performance)
+        })
+        .collect();
+    (
+        200,
+        json!({
+            "status": "success",
+            "data": {
+                "status": run_status,
+                "performance": performance,
+                "error_logs": [],
+                "general_logs": [],
+            },
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::MockBackend;
+    use crate::hummingbot_api::{
+        client::{BackendApi, DEFAULT_IMAGE},
+        config::{StrategyConfig, GRAPH_CONTROLLER_NAME},
+    };
+
+    fn config() -> StrategyConfig {
+        StrategyConfig {
+            id: "sma_cross".to_owned(),
+            controller_name: GRAPH_CONTROLLER_NAME.to_owned(),
+            controller_type: "directional_trading".to_owned(),
+            connector_name: "binance".to_owned(),
+            trading_pair: "BTC-USDT".to_owned(),
+            candles_interval: "1m".to_owned(),
+            total_amount_quote: 100.0,
+            graph: json!({ "nodes": [] }),
+        }
+    }
+
+    #[test]
+    fn deploy_start_stop_and_status() {
+        let backend = MockBackend::start(0, "admin", "admin").unwrap();
+        let api = BackendApi::new(&backend.url(), "admin", "admin");
+
+        api.add_controller_config(&config()).unwrap();
+        assert_eq!(api.controller_configs().unwrap(), vec![config()]);
+
+        let configs = vec![config().id];
+        api.deploy("studio_bot", &configs, DEFAULT_IMAGE).unwrap();
+        assert_eq!(backend.state.lock().is_running("studio_bot"), Some(true));
+
+        let status = api.bot_status("studio_bot").unwrap();
+        assert_eq!(status.status, "running");
+        let performance = &status.performance["sma_cross"].performance;
+        assert!(performance.volume_traded > 0.0);
+
+        api.stop_bot("studio_bot").unwrap();
+        assert_eq!(api.bot_status("studio_bot").unwrap().status, "stopped");
+        api.start_bot("studio_bot").unwrap();
+        assert_eq!(backend.state.lock().is_running("studio_bot"), Some(true));
+
+        let paths: Vec<String> = backend
+            .state
+            .lock()
+            .requests
+            .iter()
+            .map(|(method, path)| format!("{} {}", method, path))
+            .collect();
+        assert_eq!(paths[0], "POST /controllers/configs/sma_cross");
+        assert_eq!(paths[2], "POST /bot-orchestration/deploy-v2-controllers");
+    }
+
+    #[test]
+    fn names_stay_one_path_segment() {
+        let backend = MockBackend::start(0, "admin", "admin").unwrap();
+        let api = BackendApi::new(&backend.url(), "admin", "admin");
+        let config = StrategyConfig {
+            id: "sma/cross 2".to_owned(),
+            ..config()
+        };
+        api.add_controller_config(&config).unwrap();
+        assert_eq!(api.controller_configs().unwrap(), vec![config.clone()]);
+        api.deploy("studio bot", &[config.id], DEFAULT_IMAGE)
+            .unwrap();
+        assert_eq!(api.bot_status("studio bot").unwrap().status, "running");
+
+        let paths: Vec<String> = backend
+            .state
+            .lock()
+            .requests
+            .iter()
+            .map(|(_, path)| path.clone())
+            .collect();
+        assert_eq!(paths[0], "/controllers/configs/sma%2Fcross%202");
+        assert_eq!(paths[3], "/bot-orchestration/studio%20bot/status");
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let backend = MockBackend::start(0, "admin", "admin").unwrap();
+
+        let wrong_password = BackendApi::new(&backend.url(), "admin", "nope");
+        let error = wrong_password.add_controller_config(&config()).unwrap_err();
+        assert!(error.to_string().contains("401"), "{}", error);
+
+        let api = BackendApi::new(&backend.url(), "admin", "admin");
+        let error = api
+            .deploy("studio_bot", &["missing".to_owned()], DEFAULT_IMAGE)
+            .unwrap_err();
+        assert!(error.to_string().contains("missing"), "{}", error);
+        assert!(api.bot_status("studio_bot").is_err());
+        assert!(api.stop_bot("studio_bot").is_err());
+    }
+}
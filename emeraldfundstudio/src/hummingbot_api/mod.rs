@@ -0,0 +1,4 @@
+pub mod client;
+pub mod config;
+pub mod mock;
+pub mod panel;
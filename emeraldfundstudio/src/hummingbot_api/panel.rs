@@ -0,0 +1,319 @@
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use anyhow::{anyhow, Result};
+use egui::{Button, Color32, Grid, Spinner, TextEdit, Ui};
+use egui_snarl::Snarl;
+
+use super::{
+    client::{BackendApi, BotStatus, DEFAULT_IMAGE, DEFAULT_URL},
+    config::{export_strategy, StrategyConfig},
+    mock::MockBackend,
+};
+use crate::node_editor::node_trait::EFNodeFNSerialized;
+
+/// Pushes the graph to a Hummingbot backend API and controls the bot running it
+pub struct HummingbotPanel {
+    pub open: bool,
+    url: String,
+    username: String,
+    password: String,
+    config_id: String,
+    instance_name: String,
+    image: String,
+    mock: Option<MockBackend>,
+    status: Option<BotStatus>,
+    message: Option<Result<String, String>>,
+    /// The request on its way to the backend, the UI doesn't wait for it
+    pending: Option<Receiver<Result<Reply>>>,
+}
+
+impl Default for HummingbotPanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            url: DEFAULT_URL.to_owned(),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+            config_id: "emerald_fund_strategy".to_owned(),
+            instance_name: "emerald_fund_bot".to_owned(),
+            image: DEFAULT_IMAGE.to_owned(),
+            mock: None,
+            status: None,
+            message: None,
+            pending: None,
+        }
+    }
+}
+
+enum Action {
+    Push,
+    Deploy,
+    Start,
+    Stop,
+    Refresh,
+}
+
+/// An action with what it needs from the UI, ready to be sent from another thread
+enum Request {
+    Push(StrategyConfig),
+    Deploy {
+        bot: String,
+        config_id: String,
+        image: String,
+    },
+    Start(String),
+    Stop(String),
+    Refresh(String),
+}
+
+/// How a request went, for the message under the buttons
+struct Reply {
+    message: String,
+    status: Option<BotStatus>,
+}
+
+impl Reply {
+    fn message(message: String) -> Self {
+        Self {
+            message,
+            status: None,
+        }
+    }
+}
+
+impl Request {
+    fn send(self, api: &BackendApi) -> Result<Reply> {
+        match self {
+            Request::Push(config) => {
+                api.add_controller_config(&config)?;
+                let stored = api.controller_configs()?.len();
+                Ok(Reply::message(format!(
+                    "Pushed {} ({} {} {}), the backend has {} configs",
+                    config.id,
+                    config.connector_name,
+                    config.trading_pair,
+                    config.candles_interval,
+                    stored
+                )))
+            }
+            Request::Deploy {
+                bot,
+                config_id,
+                image,
+            } => {
+                api.deploy(&bot, &[config_id], &image)?;
+                Ok(Reply::message(format!("Deployed {}", bot)))
+            }
+            Request::Start(bot) => {
+                api.start_bot(&bot)?;
+                Ok(Reply::message(format!("Started {}", bot)))
+            }
+            Request::Stop(bot) => {
+                api.stop_bot(&bot)?;
+                Ok(Reply::message(format!("Stopped {}", bot)))
+            }
+            Request::Refresh(bot) => Ok(Reply {
+                status: Some(api.bot_status(&bot)?),
+                message: format!("Status of {}", bot),
+            }),
+        }
+    }
+}
+
+impl HummingbotPanel {
+    pub fn show(&mut self, ctx: &egui::Context, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+        self.poll();
+        let mut open = self.open;
+        egui::Window::new("Hummingbot Deploy")
+            .open(&mut open)
+            .default_width(440.0)
+            .show(ctx, |ui| {
+                self.show_connection(ui);
+                ui.separator();
+                let mut action = None;
+                let idle = self.pending.is_none();
+                Grid::new("hummingbot_bot").show(ui, |ui| {
+                    ui.label("Config");
+                    ui.text_edit_singleline(&mut self.config_id);
+                    if ui.add_enabled(idle, Button::new("Push config")).clicked() {
+                        action = Some(Action::Push);
+                    }
+                    ui.end_row();
+                    ui.label("Bot");
+                    ui.text_edit_singleline(&mut self.instance_name);
+                    if ui.add_enabled(idle, Button::new("Deploy")).clicked() {
+                        action = Some(Action::Deploy);
+                    }
+                    ui.end_row();
+                    ui.label("Image");
+                    ui.text_edit_singleline(&mut self.image);
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(idle, Button::new("Start")).clicked() {
+                        action = Some(Action::Start);
+                    }
+                    if ui.add_enabled(idle, Button::new("Stop")).clicked() {
+                        action = Some(Action::Stop);
+                    }
+                    if ui
+                        .add_enabled(idle, Button::new("Refresh status"))
+                        .clicked()
+                    {
+                        action = Some(Action::Refresh);
+                    }
+                    if !idle {
+                        ui.add(Spinner::new());
+                    }
+                });
+                if let Some(action) = action {
+                    self.start(ctx, action, snarl);
+                }
+                self.show_status(ui);
+                if let Some(message) = &self.message {
+                    match message {
+                        Ok(message) => ui.label(message),
+                        Err(error) => ui.colored_label(Color32::RED, error),
+                    };
+                }
+            });
+        self.open = open;
+    }
+
+    fn api(&self) -> BackendApi {
+        BackendApi::new(&self.url, &self.username, &self.password)
+    }
+
+    /// Sends the request of `action` on a thread, `poll` picks up the reply
+    fn start(
+        &mut self,
+        ctx: &egui::Context,
+        action: Action,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+    ) {
+        let bot = self.instance_name.trim().to_owned();
+        let request = match action {
+            // The graph is only readable from the UI thread
+            Action::Push => match export_strategy(snarl, &self.config_id) {
+                Ok(config) => Request::Push(config),
+                Err(e) => {
+                    self.message = Some(Err(e.to_string()));
+                    return;
+                }
+            },
+            Action::Deploy => Request::Deploy {
+                bot,
+                config_id: self.config_id.trim().to_owned(),
+                image: self.image.trim().to_owned(),
+            },
+            Action::Start => Request::Start(bot),
+            Action::Stop => Request::Stop(bot),
+            Action::Refresh => Request::Refresh(bot),
+        };
+        let api = self.api();
+        let ctx = ctx.clone();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            // The panel may be gone by now, then nobody needs the reply
+            let _ = sender.send(request.send(&api));
+            ctx.request_repaint();
+        });
+        self.pending = Some(receiver);
+        self.message = None;
+    }
+
+    fn poll(&mut self) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let reply = match pending.try_recv() {
+            Ok(reply) => reply,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(anyhow!("The request thread failed")),
+        };
+        self.pending = None;
+        self.message = Some(match reply {
+            Ok(reply) => {
+                if reply.status.is_some() {
+                    self.status = reply.status;
+                }
+                Ok(reply.message)
+            }
+            Err(e) => Err(e.to_string()),
+        });
+    }
+
+    fn show_connection(&mut self, ui: &mut Ui) {
+        ui.heading("Backend API");
+        ui.add_enabled_ui(self.mock.is_none(), |ui| {
+            Grid::new("hummingbot_connection").show(ui, |ui| {
+                ui.label("URL");
+                ui.add(TextEdit::singleline(&mut self.url).desired_width(260.0));
+                ui.end_row();
+                ui.label("Username");
+                ui.text_edit_singleline(&mut self.username);
+                ui.end_row();
+                ui.label("Password");
+                ui.add(TextEdit::singleline(&mut self.password).password(true));
+                ui.end_row();
+            });
+        });
+        ui.horizontal(|ui| match &self.mock {
+            Some(mock) => {
+                ui.label(format!(
+                    "Mock backend on {}, {} requests",
+                    mock.url(),
+                    mock.state.lock().requests.len()
+                ));
+                if ui.button("Stop mock").clicked() {
+                    self.mock = None;
+                    self.url = DEFAULT_URL.to_owned();
+                }
+            }
+            None => {
+                if ui
+                    .button("Start mock backend")
+                    .on_hover_text("A local stand-in for trying deployments")
+                    .clicked()
+                {
+                    match MockBackend::start(0, &self.username, &self.password) {
+                        Ok(mock) => {
+                            self.url = mock.url();
+                            self.mock = Some(mock);
+                        }
+                        Err(e) => self.message = Some(Err(e.to_string())),
+                    }
+                }
+            }
+        });
+    }
+
+    fn show_status(&self, ui: &mut Ui) {
+        let Some(status) = &self.status else {
+            return;
+        };
+        ui.label(format!("Bot is {}", status.status));
+        Grid::new("hummingbot_performance")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Controller");
+                ui.label("Status");
+                ui.label("Realized PnL");
+                ui.label("Unrealized PnL");
+                ui.label("Volume");
+                ui.end_row();
+                for (controller, controller_status) in &status.performance {
+                    let performance = &controller_status.performance;
+                    ui.label(controller);
+                    ui.label(&controller_status.status);
+                    ui.label(format!("{:.2}", performance.realized_pnl_quote));
+                    ui.label(format!("{:.2}", performance.unrealized_pnl_quote));
+                    ui.label(format!("{:.2}", performance.volume_traded));
+                    ui.end_row();
+                }
+            });
+        for log in status.error_logs.iter().rev().take(5) {
+            ui.colored_label(Color32::RED, log.to_string());
+        }
+    }
+}
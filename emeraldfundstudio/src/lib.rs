@@ -1,12 +1,36 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+mod backtest;
 mod candles;
-mod consts;
-mod macros;
+mod data_store;
+mod formula;
+#[cfg(not(target_arch = "wasm32"))]
+mod hummingbot_api;
 mod node_editor;
 mod node_runners;
+mod optimization;
+mod paper;
+#[cfg(not(target_arch = "wasm32"))]
+mod replay;
+mod script;
 mod traits;
 mod types;
 
 pub use app::EmeraldFundStudioApp;
+pub use node_editor::{
+    node_trait::{
+        CheapCloneNodeDataTypeWithValue, EFNodeFn, NodeDataType, NodeDataTypeWithValue,
+        NodeParameter, NodeState, ParameterKind,
+    },
+    registry::{register_node, NodeMigration, NodeRegistration},
+};
+// What outputs of nodes are made of, for nodes from other crates
+pub use types::{
+    candles::{Candles, MarketInfo},
+    decimal_sequence::DecimalSequence,
+    mask::Mask,
+    signal::Signal,
+    time_index::{Indexed, JoinMode, TimeIndex},
+    timestamp::TimeStamp,
+};
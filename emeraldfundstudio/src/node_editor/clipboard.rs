@@ -0,0 +1,78 @@
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Result};
+use egui::{Pos2, Vec2};
+use egui_snarl::{NodeId, Snarl};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    composite::{inner_nodes, insert_inner_nodes},
+    node_trait::EFNodeFNSerialized,
+    nodes::composite::InnerNode,
+};
+
+/// Tells copied nodes apart from any other JSON on the clipboard
+const CLIPBOARD_KIND: &str = "emeraldfund/nodes";
+/// How far pasted nodes are from the ones they were copied from, so both can be seen
+const PASTE_OFFSET: Vec2 = Vec2::new(40.0, 40.0);
+
+/// Nodes as they are put on the clipboard, with their arguments and the wires between them.
+/// Wires to nodes that weren't copied are left out.
+#[derive(Serialize, Deserialize)]
+struct CopiedNodes {
+    kind: String,
+    /// Where the middle of the nodes was
+    center: [f32; 2],
+    nodes: Vec<InnerNode>,
+}
+
+fn existing(snarl: &Snarl<EFNodeFNSerialized<'_>>, ids: &[NodeId]) -> Vec<NodeId> {
+    ids.iter()
+        .copied()
+        .collect::<BTreeSet<NodeId>>()
+        .into_iter()
+        .filter(|id| snarl.get_node(*id).is_some())
+        .collect()
+}
+
+/// The nodes `ids` as JSON for the clipboard
+pub fn copy_nodes(snarl: &Snarl<EFNodeFNSerialized<'_>>, ids: &[NodeId]) -> Result<String> {
+    let ids = existing(snarl, ids);
+    if ids.is_empty() {
+        return Err(anyhow!("Select the nodes to copy first"));
+    }
+    let (nodes, center) = inner_nodes(snarl, &ids);
+    Ok(serde_json::to_string_pretty(&CopiedNodes {
+        kind: CLIPBOARD_KIND.to_owned(),
+        center: [center.x, center.y],
+        nodes,
+    })?)
+}
+
+/// Whether `text` holds nodes copied by `copy_nodes`
+pub fn is_copied_nodes(text: &str) -> bool {
+    serde_json::from_str::<CopiedNodes>(text).is_ok_and(|copied| copied.kind == CLIPBOARD_KIND)
+}
+
+/// Adds the nodes of `copy_nodes` next to where they were copied from, with new ids, and
+/// returns those. Nothing is added if one of them doesn't load.
+pub fn paste_nodes(snarl: &mut Snarl<EFNodeFNSerialized<'_>>, text: &str) -> Result<Vec<NodeId>> {
+    let copied: CopiedNodes = serde_json::from_str(text)?;
+    if copied.kind != CLIPBOARD_KIND {
+        return Err(anyhow!("The clipboard doesn't hold nodes"));
+    }
+    let center = Pos2::new(copied.center[0], copied.center[1]) + PASTE_OFFSET;
+    let ids = insert_inner_nodes(snarl, &copied.nodes, center)?;
+    Ok(ids.into_values().collect())
+}
+
+/// Copies of the nodes `ids` and the wires between them, returns their ids
+pub fn duplicate_nodes(
+    snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+    ids: &[NodeId],
+) -> Result<Vec<NodeId>> {
+    let ids = existing(snarl, ids);
+    let (nodes, center) = inner_nodes(snarl, &ids);
+    let ids = insert_inner_nodes(snarl, &nodes, center + PASTE_OFFSET)?;
+    Ok(ids.into_values().collect())
+}
@@ -0,0 +1,497 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, Result};
+use egui::{Pos2, Vec2};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+
+use super::{
+    drop_mismatched_wires,
+    node_trait::EFNodeFNSerialized,
+    nodes::composite::{
+        CompositeDefinition, CompositeInput, CompositeNode, CompositeOutput, InnerNode, InnerPin,
+    },
+    registry::current_version,
+};
+use crate::node_runners::realtime::{clear_cache_downstream, forget_node, run_nodes};
+
+/// The composite behind a node of the graph, if it is one
+pub fn composite_definition<'a>(
+    snarl: &'a Snarl<EFNodeFNSerialized<'_>>,
+    node_id: NodeId,
+) -> Option<&'a CompositeDefinition> {
+    let node = snarl.get_node(node_id)?.get_node();
+    if node.get_name() != "CompositeNode" {
+        return None;
+    }
+    node.as_any()
+        .downcast_ref::<CompositeNode>()
+        .map(|composite| &composite.definition)
+}
+
+fn composite_node(definition: &CompositeDefinition) -> Result<EFNodeFNSerialized<'static>> {
+    let mut node = EFNodeFNSerialized {
+        node_name: "CompositeNode".into(),
+        arguments: serde_json::to_value(definition)?,
+        version: current_version("CompositeNode"),
+        loaded_node: None,
+    };
+    node.load_node()?;
+    Ok(node)
+}
+
+/// Copies the nodes `ids` with the wires between them, positions are relative to their middle
+pub fn inner_nodes(
+    snarl: &Snarl<EFNodeFNSerialized<'_>>,
+    ids: &[NodeId],
+) -> (Vec<InnerNode>, Pos2) {
+    let positions: Vec<Pos2> = ids
+        .iter()
+        .map(|id| {
+            snarl
+                .get_node_info(*id)
+                .map(|info| info.pos)
+                .unwrap_or_default()
+        })
+        .collect();
+    let center = positions
+        .iter()
+        .fold(Vec2::ZERO, |sum, pos| sum + pos.to_vec2())
+        / ids.len().max(1) as f32;
+    let nodes = ids
+        .iter()
+        .zip(&positions)
+        .map(|(id, pos)| {
+            let node = snarl[*id].get_node();
+            let inputs = (0..node.get_inputs().len())
+                .map(|input| {
+                    snarl
+                        .in_pin(InPinId { node: *id, input })
+                        .remotes
+                        .first()
+                        .filter(|remote| ids.contains(&remote.node))
+                        .map(|remote| InnerPin {
+                            node: remote.node.0,
+                            pin: remote.output,
+                        })
+                })
+                .collect();
+            InnerNode {
+                id: id.0,
+                node_name: snarl[*id].node_name.to_string(),
+                arguments: node.export_data(),
+                version: snarl[*id].version,
+                pos: [pos.x - center.x, pos.y - center.y],
+                inputs,
+            }
+        })
+        .collect();
+    (nodes, center.to_pos2())
+}
+
+/// Adds `nodes` around `center` and wires them up, returns their new ids
+pub fn insert_inner_nodes(
+    snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+    nodes: &[InnerNode],
+    center: Pos2,
+) -> Result<BTreeMap<usize, NodeId>> {
+    // Load everything first so a broken node leaves the graph as it was
+    let mut loaded = Vec::with_capacity(nodes.len());
+    for inner_node in nodes {
+        let mut node = EFNodeFNSerialized {
+            node_name: inner_node.node_name.clone().into(),
+            arguments: inner_node.arguments.clone(),
+            version: inner_node.version,
+            loaded_node: None,
+        };
+        node.load_node()?;
+        loaded.push(node);
+    }
+    let ids: BTreeMap<usize, NodeId> = nodes
+        .iter()
+        .zip(loaded)
+        .map(|(inner_node, node)| {
+            let pos = center + Vec2::new(inner_node.pos[0], inner_node.pos[1]);
+            (inner_node.id, snarl.insert_node(pos, node))
+        })
+        .collect();
+    for inner_node in nodes {
+        for (input, remote) in inner_node.inputs.iter().enumerate() {
+            let Some(remote) = remote else {
+                continue;
+            };
+            if let Some(source) = ids.get(&remote.node) {
+                snarl.connect(
+                    OutPinId {
+                        node: *source,
+                        output: remote.pin,
+                    },
+                    InPinId {
+                        node: ids[&inner_node.id],
+                        input,
+                    },
+                );
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// Replaces the `selected` nodes by a composite node. Wires from outside become its inputs,
+/// as do unconnected inputs; outputs used outside become its outputs. When nothing outside
+/// uses them, the outputs of the last nodes of the selection are exposed instead.
+pub fn collapse(
+    snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+    selected: &[NodeId],
+    name: &str,
+) -> Result<NodeId> {
+    if name.trim().is_empty() {
+        return Err(anyhow!("The composite needs a name"));
+    }
+    let ids: Vec<NodeId> = selected
+        .iter()
+        .copied()
+        .collect::<BTreeSet<NodeId>>()
+        .into_iter()
+        .filter(|id| snarl.get_node(*id).is_some())
+        .collect();
+    if ids.is_empty() {
+        return Err(anyhow!("Select the nodes to collapse first"));
+    }
+    let (nodes, center) = inner_nodes(snarl, &ids);
+
+    // Inputs fed by the same output outside share one pin
+    let mut inputs: Vec<CompositeInput> = vec![];
+    let mut sources: Vec<Option<OutPinId>> = vec![];
+    for id in &ids {
+        for (input, (label, data_type)) in snarl[*id].get_node().get_inputs().iter().enumerate() {
+            let remote = snarl
+                .in_pin(InPinId { node: *id, input })
+                .remotes
+                .first()
+                .copied();
+            if remote.is_some_and(|remote| ids.contains(&remote.node)) {
+                continue;
+            }
+            let target = InnerPin {
+                node: id.0,
+                pin: input,
+            };
+            match remote.and_then(|remote| sources.iter().position(|s| *s == Some(remote))) {
+                Some(index) => inputs[index].targets.push(target),
+                None => {
+                    inputs.push(CompositeInput {
+                        name: label.to_string(),
+                        data_type: *data_type,
+                        targets: vec![target],
+                    });
+                    sources.push(remote);
+                }
+            }
+        }
+    }
+
+    let mut outputs: Vec<CompositeOutput> = vec![];
+    let mut consumers: Vec<Vec<InPinId>> = vec![];
+    let mut last_nodes = vec![];
+    for id in &ids {
+        let mut used_inside = false;
+        for (output, (label, data_type)) in snarl[*id].get_node().get_outputs().iter().enumerate() {
+            let remotes = snarl.out_pin(OutPinId { node: *id, output }).remotes;
+            used_inside |= remotes.iter().any(|remote| ids.contains(&remote.node));
+            let outside: Vec<InPinId> = remotes
+                .into_iter()
+                .filter(|remote| !ids.contains(&remote.node))
+                .collect();
+            if !outside.is_empty() {
+                outputs.push(CompositeOutput {
+                    name: label.to_string(),
+                    data_type: *data_type,
+                    source: InnerPin {
+                        node: id.0,
+                        pin: output,
+                    },
+                });
+                consumers.push(outside);
+            }
+        }
+        if !used_inside {
+            last_nodes.push(*id);
+        }
+    }
+    if outputs.is_empty() {
+        for id in last_nodes {
+            for (output, (label, data_type)) in
+                snarl[id].get_node().get_outputs().iter().enumerate()
+            {
+                outputs.push(CompositeOutput {
+                    name: label.to_string(),
+                    data_type: *data_type,
+                    source: InnerPin {
+                        node: id.0,
+                        pin: output,
+                    },
+                });
+                consumers.push(vec![]);
+            }
+        }
+    }
+
+    let definition = CompositeDefinition {
+        name: name.trim().to_owned(),
+        nodes,
+        inputs,
+        outputs,
+    };
+    let node = composite_node(&definition)?;
+    for id in &ids {
+        forget_node(id);
+        snarl.remove_node(*id);
+    }
+    let composite_id = snarl.insert_node(center, node);
+    for (index, source) in sources.into_iter().enumerate() {
+        if let Some(source) = source {
+            snarl.connect(
+                source,
+                InPinId {
+                    node: composite_id,
+                    input: index,
+                },
+            );
+        }
+    }
+    for (index, remotes) in consumers.into_iter().enumerate() {
+        for remote in remotes {
+            snarl.connect(
+                OutPinId {
+                    node: composite_id,
+                    output: index,
+                },
+                remote,
+            );
+        }
+    }
+    clear_cache_downstream(snarl, &composite_id);
+    run_nodes(snarl);
+    Ok(composite_id)
+}
+
+/// Replaces a composite node by its nodes, wired like the composite was
+pub fn ungroup(snarl: &mut Snarl<EFNodeFNSerialized<'_>>, node_id: NodeId) -> Result<()> {
+    let definition = composite_definition(snarl, node_id)
+        .ok_or_else(|| anyhow!("Node #{} is not a composite", node_id.0))?
+        .clone();
+    let center = snarl
+        .get_node_info(node_id)
+        .map(|info| info.pos)
+        .unwrap_or_default();
+    let sources: Vec<Option<OutPinId>> = (0..definition.inputs.len())
+        .map(|input| {
+            snarl
+                .in_pin(InPinId {
+                    node: node_id,
+                    input,
+                })
+                .remotes
+                .first()
+                .copied()
+        })
+        .collect();
+    let consumers: Vec<Vec<InPinId>> = (0..definition.outputs.len())
+        .map(|output| {
+            snarl
+                .out_pin(OutPinId {
+                    node: node_id,
+                    output,
+                })
+                .remotes
+        })
+        .collect();
+
+    let ids = insert_inner_nodes(snarl, &definition.nodes, center)?;
+    forget_node(&node_id);
+    snarl.remove_node(node_id);
+    for (input, source) in definition.inputs.iter().zip(sources) {
+        let Some(source) = source else {
+            continue;
+        };
+        for target in &input.targets {
+            if let Some(node) = ids.get(&target.node) {
+                snarl.connect(
+                    source,
+                    InPinId {
+                        node: *node,
+                        input: target.pin,
+                    },
+                );
+            }
+        }
+    }
+    for (output, remotes) in definition.outputs.iter().zip(consumers) {
+        let Some(node) = ids.get(&output.source.node) else {
+            continue;
+        };
+        for remote in remotes {
+            snarl.connect(
+                OutPinId {
+                    node: *node,
+                    output: output.source.pin,
+                },
+                remote,
+            );
+        }
+    }
+    for id in ids.values() {
+        clear_cache_downstream(snarl, id);
+    }
+    run_nodes(snarl);
+    Ok(())
+}
+
+/// Adds a new instance of `definition`
+pub fn insert_composite(
+    snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+    definition: &CompositeDefinition,
+    pos: Pos2,
+) -> Result<NodeId> {
+    let node_id = snarl.insert_node(pos, composite_node(definition)?);
+    clear_cache_downstream(snarl, &node_id);
+    run_nodes(snarl);
+    Ok(node_id)
+}
+
+/// Swaps the definition of a composite node, wires to pins that no longer fit are dropped
+pub fn replace_definition(
+    snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+    node_id: NodeId,
+    definition: &CompositeDefinition,
+) -> Result<()> {
+    let node = composite_node(definition)?;
+    *snarl
+        .get_node_mut(node_id)
+        .ok_or_else(|| anyhow!("Node #{} not found", node_id.0))? = node;
+    drop_mismatched_wires(snarl, node_id);
+    forget_node(&node_id);
+    clear_cache_downstream(snarl, &node_id);
+    run_nodes(snarl);
+    Ok(())
+}
+
+/// The inside of a composite as a graph of its own, for editing. Ids of pins are translated
+/// to the node ids of that graph.
+pub fn definition_to_snarl(
+    definition: &CompositeDefinition,
+) -> Result<(
+    Snarl<EFNodeFNSerialized<'static>>,
+    Vec<CompositeInput>,
+    Vec<CompositeOutput>,
+)> {
+    let mut snarl = Snarl::new();
+    let ids = insert_inner_nodes(&mut snarl, &definition.nodes, Pos2::ZERO)?;
+    let translate = |pin: &InnerPin| {
+        ids.get(&pin.node).map(|node| InnerPin {
+            node: node.0,
+            pin: pin.pin,
+        })
+    };
+    let inputs = definition
+        .inputs
+        .iter()
+        .map(|input| CompositeInput {
+            targets: input.targets.iter().filter_map(translate).collect(),
+            ..input.clone()
+        })
+        .collect();
+    let outputs = definition
+        .outputs
+        .iter()
+        .filter_map(|output| {
+            Some(CompositeOutput {
+                source: translate(&output.source)?,
+                ..output.clone()
+            })
+        })
+        .collect();
+    Ok((snarl, inputs, outputs))
+}
+
+/// Builds a definition from an edited graph. Inputs keep their names while their targets
+/// stay unconnected and every other unconnected input gets a pin of its own; outputs whose
+/// node or pin is gone are dropped.
+pub fn definition_from_snarl(
+    snarl: &Snarl<EFNodeFNSerialized<'_>>,
+    name: &str,
+    inputs: &[CompositeInput],
+    outputs: &[CompositeOutput],
+) -> Result<CompositeDefinition> {
+    if name.trim().is_empty() {
+        return Err(anyhow!("The composite needs a name"));
+    }
+    let ids: Vec<NodeId> = snarl.node_ids().map(|(id, _)| id).collect();
+    if ids.is_empty() {
+        return Err(anyhow!("A composite needs at least one node"));
+    }
+    let (nodes, _) = inner_nodes(snarl, &ids);
+    let input_type = |pin: &InnerPin| {
+        let node = snarl.get_node(NodeId(pin.node))?.get_node();
+        node.get_inputs()
+            .get(pin.pin)
+            .map(|(_, data_type)| *data_type)
+    };
+    let output_type = |pin: &InnerPin| {
+        let node = snarl.get_node(NodeId(pin.node))?.get_node();
+        node.get_outputs()
+            .get(pin.pin)
+            .map(|(_, data_type)| *data_type)
+    };
+    let open: Vec<InnerPin> = nodes
+        .iter()
+        .flat_map(|node| {
+            node.inputs
+                .iter()
+                .enumerate()
+                .filter(|(_, remote)| remote.is_none())
+                .map(|(input, _)| InnerPin {
+                    node: node.id,
+                    pin: input,
+                })
+        })
+        .collect();
+
+    let mut new_inputs: Vec<CompositeInput> = inputs
+        .iter()
+        .map(|input| CompositeInput {
+            targets: input
+                .targets
+                .iter()
+                .filter(|target| {
+                    open.contains(target) && input_type(target) == Some(input.data_type)
+                })
+                .copied()
+                .collect(),
+            ..input.clone()
+        })
+        .filter(|input| !input.targets.is_empty())
+        .collect();
+    for pin in open {
+        if new_inputs.iter().any(|input| input.targets.contains(&pin)) {
+            continue;
+        }
+        let (label, data_type) = snarl[NodeId(pin.node)].get_node().get_inputs()[pin.pin];
+        new_inputs.push(CompositeInput {
+            name: label.to_owned(),
+            data_type,
+            targets: vec![pin],
+        });
+    }
+    let new_outputs = outputs
+        .iter()
+        .filter(|output| output_type(&output.source) == Some(output.data_type))
+        .cloned()
+        .collect();
+    Ok(CompositeDefinition {
+        name: name.trim().to_owned(),
+        nodes,
+        inputs: new_inputs,
+        outputs: new_outputs,
+    })
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use egui::{Color32, Grid, Pos2, ScrollArea, Ui};
+use egui_snarl::{NodeId, Snarl};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    composite::{
+        collapse, composite_definition, definition_from_snarl, definition_to_snarl,
+        insert_composite, replace_definition, ungroup,
+    },
+    history::History,
+    node_trait::EFNodeFNSerialized,
+    nodes::composite::{CompositeDefinition, CompositeInput, CompositeOutput, InnerPin},
+    style::default_style,
+    EFViewer,
+};
+
+/// The inside of a composite node, edited in a graph of its own until it is applied
+struct CompositeEditor {
+    node_id: NodeId,
+    name: String,
+    snarl: Snarl<EFNodeFNSerialized<'static>>,
+    inputs: Vec<CompositeInput>,
+    outputs: Vec<CompositeOutput>,
+}
+
+enum Action {
+    Collapse,
+    Edit(NodeId),
+    Ungroup(NodeId),
+    Save(CompositeDefinition),
+    Insert(String),
+    Delete(String),
+}
+
+/// Collapses selected nodes into composites, edits them and keeps a library of them
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompositePanel {
+    #[serde(skip)]
+    pub open: bool,
+    /// Saved composites by name, persisted with the app
+    pub library: BTreeMap<String, CompositeDefinition>,
+    #[serde(skip)]
+    name: String,
+    #[serde(skip)]
+    editor: Option<CompositeEditor>,
+    #[serde(skip)]
+    message: Option<Result<String, String>>,
+}
+
+impl CompositePanel {
+    pub fn is_editing(&self) -> bool {
+        self.editor.is_some()
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+        selected: &[NodeId],
+        history: &mut History,
+    ) {
+        let mut open = self.open;
+        let mut action = None;
+        egui::Window::new("Composites")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    ui.heading("Collapse selection");
+                    ui.horizontal(|ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut self.name);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} nodes selected", selected.len()));
+                        if ui.button("Collapse").clicked() {
+                            action = Some(Action::Collapse);
+                        }
+                    });
+                    ui.separator();
+                    ui.heading("In the graph");
+                    Self::show_instances(ui, snarl, &mut action);
+                    ui.separator();
+                    ui.heading("Library");
+                    self.show_library(ui, &mut action);
+                    if let Some(message) = &self.message {
+                        match message {
+                            Ok(message) => ui.label(message),
+                            Err(error) => ui.colored_label(Color32::RED, error),
+                        };
+                    }
+                });
+            });
+        self.open = open;
+        if let Some(action) = action {
+            let result = history.record(snarl, |snarl| self.run(action, snarl, selected));
+            self.message = Some(result.map_err(|e| e.to_string()));
+        }
+        self.show_editor(ctx, snarl, history);
+    }
+
+    fn show_instances(
+        ui: &mut Ui,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        action: &mut Option<Action>,
+    ) {
+        let instances: Vec<(NodeId, &CompositeDefinition)> = snarl
+            .node_ids()
+            .filter_map(|(id, _)| Some((id, composite_definition(snarl, id)?)))
+            .collect();
+        if instances.is_empty() {
+            ui.label("Select nodes with Ctrl+click and collapse them to create a composite.");
+            return;
+        }
+        Grid::new("composite_instances").show(ui, |ui| {
+            for (id, definition) in instances {
+                ui.label(format!("{} #{}", definition.name, id.0));
+                if ui.button("Edit").clicked() {
+                    *action = Some(Action::Edit(id));
+                }
+                if ui.button("Ungroup").clicked() {
+                    *action = Some(Action::Ungroup(id));
+                }
+                if ui.button("Save to library").clicked() {
+                    *action = Some(Action::Save(definition.clone()));
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn show_library(&self, ui: &mut Ui, action: &mut Option<Action>) {
+        if self.library.is_empty() {
+            ui.label("No saved composites");
+            return;
+        }
+        Grid::new("composite_library").show(ui, |ui| {
+            for (name, definition) in &self.library {
+                ui.label(name).on_hover_text(format!(
+                    "{} nodes, {} inputs, {} outputs",
+                    definition.nodes.len(),
+                    definition.inputs.len(),
+                    definition.outputs.len()
+                ));
+                if ui.button("Insert").clicked() {
+                    *action = Some(Action::Insert(name.clone()));
+                }
+                if ui.button("Delete").clicked() {
+                    *action = Some(Action::Delete(name.clone()));
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn run(
+        &mut self,
+        action: Action,
+        snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+        selected: &[NodeId],
+    ) -> Result<String> {
+        match action {
+            Action::Collapse => {
+                let node_id = collapse(snarl, selected, &self.name)?;
+                Ok(format!(
+                    "Collapsed {} nodes into #{}",
+                    selected.len(),
+                    node_id.0
+                ))
+            }
+            Action::Edit(node_id) => {
+                let Some(definition) = composite_definition(snarl, node_id) else {
+                    return Ok(String::new());
+                };
+                let (inner, inputs, outputs) = definition_to_snarl(definition)?;
+                self.editor = Some(CompositeEditor {
+                    node_id,
+                    name: definition.name.clone(),
+                    snarl: inner,
+                    inputs,
+                    outputs,
+                });
+                Ok(format!("Editing {}", definition.name))
+            }
+            Action::Ungroup(node_id) => {
+                ungroup(snarl, node_id)?;
+                Ok(format!("Ungrouped #{}", node_id.0))
+            }
+            Action::Save(definition) => {
+                let name = definition.name.clone();
+                self.library.insert(name.clone(), definition);
+                Ok(format!("Saved {}", name))
+            }
+            Action::Insert(name) => {
+                let Some(definition) = self.library.get(&name) else {
+                    return Ok(String::new());
+                };
+                // Right of everything else, where it can't cover other nodes
+                let right = snarl
+                    .nodes_pos_ids()
+                    .map(|(_, pos, _)| pos.x)
+                    .fold(0.0, f32::max);
+                let node_id = insert_composite(snarl, definition, Pos2::new(right + 200.0, 0.0))?;
+                Ok(format!("Inserted {} as #{}", name, node_id.0))
+            }
+            Action::Delete(name) => {
+                self.library.remove(&name);
+                Ok(format!("Deleted {}", name))
+            }
+        }
+    }
+
+    fn show_editor(
+        &mut self,
+        ctx: &egui::Context,
+        snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+        history: &mut History,
+    ) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        let mut open = true;
+        let mut apply = false;
+        egui::Window::new(format!("Edit composite #{}", editor.node_id.0))
+            .id(egui::Id::new(("composite_editor", editor.node_id)))
+            .open(&mut open)
+            .default_size([720.0, 480.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut editor.name);
+                    if ui.button("Apply").clicked() {
+                        apply = true;
+                    }
+                });
+                ui.collapsing("Pins", |ui| {
+                    Self::show_pins(ui, editor);
+                });
+                ui.label("Unconnected inputs of the nodes become inputs of the composite.");
+                editor.snarl.show(
+                    &mut EFViewer {
+                        detached: true,
+                        ..Default::default()
+                    },
+                    &default_style(),
+                    ("composite_editor_snarl", editor.node_id),
+                    ui,
+                );
+            });
+        if apply {
+            let result =
+                definition_from_snarl(&editor.snarl, &editor.name, &editor.inputs, &editor.outputs)
+                    .and_then(|definition| {
+                        history.record(snarl, |snarl| {
+                            replace_definition(snarl, editor.node_id, &definition)
+                        })?;
+                        Ok(format!("Updated {}", definition.name))
+                    });
+            open &= result.is_err();
+            self.message = Some(result.map_err(|e| e.to_string()));
+        }
+        if !open {
+            self.editor = None;
+        }
+    }
+
+    fn show_pins(ui: &mut Ui, editor: &mut CompositeEditor) {
+        Grid::new("composite_pins").show(ui, |ui| {
+            for input in &mut editor.inputs {
+                ui.label("Input");
+                ui.text_edit_singleline(&mut input.name);
+                ui.label(format!("{:?}", input.data_type));
+                ui.end_row();
+            }
+            let mut removed = None;
+            for (index, output) in editor.outputs.iter_mut().enumerate() {
+                ui.label("Output");
+                ui.text_edit_singleline(&mut output.name);
+                ui.label(format!("{:?}", output.data_type));
+                if ui.button("Remove").clicked() {
+                    removed = Some(index);
+                }
+                ui.end_row();
+            }
+            if let Some(index) = removed {
+                editor.outputs.remove(index);
+            }
+        });
+        ui.menu_button("Expose output", |ui| {
+            for (id, node) in editor.snarl.node_ids() {
+                let node = node.get_node();
+                for (pin, (label, data_type)) in node.get_outputs().iter().enumerate() {
+                    let source = InnerPin { node: id.0, pin };
+                    if editor.outputs.iter().any(|output| output.source == source) {
+                        continue;
+                    }
+                    let text = format!("{} #{}: {}", node.get_name(), id.0, label);
+                    if ui.button(text).clicked() {
+                        editor.outputs.push(CompositeOutput {
+                            name: label.to_string(),
+                            data_type: *data_type,
+                            source,
+                        });
+                        ui.close_menu();
+                    }
+                }
+            }
+        });
+    }
+}
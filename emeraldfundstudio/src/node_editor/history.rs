@@ -0,0 +1,654 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use egui::Pos2;
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use log::error;
+use serde_json::Value;
+use web_time::Instant;
+
+use super::node_trait::{EFNodeFNSerialized, NodeDataType};
+use crate::node_runners::realtime::{clear_cache_downstream, forget_node, run_nodes};
+
+/// Undo steps kept, older ones are dropped
+const MAX_STEPS: usize = 100;
+/// Edits of one node's arguments closer together than this are one step, so typing a value
+/// isn't undone a character at a time
+const COALESCE: Duration = Duration::from_secs(1);
+
+/// Identifies a node in the history. Node ids can't, a node put back by undo gets a new one.
+type NodeKey = u64;
+type PinKey = (NodeKey, usize);
+
+/// Everything needed to put a node back
+#[derive(Clone)]
+struct StoredNode {
+    node_name: String,
+    arguments: Value,
+    version: u32,
+    pos: Pos2,
+    /// Pins of the node, for a placeholder if it doesn't load anymore
+    inputs: Vec<NodeDataType>,
+    outputs: Vec<NodeDataType>,
+}
+
+impl StoredNode {
+    fn new(node: &EFNodeFNSerialized<'_>, pos: Pos2) -> Self {
+        let loaded = node.get_node();
+        Self {
+            node_name: node.node_name.to_string(),
+            arguments: loaded.export_data(),
+            version: node.version,
+            pos,
+            inputs: loaded.get_inputs().iter().map(|(_, t)| *t).collect(),
+            outputs: loaded.get_outputs().iter().map(|(_, t)| *t).collect(),
+        }
+    }
+
+    fn insert(&self, snarl: &mut Snarl<EFNodeFNSerialized<'_>>) -> NodeId {
+        let mut node = EFNodeFNSerialized {
+            node_name: self.node_name.clone().into(),
+            arguments: self.arguments.clone(),
+            version: self.version,
+            loaded_node: None,
+        };
+        if let Err(e) = node.load_or_placeholder(self.inputs.clone(), self.outputs.clone()) {
+            error!("{} can't be loaded: {}", self.node_name, e);
+        }
+        snarl.insert_node(self.pos, node)
+    }
+}
+
+/// What changed in the arguments of a node. Objects keep only the top-level fields that
+/// changed, `None` where a field is missing, anything else is kept whole.
+#[derive(Clone)]
+enum ArgumentsDiff {
+    Fields(Vec<(String, Option<Value>, Option<Value>)>),
+    Whole(Value, Value),
+}
+
+impl ArgumentsDiff {
+    fn new(before: &Value, after: &Value) -> Option<Self> {
+        if before == after {
+            return None;
+        }
+        match (before.as_object(), after.as_object()) {
+            (Some(before), Some(after)) => {
+                let fields = before
+                    .keys()
+                    .chain(after.keys().filter(|key| !before.contains_key(*key)))
+                    .filter(|key| before.get(*key) != after.get(*key))
+                    .map(|key| {
+                        (
+                            key.clone(),
+                            before.get(key).cloned(),
+                            after.get(key).cloned(),
+                        )
+                    })
+                    .collect();
+                Some(Self::Fields(fields))
+            }
+            _ => Some(Self::Whole(before.clone(), after.clone())),
+        }
+    }
+
+    /// `arguments` with the change made, or reverted when `forward` is false
+    fn apply(&self, arguments: &Value, forward: bool) -> Value {
+        match self {
+            Self::Whole(before, after) => if forward { after } else { before }.clone(),
+            Self::Fields(fields) => {
+                let mut arguments = arguments.clone();
+                if let Some(object) = arguments.as_object_mut() {
+                    for (key, before, after) in fields {
+                        match if forward { after } else { before } {
+                            Some(value) => object.insert(key.clone(), value.clone()),
+                            None => object.remove(key),
+                        };
+                    }
+                }
+                arguments
+            }
+        }
+    }
+
+    /// Folds `next`, which happened after this change, into it
+    fn merge(&mut self, next: &ArgumentsDiff) -> bool {
+        match (self, next) {
+            (Self::Whole(_, after), Self::Whole(_, next_after)) => {
+                *after = next_after.clone();
+                true
+            }
+            (Self::Fields(fields), Self::Fields(next_fields)) => {
+                for (key, before, after) in next_fields {
+                    match fields.iter_mut().find(|(field, _, _)| field == key) {
+                        Some((_, _, merged)) => *merged = after.clone(),
+                        None => fields.push((key.clone(), before.clone(), after.clone())),
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Change {
+    Insert { key: NodeKey, node: StoredNode },
+    Remove { key: NodeKey, node: StoredNode },
+    Move { key: NodeKey, from: Pos2, to: Pos2 },
+    Connect { from: PinKey, to: PinKey },
+    Disconnect { from: PinKey, to: PinKey },
+    Arguments { key: NodeKey, diff: ArgumentsDiff },
+}
+
+/// What is undone at once, the changes in the order they were made
+struct Step {
+    changes: Vec<Change>,
+    time: Instant,
+}
+
+impl Step {
+    /// Merges `changes` into this step when both only edit the arguments of the same node
+    fn merge(&mut self, changes: &[Change]) -> bool {
+        match (self.changes.as_mut_slice(), changes) {
+            (
+                [Change::Arguments { key, diff }],
+                [Change::Arguments {
+                    key: next_key,
+                    diff: next,
+                }],
+            ) if *key == *next_key => diff.merge(next),
+            _ => false,
+        }
+    }
+}
+
+struct Snapshot {
+    nodes: HashMap<NodeId, StoredNode>,
+    wires: Vec<(OutPinId, InPinId)>,
+}
+
+impl Snapshot {
+    fn new(snarl: &Snarl<EFNodeFNSerialized<'_>>) -> Self {
+        Self {
+            nodes: snarl
+                .nodes_pos_ids()
+                .map(|(id, pos, node)| (id, StoredNode::new(node, pos)))
+                .collect(),
+            wires: snarl.wires().collect(),
+        }
+    }
+}
+
+/// Undo and redo for the edits of a graph. Structural edits go through `record`, changes
+/// made in node headers through `arguments_changed`, and moves are picked up by `end_frame`,
+/// which also turns everything that changed in a frame into one step.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    /// Changes of this frame
+    pending: Vec<Change>,
+    keys: HashMap<NodeId, NodeKey>,
+    ids: HashMap<NodeKey, NodeId>,
+    next_key: NodeKey,
+    /// Where nodes were when their last move was recorded
+    positions: HashMap<NodeId, Pos2>,
+}
+
+impl History {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || !self.pending.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forgets everything, for when the graph is replaced
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn key(&mut self, id: NodeId) -> NodeKey {
+        match self.keys.get(&id) {
+            Some(key) => *key,
+            None => self.new_key(id),
+        }
+    }
+
+    fn new_key(&mut self, id: NodeId) -> NodeKey {
+        let key = self.next_key;
+        self.next_key += 1;
+        self.bind(key, id);
+        key
+    }
+
+    fn bind(&mut self, key: NodeKey, id: NodeId) {
+        if let Some(old) = self.keys.insert(id, key) {
+            self.ids.remove(&old);
+        }
+        self.ids.insert(key, id);
+    }
+
+    fn unbind(&mut self, key: NodeKey) {
+        if let Some(id) = self.ids.remove(&key) {
+            self.keys.remove(&id);
+        }
+    }
+
+    fn id(&self, key: NodeKey) -> Result<NodeId> {
+        self.ids
+            .get(&key)
+            .copied()
+            .ok_or_else(|| anyhow!("A node of the step is not in the graph"))
+    }
+
+    fn pins(&self, from: PinKey, to: PinKey) -> Result<(OutPinId, InPinId)> {
+        Ok((
+            OutPinId {
+                node: self.id(from.0)?,
+                output: from.1,
+            },
+            InPinId {
+                node: self.id(to.0)?,
+                input: to.1,
+            },
+        ))
+    }
+
+    /// Runs `edit` on the graph and records what it changed
+    pub fn record<'a, R>(
+        &mut self,
+        snarl: &mut Snarl<EFNodeFNSerialized<'a>>,
+        edit: impl FnOnce(&mut Snarl<EFNodeFNSerialized<'a>>) -> R,
+    ) -> R {
+        let before = Snapshot::new(snarl);
+        let result = edit(snarl);
+        let after = Snapshot::new(snarl);
+        self.diff(snarl, before, after);
+        result
+    }
+
+    fn diff(
+        &mut self,
+        snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+        before: Snapshot,
+        after: Snapshot,
+    ) {
+        // An id that holds another kind of node now was removed and reused
+        let kept = |id: &NodeId| {
+            matches!(
+                (before.nodes.get(id), after.nodes.get(id)),
+                (Some(old), Some(new)) if old.node_name == new.node_name
+            )
+        };
+        let old_keys: HashMap<NodeId, NodeKey> =
+            before.nodes.keys().map(|id| (*id, self.key(*id))).collect();
+        let removed: Vec<NodeId> = before
+            .nodes
+            .keys()
+            .filter(|id| !kept(id))
+            .copied()
+            .collect();
+        for id in &removed {
+            self.unbind(old_keys[id]);
+            self.positions.remove(id);
+        }
+        let new_keys: HashMap<NodeId, NodeKey> = after
+            .nodes
+            .keys()
+            .map(|id| {
+                let key = if kept(id) {
+                    old_keys[id]
+                } else {
+                    self.new_key(*id)
+                };
+                (*id, key)
+            })
+            .collect();
+        let wires = |keys: &HashMap<NodeId, NodeKey>, wires: &[(OutPinId, InPinId)]| {
+            wires
+                .iter()
+                .filter_map(|(out_pin, in_pin)| {
+                    let from = (*keys.get(&out_pin.node)?, out_pin.output);
+                    let to = (*keys.get(&in_pin.node)?, in_pin.input);
+                    Some((from, to))
+                })
+                .collect::<HashSet<(PinKey, PinKey)>>()
+        };
+        let old_wires = wires(&old_keys, &before.wires);
+        let new_wires = wires(&new_keys, &after.wires);
+
+        // Wires go before their nodes and come back after them
+        for (from, to) in old_wires.difference(&new_wires) {
+            self.pending.push(Change::Disconnect {
+                from: *from,
+                to: *to,
+            });
+        }
+        for id in &removed {
+            self.pending.push(Change::Remove {
+                key: old_keys[id],
+                node: before.nodes[id].clone(),
+            });
+        }
+        for (id, node) in &after.nodes {
+            let key = new_keys[id];
+            match before.nodes.get(id).filter(|_| kept(id)) {
+                None => self.pending.push(Change::Insert {
+                    key,
+                    node: node.clone(),
+                }),
+                Some(old) => {
+                    if let Some(diff) = ArgumentsDiff::new(&old.arguments, &node.arguments) {
+                        self.pending.push(Change::Arguments { key, diff });
+                    }
+                    if old.pos != node.pos {
+                        self.pending.push(Change::Move {
+                            key,
+                            from: old.pos,
+                            to: node.pos,
+                        });
+                    }
+                }
+            }
+            self.positions.insert(*id, node.pos);
+            // Later edits in the header are diffed against this
+            snarl[*id].arguments = node.arguments.clone();
+        }
+        for (from, to) in new_wires.difference(&old_wires) {
+            self.pending.push(Change::Connect {
+                from: *from,
+                to: *to,
+            });
+        }
+    }
+
+    /// Records the change of a node's arguments made in its header. The arguments the node
+    /// was loaded or last recorded with are the ones before.
+    pub fn arguments_changed(&mut self, snarl: &mut Snarl<EFNodeFNSerialized<'_>>, id: NodeId) {
+        let Some(node) = snarl.get_node_mut(id) else {
+            return;
+        };
+        let arguments = node.get_node().export_data();
+        let Some(diff) = ArgumentsDiff::new(&node.arguments, &arguments) else {
+            return;
+        };
+        node.arguments = arguments;
+        let key = self.key(id);
+        self.pending.push(Change::Arguments { key, diff });
+    }
+
+    /// Call once a frame after the graph was shown. Records moves once the pointer is
+    /// released, so dragging a node is one step, and closes the step of the frame.
+    pub fn end_frame(&mut self, ctx: &egui::Context, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+        // Nodes removed without being recorded, like by File > Reset
+        let live: HashSet<NodeId> = snarl.node_ids().map(|(id, _)| id).collect();
+        let gone: Vec<NodeKey> = self
+            .ids
+            .iter()
+            .filter(|(_, id)| !live.contains(id))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in gone {
+            self.unbind(key);
+        }
+        self.positions.retain(|id, _| live.contains(id));
+
+        let dragging = ctx.input(|i| i.pointer.any_down());
+        for (id, pos, _) in snarl.nodes_pos_ids() {
+            match self.positions.get(&id).copied() {
+                Some(from) if from != pos && !dragging => {
+                    let key = self.key(id);
+                    self.pending.push(Change::Move { key, from, to: pos });
+                    self.positions.insert(id, pos);
+                }
+                Some(_) => {}
+                None => {
+                    self.positions.insert(id, pos);
+                }
+            }
+        }
+        self.commit();
+    }
+
+    fn commit(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let changes = std::mem::take(&mut self.pending);
+        let now = Instant::now();
+        self.redo.clear();
+        if let Some(last) = self.undo.last_mut() {
+            if now.duration_since(last.time) < COALESCE && last.merge(&changes) {
+                last.time = now;
+                return;
+            }
+        }
+        self.undo.push(Step { changes, time: now });
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Reverts the last step and reruns what it touched, returns whether there was one
+    pub fn undo(&mut self, snarl: &mut Snarl<EFNodeFNSerialized<'_>>) -> bool {
+        self.commit();
+        let Some(step) = self.undo.pop() else {
+            return false;
+        };
+        let result = self.apply(snarl, &step, false);
+        if self.finish(snarl, result) {
+            self.redo.push(step);
+            return true;
+        }
+        false
+    }
+
+    /// Makes the last undone step again, returns whether there was one
+    pub fn redo(&mut self, snarl: &mut Snarl<EFNodeFNSerialized<'_>>) -> bool {
+        self.commit();
+        let Some(step) = self.redo.pop() else {
+            return false;
+        };
+        let result = self.apply(snarl, &step, true);
+        if self.finish(snarl, result) {
+            self.undo.push(step);
+            return true;
+        }
+        false
+    }
+
+    /// Clears the results of the nodes a step touched and of those depending on them
+    fn finish(
+        &mut self,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        result: Result<Vec<NodeId>>,
+    ) -> bool {
+        let ok = match result {
+            Ok(touched) => {
+                for id in touched {
+                    forget_node(&id);
+                    clear_cache_downstream(snarl, &id);
+                }
+                true
+            }
+            Err(e) => {
+                // Something changed the graph without recording it
+                error!("Undo history doesn't match the graph anymore: {}", e);
+                self.clear();
+                false
+            }
+        };
+        run_nodes(snarl);
+        ok
+    }
+
+    /// Makes the changes of `step`, or reverts them in reverse order, and returns the nodes
+    /// whose results are outdated
+    fn apply(
+        &mut self,
+        snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+        step: &Step,
+        forward: bool,
+    ) -> Result<Vec<NodeId>> {
+        let mut changes: Vec<&Change> = step.changes.iter().collect();
+        if !forward {
+            changes.reverse();
+        }
+        let mut touched = vec![];
+        for change in changes {
+            match (change, forward) {
+                (Change::Insert { key, node }, true) | (Change::Remove { key, node }, false) => {
+                    let id = node.insert(snarl);
+                    self.bind(*key, id);
+                    self.positions.insert(id, node.pos);
+                    touched.push(id);
+                }
+                (Change::Remove { key, .. }, true) | (Change::Insert { key, .. }, false) => {
+                    let id = self.id(*key)?;
+                    forget_node(&id);
+                    snarl.remove_node(id);
+                    self.unbind(*key);
+                    self.positions.remove(&id);
+                }
+                (Change::Move { key, from, to }, forward) => {
+                    let id = self.id(*key)?;
+                    let pos = if forward { *to } else { *from };
+                    if let Some(info) = snarl.get_node_info_mut(id) {
+                        info.pos = pos;
+                    }
+                    self.positions.insert(id, pos);
+                }
+                (Change::Connect { from, to }, true) | (Change::Disconnect { from, to }, false) => {
+                    let (out_pin, in_pin) = self.pins(*from, *to)?;
+                    snarl.connect(out_pin, in_pin);
+                    touched.push(in_pin.node);
+                }
+                (Change::Disconnect { from, to }, true) | (Change::Connect { from, to }, false) => {
+                    let (out_pin, in_pin) = self.pins(*from, *to)?;
+                    snarl.disconnect(out_pin, in_pin);
+                    touched.push(in_pin.node);
+                }
+                (Change::Arguments { key, diff }, forward) => {
+                    let id = self.id(*key)?;
+                    let node = snarl
+                        .get_node_mut(id)
+                        .ok_or_else(|| anyhow!("Node #{} not found", id.0))?;
+                    let previous = node.arguments.clone();
+                    node.arguments = diff.apply(&previous, forward);
+                    if let Err(e) = node.load_node() {
+                        node.arguments = previous;
+                        return Err(e);
+                    }
+                    touched.push(id);
+                }
+            }
+        }
+        Ok(touched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_editor::nodes::sma::SMANode;
+
+    fn sma(period: usize) -> EFNodeFNSerialized<'static> {
+        let mut node = EFNodeFNSerialized {
+            node_name: "SMANode".into(),
+            arguments: serde_json::json!({ "period": period }),
+            version: 0,
+            loaded_node: None,
+        };
+        node.load_node().unwrap();
+        node
+    }
+
+    fn period(snarl: &Snarl<EFNodeFNSerialized<'_>>, id: NodeId) -> u64 {
+        snarl[id].get_node().export_data()["period"]
+            .as_u64()
+            .unwrap()
+    }
+
+    /// The periods of the nodes and the wires between them, node ids change with undo
+    fn graph(snarl: &Snarl<EFNodeFNSerialized<'_>>) -> (Vec<u64>, Vec<(u64, u64)>) {
+        let mut nodes: Vec<u64> = snarl.node_ids().map(|(id, _)| period(snarl, id)).collect();
+        nodes.sort();
+        let mut wires: Vec<(u64, u64)> = snarl
+            .wires()
+            .map(|(out_pin, in_pin)| (period(snarl, out_pin.node), period(snarl, in_pin.node)))
+            .collect();
+        wires.sort();
+        (nodes, wires)
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_graph() {
+        let ctx = egui::Context::default();
+        let mut snarl = Snarl::new();
+        let first = snarl.insert_node(Pos2::ZERO, sma(5));
+        let mut history = History::default();
+        let mut states = vec![graph(&snarl)];
+
+        let second = history.record(&mut snarl, |snarl| {
+            snarl.insert_node(Pos2::new(200.0, 0.0), sma(10))
+        });
+        history.end_frame(&ctx, &snarl);
+        states.push(graph(&snarl));
+        history.record(&mut snarl, |snarl| {
+            snarl.connect(
+                OutPinId {
+                    node: first,
+                    output: 0,
+                },
+                InPinId {
+                    node: second,
+                    input: 0,
+                },
+            )
+        });
+        history.end_frame(&ctx, &snarl);
+        states.push(graph(&snarl));
+        history.record(&mut snarl, |snarl| snarl.remove_node(first));
+        history.end_frame(&ctx, &snarl);
+        states.push(graph(&snarl));
+        assert_eq!(states[2], (vec![5, 10], vec![(5, 10)]));
+        assert_eq!(states[3], (vec![10], vec![]));
+
+        for state in states[..3].iter().rev() {
+            assert!(history.undo(&mut snarl));
+            assert_eq!(graph(&snarl), *state);
+        }
+        assert!(!history.can_undo());
+        for state in &states[1..] {
+            assert!(history.redo(&mut snarl));
+            assert_eq!(graph(&snarl), *state);
+        }
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn quick_argument_edits_are_one_step() {
+        let ctx = egui::Context::default();
+        let mut snarl = Snarl::new();
+        let id = snarl.insert_node(Pos2::ZERO, sma(5));
+        let mut history = History::default();
+        for period in [6, 7] {
+            snarl[id].loaded_node = Some(Box::new(SMANode { period }));
+            history.arguments_changed(&mut snarl, id);
+            history.end_frame(&ctx, &snarl);
+        }
+        assert_eq!(history.undo.len(), 1);
+        assert!(history.undo(&mut snarl));
+        assert_eq!(period(&snarl, id), 5);
+        assert!(!history.can_undo());
+        assert!(history.redo(&mut snarl));
+        assert_eq!(period(&snarl, id), 7);
+    }
+}
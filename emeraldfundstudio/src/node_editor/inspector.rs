@@ -0,0 +1,372 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::DateTime;
+use egui::{
+    CollapsingHeader, Color32, Grid, Label, RichText, ScrollArea, Sense, Shape, Stroke, Ui,
+};
+use egui_snarl::{NodeId, Snarl};
+use polars::prelude::DataFrame;
+
+use super::node_trait::{
+    CheapCloneNodeDataTypeWithValue, EFNodeFNSerialized, NodeDataType, NodeDataTypeWithValue,
+};
+use crate::{
+    node_runners::realtime::NODE_COMPUTE_CACHE,
+    types::{time_index::TimeIndex, timestamp::TimeStamp},
+};
+
+const CANDLE_FIELDS: [&str; 5] = ["open", "high", "low", "close", "volume"];
+/// Last values drawn by a sparkline
+const SPARKLINE_POINTS: usize = 200;
+const TIME_WIDTH: f32 = 110.0;
+const VALUE_WIDTH: f32 = 80.0;
+
+fn format_time(timestamp: TimeStamp) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|datetime| datetime.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn candle_column(df: &DataFrame, field: &str) -> Result<Vec<Option<f64>>> {
+    Ok(df.column(field)?.f64()?.into_iter().collect())
+}
+
+/// Values of an output by name, candles have a column per field
+type Columns = Vec<(&'static str, Vec<Option<f64>>)>;
+
+/// The values of an output as numbers, masks are 1 or 0 and signals 1, 0 or -1
+fn columns(value: &NodeDataTypeWithValue) -> Result<Columns> {
+    Ok(match value {
+        NodeDataTypeWithValue::DecimalSequence(seq) => {
+            vec![("value", seq.values.iter().collect())]
+        }
+        NodeDataTypeWithValue::Mask(mask) => vec![(
+            "value",
+            mask.values
+                .iter()
+                .map(|value| Some(if *value { 1.0 } else { 0.0 }))
+                .collect(),
+        )],
+        NodeDataTypeWithValue::Signal(signal) => vec![(
+            "value",
+            signal
+                .values
+                .iter()
+                .map(|value| Some(*value as f64))
+                .collect(),
+        )],
+        NodeDataTypeWithValue::Candles(candles) => CANDLE_FIELDS
+            .iter()
+            .map(|field| Ok((*field, candle_column(&candles.df, field)?)))
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn format_value(data_type: NodeDataType, value: Option<f64>) -> String {
+    match (data_type, value) {
+        (_, None) => "null".to_owned(),
+        (NodeDataType::Mask, Some(value)) => (value != 0.0).to_string(),
+        (NodeDataType::Signal, Some(value)) if value > 0.0 => "buy".to_owned(),
+        (NodeDataType::Signal, Some(value)) if value < 0.0 => "sell".to_owned(),
+        (NodeDataType::Signal, Some(_)) => "-".to_owned(),
+        (_, Some(value)) => format!("{:.4}", value),
+    }
+}
+
+/// Summary of the values of an output, of the closes for candles
+#[derive(Default)]
+struct Stats {
+    count: usize,
+    nulls: usize,
+    min: Option<f64>,
+    max: Option<f64>,
+    mean: Option<f64>,
+    /// Rows that are true, or buy signals
+    positives: usize,
+    /// Rows that are sell signals
+    negatives: usize,
+}
+
+impl Stats {
+    fn new(values: &[Option<f64>]) -> Self {
+        let mut stats = Self {
+            count: values.len(),
+            ..Default::default()
+        };
+        let mut sum = 0.0;
+        let mut finite = 0;
+        for value in values {
+            let Some(value) = value.filter(|value| !value.is_nan()) else {
+                stats.nulls += 1;
+                continue;
+            };
+            if value > 0.0 {
+                stats.positives += 1;
+            } else if value < 0.0 {
+                stats.negatives += 1;
+            }
+            if value.is_finite() {
+                stats.min = Some(stats.min.map_or(value, |min| min.min(value)));
+                stats.max = Some(stats.max.map_or(value, |max| max.max(value)));
+                sum += value;
+                finite += 1;
+            }
+        }
+        if finite > 0 {
+            stats.mean = Some(sum / finite as f64);
+        }
+        stats
+    }
+
+    fn show(&self, ui: &mut Ui, id_salt: impl std::hash::Hash, data_type: NodeDataType) {
+        let number = |value: Option<f64>| value.map_or("-".to_owned(), |v| format!("{:.4}", v));
+        Grid::new(id_salt).num_columns(2).show(ui, |ui| {
+            ui.label("Count");
+            ui.label(self.count.to_string());
+            ui.end_row();
+            ui.label("Nulls");
+            ui.label(self.nulls.to_string());
+            ui.end_row();
+            match data_type {
+                NodeDataType::Mask => {
+                    ui.label("True");
+                    ui.label(self.positives.to_string());
+                    ui.end_row();
+                }
+                NodeDataType::Signal => {
+                    ui.label("Buy");
+                    ui.label(self.positives.to_string());
+                    ui.end_row();
+                    ui.label("Sell");
+                    ui.label(self.negatives.to_string());
+                    ui.end_row();
+                }
+                NodeDataType::DecimalSequence | NodeDataType::Candles => {
+                    ui.label("Min");
+                    ui.label(number(self.min));
+                    ui.end_row();
+                    ui.label("Max");
+                    ui.label(number(self.max));
+                    ui.end_row();
+                    ui.label("Mean");
+                    ui.label(number(self.mean));
+                    ui.end_row();
+                }
+            }
+        });
+    }
+}
+
+/// An output as the inspector shows it
+struct OutputView {
+    label: &'static str,
+    data_type: NodeDataType,
+    index: TimeIndex,
+    columns: Columns,
+    stats: Stats,
+}
+
+impl OutputView {
+    fn new(
+        label: &'static str,
+        data_type: NodeDataType,
+        value: &NodeDataTypeWithValue,
+    ) -> Result<Self> {
+        let columns = columns(value)?;
+        let summarized = columns
+            .iter()
+            .find(|(name, _)| *name == "value" || *name == "close")
+            .map(|(_, values)| Stats::new(values))
+            .unwrap_or_default();
+        Ok(Self {
+            label,
+            data_type,
+            index: value.time_index()?,
+            columns,
+            stats: summarized,
+        })
+    }
+
+    fn show(&self, ui: &mut Ui, output: usize) {
+        self.stats
+            .show(ui, ("inspector_stats", output), self.data_type);
+        ui.add_space(4.0);
+
+        let cell = |ui: &mut Ui, width: f32, text: String| {
+            ui.add_sized([width, ui.spacing().interact_size.y], Label::new(text));
+        };
+        Grid::new(("inspector_header", output)).show(ui, |ui| {
+            cell(ui, TIME_WIDTH, "time".to_owned());
+            for (name, _) in &self.columns {
+                cell(ui, VALUE_WIDTH, name.to_string());
+            }
+            ui.end_row();
+        });
+        let row_height = ui.spacing().interact_size.y;
+        ScrollArea::both()
+            .id_salt(("inspector_rows", output))
+            .max_height(320.0)
+            .show_rows(ui, row_height, self.index.len(), |ui, rows| {
+                Grid::new(("inspector_table", output))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for row in rows {
+                            cell(ui, TIME_WIDTH, format_time(self.index[row]));
+                            for (_, values) in &self.columns {
+                                let value = values.get(row).copied().flatten();
+                                cell(ui, VALUE_WIDTH, format_value(self.data_type, value));
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
+
+struct InspectedNode {
+    node_id: NodeId,
+    results: Vec<CheapCloneNodeDataTypeWithValue>,
+    outputs: Vec<Result<OutputView, String>>,
+}
+
+/// Shows what the selected node computed, from `NODE_COMPUTE_CACHE`
+#[derive(Default)]
+pub struct InspectorPanel {
+    pub open: bool,
+    /// Rebuilt when another node is selected or its results change
+    inspected: Option<InspectedNode>,
+}
+
+impl InspectorPanel {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        selected: &[NodeId],
+    ) {
+        egui::SidePanel::right("inspector")
+            .resizable(true)
+            .default_width(340.0)
+            .show(ctx, |ui| {
+                ui.heading("Inspector");
+                let node_id = selected
+                    .last()
+                    .copied()
+                    .filter(|id| snarl.get_node(*id).is_some());
+                let Some(node_id) = node_id else {
+                    ui.label("Select a node to see what it computed.");
+                    return;
+                };
+                let node = snarl[node_id].get_node();
+                ui.label(RichText::new(format!("{} #{}", node.get_name(), node_id.0)).strong());
+                let Some(results) = NODE_COMPUTE_CACHE.get(&node_id.0).map(|r| r.clone()) else {
+                    ui.label("Not computed yet, are all its inputs connected?");
+                    return;
+                };
+
+                let outdated = self.inspected.as_ref().is_none_or(|inspected| {
+                    inspected.node_id != node_id
+                        || inspected.results.len() != results.len()
+                        || !inspected
+                            .results
+                            .iter()
+                            .zip(&results)
+                            .all(|(a, b)| Arc::ptr_eq(a, b))
+                });
+                if outdated {
+                    let outputs = node
+                        .get_outputs()
+                        .iter()
+                        .zip(&results)
+                        .map(|((label, data_type), value)| {
+                            OutputView::new(label, *data_type, value).map_err(|e| e.to_string())
+                        })
+                        .collect();
+                    self.inspected = Some(InspectedNode {
+                        node_id,
+                        results,
+                        outputs,
+                    });
+                }
+                let Some(inspected) = &self.inspected else {
+                    return;
+                };
+                ScrollArea::vertical().show(ui, |ui| {
+                    for (output, view) in inspected.outputs.iter().enumerate() {
+                        match view {
+                            Ok(view) => {
+                                CollapsingHeader::new(format!(
+                                    "{} ({:?})",
+                                    view.label, view.data_type
+                                ))
+                                .id_salt(("inspector_output", node_id, output))
+                                .default_open(output == 0)
+                                .show(ui, |ui| view.show(ui, output));
+                            }
+                            Err(e) => {
+                                ui.colored_label(Color32::RED, e);
+                            }
+                        }
+                    }
+                });
+            });
+    }
+}
+
+/// The last values of an output as numbers, like `columns` but without copying everything
+fn tail(value: &NodeDataTypeWithValue, n: usize) -> Vec<Option<f64>> {
+    match value {
+        NodeDataTypeWithValue::DecimalSequence(seq) => {
+            seq.values.slice(-(n as i64), n).iter().collect()
+        }
+        NodeDataTypeWithValue::Mask(mask) => mask.values[mask.values.len().saturating_sub(n)..]
+            .iter()
+            .map(|value| Some(if *value { 1.0 } else { 0.0 }))
+            .collect(),
+        NodeDataTypeWithValue::Signal(signal) => signal.values
+            [signal.values.len().saturating_sub(n)..]
+            .iter()
+            .map(|value| Some(*value as f64))
+            .collect(),
+        NodeDataTypeWithValue::Candles(candles) => candles
+            .df
+            .column("close")
+            .ok()
+            .and_then(|column| column.f64().ok())
+            .map(|closes| closes.slice(-(n as i64), n).iter().collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// A tiny line of the last values of `value`, for node bodies
+pub fn sparkline(ui: &mut Ui, value: &NodeDataTypeWithValue, color: Color32, scale: f32) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(120.0, 24.0) * scale, Sense::hover());
+    let values = tail(value, SPARKLINE_POINTS);
+    let finite: Vec<(usize, f64)> = values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, value)| value.filter(|value| value.is_finite()).map(|v| (i, v)))
+        .collect();
+    if finite.len() < 2 {
+        return;
+    }
+    let min = finite.iter().map(|(_, v)| *v).fold(f64::INFINITY, f64::min);
+    let max = finite
+        .iter()
+        .map(|(_, v)| *v)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let range = (max - min).max(f64::EPSILON);
+    let last = (values.len() - 1).max(1) as f32;
+    let points = finite
+        .iter()
+        .map(|(i, v)| {
+            egui::pos2(
+                rect.left() + *i as f32 / last * rect.width(),
+                rect.bottom() - ((v - min) / range) as f32 * rect.height(),
+            )
+        })
+        .collect();
+    ui.painter()
+        .add(Shape::line(points, Stroke::new(1.0 * scale, color)));
+}
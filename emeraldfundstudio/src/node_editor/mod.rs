@@ -1,22 +1,38 @@
 #![allow(clippy::use_self)]
 
+pub mod clipboard;
+pub mod composite;
+pub mod composite_panel;
+pub mod history;
+pub mod inspector;
 pub mod node_trait;
 pub mod nodes;
+pub mod palette;
+pub mod profiler_panel;
+pub mod registry;
 pub mod style;
+pub mod widgets;
 
-use egui::{Align, Color32, Layout, RichText, Ui};
+use egui::{Align, Color32, Layout, RichText, Spinner, Ui};
 use egui_snarl::{
-    ui::{PinInfo, SnarlViewer},
-    InPin, NodeId, OutPin, OutPinId, Snarl,
+    ui::{AnyPins, PinInfo, SnarlViewer},
+    InPin, InPinId, NodeId, OutPin, OutPinId, Snarl,
 };
-use log::debug;
+use clipboard::{copy_nodes, duplicate_nodes};
+use history::History;
+use inspector::sparkline;
+use log::{debug, error};
 use node_trait::{EFNodeFNSerialized, NodeDataType};
+use palette::{node_palette, WireEnd};
 use strum::{Display, EnumIter};
 
-use crate::{
-    consts::NODE_DEFAULT_VALUES,
-    node_runners::realtime::{
-        clear_cache_from_node_onward, is_node_realtime_executable, run_nodes,
+use registry::registry;
+
+use crate::node_runners::{
+    profiler::node_summary,
+    realtime::{
+        clear_cache_downstream, clear_cache_from_node_onward, forget_node, is_node_running,
+        is_node_realtime_executable, run_nodes, NODE_COMPUTE_CACHE,
     },
 };
 
@@ -54,6 +70,76 @@ fn get_output_color(snarl: &Snarl<EFNodeFNSerialized<'_>>, pin: &OutPin) -> Colo
     node_row_to_color(output_type)
 }
 
+/// Wires of a node whose pins are gone or changed type. Nodes like MarketDataNode or
+/// FormulaNode change their pins with their settings.
+fn mismatched_wires(
+    snarl: &Snarl<EFNodeFNSerialized<'_>>,
+    node_id: NodeId,
+) -> Vec<(OutPinId, InPinId)> {
+    let fits = |out_pin: OutPinId, in_pin: InPinId| {
+        let output = snarl[out_pin.node].get_node().get_outputs().get(out_pin.output);
+        let input = snarl[in_pin.node].get_node().get_inputs().get(in_pin.input);
+        matches!((output, input), (Some((_, from)), Some((_, to))) if from == to)
+    };
+    snarl
+        .wires()
+        .filter(|(out_pin, in_pin)| out_pin.node == node_id || in_pin.node == node_id)
+        .filter(|(out_pin, in_pin)| !fits(*out_pin, *in_pin))
+        .collect()
+}
+
+/// Drops the wires of a node whose pins are gone or changed type
+pub fn drop_mismatched_wires(snarl: &mut Snarl<EFNodeFNSerialized<'_>>, node_id: NodeId) {
+    for (out_pin, in_pin) in mismatched_wires(snarl, node_id) {
+        snarl.disconnect(out_pin, in_pin);
+    }
+}
+
+/// Loads every node of a restored graph. A node that can't be loaded becomes a placeholder
+/// with the pins its wires use, typed after the other end, so no wire or data is lost.
+pub fn load_snarl(snarl: &mut Snarl<EFNodeFNSerialized<'_>>) {
+    let ids: Vec<NodeId> = snarl.node_ids().map(|(id, _)| id).collect();
+    let mut failed = vec![];
+    for id in ids {
+        if snarl[id].load_node().is_err() {
+            failed.push(id);
+        }
+    }
+    let wires: Vec<(OutPinId, InPinId)> = snarl.wires().collect();
+    for id in failed {
+        // The other end may be missing too, its type is unknown then
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+        for (out_pin, in_pin) in &wires {
+            if in_pin.node == id {
+                let data_type = snarl[out_pin.node].loaded_node.as_ref().and_then(|node| {
+                    node.get_outputs().get(out_pin.output).map(|(_, data_type)| *data_type)
+                });
+                set_pin_type(&mut inputs, in_pin.input, data_type);
+            }
+            if out_pin.node == id {
+                let data_type = snarl[in_pin.node].loaded_node.as_ref().and_then(|node| {
+                    node.get_inputs().get(in_pin.input).map(|(_, data_type)| *data_type)
+                });
+                set_pin_type(&mut outputs, out_pin.output, data_type);
+            }
+        }
+        let node = &mut snarl[id];
+        if let Err(e) = node.load_or_placeholder(inputs, outputs) {
+            error!("{} #{} can't be loaded: {}", node.node_name, id.0, e);
+        }
+    }
+}
+
+fn set_pin_type(pins: &mut Vec<NodeDataType>, pin: usize, data_type: Option<NodeDataType>) {
+    if pins.len() <= pin {
+        pins.resize(pin + 1, NodeDataType::DecimalSequence);
+    }
+    if let Some(data_type) = data_type {
+        pins[pin] = data_type;
+    }
+}
+
 #[derive(Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq, Display, EnumIter)]
 pub enum Comparison {
     #[strum(serialize = "Bigger Than")]
@@ -66,13 +152,17 @@ pub enum Comparison {
     NotEqual,
 }
 
-#[derive(Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq, Display, EnumIter)]
+#[derive(
+    Clone, Copy, Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Display, EnumIter,
+)]
 pub enum OrderDirection {
     Buy,
     Sell,
 }
 
-#[derive(Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq, Display, EnumIter)]
+#[derive(
+    Clone, Copy, Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Display, EnumIter,
+)]
 pub enum OrderType {
     #[strum(serialize = "Limit Maker")]
     LimitMaker,
@@ -80,9 +170,34 @@ pub enum OrderType {
     Market,
 }
 
-pub struct EFViewer;
+#[derive(Default)]
+pub struct EFViewer<'h> {
+    /// Set for graphs other than the main one, like the inside of a composite being edited.
+    /// They aren't run, so they never touch the caches of the main graph.
+    pub detached: bool,
+    /// Where edits are recorded for undo, graphs without one can't undo
+    pub history: Option<&'h mut History>,
+    /// Selected nodes, the node menu acts on all of them when opened on one
+    pub selected: Vec<NodeId>,
+    /// Show how long each node took in its header, see `ProfilerPanel`
+    pub timings: bool,
+}
+
+impl EFViewer<'_> {
+    /// Runs `edit` on the graph, recorded when there is a history
+    fn edit<'a, R>(
+        &mut self,
+        snarl: &mut Snarl<EFNodeFNSerialized<'a>>,
+        edit: impl FnOnce(&mut Snarl<EFNodeFNSerialized<'a>>) -> R,
+    ) -> R {
+        match self.history.as_deref_mut() {
+            Some(history) => history.record(snarl, edit),
+            None => edit(snarl),
+        }
+    }
+}
 
-impl<'a> SnarlViewer<EFNodeFNSerialized<'a>> for EFViewer {
+impl<'a> SnarlViewer<EFNodeFNSerialized<'a>> for EFViewer<'_> {
     #[inline]
     fn connect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<EFNodeFNSerialized<'_>>) {
         // Make sure this connection is not to the same node
@@ -132,16 +247,54 @@ impl<'a> SnarlViewer<EFNodeFNSerialized<'a>> for EFViewer {
             return;
         }
 
-        for &remote in &to.remotes {
-            snarl.disconnect(remote, to.id);
+        self.edit(snarl, |snarl| {
+            for &remote in &to.remotes {
+                snarl.disconnect(remote, to.id);
+            }
+            snarl.connect(from.id, to.id);
+        });
+        if !self.detached {
+            run_nodes(snarl);
+        }
+    }
+
+    fn disconnect(
+        &mut self,
+        from: &OutPin,
+        to: &InPin,
+        snarl: &mut Snarl<EFNodeFNSerialized<'a>>,
+    ) {
+        self.edit(snarl, |snarl| snarl.disconnect(from.id, to.id));
+        if !self.detached {
+            clear_cache_from_node_onward(snarl, &to.id.node);
         }
+    }
 
-        snarl.connect(from.id, to.id);
-        run_nodes(snarl);
+    fn drop_inputs(&mut self, pin: &InPin, snarl: &mut Snarl<EFNodeFNSerialized<'a>>) {
+        self.edit(snarl, |snarl| snarl.drop_inputs(pin.id));
+        if !self.detached {
+            clear_cache_from_node_onward(snarl, &pin.id.node);
+        }
+    }
+
+    fn drop_outputs(&mut self, pin: &OutPin, snarl: &mut Snarl<EFNodeFNSerialized<'a>>) {
+        self.edit(snarl, |snarl| snarl.drop_outputs(pin.id));
+        if !self.detached {
+            for remote in &pin.remotes {
+                clear_cache_downstream(snarl, &remote.node);
+            }
+            run_nodes(snarl);
+        }
     }
 
     fn title(&mut self, node: &EFNodeFNSerialized<'_>) -> String {
-        node.get_node().get_name().to_owned()
+        if node.is_missing() {
+            return format!("{} (missing)", node.node_name);
+        }
+        registry()
+            .get(&node.node_name)
+            .map(|registration| registration.title.to_owned())
+            .unwrap_or_else(|| node.get_node().get_name().to_owned())
     }
 
     fn inputs(&mut self, node: &EFNodeFNSerialized<'_>) -> usize {
@@ -194,19 +347,110 @@ impl<'a> SnarlViewer<EFNodeFNSerialized<'a>> for EFViewer {
         snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
     ) {
         ui.label("Add node");
+        if let Some(node) = node_palette(ui, None).and_then(|r| r.new_node()) {
+            let node_id = self.edit(snarl, |snarl| snarl.insert_node(pos, node));
+            if !self.detached {
+                // Ids of removed nodes are reused, their results must not be
+                forget_node(&node_id);
+            }
+            ui.close_menu();
+        }
+    }
 
-        for node in NODE_DEFAULT_VALUES.keys() {
-            if ui.button(*node).clicked() {
-                let mut node = EFNodeFNSerialized {
-                    loaded_node: None,
-                    node_name: (*node).into(),
-                    arguments: NODE_DEFAULT_VALUES.get(node).unwrap().clone(),
-                };
-                node.load_node().expect("Loading node failed");
-                snarl.insert_node(pos, node);
-                ui.close_menu();
+    fn has_body(&mut self, node: &EFNodeFNSerialized<'a>) -> bool {
+        // Detached graphs aren't run, there is nothing to draw
+        !self.detached && !node.get_node().get_outputs().is_empty()
+    }
+
+    /// A sparkline of the last values of the first output
+    fn show_body(
+        &mut self,
+        node: NodeId,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+        scale: f32,
+        snarl: &mut Snarl<EFNodeFNSerialized<'a>>,
+    ) {
+        let Some((_, data_type)) = snarl[node].get_node().get_outputs().first() else {
+            return;
+        };
+        let color = node_row_to_color(data_type);
+        if let Some(results) = NODE_COMPUTE_CACHE.get(&node.0) {
+            if let Some(value) = results.first() {
+                sparkline(ui, value, color, scale);
+            }
+        }
+    }
+
+    fn has_dropped_wire_menu(
+        &mut self,
+        _src_pins: AnyPins<'_>,
+        _snarl: &mut Snarl<EFNodeFNSerialized<'a>>,
+    ) -> bool {
+        true
+    }
+
+    /// The node palette with the nodes that fit the wire, the one picked gets connected
+    fn show_dropped_wire_menu(
+        &mut self,
+        pos: egui::Pos2,
+        ui: &mut Ui,
+        _scale: f32,
+        src_pins: AnyPins<'_>,
+        snarl: &mut Snarl<EFNodeFNSerialized<'a>>,
+    ) {
+        let wire = match src_pins {
+            AnyPins::Out(pins) => pins.first().map(|pin| {
+                WireEnd::Output(snarl[pin.node].get_node().get_outputs()[pin.output].1)
+            }),
+            AnyPins::In(pins) => pins.first().map(|pin| {
+                WireEnd::Input(snarl[pin.node].get_node().get_inputs()[pin.input].1)
+            }),
+        };
+        let Some(node) = node_palette(ui, wire).and_then(|r| r.new_node()) else {
+            return;
+        };
+        let node_id = self.edit(snarl, |snarl| {
+            let node_id = snarl.insert_node(pos, node);
+            match src_pins {
+                AnyPins::Out(pins) => {
+                    for pin in pins {
+                        let data_type = snarl[pin.node].get_node().get_outputs()[pin.output].1;
+                        let input = snarl[node_id]
+                            .get_node()
+                            .get_inputs()
+                            .iter()
+                            .position(|(_, t)| *t == data_type);
+                        if let Some(input) = input {
+                            snarl.connect(*pin, InPinId { node: node_id, input });
+                        }
+                    }
+                }
+                AnyPins::In(pins) => {
+                    for pin in pins {
+                        let data_type = snarl[pin.node].get_node().get_inputs()[pin.input].1;
+                        let output = snarl[node_id]
+                            .get_node()
+                            .get_outputs()
+                            .iter()
+                            .position(|(_, t)| *t == data_type);
+                        if let Some(output) = output {
+                            // An input takes one wire
+                            snarl.drop_inputs(*pin);
+                            snarl.connect(OutPinId { node: node_id, output }, *pin);
+                        }
+                    }
+                }
             }
+            node_id
+        });
+        if !self.detached {
+            forget_node(&node_id);
+            clear_cache_downstream(snarl, &node_id);
+            run_nodes(snarl);
         }
+        ui.close_menu();
     }
 
     fn show_header(
@@ -224,34 +468,192 @@ impl<'a> SnarlViewer<EFNodeFNSerialized<'a>> for EFViewer {
             Layout::top_down(Align::Min).with_cross_align(Align::Center),
             |ui| {
                 let node = snarl.get_node(node_id).unwrap();
+                let color = if self.detached {
+                    Color32::GRAY
+                } else if is_node_realtime_executable(snarl, node_id, node) {
+                    DEBUG_COLOR_EXECUTABLE
+                } else {
+                    DEBUG_COLOR
+                };
 
                 #[cfg(debug_assertions)]
                 ui.label(
-                    RichText::new(format!("{} #{}", self.title(node), node_id.0)).color(
-                        if is_node_realtime_executable(snarl, node_id, node) {
-                            DEBUG_COLOR_EXECUTABLE
-                        } else {
-                            DEBUG_COLOR
-                        },
-                    ),
+                    RichText::new(format!("{} #{}", self.title(node), node_id.0)).color(color),
                 );
                 #[cfg(not(debug_assertions))]
-                ui.label(RichText::new(self.title(node)).color(
-                    if is_node_realtime_executable(snarl, node_id, node) {
-                        DEBUG_COLOR_EXECUTABLE
-                    } else {
-                        DEBUG_COLOR
-                    },
-                ));
+                ui.label(RichText::new(self.title(node)).color(color));
+                if !self.detached && is_node_running(&node_id) {
+                    ui.add(Spinner::new().size(10.0 * scale));
+                }
+                if self.timings && !self.detached {
+                    if let Some(summary) = node_summary(node_id.0) {
+                        ui.label(RichText::new(summary).small().weak());
+                    }
+                }
 
                 let node = snarl.get_node_mut(node_id).unwrap();
                 let changed = node
                     .get_node_mut()
                     .show_header(node_id, inputs, outputs, ui, scale);
                 if changed {
-                    clear_cache_from_node_onward(snarl, &node_id);
+                    // The arguments first, so undo puts the wires back once the pins are
+                    if let Some(history) = self.history.as_deref_mut() {
+                        history.arguments_changed(snarl, node_id);
+                    }
+                    let mismatched = mismatched_wires(snarl, node_id);
+                    if !mismatched.is_empty() {
+                        self.edit(snarl, |snarl| {
+                            for (out_pin, in_pin) in mismatched {
+                                snarl.disconnect(out_pin, in_pin);
+                            }
+                        });
+                    }
+                    if !self.detached {
+                        clear_cache_from_node_onward(snarl, &node_id);
+                    }
                 }
             },
         );
     }
+    fn has_node_menu(&mut self, _node: &EFNodeFNSerialized<'a>) -> bool {
+        true
+    }
+
+    fn show_node_menu(
+        &mut self,
+        node: NodeId,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+        _scale: f32,
+        snarl: &mut Snarl<EFNodeFNSerialized<'a>>,
+    ) {
+        let ids: Vec<NodeId> = if self.selected.contains(&node) {
+            // The selection is kept by the snarl and can name nodes that are gone
+            self.selected
+                .iter()
+                .copied()
+                .filter(|id| snarl.get_node(*id).is_some())
+                .collect()
+        } else {
+            vec![node]
+        };
+        if ui.button("Copy").clicked() {
+            match copy_nodes(snarl, &ids) {
+                Ok(text) => ui.ctx().copy_text(text),
+                Err(e) => error!("Can't copy: {}", e),
+            }
+            ui.close_menu();
+        }
+        if ui.button("Duplicate").clicked() {
+            match self.edit(snarl, |snarl| duplicate_nodes(snarl, &ids)) {
+                Ok(duplicates) if !self.detached => {
+                    // Ids of removed nodes are reused, their results must not be
+                    for id in &duplicates {
+                        forget_node(id);
+                    }
+                    run_nodes(snarl);
+                }
+                Ok(_) => {}
+                Err(e) => error!("Can't duplicate: {}", e),
+            }
+            ui.close_menu();
+        }
+        if ui.button("Remove").clicked() {
+            let downstream: Vec<NodeId> = snarl
+                .wires()
+                .filter(|(out_pin, in_pin)| {
+                    ids.contains(&out_pin.node) && !ids.contains(&in_pin.node)
+                })
+                .map(|(_, in_pin)| in_pin.node)
+                .collect();
+            self.edit(snarl, |snarl| {
+                for id in &ids {
+                    snarl.remove_node(*id);
+                }
+            });
+            if !self.detached {
+                for id in &ids {
+                    forget_node(id);
+                }
+                for id in &downstream {
+                    clear_cache_downstream(snarl, id);
+                }
+                run_nodes(snarl);
+            }
+            ui.close_menu();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::pos2;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn saved(
+        node_name: &'static str,
+        arguments: Value,
+        version: u32,
+    ) -> EFNodeFNSerialized<'static> {
+        EFNodeFNSerialized {
+            node_name: node_name.into(),
+            arguments,
+            version,
+            loaded_node: None,
+        }
+    }
+
+    #[test]
+    fn load_snarl_keeps_nodes_that_cant_load_as_placeholders() {
+        let mut snarl = Snarl::new();
+        let market_data =
+            snarl.insert_node(pos2(0.0, 0.0), saved("MarketDataNode", Value::Null, 0));
+        let split = snarl.insert_node(pos2(0.0, 0.0), saved("SplitCandlesNode", Value::Null, 0));
+        let unknown =
+            snarl.insert_node(pos2(0.0, 0.0), saved("RsiNode", json!({"period": 7}), 0));
+        let preview = snarl.insert_node(
+            pos2(0.0, 0.0),
+            saved("PreviewNode", json!({"output_color": [255, 0, 0]}), 0),
+        );
+        let wire = |snarl: &mut Snarl<_>, from, output, to, input| {
+            snarl.connect(OutPinId { node: from, output }, InPinId { node: to, input });
+        };
+        wire(&mut snarl, market_data, 0, split, 0);
+        wire(&mut snarl, split, 3, unknown, 1);
+        wire(&mut snarl, unknown, 0, preview, 0);
+
+        load_snarl(&mut snarl);
+
+        assert!(!snarl[split].is_missing());
+        assert!(!snarl[preview].is_missing());
+        let node = snarl[unknown].get_node();
+        assert!(snarl[unknown].is_missing());
+        // The first input isn't wired, its type is a guess
+        let inputs: Vec<NodeDataType> = node.get_inputs().iter().map(|(_, t)| *t).collect();
+        assert_eq!(inputs, [NodeDataType::DecimalSequence, NodeDataType::DecimalSequence]);
+        let outputs: Vec<NodeDataType> = node.get_outputs().iter().map(|(_, t)| *t).collect();
+        assert_eq!(outputs, [NodeDataType::DecimalSequence]);
+        assert_eq!(node.export_data(), json!({"period": 7}));
+    }
+
+    #[test]
+    fn load_snarl_doesnt_load_nodes_of_newer_versions() {
+        let mut snarl = Snarl::new();
+        let arguments = json!({"period": 20, "source": "hl2"});
+        let sma = snarl.insert_node(pos2(0.0, 0.0), saved("SMANode", arguments.clone(), 5));
+
+        load_snarl(&mut snarl);
+
+        let node = &mut snarl[sma];
+        assert!(node.is_missing());
+        // Saving writes back what was loaded, for the newer version to open
+        node.save_node();
+        assert_eq!(node.arguments, arguments);
+        assert_eq!(node.version, 5);
+        let error = node.load_node().unwrap_err();
+        assert!(error.to_string().contains("newer version"), "{}", error);
+    }
 }
@@ -1,19 +1,23 @@
-use super::nodes::{
-    compare::CompareNode, execute_position::ExecutePositionNode, fuse_signals::FuseSignalsNode,
-    market_data::MarketDataNode, preview::PreviewNode, sma::SMANode,
-    split_candles::SplitCandlesNode, to_signal::ToSignalNode,
+use super::{
+    nodes::missing::{MissingNode, MISSING_NODE},
+    registry::registry,
 };
-use crate::{
-    create_nodes,
-    types::{decimal_sequence::DecimalSequence, mask::Mask, signal::Signal},
+use crate::types::{
+    candles::Candles,
+    decimal_sequence::DecimalSequence,
+    mask::Mask,
+    signal::Signal,
+    time_index::{Indexed, TimeIndex},
 };
 use anyhow::{anyhow, Result};
-use egui::{TextBuffer, Ui};
+use egui::Ui;
 use egui_snarl::{InPin, NodeId, OutPin};
-use polars::frame::DataFrame;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::{any::Any, borrow::Cow, sync::Arc};
+use std::{any::Any, borrow::Cow, collections::HashSet, sync::Arc};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NodeDataType {
     Mask,
     Signal,
@@ -23,39 +27,109 @@ pub enum NodeDataType {
 
 #[derive(Clone, Debug)]
 pub enum NodeDataTypeWithValue {
-    Mask(Mask),
-    Signal(Signal),
-    DecimalSequence(DecimalSequence),
-    Candles(DataFrame),
+    Mask(Indexed<Mask>),
+    Signal(Indexed<Signal>),
+    DecimalSequence(Indexed<DecimalSequence>),
+    Candles(Candles),
+}
+
+impl NodeDataTypeWithValue {
+    /// Timestamps of the values, see `TimeIndex`
+    pub fn time_index(&self) -> Result<TimeIndex> {
+        match self {
+            NodeDataTypeWithValue::DecimalSequence(seq) => Ok(seq.index.clone()),
+            NodeDataTypeWithValue::Mask(mask) => Ok(mask.index.clone()),
+            NodeDataTypeWithValue::Signal(signal) => Ok(signal.index.clone()),
+            NodeDataTypeWithValue::Candles(candles) => candles.time_index(),
+        }
+    }
 }
 
 pub type CheapCloneNodeDataTypeWithValue = Arc<NodeDataTypeWithValue>;
 
+/// What a node keeps between incremental updates, only the node knows what's inside
+pub type NodeState = Box<dyn Any + Send + Sync>;
+
+/// Pin labels have to be `'static`, nodes with pins named at runtime (composites, formulas)
+/// get them from here. Every distinct label is leaked once.
+pub fn pin_label(name: &str) -> &'static str {
+    static LABELS: Lazy<Mutex<HashSet<&'static str>>> = Lazy::new(Default::default);
+    let mut labels = LABELS.lock();
+    if let Some(label) = labels.get(name) {
+        return label;
+    }
+    let label: &'static str = Box::leak(name.to_owned().into_boxed_str());
+    labels.insert(label);
+    label
+}
+
+/// Describes what values a node argument can take, so tools like the parameter sweep
+/// know how to generate ranges for it.
+#[derive(Clone, Debug)]
+pub enum ParameterKind {
+    Integer { min: i64, max: i64 },
+    Decimal { min: f64, max: f64 },
+    Choice(Vec<serde_json::Value>),
+}
+
+/// A top-level key in the node's exported arguments that can be tweaked from outside the node.
+#[derive(Clone, Debug)]
+pub struct NodeParameter {
+    pub key: &'static str,
+    pub kind: ParameterKind,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct EFNodeFNSerialized<'a> {
     pub node_name: Cow<'a, str>,
     pub arguments: serde_json::Value,
+    /// Schema version of `arguments`, see `NodeRegistration::migrations`. Graphs saved before
+    /// nodes had versions are version 0.
+    #[serde(default)]
+    pub version: u32,
     #[serde(skip)]
     pub loaded_node: Option<Box<dyn EFNodeFn>>,
 }
 
 impl EFNodeFNSerialized<'_> {
+    /// Migrates the arguments to the current version and loads them. On failure the arguments
+    /// and version are left as they were.
     pub fn load_node(&mut self) -> Result<()> {
-        let loaded_node: Box<dyn EFNodeFn> = create_nodes!(
-            self,
-            SMANode,
-            CompareNode,
-            MarketDataNode,
-            ExecutePositionNode,
-            SplitCandlesNode,
-            ToSignalNode,
-            FuseSignalsNode,
-            PreviewNode
-        );
-        self.loaded_node = Some(loaded_node);
+        let registration = *registry()
+            .get(&self.node_name)
+            .ok_or_else(|| anyhow!("Node {} not found", self.node_name))?;
+        let arguments = registration.migrate(self.arguments.clone(), self.version)?;
+        let node = (registration.deserialize)(arguments.clone())?;
+        self.arguments = arguments;
+        self.version = registration.version();
+        self.loaded_node = Some(node);
         Ok(())
     }
 
+    /// Like `load_node`, but a node that can't be loaded becomes a `MissingNode` with
+    /// `inputs` and `outputs` as pins, which keeps its data until it loads again
+    pub fn load_or_placeholder(
+        &mut self,
+        inputs: Vec<NodeDataType>,
+        outputs: Vec<NodeDataType>,
+    ) -> Result<()> {
+        let result = self.load_node();
+        if let Err(e) = &result {
+            self.loaded_node = Some(Box::new(MissingNode::new(
+                self.node_name.to_string(),
+                self.arguments.clone(),
+                e.to_string(),
+                inputs,
+                outputs,
+            )));
+        }
+        result
+    }
+
+    pub fn is_missing(&self) -> bool {
+        self.get_node().get_name() == MISSING_NODE
+    }
+
     pub fn save_node(&mut self) {
         self.arguments = self.get_node().export_data();
     }
@@ -82,6 +156,9 @@ pub trait EFNodeFn: Send + Sync {
     fn get_name(&self) -> &'static str;
     fn get_inputs(&self) -> &[(&'static str, NodeDataType)];
     fn get_outputs(&self) -> &[(&'static str, NodeDataType)];
+    fn get_parameters(&self) -> Vec<NodeParameter> {
+        vec![]
+    }
     fn show_header(
         &mut self,
         node_id: NodeId,
@@ -99,6 +176,19 @@ pub trait EFNodeFn: Send + Sync {
         Ok(vec![])
     }
 
+    /// Incremental `process_data` for live data. The inputs are those of the last call with
+    /// bars appended (or the last bar updated) and `state` is whatever the node kept from that
+    /// call, `None` if there is nothing to continue from. Must return what `process_data`
+    /// returns for the same inputs. By default everything is recomputed.
+    fn process_update(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+        state: &mut Option<NodeState>,
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        *state = None;
+        self.process_data(input_args)
+    }
+
     fn as_any(&self) -> &dyn Any {
         todo!("If this fails you need to implement this");
     }
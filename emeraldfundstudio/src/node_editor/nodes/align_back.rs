@@ -0,0 +1,147 @@
+use crate::{
+    node_editor::node_trait::{EFNodeFn, NodeDataType, NodeDataTypeWithValue},
+    traits::IntoArc,
+    types::{decimal_sequence::DecimalSequence, time_index::Indexed},
+};
+use anyhow::{anyhow, Result};
+use polars::frame::DataFrame;
+use serde::{Deserialize, Serialize};
+
+use super::resample::LAST_TIMESTAMP_COLUMN;
+
+/// Forward-fills a sequence computed on resampled candles back onto the base candles
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AlignBackNode;
+
+fn timestamps(df: &DataFrame, column: &str) -> Result<Vec<u64>> {
+    Ok(df.column(column)?.u64()?.into_no_null_iter().collect())
+}
+
+fn bar_seconds(timestamps: &[u64]) -> u64 {
+    match timestamps {
+        [first, second, ..] => second.saturating_sub(*first),
+        _ => 0,
+    }
+}
+
+/// For every base candle, takes the value of the newest higher timeframe candle that was
+/// complete when the base candle closed. A higher candle is complete once the last base
+/// candle in it has closed, so a base candle never sees the close of its own bucket early.
+pub fn align_back(
+    base: &DataFrame,
+    higher: &DataFrame,
+    seq: &DecimalSequence,
+) -> Result<DecimalSequence> {
+    if seq.len() != higher.height() {
+        return Err(anyhow!(
+            "Sequence has {} values but the higher timeframe has {} candles",
+            seq.len(),
+            higher.height()
+        ));
+    }
+    let base_timestamps = timestamps(base, "timestamp")?;
+    let completed_at = if higher.column(LAST_TIMESTAMP_COLUMN).is_ok() {
+        timestamps(higher, LAST_TIMESTAMP_COLUMN)?
+    } else {
+        // Candles that didn't come from a ResampleNode are assumed complete at the start of
+        // the last base candle they span
+        let higher_timestamps = timestamps(higher, "timestamp")?;
+        let span = bar_seconds(&higher_timestamps).saturating_sub(bar_seconds(&base_timestamps));
+        higher_timestamps
+            .iter()
+            .map(|timestamp| timestamp + span)
+            .collect()
+    };
+    let values: Vec<Option<f64>> = seq.iter().collect();
+
+    let mut complete = 0;
+    let result: DecimalSequence = base_timestamps
+        .iter()
+        .map(|timestamp| {
+            while complete < completed_at.len() && completed_at[complete] <= *timestamp {
+                complete += 1;
+            }
+            if complete == 0 {
+                None
+            } else {
+                values[complete - 1]
+            }
+        })
+        .collect();
+    Ok(result.with_name(seq.name().clone()))
+}
+
+impl EFNodeFn for AlignBackNode {
+    fn get_name(&self) -> &'static str {
+        "AlignBackNode"
+    }
+
+    fn get_inputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &[
+            ("Base", NodeDataType::Candles),
+            ("Higher", NodeDataType::Candles),
+            ("Sequence", NodeDataType::DecimalSequence),
+        ];
+    }
+
+    fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &[("Output", NodeDataType::DecimalSequence)];
+    }
+
+    fn process_data(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        if input_args.len() != 3 {
+            return Err(anyhow!("should have 3 inputs!"));
+        }
+
+        match (&*input_args[0], &*input_args[1], &*input_args[2]) {
+            (
+                NodeDataTypeWithValue::Candles(base),
+                NodeDataTypeWithValue::Candles(higher),
+                NodeDataTypeWithValue::DecimalSequence(seq),
+            ) => {
+                let aligned = align_back(&base.df, &higher.df, seq)?;
+                return Ok(vec![NodeDataTypeWithValue::DecimalSequence(Indexed::new(
+                    base.time_index()?,
+                    aligned,
+                ))
+                .into_arc()]);
+            }
+            _ => return Err(anyhow!("Unknown input")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::df;
+
+    use super::*;
+    use crate::node_editor::nodes::resample::resample_candles;
+
+    #[test]
+    fn higher_values_appear_once_their_candle_closed() {
+        let prices: Vec<f64> = (0..12).map(|i| 100.0 + i as f64).collect();
+        let base = df![
+            "open" => prices.clone(),
+            "high" => prices.clone(),
+            "low" => prices.clone(),
+            "close" => prices,
+            "volume" => vec![1.0; 12],
+            "timestamp" => (0..12).map(|i| i * 60).collect::<Vec<u64>>(),
+        ]
+        .unwrap();
+        let higher = resample_candles(&base, 300).unwrap();
+        let seq: DecimalSequence = [Some(1.0), Some(2.0), Some(3.0)].into_iter().collect();
+        let aligned: Vec<Option<f64>> = align_back(&base, &higher, &seq).unwrap().iter().collect();
+        // A 5m candle closes with its 5th minute, the minutes before it can't see its value.
+        // The last one is partial and closes with the last minute so far.
+        let mut expected = vec![None; 4];
+        expected.extend([Some(1.0); 5]);
+        expected.extend([Some(2.0); 2]);
+        expected.push(Some(3.0));
+        assert_eq!(aligned, expected);
+    }
+}
@@ -1,10 +1,16 @@
 use crate::{
-    node_editor::node_trait::{EFNodeFn, NodeDataType, NodeDataTypeWithValue},
+    node_editor::{
+        node_trait::{EFNodeFn, NodeDataType, NodeDataTypeWithValue, NodeParameter, ParameterKind},
+        widgets::join_mode_combo_box,
+    },
     traits::IntoArc,
+    types::{
+        mask::Mask,
+        time_index::{check_length, join, take, Indexed, JoinMode},
+    },
 };
 use anyhow::{anyhow, Result};
 use egui::ComboBox;
-use polars::{prelude::ChunkCompareIneq, series::ChunkCompareEq};
 use serde::{Deserialize, Serialize};
 use strum::{AsRefStr, Display, EnumIter, IntoEnumIterator};
 
@@ -27,11 +33,12 @@ pub enum CompareMode {
 #[serde(default)]
 pub struct CompareNode {
     pub mode: CompareMode,
+    pub join: JoinMode,
 }
 
 impl EFNodeFn for CompareNode {
     fn get_name(&self) -> &'static str {
-        "Compare"
+        "CompareNode"
     }
 
     fn get_inputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
@@ -45,6 +52,17 @@ impl EFNodeFn for CompareNode {
         return &[("Mask", NodeDataType::Mask)];
     }
 
+    fn get_parameters(&self) -> Vec<NodeParameter> {
+        vec![NodeParameter {
+            key: "mode",
+            kind: ParameterKind::Choice(
+                CompareMode::iter()
+                    .map(|mode| serde_json::to_value(mode).unwrap())
+                    .collect(),
+            ),
+        }]
+    }
+
     fn process_data(
         &self,
         input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
@@ -55,15 +73,29 @@ impl EFNodeFn for CompareNode {
 
         if let NodeDataTypeWithValue::DecimalSequence(df0) = &*input_args[0] {
             if let NodeDataTypeWithValue::DecimalSequence(df1) = &*input_args[1] {
-                let result = match self.mode {
-                    CompareMode::Equal => df0.equal(df1),
-                    CompareMode::NotEqual => df0.not_equal(df1),
-                    CompareMode::LessThan => df0.lt(df1),
-                    CompareMode::BiggerThan => df0.gt(df1),
-                };
-                return Ok(vec![NodeDataTypeWithValue::Mask(
-                    result.iter().map(|x| x.unwrap()).collect(),
-                )
+                check_length(&df0.index, df0.len())?;
+                check_length(&df1.index, df1.len())?;
+                let joined = join(&df0.index, &df1.index, self.join)?;
+                let values0 = take(&df0.iter().collect::<Vec<_>>(), &joined.left, None);
+                let values1 = take(&df1.iter().collect::<Vec<_>>(), &joined.right, None);
+                let result: Mask = values0
+                    .into_iter()
+                    .zip(values1)
+                    .map(|(value0, value1)| match (value0, value1) {
+                        (Some(value0), Some(value1)) => match self.mode {
+                            CompareMode::Equal => value0 == value1,
+                            CompareMode::NotEqual => value0 != value1,
+                            CompareMode::LessThan => value0 < value1,
+                            CompareMode::BiggerThan => value0 > value1,
+                        },
+                        // Missing values (e.g. the warmup of an indicator) never match
+                        _ => false,
+                    })
+                    .collect();
+                return Ok(vec![NodeDataTypeWithValue::Mask(Indexed::new(
+                    joined.index,
+                    result,
+                ))
                 .into_arc()]);
             }
         }
@@ -79,16 +111,19 @@ impl EFNodeFn for CompareNode {
         scale: f32,
     ) -> bool {
         let mut result = false;
-        ComboBox::from_id_salt(0)
-            .selected_text(self.mode.to_string())
-            .show_ui(ui, |ui| {
-                for v in CompareMode::iter() {
-                    let value = ui.selectable_value(&mut self.mode, v.clone(), v.to_string());
-                    if value.changed() {
-                        result = true;
+        ui.horizontal(|ui| {
+            ComboBox::from_id_salt(0)
+                .selected_text(self.mode.to_string())
+                .show_ui(ui, |ui| {
+                    for v in CompareMode::iter() {
+                        let value = ui.selectable_value(&mut self.mode, v.clone(), v.to_string());
+                        if value.changed() {
+                            result = true;
+                        }
                     }
-                }
-            });
+                });
+            result |= join_mode_combo_box(ui, node_id, &mut self.join);
+        });
         result
     }
 
@@ -0,0 +1,208 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+};
+
+use crate::{
+    node_editor::node_trait::{pin_label, EFNodeFNSerialized, EFNodeFn, NodeDataType},
+    node_runners::graph::{ComputeCache, GraphNode, GraphSnapshot},
+};
+use anyhow::{anyhow, Result};
+use egui::RichText;
+use egui_snarl::{NodeId, OutPinId};
+use serde::{Deserialize, Serialize};
+
+/// Id of the pseudo node whose outputs are the inputs of the composite while it runs
+const INPUTS_NODE: usize = usize::MAX;
+
+/// A pin of a node inside a composite, `pin` is an input or an output depending on context
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct InnerPin {
+    pub node: usize,
+    pub pin: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct InnerNode {
+    /// Only unique within the composite
+    pub id: usize,
+    pub node_name: String,
+    pub arguments: serde_json::Value,
+    /// Schema version of `arguments`
+    pub version: u32,
+    /// Relative to the middle of the nodes, for editing and ungrouping
+    pub pos: [f32; 2],
+    /// The output connected to each input, `None` if it is fed from outside
+    pub inputs: Vec<Option<InnerPin>>,
+}
+
+/// An input of the composite, its value goes to every target
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CompositeInput {
+    pub name: String,
+    pub data_type: NodeDataType,
+    pub targets: Vec<InnerPin>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CompositeOutput {
+    pub name: String,
+    pub data_type: NodeDataType,
+    pub source: InnerPin,
+}
+
+/// A subgraph that acts as a single node. Instances carry a copy of the definition, so
+/// saved graphs don't depend on the library they came from.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CompositeDefinition {
+    pub name: String,
+    pub nodes: Vec<InnerNode>,
+    pub inputs: Vec<CompositeInput>,
+    pub outputs: Vec<CompositeOutput>,
+}
+
+impl CompositeDefinition {
+    /// Loads the inner nodes, the inputs of the composite are wired to `INPUTS_NODE`
+    fn graph(&self) -> Result<GraphSnapshot> {
+        let mut external = HashMap::new();
+        for (index, input) in self.inputs.iter().enumerate() {
+            for target in &input.targets {
+                external.insert(*target, index);
+            }
+        }
+        let mut nodes = BTreeMap::new();
+        for inner_node in &self.nodes {
+            if inner_node.id == INPUTS_NODE {
+                return Err(anyhow!("Node id {} is reserved", INPUTS_NODE));
+            }
+            let mut node = EFNodeFNSerialized {
+                node_name: inner_node.node_name.clone().into(),
+                arguments: inner_node.arguments.clone(),
+                version: inner_node.version,
+                loaded_node: None,
+            };
+            node.load_node()?;
+            let inputs = (0..node.get_node().get_inputs().len())
+                .map(|input| {
+                    let target = InnerPin {
+                        node: inner_node.id,
+                        pin: input,
+                    };
+                    match inner_node.inputs.get(input).copied().flatten() {
+                        Some(remote) => Some(OutPinId {
+                            node: NodeId(remote.node),
+                            output: remote.pin,
+                        }),
+                        None => external.get(&target).map(|index| OutPinId {
+                            node: NodeId(INPUTS_NODE),
+                            output: *index,
+                        }),
+                    }
+                })
+                .collect();
+            nodes.insert(inner_node.id, GraphNode { node, inputs });
+        }
+        Ok(GraphSnapshot { nodes })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "CompositeDefinition")]
+pub struct CompositeNode {
+    pub definition: CompositeDefinition,
+    inputs: Vec<(&'static str, NodeDataType)>,
+    outputs: Vec<(&'static str, NodeDataType)>,
+    graph: GraphSnapshot,
+}
+
+impl TryFrom<CompositeDefinition> for CompositeNode {
+    type Error = anyhow::Error;
+
+    fn try_from(definition: CompositeDefinition) -> Result<Self> {
+        let graph = definition
+            .graph()
+            .map_err(|e| anyhow!("Composite {}: {}", definition.name, e))?;
+        let inputs = definition
+            .inputs
+            .iter()
+            .map(|input| (pin_label(&input.name), input.data_type))
+            .collect();
+        let outputs = definition
+            .outputs
+            .iter()
+            .map(|output| (pin_label(&output.name), output.data_type))
+            .collect();
+        Ok(Self {
+            definition,
+            inputs,
+            outputs,
+            graph,
+        })
+    }
+}
+
+impl EFNodeFn for CompositeNode {
+    fn get_name(&self) -> &'static str {
+        "CompositeNode"
+    }
+
+    fn get_inputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &self.inputs;
+    }
+
+    fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &self.outputs;
+    }
+
+    fn process_data(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        if input_args.len() != self.inputs.len() {
+            return Err(anyhow!("should have {} inputs!", self.inputs.len()));
+        }
+        let mut cache = ComputeCache::new();
+        cache.insert(INPUTS_NODE, input_args.to_vec());
+        self.graph.run(&mut cache)?;
+        return self
+            .definition
+            .outputs
+            .iter()
+            .map(|output| {
+                cache
+                    .get(&output.source.node)
+                    .and_then(|results| results.get(output.source.pin))
+                    .cloned()
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "{} has no value, are all inner inputs connected?",
+                            output.name
+                        )
+                    })
+            })
+            .collect();
+    }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        ui.label(RichText::new(&self.definition.name).strong())
+            .on_hover_text(format!("{} nodes", self.definition.nodes.len()));
+        false
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(&self.definition).unwrap();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
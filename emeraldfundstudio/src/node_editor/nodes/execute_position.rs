@@ -1,8 +1,45 @@
-use crate::node_editor::node_trait::{EFNodeFn, NodeDataType};
+use std::any::Any;
+
+use crate::node_editor::{
+    node_trait::{EFNodeFn, NodeDataType},
+    OrderType,
+};
+use egui::{ComboBox, DragValue, Grid};
 use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+/// Where the signal of an `ExecutePositionNode` is traded
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Display, EnumIter)]
+pub enum ExecutionTarget {
+    /// Only the backtests use the signal
+    #[default]
+    Backtest,
+    /// New bars of the replay feed are traded on the paper exchange
+    #[strum(serialize = "Paper exchange")]
+    Paper,
+}
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct ExecutePositionNode;
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecutePositionNode {
+    pub target: ExecutionTarget,
+    pub order_type: OrderType,
+    /// Quote amount spent when a buy signal opens a position
+    pub order_size: f64,
+    /// Distance of limit orders from the middle of the book, as a fraction of the price
+    pub limit_offset: f64,
+}
+
+impl Default for ExecutePositionNode {
+    fn default() -> Self {
+        Self {
+            target: ExecutionTarget::Backtest,
+            order_type: OrderType::Market,
+            order_size: 100.0,
+            limit_offset: 0.001,
+        }
+    }
+}
 
 impl EFNodeFn for ExecutePositionNode {
     fn get_name(&self) -> &'static str {
@@ -16,4 +53,79 @@ impl EFNodeFn for ExecutePositionNode {
     fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
         return &[];
     }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        let mut changed = false;
+        ComboBox::from_id_salt(("execute_target", node_id))
+            .selected_text(self.target.to_string())
+            .show_ui(ui, |ui| {
+                for target in ExecutionTarget::iter() {
+                    changed |= ui
+                        .selectable_value(&mut self.target, target, target.to_string())
+                        .changed();
+                }
+            });
+        if self.target != ExecutionTarget::Paper {
+            return changed;
+        }
+        Grid::new(("execute_paper", node_id)).show(ui, |ui| {
+            ui.label("Order type");
+            ComboBox::from_id_salt(("execute_order_type", node_id))
+                .selected_text(self.order_type.to_string())
+                .show_ui(ui, |ui| {
+                    for order_type in OrderType::iter() {
+                        changed |= ui
+                            .selectable_value(
+                                &mut self.order_type,
+                                order_type,
+                                order_type.to_string(),
+                            )
+                            .changed();
+                    }
+                });
+            ui.end_row();
+            ui.label("Order size");
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.order_size)
+                        .range(0.0..=f64::MAX)
+                        .speed(1.0),
+                )
+                .changed();
+            ui.end_row();
+            if self.order_type != OrderType::Market {
+                ui.label("Limit offset");
+                let mut percent = self.limit_offset * 100.0;
+                if ui
+                    .add(
+                        DragValue::new(&mut percent)
+                            .range(0.0..=50.0)
+                            .speed(0.01)
+                            .suffix("%"),
+                    )
+                    .changed()
+                {
+                    self.limit_offset = percent / 100.0;
+                    changed = true;
+                }
+                ui.end_row();
+            }
+        });
+        changed
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(self).unwrap();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
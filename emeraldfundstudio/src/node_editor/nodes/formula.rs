@@ -0,0 +1,155 @@
+use crate::{
+    formula::{parser::FormulaError, Formula},
+    node_editor::{
+        node_trait::{pin_label, EFNodeFn, NodeDataType},
+        widgets::join_mode_combo_box,
+    },
+    traits::IntoArc,
+    types::time_index::JoinMode,
+};
+use anyhow::{anyhow, Result};
+use egui::{Color32, TextEdit, TextStyle};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormulaSettings {
+    pub formula: String,
+    pub join: JoinMode,
+}
+
+impl Default for FormulaSettings {
+    fn default() -> Self {
+        Self {
+            formula: "(close - sma) / std > 2".to_owned(),
+            join: JoinMode::Strict,
+        }
+    }
+}
+
+/// Computes a formula like `(close - sma) / std > 2`, every variable becomes an input. The
+/// output is a decimal sequence, a mask or, with `signal(buy, sell)`, a signal.
+#[derive(Deserialize)]
+#[serde(from = "FormulaSettings")]
+pub struct FormulaNode {
+    pub settings: FormulaSettings,
+    /// The last formula that compiled, its pins stay while the text has errors
+    compiled: Option<Formula>,
+    error: Option<FormulaError>,
+    /// The text changed since the pins were last updated
+    edited: bool,
+    inputs: Vec<(&'static str, NodeDataType)>,
+    outputs: Vec<(&'static str, NodeDataType)>,
+}
+
+impl From<FormulaSettings> for FormulaNode {
+    fn from(settings: FormulaSettings) -> Self {
+        let mut node = Self {
+            settings,
+            compiled: None,
+            error: None,
+            edited: false,
+            inputs: vec![],
+            outputs: vec![],
+        };
+        node.compile();
+        node
+    }
+}
+
+impl Default for FormulaNode {
+    fn default() -> Self {
+        FormulaSettings::default().into()
+    }
+}
+
+impl FormulaNode {
+    /// Only shows the error of the text being edited, the pins stay as they are
+    fn check(&mut self) {
+        self.error = Formula::compile(&self.settings.formula).err();
+    }
+
+    fn compile(&mut self) {
+        match Formula::compile(&self.settings.formula) {
+            Ok(formula) => {
+                self.inputs = formula
+                    .variables
+                    .iter()
+                    .map(|variable| (pin_label(&variable.name), variable.data_type))
+                    .collect();
+                self.outputs = vec![("Result", formula.output)];
+                self.compiled = Some(formula);
+                self.error = None;
+            }
+            Err(error) => self.error = Some(error),
+        }
+    }
+}
+
+impl EFNodeFn for FormulaNode {
+    fn get_name(&self) -> &'static str {
+        "FormulaNode"
+    }
+
+    fn get_inputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &self.inputs;
+    }
+
+    fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &self.outputs;
+    }
+
+    fn process_data(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        if let Some(error) = &self.error {
+            return Err(anyhow!("{}", error));
+        }
+        let formula = self
+            .compiled
+            .as_ref()
+            .ok_or_else(|| anyhow!("Formula didn't compile"))?;
+        return Ok(vec![formula
+            .evaluate(input_args, self.settings.join, &self.settings.formula)?
+            .into_arc()]);
+    }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        let mut result = false;
+        let response = ui.add(
+            TextEdit::singleline(&mut self.settings.formula)
+                .font(TextStyle::Monospace)
+                .desired_width(200.0 * scale),
+        );
+        if response.changed() {
+            self.check();
+            self.edited = true;
+        }
+        if response.lost_focus() && self.edited {
+            // Pins only change once editing is done and the text compiles, so typing neither
+            // drops wires nor interns a label for every keystroke
+            self.compile();
+            self.edited = false;
+            result = true;
+        }
+        if self.inputs.len() > 1 {
+            result |= join_mode_combo_box(ui, node_id, &mut self.settings.join);
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error.to_string());
+        }
+        result
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(&self.settings).unwrap();
+    }
+}
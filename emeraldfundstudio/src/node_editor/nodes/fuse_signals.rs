@@ -1,7 +1,13 @@
 use crate::{
-    node_editor::node_trait::{EFNodeFn, NodeDataType, NodeDataTypeWithValue},
+    node_editor::{
+        node_trait::{EFNodeFn, NodeDataType, NodeDataTypeWithValue},
+        widgets::join_mode_combo_box,
+    },
     traits::IntoArc,
-    types::signal::Signal,
+    types::{
+        signal::Signal,
+        time_index::{check_length, join, take, Indexed, JoinMode},
+    },
 };
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
@@ -9,7 +15,11 @@ use strum::IntoEnumIterator;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(default)]
-pub struct FuseSignalsNode {}
+pub struct FuseSignalsNode {
+    /// Signals are events, so a left join only takes the second signal on its own
+    /// timestamps instead of forward-filling it
+    pub join: JoinMode,
+}
 
 impl EFNodeFn for FuseSignalsNode {
     fn get_name(&self) -> &'static str {
@@ -37,9 +47,14 @@ impl EFNodeFn for FuseSignalsNode {
 
         if let NodeDataTypeWithValue::Signal(sig0) = &*input_args[0] {
             if let NodeDataTypeWithValue::Signal(sig1) = &*input_args[1] {
-                let fused_signal: Signal = sig0
+                check_length(&sig0.index, sig0.len())?;
+                check_length(&sig1.index, sig1.len())?;
+                let joined = join(&sig0.index, &sig1.index, self.join)?;
+                let sig0_values = take(sig0, &joined.left, 0);
+                let sig1_values = take(sig1, &joined.right_exact(&sig1.index), 0);
+                let fused_signal: Signal = sig0_values
                     .iter()
-                    .zip(sig1.iter())
+                    .zip(sig1_values.iter())
                     .map(|(sig0, sig1)| {
                         if *sig0 == 0 {
                             return *sig1;
@@ -48,9 +63,28 @@ impl EFNodeFn for FuseSignalsNode {
                         }
                     })
                     .collect();
-                return Ok(vec![NodeDataTypeWithValue::Signal(fused_signal).into_arc()]);
+                return Ok(vec![NodeDataTypeWithValue::Signal(Indexed::new(
+                    joined.index,
+                    fused_signal,
+                ))
+                .into_arc()]);
             }
         }
         return Err(anyhow!("Unknown input"));
     }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        join_mode_combo_box(ui, node_id, &mut self.join)
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(self).unwrap();
+    }
 }
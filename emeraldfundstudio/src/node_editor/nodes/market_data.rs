@@ -1,13 +1,339 @@
+use std::any::Any;
+
 use crate::{
-    node_editor::node_trait::{EFNodeFn, NodeDataType, NodeDataTypeWithValue},
+    data_store::quality::{repair, RepairMode, DEFAULT_OUTLIER_THRESHOLD},
+    node_editor::node_trait::{
+        EFNodeFn, NodeDataType, NodeDataTypeWithValue, NodeParameter, ParameterKind,
+    },
+    node_runners::streaming::live_candles,
     traits::IntoArc,
-    types::candles::generate_candles,
+    types::{
+        candles::{correlate_candles, generate_candles, CandleGenerator, Candles, MarketInfo},
+        timeframe::Timeframe,
+        timestamp::TimeStamp,
+    },
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use egui::{ComboBox, DragValue, Grid, TextEdit, Ui};
 use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+const MAX_LEN: usize = 100_000;
+const MAX_SYMBOLS: usize = 8;
+/// Highest GARCH alpha + beta the header allows, the generator needs them below 1
+const MAX_GARCH_PERSISTENCE: f64 = 0.99;
+const OUTPUTS: [(&str, NodeDataType); MAX_SYMBOLS] = [
+    ("Candles", NodeDataType::Candles),
+    ("Candles 2", NodeDataType::Candles),
+    ("Candles 3", NodeDataType::Candles),
+    ("Candles 4", NodeDataType::Candles),
+    ("Candles 5", NodeDataType::Candles),
+    ("Candles 6", NodeDataType::Candles),
+    ("Candles 7", NodeDataType::Candles),
+    ("Candles 8", NodeDataType::Candles),
+];
+
+const SECONDS_PER_DAY: TimeStamp = 24 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum DataSource {
+    #[default]
+    #[strum(serialize = "Synthetic")]
+    Synthetic,
+    /// Candles imported into the local data store
+    #[strum(serialize = "Data store")]
+    Store,
+    /// Bars received from a replay or live feed so far
+    #[strum(serialize = "Replay feed")]
+    Replay,
+}
+
+/// One market, every symbol gets its own output
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SymbolSpec {
+    pub exchange: String,
+    pub pair: String,
+    /// Multiplies the generator's start price
+    pub price_scale: f64,
+    /// How much the symbol follows the first symbol, ignored for the first symbol itself
+    pub correlation: f64,
+}
+
+impl Default for SymbolSpec {
+    fn default() -> Self {
+        Self {
+            exchange: "synthetic".to_owned(),
+            pair: "SYN-USD".to_owned(),
+            price_scale: 1.0,
+            correlation: 0.8,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarketDataNode {
+    pub source: DataSource,
+    /// Also used for the timeframe of stored candles
+    #[serde(flatten)]
+    pub generator: CandleGenerator,
+    pub symbols: Vec<SymbolSpec>,
+    /// First day of stored candles as "YYYY-MM-DD", empty for all of them
+    pub start: String,
+    /// Last day of stored candles as "YYYY-MM-DD", empty for all of them
+    pub end: String,
+    /// What to do with missing bars and outliers in stored candles
+    pub repair: RepairMode,
+}
+
+impl Default for MarketDataNode {
+    fn default() -> Self {
+        Self {
+            source: DataSource::Synthetic,
+            generator: CandleGenerator::default(),
+            symbols: vec![SymbolSpec::default()],
+            start: String::new(),
+            end: String::new(),
+            repair: RepairMode::Keep,
+        }
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct MarketDataNode;
+/// Start of the day, `None` for an empty text
+fn parse_date(text: &str) -> Result<Option<TimeStamp>> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map_err(|e| anyhow!("Invalid date \"{}\", use YYYY-MM-DD: {}", text, e))?;
+    let timestamp = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    Ok(Some(timestamp.max(0) as TimeStamp))
+}
+
+impl MarketDataNode {
+    /// Candles of every symbol. Each symbol gets its own seed, all of them share the timestamps.
+    pub fn generate(&self) -> Result<Vec<Candles>> {
+        if self.symbols.is_empty() {
+            return Err(anyhow!("MarketDataNode needs at least one symbol"));
+        }
+        let mut result: Vec<Candles> = Vec::with_capacity(self.symbols.len());
+        for (idx, symbol) in self.symbols.iter().take(MAX_SYMBOLS).enumerate() {
+            let generator = CandleGenerator {
+                seed: self.generator.seed.wrapping_add(idx as u64),
+                start_price: self.generator.start_price * symbol.price_scale,
+                ..self.generator.clone()
+            };
+            let mut df = generate_candles(&generator)?;
+            if let Some(leader) = result.first() {
+                df = correlate_candles(&leader.df, &df, symbol.correlation)?;
+            }
+            let info = MarketInfo {
+                exchange: symbol.exchange.clone(),
+                pair: symbol.pair.clone(),
+                timeframe_seconds: generator.timeframe.seconds(),
+            };
+            result.push(Candles::new(info, df));
+        }
+        Ok(result)
+    }
+
+    /// Candles of every symbol from the data store, each symbol is looked up by its exchange
+    /// and pair
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_stored(&self) -> Result<Vec<Candles>> {
+        if self.symbols.is_empty() {
+            return Err(anyhow!("MarketDataNode needs at least one symbol"));
+        }
+        let start = parse_date(&self.start)?;
+        let end = parse_date(&self.end)?.map(|end| end + SECONDS_PER_DAY);
+        let mut store = crate::data_store::store::CANDLE_STORE.lock();
+        let mut result = Vec::with_capacity(self.symbols.len());
+        for symbol in self.symbols.iter().take(MAX_SYMBOLS) {
+            let info = MarketInfo {
+                exchange: symbol.exchange.clone(),
+                pair: symbol.pair.clone(),
+                timeframe_seconds: self.generator.timeframe.seconds(),
+            };
+            let mut candles = store.query(&info, start, end)?;
+            candles.df = repair(
+                &candles.df,
+                info.timeframe_seconds,
+                self.repair,
+                DEFAULT_OUTLIER_THRESHOLD,
+            )?;
+            result.push(candles);
+        }
+        Ok(result)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn load_stored(&self) -> Result<Vec<Candles>> {
+        Err(anyhow!(
+            "The data store is only available in the desktop app"
+        ))
+    }
+
+    /// Candles of every symbol that arrived from the feed so far
+    pub fn live(&self) -> Result<Vec<Candles>> {
+        if self.symbols.is_empty() {
+            return Err(anyhow!("MarketDataNode needs at least one symbol"));
+        }
+        self.symbols
+            .iter()
+            .take(MAX_SYMBOLS)
+            .map(|symbol| {
+                live_candles(&symbol.exchange, &symbol.pair).ok_or_else(|| {
+                    anyhow!(
+                        "No bars of {} {} from the replay feed yet",
+                        symbol.exchange,
+                        symbol.pair
+                    )
+                })
+            })
+            .collect()
+    }
+
+    pub fn candles(&self) -> Result<Vec<Candles>> {
+        match self.source {
+            DataSource::Synthetic => self.generate(),
+            DataSource::Store => self.load_stored(),
+            DataSource::Replay => self.live(),
+        }
+    }
+
+    fn show_range(&mut self, ui: &mut Ui, node_id: egui_snarl::NodeId) -> bool {
+        let mut changed = false;
+        Grid::new(("market_data_range", node_id)).show(ui, |ui| {
+            ui.label("Timeframe");
+            changed |= timeframe_combo_box(ui, node_id, &mut self.generator.timeframe);
+            ui.end_row();
+            for (label, date) in [("From", &mut self.start), ("To", &mut self.end)] {
+                ui.label(label);
+                changed |= ui
+                    .add(
+                        TextEdit::singleline(date)
+                            .hint_text("YYYY-MM-DD")
+                            .desired_width(90.0),
+                    )
+                    .changed();
+                ui.end_row();
+            }
+            ui.label("Gaps and outliers");
+            ComboBox::from_id_salt(("market_data_repair", node_id))
+                .selected_text(self.repair.to_string())
+                .show_ui(ui, |ui| {
+                    for mode in RepairMode::iter() {
+                        changed |= ui
+                            .selectable_value(&mut self.repair, mode, mode.to_string())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+        });
+        if let Err(e) = parse_date(&self.start).and(parse_date(&self.end)) {
+            ui.colored_label(egui::Color32::RED, e.to_string());
+        }
+        changed
+    }
+
+    fn show_symbols(&mut self, ui: &mut Ui, node_id: egui_snarl::NodeId) -> bool {
+        let mut changed = false;
+        let mut removed = None;
+        let can_remove = self.symbols.len() > 1;
+        let synthetic = self.source == DataSource::Synthetic;
+        Grid::new(("market_data_symbols", node_id)).show(ui, |ui| {
+            ui.label("Exchange");
+            ui.label("Pair");
+            if synthetic {
+                ui.label("Price scale");
+                ui.label("Correlation");
+            }
+            ui.end_row();
+            for (idx, symbol) in self.symbols.iter_mut().enumerate() {
+                changed |= ui
+                    .add(TextEdit::singleline(&mut symbol.exchange).desired_width(80.0))
+                    .changed();
+                changed |= ui
+                    .add(TextEdit::singleline(&mut symbol.pair).desired_width(80.0))
+                    .changed();
+                if !synthetic {
+                    if can_remove && ui.button("Remove").clicked() {
+                        removed = Some(idx);
+                    }
+                    ui.end_row();
+                    continue;
+                }
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut symbol.price_scale)
+                            .speed(0.01)
+                            .range(0.0001..=1_000_000.0),
+                    )
+                    .changed();
+                if idx == 0 {
+                    ui.label("");
+                } else {
+                    changed |= ui
+                        .add(
+                            DragValue::new(&mut symbol.correlation)
+                                .speed(0.01)
+                                .range(0.0..=1.0),
+                        )
+                        .changed();
+                }
+                if can_remove && ui.button("Remove").clicked() {
+                    removed = Some(idx);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(idx) = removed {
+            self.symbols.remove(idx);
+            changed = true;
+        }
+        if self.symbols.len() < MAX_SYMBOLS && ui.button("Add symbol").clicked() {
+            self.symbols.push(SymbolSpec {
+                pair: format!("SYN{}-USD", self.symbols.len() + 1),
+                ..Default::default()
+            });
+            changed = true;
+        }
+        changed
+    }
+}
+
+fn decimal(key: &'static str, min: f64, max: f64) -> NodeParameter {
+    NodeParameter {
+        key,
+        kind: ParameterKind::Decimal { min, max },
+    }
+}
+
+fn timeframe_combo_box(ui: &mut Ui, node_id: egui_snarl::NodeId, value: &mut Timeframe) -> bool {
+    let mut changed = false;
+    ComboBox::from_id_salt(("market_data_timeframe", node_id))
+        .selected_text(value.to_string())
+        .show_ui(ui, |ui| {
+            for timeframe in Timeframe::iter() {
+                changed |= ui
+                    .selectable_value(value, timeframe, timeframe.to_string())
+                    .changed();
+            }
+        });
+    changed
+}
+
+fn decimal_row(ui: &mut Ui, label: &str, value: &mut f64, speed: f64, max: f64) -> bool {
+    ui.label(label);
+    let changed = ui
+        .add(DragValue::new(value).speed(speed).range(0.0..=max))
+        .changed();
+    ui.end_row();
+    changed
+}
 
 impl EFNodeFn for MarketDataNode {
     fn get_name(&self) -> &'static str {
@@ -19,13 +345,179 @@ impl EFNodeFn for MarketDataNode {
     }
 
     fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
-        return &[("Candles", NodeDataType::Candles)];
+        return &OUTPUTS[..self.symbols.len().clamp(1, MAX_SYMBOLS)];
     }
+
+    fn get_parameters(&self) -> Vec<NodeParameter> {
+        vec![
+            NodeParameter {
+                key: "timeframe",
+                kind: ParameterKind::Choice(
+                    Timeframe::iter()
+                        .map(|timeframe| serde_json::to_value(timeframe).unwrap())
+                        .collect(),
+                ),
+            },
+            decimal("drift", -10.0, 10.0),
+            decimal("volatility", 0.0, 10.0),
+            decimal("garch_alpha", 0.0, 1.0),
+            decimal("garch_beta", 0.0, 1.0),
+            decimal("regime_switch", 0.0, 1.0),
+            decimal("trend_strength", 0.0, 1.0),
+            decimal("range_reversion", 0.0, 1.0),
+            decimal("jump_probability", 0.0, 1.0),
+            decimal("jump_mean", -1.0, 1.0),
+            decimal("jump_volatility", 0.0, 1.0),
+        ]
+    }
+
     fn process_data(
         &self,
         input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
     ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
-        let candles = generate_candles(21, 500)?;
-        return Ok(vec![NodeDataTypeWithValue::Candles(candles).into_arc()]);
+        return Ok(self
+            .candles()?
+            .into_iter()
+            .map(|candles| NodeDataTypeWithValue::Candles(candles).into_arc())
+            .collect());
+    }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        let mut changed = false;
+        ComboBox::from_id_salt(("market_data_source", node_id))
+            .selected_text(self.source.to_string())
+            .show_ui(ui, |ui| {
+                for source in DataSource::iter() {
+                    changed |= ui
+                        .selectable_value(&mut self.source, source, source.to_string())
+                        .changed();
+                }
+            });
+        ui.menu_button("Symbols", |ui| {
+            changed |= self.show_symbols(ui, node_id);
+        });
+        match self.source {
+            DataSource::Synthetic => {}
+            DataSource::Store => {
+                ui.menu_button("Range", |ui| {
+                    changed |= self.show_range(ui, node_id);
+                });
+                return changed;
+            }
+            DataSource::Replay => return changed,
+        }
+        let generator = &mut self.generator;
+        ui.menu_button("Generator", |ui| {
+            Grid::new(("market_data_generator", node_id)).show(ui, |ui| {
+                ui.label("Seed");
+                changed |= ui.add(DragValue::new(&mut generator.seed)).changed();
+                ui.end_row();
+                ui.label("Candles");
+                changed |= ui
+                    .add(DragValue::new(&mut generator.len).range(1..=MAX_LEN))
+                    .changed();
+                ui.end_row();
+                ui.label("Timeframe");
+                changed |= timeframe_combo_box(ui, node_id, &mut generator.timeframe);
+                ui.end_row();
+                ui.label("Start price");
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut generator.start_price)
+                            .speed(0.1)
+                            .range(0.0001..=f64::MAX),
+                    )
+                    .changed();
+                ui.end_row();
+                ui.label("Drift (yearly)");
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut generator.drift)
+                            .speed(0.01)
+                            .range(-10.0..=10.0),
+                    )
+                    .changed();
+                ui.end_row();
+                changed |= decimal_row(
+                    ui,
+                    "Volatility (yearly)",
+                    &mut generator.volatility,
+                    0.01,
+                    10.0,
+                );
+
+                ui.label("GARCH");
+                ui.end_row();
+                // Alpha and beta must sum to less than 1, each is limited by the other
+                let max_alpha = (MAX_GARCH_PERSISTENCE - generator.garch_beta).max(0.0);
+                changed |= decimal_row(ui, "Alpha", &mut generator.garch_alpha, 0.01, max_alpha);
+                let max_beta = (MAX_GARCH_PERSISTENCE - generator.garch_alpha).max(0.0);
+                changed |= decimal_row(ui, "Beta", &mut generator.garch_beta, 0.01, max_beta);
+
+                ui.label("Regimes");
+                ui.end_row();
+                changed |= decimal_row(
+                    ui,
+                    "Switch chance",
+                    &mut generator.regime_switch,
+                    0.001,
+                    1.0,
+                );
+                changed |= decimal_row(
+                    ui,
+                    "Trend strength",
+                    &mut generator.trend_strength,
+                    0.01,
+                    1.0,
+                );
+                changed |= decimal_row(
+                    ui,
+                    "Range reversion",
+                    &mut generator.range_reversion,
+                    0.01,
+                    1.0,
+                );
+
+                ui.label("Jumps");
+                ui.end_row();
+                changed |= decimal_row(ui, "Chance", &mut generator.jump_probability, 0.001, 1.0);
+                ui.label("Mean size");
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut generator.jump_mean)
+                            .speed(0.001)
+                            .range(-1.0..=1.0),
+                    )
+                    .changed();
+                ui.end_row();
+                changed |= decimal_row(
+                    ui,
+                    "Size volatility",
+                    &mut generator.jump_volatility,
+                    0.001,
+                    1.0,
+                );
+            });
+            if ui.button("Reset").clicked() {
+                *generator = CandleGenerator::default();
+                changed = true;
+            }
+        });
+        changed
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(self).unwrap();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
@@ -0,0 +1,83 @@
+use crate::node_editor::node_trait::{pin_label, EFNodeFn, NodeDataType};
+use anyhow::{anyhow, Result};
+use egui::Color32;
+
+pub const MISSING_NODE: &str = "MissingNode";
+
+/// Stands in for a node that can't be loaded, because its type is unknown or its arguments
+/// are broken. Keeps the arguments as they were so saving doesn't lose them.
+pub struct MissingNode {
+    pub node_name: String,
+    pub arguments: serde_json::Value,
+    pub error: String,
+    inputs: Vec<(&'static str, NodeDataType)>,
+    outputs: Vec<(&'static str, NodeDataType)>,
+}
+
+impl MissingNode {
+    /// `inputs` and `outputs` are the types of the pins the node's wires use
+    pub fn new(
+        node_name: String,
+        arguments: serde_json::Value,
+        error: String,
+        inputs: Vec<NodeDataType>,
+        outputs: Vec<NodeDataType>,
+    ) -> Self {
+        let pins = |prefix: &str, types: Vec<NodeDataType>| {
+            types
+                .into_iter()
+                .enumerate()
+                .map(|(i, data_type)| (pin_label(&format!("{} {}", prefix, i + 1)), data_type))
+                .collect()
+        };
+        Self {
+            node_name,
+            arguments,
+            error,
+            inputs: pins("Input", inputs),
+            outputs: pins("Output", outputs),
+        }
+    }
+}
+
+impl EFNodeFn for MissingNode {
+    fn get_name(&self) -> &'static str {
+        MISSING_NODE
+    }
+
+    fn get_inputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &self.inputs;
+    }
+
+    fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &self.outputs;
+    }
+
+    fn process_data(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        return Err(anyhow!(
+            "{} can't be loaded: {}",
+            self.node_name,
+            self.error
+        ));
+    }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        ui.colored_label(Color32::RED, &self.error)
+            .on_hover_text("The node's data is kept and saved, it loads again once fixed");
+        false
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return self.arguments.clone();
+    }
+}
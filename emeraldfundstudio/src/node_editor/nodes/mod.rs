@@ -1,8 +1,16 @@
+pub mod align_back;
 pub mod compare;
+pub mod composite;
+pub mod ema;
 pub mod execute_position;
+pub mod formula;
 pub mod fuse_signals;
 pub mod market_data;
+pub mod missing;
 pub mod preview;
+pub mod resample;
+pub mod script;
 pub mod sma;
 pub mod split_candles;
+pub mod spread;
 pub mod to_signal;
@@ -0,0 +1,252 @@
+use crate::{
+    node_editor::node_trait::{
+        EFNodeFn, NodeDataType, NodeDataTypeWithValue, NodeParameter, ParameterKind,
+    },
+    traits::IntoArc,
+    types::{
+        candles::{Candles, MarketInfo},
+        timeframe::Timeframe,
+    },
+};
+use anyhow::{anyhow, Result};
+use egui::{ComboBox, DragValue};
+use polars::{
+    frame::DataFrame,
+    prelude::{col, lit, IntoLazy},
+};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+/// Timestamp of the last base candle that went into a resampled candle. A resampled candle is
+/// only known once that base candle has closed, see `AlignBackNode`.
+pub const LAST_TIMESTAMP_COLUMN: &str = "last_timestamp";
+
+const MAX_CUSTOM_MINUTES: u64 = 60 * 24 * 30;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResampleNode {
+    pub timeframe: Timeframe,
+    /// Overrides `timeframe` when set
+    pub custom_minutes: Option<u64>,
+}
+
+impl Default for ResampleNode {
+    fn default() -> Self {
+        Self {
+            timeframe: Timeframe::H1,
+            custom_minutes: None,
+        }
+    }
+}
+
+impl ResampleNode {
+    fn seconds(&self) -> u64 {
+        match self.custom_minutes {
+            Some(minutes) => minutes.max(1) * 60,
+            None => self.timeframe.seconds(),
+        }
+    }
+}
+
+/// Aggregates candles into buckets of `seconds`, aligned to the unix epoch (so days start at
+/// 00:00 UTC). The timestamp of a bucket is its start, like the timestamp of a base candle.
+pub fn resample_candles(candles: &DataFrame, seconds: u64) -> Result<DataFrame> {
+    if seconds == 0 {
+        return Err(anyhow!("Timeframe can't be 0"));
+    }
+    let resampled = candles
+        .clone()
+        .lazy()
+        .with_column((col("timestamp") / lit(seconds) * lit(seconds)).alias("bucket"))
+        .group_by_stable([col("bucket")])
+        .agg([
+            col("open").first(),
+            col("high").max(),
+            col("low").min(),
+            col("close").last(),
+            col("volume").sum(),
+            col("timestamp").last().alias(LAST_TIMESTAMP_COLUMN),
+        ])
+        .select([
+            col("open"),
+            col("high"),
+            col("low"),
+            col("close"),
+            col("volume"),
+            col("bucket").alias("timestamp"),
+            col(LAST_TIMESTAMP_COLUMN),
+        ])
+        .collect()?;
+    Ok(resampled)
+}
+
+impl EFNodeFn for ResampleNode {
+    fn get_name(&self) -> &'static str {
+        "ResampleNode"
+    }
+
+    fn get_inputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &[("Candles", NodeDataType::Candles)];
+    }
+
+    fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &[("Candles", NodeDataType::Candles)];
+    }
+
+    fn get_parameters(&self) -> Vec<NodeParameter> {
+        vec![
+            NodeParameter {
+                key: "timeframe",
+                kind: ParameterKind::Choice(
+                    Timeframe::iter()
+                        .map(|timeframe| serde_json::to_value(timeframe).unwrap())
+                        .collect(),
+                ),
+            },
+            // Sweeping this overrides the timeframe, like picking "Custom"
+            NodeParameter {
+                key: "custom_minutes",
+                kind: ParameterKind::Integer {
+                    min: 1,
+                    max: MAX_CUSTOM_MINUTES as i64,
+                },
+            },
+        ]
+    }
+
+    fn process_data(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        if input_args.len() != 1 {
+            return Err(anyhow!("should have 1 input!"));
+        }
+
+        if let NodeDataTypeWithValue::Candles(candles) = &*input_args[0] {
+            let seconds = self.seconds();
+            let resampled = Candles::new(
+                MarketInfo {
+                    timeframe_seconds: seconds,
+                    ..candles.info.clone()
+                },
+                resample_candles(&candles.df, seconds)?,
+            );
+            return Ok(vec![NodeDataTypeWithValue::Candles(resampled).into_arc()]);
+        }
+        return Err(anyhow!("First argument must be a DataFrame"));
+    }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        let mut result = false;
+        let selected_text = match self.custom_minutes {
+            Some(_) => "Custom".to_owned(),
+            None => self.timeframe.to_string(),
+        };
+        ui.horizontal(|ui| {
+            ComboBox::from_id_salt(("resample_timeframe", node_id))
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    for timeframe in Timeframe::iter() {
+                        let selected = self.custom_minutes.is_none() && self.timeframe == timeframe;
+                        if ui
+                            .selectable_label(selected, timeframe.to_string())
+                            .clicked()
+                        {
+                            self.timeframe = timeframe;
+                            self.custom_minutes = None;
+                            result = true;
+                        }
+                    }
+                    if ui
+                        .selectable_label(self.custom_minutes.is_some(), "Custom")
+                        .clicked()
+                    {
+                        self.custom_minutes = Some(self.timeframe.seconds() / 60);
+                        result = true;
+                    }
+                });
+            if let Some(minutes) = &mut self.custom_minutes {
+                result |= ui
+                    .add(
+                        DragValue::new(minutes)
+                            .range(1..=MAX_CUSTOM_MINUTES)
+                            .suffix("m"),
+                    )
+                    .changed();
+            }
+        });
+        result
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(self).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::df;
+
+    use super::*;
+
+    /// 12 one-minute candles, the lowest low in the 3rd and the highest high in the 8th
+    fn minute_candles() -> DataFrame {
+        let prices = |offset: f64| (0..12).map(move |i| i as f64 + offset);
+        let highs: Vec<f64> = prices(101.0)
+            .enumerate()
+            .map(|(i, high)| if i == 7 { 200.0 } else { high })
+            .collect();
+        let lows: Vec<f64> = prices(99.0)
+            .enumerate()
+            .map(|(i, low)| if i == 2 { 50.0 } else { low })
+            .collect();
+        df![
+            "open" => prices(100.0).collect::<Vec<_>>(),
+            "high" => highs,
+            "low" => lows,
+            "close" => prices(100.5).collect::<Vec<_>>(),
+            "volume" => prices(1.0).collect::<Vec<_>>(),
+            "timestamp" => (0..12).map(|i| i * 60).collect::<Vec<u64>>(),
+        ]
+        .unwrap()
+    }
+
+    fn column(df: &DataFrame, column: &str) -> Vec<f64> {
+        df.column(column)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn resamples_minutes_into_five_minutes() {
+        let resampled = resample_candles(&minute_candles(), 300).unwrap();
+        // The last bucket only has 2 candles so far
+        assert_eq!(column(&resampled, "open"), vec![100.0, 105.0, 110.0]);
+        assert_eq!(column(&resampled, "high"), vec![105.0, 200.0, 112.0]);
+        assert_eq!(column(&resampled, "low"), vec![50.0, 104.0, 109.0]);
+        assert_eq!(column(&resampled, "close"), vec![104.5, 109.5, 111.5]);
+        assert_eq!(column(&resampled, "volume"), vec![15.0, 40.0, 23.0]);
+        let timestamps = |column: &str| -> Vec<u64> {
+            resampled
+                .column(column)
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        assert_eq!(timestamps("timestamp"), vec![0, 300, 600]);
+        assert_eq!(timestamps(LAST_TIMESTAMP_COLUMN), vec![240, 540, 660]);
+    }
+}
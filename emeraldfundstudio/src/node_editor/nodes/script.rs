@@ -0,0 +1,226 @@
+use crate::{
+    node_editor::{
+        node_trait::{pin_label, EFNodeFn, NodeDataType},
+        widgets::{data_type_combo_box, join_mode_combo_box},
+    },
+    script::{Script, ScriptMode, ScriptPin, ScriptSettings},
+};
+use anyhow::{anyhow, Result};
+use egui::{Color32, ComboBox, DragValue, Grid, TextEdit};
+use serde::Deserialize;
+use strum::IntoEnumIterator;
+
+const INPUT_TYPES: [NodeDataType; 4] = [
+    NodeDataType::DecimalSequence,
+    NodeDataType::Mask,
+    NodeDataType::Signal,
+    NodeDataType::Candles,
+];
+const OUTPUT_TYPES: [NodeDataType; 3] = [
+    NodeDataType::DecimalSequence,
+    NodeDataType::Mask,
+    NodeDataType::Signal,
+];
+
+/// Runs a user script (Rhai) with declared inputs and outputs, for indicators that don't
+/// deserve a node of their own
+#[derive(Deserialize)]
+#[serde(from = "ScriptSettings")]
+pub struct ScriptNode {
+    pub settings: ScriptSettings,
+    /// The last version that compiled, its pins stay while the script has errors
+    compiled: Option<Script>,
+    error: Option<String>,
+    inputs: Vec<(&'static str, NodeDataType)>,
+    outputs: Vec<(&'static str, NodeDataType)>,
+}
+
+impl From<ScriptSettings> for ScriptNode {
+    fn from(settings: ScriptSettings) -> Self {
+        let mut node = Self {
+            settings,
+            compiled: None,
+            error: None,
+            inputs: vec![],
+            outputs: vec![],
+        };
+        node.compile();
+        node
+    }
+}
+
+impl Default for ScriptNode {
+    fn default() -> Self {
+        ScriptSettings::default().into()
+    }
+}
+
+fn pins(pins: &[ScriptPin]) -> Vec<(&'static str, NodeDataType)> {
+    pins.iter()
+        .map(|pin| (pin_label(&pin.name), pin.data_type))
+        .collect()
+}
+
+/// Name, type and removal of every pin, returns whether anything changed
+fn show_pins(
+    ui: &mut egui::Ui,
+    node_id: egui_snarl::NodeId,
+    label: &str,
+    pins: &mut Vec<ScriptPin>,
+    types: &[NodeDataType],
+) -> bool {
+    let mut changed = false;
+    let mut removed = None;
+    ui.strong(label);
+    Grid::new(("script_pins", label, node_id)).show(ui, |ui| {
+        for (i, pin) in pins.iter_mut().enumerate() {
+            changed |= ui
+                .add(TextEdit::singleline(&mut pin.name).desired_width(100.0))
+                .changed();
+            changed |= data_type_combo_box(
+                ui,
+                ("script_pin_type", label, node_id, i),
+                &mut pin.data_type,
+                types,
+            );
+            if ui.small_button("🗑").clicked() {
+                removed = Some(i);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(i) = removed {
+        pins.remove(i);
+        changed = true;
+    }
+    if ui.button(format!("+ {}", label)).clicked() {
+        let prefix = label.to_lowercase();
+        let name = (1..)
+            .map(|n| format!("{}{}", prefix, n))
+            .find(|name| pins.iter().all(|pin| pin.name != *name))
+            .unwrap();
+        pins.push(ScriptPin {
+            name,
+            data_type: types[0],
+        });
+        changed = true;
+    }
+    changed
+}
+
+impl ScriptNode {
+    fn compile(&mut self) {
+        match Script::compile(&self.settings) {
+            Ok(script) => {
+                self.inputs = pins(&script.settings.inputs);
+                self.outputs = pins(&script.settings.outputs);
+                self.compiled = Some(script);
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+}
+
+impl EFNodeFn for ScriptNode {
+    fn get_name(&self) -> &'static str {
+        "ScriptNode"
+    }
+
+    fn get_inputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &self.inputs;
+    }
+
+    fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &self.outputs;
+    }
+
+    fn process_data(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        if let Some(error) = &self.error {
+            return Err(anyhow!("{}", error));
+        }
+        let script = self
+            .compiled
+            .as_ref()
+            .ok_or_else(|| anyhow!("Script didn't compile"))?;
+        return script.run(input_args);
+    }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        let mut changed = false;
+        let settings = &mut self.settings;
+        ComboBox::from_id_salt(("script_mode", node_id))
+            .selected_text(settings.mode.to_string())
+            .show_ui(ui, |ui| {
+                for mode in ScriptMode::iter() {
+                    changed |= ui
+                        .selectable_value(&mut settings.mode, mode, mode.to_string())
+                        .changed();
+                }
+            });
+        ui.menu_button("Script", |ui| {
+            changed |= ui
+                .add(
+                    TextEdit::multiline(&mut settings.code)
+                        .code_editor()
+                        .desired_rows(12)
+                        .desired_width(400.0),
+                )
+                .changed();
+        });
+        ui.menu_button("Pins", |ui| {
+            changed |= show_pins(ui, node_id, "Input", &mut settings.inputs, &INPUT_TYPES);
+            ui.separator();
+            changed |= show_pins(ui, node_id, "Output", &mut settings.outputs, &OUTPUT_TYPES);
+        });
+        ui.menu_button("Limits", |ui| {
+            let limits = &mut settings.limits;
+            Grid::new(("script_limits", node_id)).show(ui, |ui| {
+                ui.label("Operations per bar");
+                changed |= ui
+                    .add(DragValue::new(&mut limits.max_operations).range(1_000..=1_000_000_000))
+                    .changed();
+                ui.end_row();
+                ui.label("Time");
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut limits.max_millis)
+                            .range(10..=60_000)
+                            .suffix(" ms"),
+                    )
+                    .changed();
+                ui.end_row();
+                ui.label("Array, map and string size");
+                changed |= ui
+                    .add(DragValue::new(&mut limits.max_collection_size).range(1_000..=100_000_000))
+                    .changed();
+                ui.end_row();
+            });
+        });
+        if settings.inputs.len() > 1 {
+            changed |= join_mode_combo_box(ui, node_id, &mut settings.join);
+        }
+        if changed {
+            // Pins only change when the script compiles, so typing a name doesn't drop wires
+            self.compile();
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+        changed
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(&self.settings).unwrap();
+    }
+}
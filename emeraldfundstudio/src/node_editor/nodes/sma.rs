@@ -1,9 +1,50 @@
-use crate::node_editor::node_trait::{EFNodeFn, NodeDataType};
-use anyhow::Result;
+use crate::{
+    node_editor::node_trait::{
+        EFNodeFn, NodeDataType, NodeDataTypeWithValue, NodeParameter, NodeState, ParameterKind,
+    },
+    traits::IntoArc,
+    types::{
+        decimal_sequence::DecimalSequence,
+        rolling::{rolling_batch, rolling_update, RollingSma},
+        time_index::Indexed,
+    },
+};
+use anyhow::{anyhow, Result};
+use egui::DragValue;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct SMANode;
+pub const MAX_PERIOD: usize = 500;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SMANode {
+    pub period: usize,
+}
+
+impl Default for SMANode {
+    fn default() -> Self {
+        Self { period: 14 }
+    }
+}
+
+pub fn sma(seq: &DecimalSequence, period: usize) -> DecimalSequence {
+    rolling_batch(seq, RollingSma::new(period))
+}
+
+impl SMANode {
+    fn input<'a>(
+        &self,
+        input_args: &'a [crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+    ) -> Result<&'a Indexed<DecimalSequence>> {
+        if input_args.len() != 1 {
+            return Err(anyhow!("should have 1 input!"));
+        }
+        if let NodeDataTypeWithValue::DecimalSequence(seq) = &*input_args[0] {
+            return Ok(seq);
+        }
+        return Err(anyhow!("Unknown input"));
+    }
+}
 
 impl EFNodeFn for SMANode {
     fn get_name(&self) -> &'static str {
@@ -18,10 +59,56 @@ impl EFNodeFn for SMANode {
         return &[("Output", NodeDataType::DecimalSequence)];
     }
 
+    fn get_parameters(&self) -> Vec<NodeParameter> {
+        vec![NodeParameter {
+            key: "period",
+            kind: ParameterKind::Integer {
+                min: 1,
+                max: MAX_PERIOD as i64,
+            },
+        }]
+    }
+
     fn process_data(
         &self,
         input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
     ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
-        return Ok(vec![input_args[0].clone()]);
+        let seq = self.input(input_args)?;
+        return Ok(vec![NodeDataTypeWithValue::DecimalSequence(
+            seq.with_values(sma(seq, self.period)),
+        )
+        .into_arc()]);
+    }
+
+    fn process_update(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+        state: &mut Option<NodeState>,
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        let seq = self.input(input_args)?;
+        let result = rolling_update(seq, RollingSma::new(self.period), state)?;
+        return Ok(vec![
+            NodeDataTypeWithValue::DecimalSequence(result).into_arc()
+        ]);
+    }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        let response = ui.add(
+            DragValue::new(&mut self.period)
+                .range(1..=MAX_PERIOD)
+                .prefix("Period: "),
+        );
+        response.changed()
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(self).unwrap();
     }
 }
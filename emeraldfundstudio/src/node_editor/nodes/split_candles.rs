@@ -3,6 +3,7 @@ use crate::{
         CheapCloneNodeDataTypeWithValue, EFNodeFn, NodeDataType, NodeDataTypeWithValue,
     },
     traits::IntoArc,
+    types::time_index::{Indexed, TimeIndex},
 };
 use anyhow::{anyhow, Result};
 use polars::prelude::{ChunkedArray, Float64Type};
@@ -12,9 +13,10 @@ use serde::{Deserialize, Serialize};
 pub struct SplitCandlesNode;
 
 fn nd_column_to_decimal_sequence(
+    index: &TimeIndex,
     series: &ChunkedArray<Float64Type>,
 ) -> CheapCloneNodeDataTypeWithValue {
-    NodeDataTypeWithValue::DecimalSequence(series.clone()).into_arc()
+    NodeDataTypeWithValue::DecimalSequence(Indexed::new(index.clone(), series.clone())).into_arc()
 }
 
 impl EFNodeFn for SplitCandlesNode {
@@ -44,7 +46,9 @@ impl EFNodeFn for SplitCandlesNode {
             return Err(anyhow!("should have 1 input!"));
         }
 
-        if let NodeDataTypeWithValue::Candles(df) = &*input_args[0] {
+        if let NodeDataTypeWithValue::Candles(candles) = &*input_args[0] {
+            let df = &candles.df;
+            let index = candles.time_index()?;
             let opens = df.column("open").unwrap().f64().unwrap();
             let highs = df.column("high").unwrap().f64().unwrap();
             let lows = df.column("low").unwrap().f64().unwrap();
@@ -53,11 +57,11 @@ impl EFNodeFn for SplitCandlesNode {
             // let timestamps = df.column("timestamp").unwrap().u64().unwrap();
 
             return Ok(vec![
-                nd_column_to_decimal_sequence(opens),
-                nd_column_to_decimal_sequence(highs),
-                nd_column_to_decimal_sequence(lows),
-                nd_column_to_decimal_sequence(closes),
-                nd_column_to_decimal_sequence(volumes),
+                nd_column_to_decimal_sequence(&index, opens),
+                nd_column_to_decimal_sequence(&index, highs),
+                nd_column_to_decimal_sequence(&index, lows),
+                nd_column_to_decimal_sequence(&index, closes),
+                nd_column_to_decimal_sequence(&index, volumes),
             ]);
         }
 
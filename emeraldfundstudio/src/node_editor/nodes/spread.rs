@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use crate::{
+    node_editor::node_trait::{
+        EFNodeFn, NodeDataType, NodeDataTypeWithValue, NodeParameter, ParameterKind,
+    },
+    traits::IntoArc,
+    types::{candles::Candles, decimal_sequence::DecimalSequence, time_index::Indexed},
+};
+use anyhow::{anyhow, Result};
+use egui::{ComboBox, DragValue};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+#[derive(Debug, Serialize, Deserialize, Default, EnumIter, PartialEq, Eq, Clone, Copy, Display)]
+pub enum SpreadMode {
+    /// `a - hedge_ratio * b`
+    #[default]
+    #[strum(serialize = "Spread")]
+    Spread,
+    /// `a / b`
+    #[strum(serialize = "Ratio")]
+    Ratio,
+    /// `ln(a) - hedge_ratio * ln(b)`
+    #[strum(serialize = "Log Spread")]
+    LogSpread,
+}
+
+/// Relation between the closes of two symbols, for pair trading and arbitrage
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpreadNode {
+    pub mode: SpreadMode,
+    pub hedge_ratio: f64,
+}
+
+impl Default for SpreadNode {
+    fn default() -> Self {
+        Self {
+            mode: SpreadMode::Spread,
+            hedge_ratio: 1.0,
+        }
+    }
+}
+
+/// One value per candle of `a`, `None` where `b` has no candle with the same timestamp
+pub fn spread(
+    a: &Candles,
+    b: &Candles,
+    mode: SpreadMode,
+    hedge_ratio: f64,
+) -> Result<Indexed<DecimalSequence>> {
+    if a.info.timeframe_seconds != b.info.timeframe_seconds {
+        return Err(anyhow!(
+            "Can't compare {} with {}, the timeframes differ",
+            a.info,
+            b.info
+        ));
+    }
+    let b_closes: HashMap<u64, f64> = b
+        .timestamps()?
+        .into_iter()
+        .zip(b.closes()?)
+        .filter_map(|(timestamp, close)| Some((timestamp, close?)))
+        .collect();
+
+    let result: DecimalSequence = a
+        .timestamps()?
+        .into_iter()
+        .zip(a.closes()?)
+        .map(|(timestamp, a_close)| {
+            let a_close = a_close?;
+            let b_close = *b_closes.get(&timestamp)?;
+            let value = match mode {
+                SpreadMode::Spread => a_close - hedge_ratio * b_close,
+                SpreadMode::Ratio => a_close / b_close,
+                SpreadMode::LogSpread => a_close.ln() - hedge_ratio * b_close.ln(),
+            };
+            value.is_finite().then_some(value)
+        })
+        .collect();
+    Ok(Indexed::new(
+        a.time_index()?,
+        result.with_name(format!("{} / {}", a.info.pair, b.info.pair).into()),
+    ))
+}
+
+impl EFNodeFn for SpreadNode {
+    fn get_name(&self) -> &'static str {
+        "SpreadNode"
+    }
+
+    fn get_inputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &[
+            ("Candles A", NodeDataType::Candles),
+            ("Candles B", NodeDataType::Candles),
+        ];
+    }
+
+    fn get_outputs(&self) -> &[(&'static str, crate::node_editor::node_trait::NodeDataType)] {
+        return &[("Output", NodeDataType::DecimalSequence)];
+    }
+
+    fn get_parameters(&self) -> Vec<NodeParameter> {
+        vec![
+            NodeParameter {
+                key: "mode",
+                kind: ParameterKind::Choice(
+                    SpreadMode::iter()
+                        .map(|mode| serde_json::to_value(mode).unwrap())
+                        .collect(),
+                ),
+            },
+            NodeParameter {
+                key: "hedge_ratio",
+                kind: ParameterKind::Decimal {
+                    min: 0.0,
+                    max: 100.0,
+                },
+            },
+        ]
+    }
+
+    fn process_data(
+        &self,
+        input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
+    ) -> Result<Vec<crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue>> {
+        if input_args.len() != 2 {
+            return Err(anyhow!("should have 2 inputs!"));
+        }
+
+        if let NodeDataTypeWithValue::Candles(a) = &*input_args[0] {
+            if let NodeDataTypeWithValue::Candles(b) = &*input_args[1] {
+                return Ok(vec![NodeDataTypeWithValue::DecimalSequence(spread(
+                    a,
+                    b,
+                    self.mode,
+                    self.hedge_ratio,
+                )?)
+                .into_arc()]);
+            }
+        }
+        return Err(anyhow!("Unknown input"));
+    }
+
+    fn show_header(
+        &mut self,
+        node_id: egui_snarl::NodeId,
+        _inputs: &[egui_snarl::InPin],
+        _outputs: &[egui_snarl::OutPin],
+        ui: &mut egui::Ui,
+        scale: f32,
+    ) -> bool {
+        let mut result = false;
+        ui.horizontal(|ui| {
+            ComboBox::from_id_salt(("spread_mode", node_id))
+                .selected_text(self.mode.to_string())
+                .show_ui(ui, |ui| {
+                    for mode in SpreadMode::iter() {
+                        result |= ui
+                            .selectable_value(&mut self.mode, mode, mode.to_string())
+                            .changed();
+                    }
+                });
+            if self.mode != SpreadMode::Ratio {
+                result |= ui
+                    .add(
+                        DragValue::new(&mut self.hedge_ratio)
+                            .speed(0.01)
+                            .range(0.0..=100.0)
+                            .prefix("Hedge: "),
+                    )
+                    .changed();
+            }
+        });
+        result
+    }
+
+    fn export_data(&self) -> serde_json::Value {
+        return serde_json::to_value(self).unwrap();
+    }
+}
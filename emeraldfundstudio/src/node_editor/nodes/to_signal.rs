@@ -1,5 +1,7 @@
 use crate::{
-    node_editor::node_trait::{EFNodeFn, NodeDataType, NodeDataTypeWithValue},
+    node_editor::node_trait::{
+        EFNodeFn, NodeDataType, NodeDataTypeWithValue, NodeParameter, ParameterKind,
+    },
     traits::IntoArc,
 };
 use anyhow::{anyhow, Result};
@@ -35,6 +37,17 @@ impl EFNodeFn for ToSignalNode {
         return &[("Signal", NodeDataType::Signal)];
     }
 
+    fn get_parameters(&self) -> Vec<NodeParameter> {
+        vec![NodeParameter {
+            key: "mode",
+            kind: ParameterKind::Choice(
+                ToSignalMode::iter()
+                    .map(|mode| serde_json::to_value(mode).unwrap())
+                    .collect(),
+            ),
+        }]
+    }
+
     fn process_data(
         &self,
         input_args: &[crate::node_editor::node_trait::CheapCloneNodeDataTypeWithValue],
@@ -57,7 +70,10 @@ impl EFNodeFn for ToSignalNode {
                     }
                 })
                 .collect();
-            return Ok(vec![NodeDataTypeWithValue::Signal(result).into_arc()]);
+            return Ok(vec![NodeDataTypeWithValue::Signal(
+                mask.with_values(result),
+            )
+            .into_arc()]);
         }
         return Err(anyhow!("Unknown input"));
     }
@@ -0,0 +1,206 @@
+use egui::{Key, Pos2, RichText, ScrollArea, TextEdit, Ui};
+use egui_snarl::Snarl;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+
+use super::{
+    history::History,
+    node_trait::{EFNodeFNSerialized, NodeDataType},
+    registry::{registry, NodeRegistration},
+};
+use crate::node_runners::realtime::{forget_node, run_nodes};
+
+/// The end of a wire dropped into empty space, the new node is connected to it
+#[derive(Clone, Copy)]
+pub enum WireEnd {
+    /// The wire comes from an output of this type, the node needs an input of it
+    Output(NodeDataType),
+    /// The wire goes into an input of this type, the node needs an output of it
+    Input(NodeDataType),
+}
+
+struct PaletteEntry {
+    registration: NodeRegistration,
+    inputs: Vec<(&'static str, NodeDataType)>,
+    outputs: Vec<(&'static str, NodeDataType)>,
+    /// Everything the search looks at, in lowercase
+    text: String,
+}
+
+impl PaletteEntry {
+    fn fits(&self, wire: Option<WireEnd>) -> bool {
+        match wire {
+            None => true,
+            Some(WireEnd::Output(data_type)) => self.inputs.iter().any(|(_, t)| *t == data_type),
+            Some(WireEnd::Input(data_type)) => self.outputs.iter().any(|(_, t)| *t == data_type),
+        }
+    }
+
+    /// Every word of `query` is somewhere in the title, name, category or description
+    fn matches(&self, query: &str) -> bool {
+        query
+            .split_whitespace()
+            .all(|word| self.text.contains(&word.to_lowercase()))
+    }
+
+    /// Like "DecimalSequence, DecimalSequence → Mask"
+    fn pin_summary(&self) -> String {
+        let types = |pins: &[(&str, NodeDataType)]| {
+            pins.iter()
+                .map(|(_, data_type)| format!("{:?}", data_type))
+                .join(", ")
+        };
+        format!("{} → {}", types(&self.inputs), types(&self.outputs))
+    }
+
+    fn pin_details(&self) -> String {
+        let pins = |pins: &[(&str, NodeDataType)]| {
+            if pins.is_empty() {
+                return "none".to_owned();
+            }
+            pins.iter()
+                .map(|(label, data_type)| format!("{} ({:?})", label, data_type))
+                .join(", ")
+        };
+        format!(
+            "{}\n\nInputs: {}\nOutputs: {}",
+            self.registration.name,
+            pins(&self.inputs),
+            pins(&self.outputs)
+        )
+    }
+}
+
+/// Nodes by category with the pins of their defaults. Built once since it creates a node of
+/// every type, nodes registered after the app started aren't in it.
+static ENTRIES: Lazy<Vec<(&'static str, Vec<PaletteEntry>)>> = Lazy::new(|| {
+    registry()
+        .categories()
+        .into_iter()
+        .map(|(category, registrations)| {
+            let entries = registrations
+                .into_iter()
+                .filter_map(|registration| {
+                    let node = (registration.create?)();
+                    Some(PaletteEntry {
+                        registration: *registration,
+                        inputs: node.get_inputs().to_vec(),
+                        outputs: node.get_outputs().to_vec(),
+                        text: format!(
+                            "{} {} {} {}",
+                            registration.title,
+                            registration.name,
+                            registration.category,
+                            registration.description
+                        )
+                        .to_lowercase(),
+                    })
+                })
+                .collect();
+            (category, entries)
+        })
+        .collect()
+});
+
+/// Search box with the matching nodes by category, only those that can take `wire` if there
+/// is one. Returns the node picked, Enter picks the first match.
+pub fn node_palette(ui: &mut Ui, wire: Option<WireEnd>) -> Option<NodeRegistration> {
+    let id = egui::Id::new("node_palette_query");
+    let mut query: String = ui.data_mut(|data| data.get_temp(id)).unwrap_or_default();
+    let search = ui.add(
+        TextEdit::singleline(&mut query)
+            .hint_text("Search nodes")
+            .desired_width(280.0),
+    );
+    if ui.memory(|memory| memory.focused().is_none()) {
+        search.request_focus();
+    }
+    let enter = search.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+
+    let mut picked = None;
+    let mut first = None;
+    ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+        for (category, entries) in ENTRIES.iter() {
+            let entries: Vec<&PaletteEntry> = entries
+                .iter()
+                .filter(|entry| entry.fits(wire) && entry.matches(&query))
+                .collect();
+            if entries.is_empty() {
+                continue;
+            }
+            ui.label(RichText::new(*category).strong());
+            for entry in entries {
+                first.get_or_insert(entry.registration);
+                let response = ui
+                    .horizontal(|ui| {
+                        let response = ui.selectable_label(false, entry.registration.title);
+                        ui.label(RichText::new(entry.pin_summary()).small().weak());
+                        response
+                    })
+                    .inner
+                    .on_hover_text(entry.pin_details());
+                ui.label(RichText::new(entry.registration.description).small().weak());
+                if response.clicked() {
+                    picked = Some(entry.registration);
+                }
+            }
+            ui.add_space(4.0);
+        }
+        if first.is_none() {
+            ui.label("No node matches");
+        }
+    });
+    if enter {
+        picked = picked.or(first);
+    }
+    if picked.is_some() {
+        query.clear();
+    }
+    ui.data_mut(|data| data.insert_temp(id, query));
+    picked
+}
+
+/// The node palette in a window of its own, for adding nodes from the keyboard
+#[derive(Default)]
+pub struct NodePaletteWindow {
+    pub open: bool,
+}
+
+impl NodePaletteWindow {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+        history: &mut History,
+    ) {
+        let mut open = self.open;
+        let mut picked = None;
+        let mut window = egui::Window::new("Add node")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false);
+        if let Some(pos) = ctx.input(|i| i.pointer.hover_pos()) {
+            window = window.default_pos(pos);
+        }
+        window.show(ctx, |ui| {
+            picked = node_palette(ui, None);
+        });
+        if ctx.input(|i| i.key_pressed(Key::Escape)) {
+            open = false;
+        }
+        if let Some(node) = picked.and_then(|registration| registration.new_node()) {
+            // Right of everything else, where it can't cover other nodes
+            let right = snarl
+                .nodes_pos_ids()
+                .map(|(_, pos, _)| pos.x)
+                .fold(0.0, f32::max);
+            let pos = Pos2::new(right + 200.0, 0.0);
+            let node_id = history.record(snarl, |snarl| snarl.insert_node(pos, node));
+            // Ids of removed nodes are reused, their results must not be
+            forget_node(&node_id);
+            run_nodes(snarl);
+            open = false;
+        }
+        self.open = open;
+    }
+}
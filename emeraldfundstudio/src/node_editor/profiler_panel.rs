@@ -0,0 +1,180 @@
+use anyhow::Result;
+use egui::{Color32, Grid, RichText, ScrollArea, Ui};
+use egui_snarl::Snarl;
+
+use super::node_trait::EFNodeFNSerialized;
+use crate::node_runners::{
+    profiler::{format_bytes, format_duration, last_run, NodeTiming, RunProfile},
+    realtime::{clear_cache, run_nodes},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortBy {
+    Node,
+    Time,
+    Rows,
+    Size,
+    Cache,
+}
+
+const COLUMNS: [(SortBy, &str); 5] = [
+    (SortBy::Node, "Node"),
+    (SortBy::Time, "Time"),
+    (SortBy::Rows, "Rows"),
+    (SortBy::Size, "Size"),
+    (SortBy::Cache, "Cache"),
+];
+
+/// Where the time of the last graph run went, node by node
+pub struct ProfilerPanel {
+    pub open: bool,
+    /// Show the time of each node in its header
+    pub overlay: bool,
+    sort_by: SortBy,
+    descending: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    trace_path: String,
+    message: Option<Result<String, String>>,
+}
+
+impl Default for ProfilerPanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            overlay: false,
+            sort_by: SortBy::Time,
+            descending: true,
+            #[cfg(not(target_arch = "wasm32"))]
+            trace_path: "trace.json".to_owned(),
+            message: None,
+        }
+    }
+}
+
+impl ProfilerPanel {
+    pub fn show(&mut self, ctx: &egui::Context, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+        let mut open = self.open;
+        egui::Window::new("Profiler")
+            .open(&mut open)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.overlay, "Times in node headers");
+                    if ui
+                        .button("Profile whole graph")
+                        .on_hover_text("Runs every node again, without the cached results")
+                        .clicked()
+                    {
+                        clear_cache();
+                        run_nodes(snarl);
+                    }
+                });
+                let Some(profile) = last_run() else {
+                    ui.label("Nothing ran yet.");
+                    return;
+                };
+                ui.label(format!(
+                    "Last {}run: {} executed, {} cached, {}",
+                    if profile.incremental {
+                        "incremental "
+                    } else {
+                        ""
+                    },
+                    profile.executed(),
+                    profile.nodes.len() - profile.executed(),
+                    format_duration(profile.duration)
+                ));
+                self.show_export(ui, &profile);
+                if let Some(message) = &self.message {
+                    match message {
+                        Ok(message) => ui.label(message),
+                        Err(error) => ui.colored_label(Color32::RED, error),
+                    };
+                }
+                ui.separator();
+                self.show_table(ui, &profile);
+            });
+        self.open = open;
+    }
+
+    fn show_export(&mut self, ui: &mut Ui, profile: &RunProfile) {
+        ui.horizontal(|ui| {
+            ui.label("Chrome trace");
+            if ui.button("Copy").clicked() {
+                match serde_json::to_string(&profile.chrome_trace()) {
+                    Ok(trace) => {
+                        ui.ctx().copy_text(trace);
+                        self.message = Some(Ok("Trace copied to the clipboard".to_owned()));
+                    }
+                    Err(e) => self.message = Some(Err(e.to_string())),
+                }
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                ui.text_edit_singleline(&mut self.trace_path);
+                if ui.button("Save").clicked() {
+                    let result = serde_json::to_string(&profile.chrome_trace())
+                        .map_err(anyhow::Error::from)
+                        .and_then(|trace| Ok(std::fs::write(&self.trace_path, trace)?));
+                    self.message = Some(
+                        result
+                            .map(|_| format!("Trace saved to {}", self.trace_path))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+            }
+        });
+    }
+
+    fn show_table(&mut self, ui: &mut Ui, profile: &RunProfile) {
+        let mut nodes: Vec<&NodeTiming> = profile.nodes.iter().collect();
+        match self.sort_by {
+            SortBy::Node => nodes.sort_by_key(|node| (node.node_name.as_str(), node.node_id)),
+            SortBy::Time => nodes.sort_by_key(|node| node.duration),
+            SortBy::Rows => nodes.sort_by_key(|node| node.rows),
+            SortBy::Size => nodes.sort_by_key(|node| node.bytes),
+            SortBy::Cache => nodes.sort_by_key(|node| node.cache_hit),
+        }
+        if self.descending {
+            nodes.reverse();
+        }
+
+        ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+            Grid::new("profiler_table").striped(true).show(ui, |ui| {
+                for (sort_by, name) in COLUMNS {
+                    let text = match (self.sort_by == sort_by, self.descending) {
+                        (true, true) => format!("{} ⏷", name),
+                        (true, false) => format!("{} ⏶", name),
+                        (false, _) => name.to_owned(),
+                    };
+                    if ui.selectable_label(self.sort_by == sort_by, text).clicked() {
+                        if self.sort_by == sort_by {
+                            self.descending = !self.descending;
+                        } else {
+                            self.sort_by = sort_by;
+                            self.descending = sort_by != SortBy::Node;
+                        }
+                    }
+                }
+                ui.end_row();
+                for node in nodes {
+                    let name = format!("{} #{}", node.node_name, node.node_id);
+                    if node.failed {
+                        ui.colored_label(Color32::RED, name);
+                    } else {
+                        ui.label(name);
+                    }
+                    if node.cache_hit {
+                        ui.label(RichText::new("-").weak());
+                    } else {
+                        ui.label(format_duration(node.duration));
+                    }
+                    ui.label(node.rows.to_string());
+                    ui.label(format_bytes(node.bytes));
+                    ui.label(if node.cache_hit { "hit" } else { "miss" });
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
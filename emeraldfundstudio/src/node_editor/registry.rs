@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::{RwLock, RwLockReadGuard};
+use serde::de::DeserializeOwned;
+
+use super::{
+    node_trait::{EFNodeFNSerialized, EFNodeFn},
+    nodes::{
+        align_back::AlignBackNode, compare::CompareNode, composite::CompositeNode, ema::EMANode,
+        execute_position::ExecutePositionNode, formula::FormulaNode, fuse_signals::FuseSignalsNode,
+        market_data::MarketDataNode, preview::PreviewNode, resample::ResampleNode,
+        script::ScriptNode, sma::SMANode, split_candles::SplitCandlesNode, spread::SpreadNode,
+        to_signal::ToSignalNode,
+    },
+};
+
+use crate::types::time_index::JoinMode;
+
+pub type NodeConstructor = fn() -> Box<dyn EFNodeFn>;
+pub type NodeDeserializer = fn(serde_json::Value) -> Result<Box<dyn EFNodeFn>>;
+/// Turns arguments of one schema version into those of the next
+pub type NodeMigration = fn(serde_json::Value) -> Result<serde_json::Value>;
+
+/// Categories of the node palette in the order it shows them, others come after these
+pub const CATEGORIES: [&str; 7] = [
+    "Data",
+    "Indicators",
+    "Math",
+    "Logic",
+    "Signals",
+    "Execution",
+    "Output",
+];
+
+/// Everything the studio needs to know about a node type
+#[derive(Clone, Copy)]
+pub struct NodeRegistration {
+    /// What `get_name` returns, saved graphs refer to nodes by it
+    pub name: &'static str,
+    /// What the node is called in the editor
+    pub title: &'static str,
+    /// Section of the node palette, see `CATEGORIES`
+    pub category: &'static str,
+    pub description: &'static str,
+    /// `None` for nodes only tools create, like composites. Those aren't in the node palette.
+    pub create: Option<NodeConstructor>,
+    /// Loads a node from its exported arguments
+    pub deserialize: NodeDeserializer,
+    /// The first one upgrades arguments saved before the node had a version (version 0). Only
+    /// ever append, the current version is the number of migrations.
+    pub migrations: &'static [NodeMigration],
+}
+
+impl NodeRegistration {
+    pub fn new<T>(title: &'static str, category: &'static str, description: &'static str) -> Self
+    where
+        T: EFNodeFn + Default + DeserializeOwned + 'static,
+    {
+        Self {
+            name: T::default().get_name(),
+            title,
+            category,
+            description,
+            create: Some(|| Box::new(T::default())),
+            deserialize: Self::deserializer::<T>(),
+            migrations: &[],
+        }
+    }
+
+    pub fn with_migrations(self, migrations: &'static [NodeMigration]) -> Self {
+        Self { migrations, ..self }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    /// A node with default settings, ready to be inserted
+    pub fn new_node(&self) -> Option<EFNodeFNSerialized<'static>> {
+        let node = (self.create?)();
+        Some(EFNodeFNSerialized {
+            node_name: self.name.into(),
+            arguments: node.export_data(),
+            version: self.version(),
+            loaded_node: Some(node),
+        })
+    }
+
+    /// Brings arguments saved with schema `version` up to date
+    pub fn migrate(
+        &self,
+        mut arguments: serde_json::Value,
+        version: u32,
+    ) -> Result<serde_json::Value> {
+        if version > self.version() {
+            return Err(anyhow!(
+                "{} was saved by a newer version of the studio (schema {}, this one knows {})",
+                self.name,
+                version,
+                self.version()
+            ));
+        }
+        for (from, migration) in self.migrations.iter().enumerate().skip(version as usize) {
+            arguments = migration(arguments)
+                .map_err(|e| anyhow!("Migrating {} from schema {}: {}", self.name, from, e))?;
+        }
+        Ok(arguments)
+    }
+
+    pub fn deserializer<T>() -> NodeDeserializer
+    where
+        T: EFNodeFn + DeserializeOwned + 'static,
+    {
+        |arguments| Ok(Box::new(serde_json::from_value::<T>(arguments)?))
+    }
+}
+
+/// Version 1 of nodes that were unit structs before they got settings. Those saved `null`, which
+/// becomes the default settings.
+pub fn null_to_defaults<T>(arguments: serde_json::Value) -> Result<serde_json::Value>
+where
+    T: EFNodeFn + Default,
+{
+    if arguments.is_null() {
+        return Ok(T::default().export_data());
+    }
+    Ok(arguments)
+}
+
+/// Version 1 of nodes that combine two inputs. Before `join` they paired values by position,
+/// which is what `JoinMode::Strict` does.
+fn strict_join(mut arguments: serde_json::Value) -> Result<serde_json::Value> {
+    if arguments.is_null() {
+        arguments = serde_json::json!({});
+    }
+    let Some(fields) = arguments.as_object_mut() else {
+        return Err(anyhow!("Expected an object, got {}", arguments));
+    };
+    if !fields.contains_key("join") {
+        fields.insert("join".into(), serde_json::to_value(JoinMode::Strict)?);
+    }
+    Ok(arguments)
+}
+
+/// All node types by name
+pub struct NodeRegistry {
+    nodes: BTreeMap<&'static str, NodeRegistration>,
+}
+
+impl NodeRegistry {
+    fn builtin() -> Self {
+        let mut registry = Self {
+            nodes: BTreeMap::new(),
+        };
+        let builtin = [
+            NodeRegistration::new::<MarketDataNode>(
+                "Market Data",
+                "Data",
+                "Candles from the synthetic generator, the data store or a replay feed",
+            )
+            .with_migrations(&[null_to_defaults::<MarketDataNode>]),
+            NodeRegistration::new::<SplitCandlesNode>(
+                "Split Candles",
+                "Data",
+                "Splits candles into open, high, low, close and volume",
+            )
+            .with_migrations(&[null_to_defaults::<SplitCandlesNode>]),
+            NodeRegistration::new::<ResampleNode>(
+                "Resample",
+                "Data",
+                "Candles on a longer timeframe",
+            ),
+            NodeRegistration::new::<AlignBackNode>(
+                "Align Back",
+                "Data",
+                "Forward-fills a sequence of resampled candles back onto the base candles",
+            ),
+            NodeRegistration::new::<SMANode>(
+                "Simple Moving Average",
+                "Indicators",
+                "Average of the last values",
+            )
+            .with_migrations(&[null_to_defaults::<SMANode>]),
+            NodeRegistration::new::<EMANode>(
+                "Exponential Moving Average",
+                "Indicators",
+                "Moving average weighing recent values more",
+            ),
+            NodeRegistration::new::<SpreadNode>(
+                "Spread",
+                "Math",
+                "Relation between the closes of two symbols",
+            ),
+            NodeRegistration::new::<FormulaNode>(
+                "Formula",
+                "Math",
+                "Computes a formula like (close - sma) / std > 2",
+            ),
+            NodeRegistration::new::<ScriptNode>(
+                "Script",
+                "Math",
+                "Runs a Rhai script per bar or per series",
+            ),
+            NodeRegistration::new::<CompareNode>(
+                "Compare",
+                "Logic",
+                "Compares two sequences into a mask",
+            )
+            .with_migrations(&[strict_join]),
+            NodeRegistration::new::<ToSignalNode>(
+                "To Signal",
+                "Signals",
+                "Turns a mask into buy or sell signals",
+            ),
+            NodeRegistration::new::<FuseSignalsNode>(
+                "Fuse Signals",
+                "Signals",
+                "Combines two signals into one",
+            )
+            .with_migrations(&[strict_join]),
+            NodeRegistration {
+                name: "CompositeNode",
+                title: "Composite",
+                category: "Math",
+                description: "A subgraph collapsed into a single node",
+                create: None,
+                deserialize: NodeRegistration::deserializer::<CompositeNode>(),
+                migrations: &[],
+            },
+            NodeRegistration::new::<ExecutePositionNode>(
+                "Execute Position",
+                "Execution",
+                "Trades the signal in a backtest or on the paper exchange",
+            )
+            .with_migrations(&[null_to_defaults::<ExecutePositionNode>]),
+            NodeRegistration::new::<PreviewNode>(
+                "Preview",
+                "Output",
+                "Plots a sequence on the chart",
+            ),
+        ];
+        for registration in builtin {
+            registry
+                .register(registration)
+                .expect("Built-in node names are unique");
+        }
+        registry
+    }
+
+    pub fn register(&mut self, registration: NodeRegistration) -> Result<()> {
+        if self.nodes.contains_key(registration.name) {
+            return Err(anyhow!("Node {} is already registered", registration.name));
+        }
+        self.nodes.insert(registration.name, registration);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NodeRegistration> {
+        self.nodes.get(name)
+    }
+
+    /// Nodes that can be added from the node palette, by category in the order of
+    /// `CATEGORIES`, each sorted by title
+    pub fn categories(&self) -> Vec<(&'static str, Vec<&NodeRegistration>)> {
+        let mut categories: BTreeMap<(usize, &'static str), Vec<&NodeRegistration>> =
+            BTreeMap::new();
+        for registration in self.nodes.values() {
+            if registration.create.is_some() {
+                let order = CATEGORIES
+                    .iter()
+                    .position(|category| *category == registration.category)
+                    .unwrap_or(CATEGORIES.len());
+                categories
+                    .entry((order, registration.category))
+                    .or_default()
+                    .push(registration);
+            }
+        }
+        categories
+            .into_iter()
+            .map(|((_, category), mut registrations)| {
+                registrations.sort_by_key(|registration| registration.title);
+                (category, registrations)
+            })
+            .collect()
+    }
+}
+
+static REGISTRY: Lazy<RwLock<NodeRegistry>> = Lazy::new(|| RwLock::new(NodeRegistry::builtin()));
+
+/// Don't hold on to it while loading nodes, composites load their inner nodes through it
+pub fn registry() -> RwLockReadGuard<'static, NodeRegistry> {
+    REGISTRY.read()
+}
+
+/// Schema version new arguments of `node_name` are saved with
+pub fn current_version(node_name: &str) -> u32 {
+    registry()
+        .get(node_name)
+        .map(|registration| registration.version())
+        .unwrap_or_default()
+}
+
+/// Adds a node type from outside the studio. Register before the app starts, saved graphs
+/// with the node only load once it is known.
+pub fn register_node(registration: NodeRegistration) -> Result<()> {
+    REGISTRY.write().register(registration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nodes as the studio saved them before the registry, unit structs were saved as `null`
+    const BASELINE_NODES: &str = r#"[
+        {"node_name": "MarketDataNode", "arguments": null},
+        {"node_name": "SplitCandlesNode", "arguments": null},
+        {"node_name": "SMANode", "arguments": null},
+        {"node_name": "CompareNode", "arguments": {"mode": "BiggerThan"}},
+        {"node_name": "ToSignalNode", "arguments": {"mode": "Sell"}},
+        {"node_name": "FuseSignalsNode", "arguments": null},
+        {"node_name": "ExecutePositionNode", "arguments": null},
+        {"node_name": "PreviewNode", "arguments": {"output_color": [255, 0, 0]}}
+    ]"#;
+
+    #[test]
+    fn loads_baseline_nodes() {
+        let mut nodes: Vec<EFNodeFNSerialized<'_>> = serde_json::from_str(BASELINE_NODES).unwrap();
+        for node in nodes.iter_mut() {
+            node.load_node()
+                .unwrap_or_else(|e| panic!("{} doesn't load: {}", node.node_name, e));
+            assert_eq!(node.get_node().get_name(), node.node_name);
+        }
+        assert_eq!(nodes[3].arguments["mode"], "BiggerThan");
+        assert_eq!(nodes[4].arguments["mode"], "Sell");
+        for node in &nodes {
+            assert_eq!(node.version, current_version(&node.node_name));
+        }
+    }
+
+    #[test]
+    fn migrate_gives_former_unit_structs_their_defaults() {
+        let registry = registry();
+        let sma = registry.get("SMANode").unwrap();
+        assert_eq!(sma.version(), 1);
+        let arguments = sma.migrate(serde_json::Value::Null, 0).unwrap();
+        assert_eq!(arguments, SMANode::default().export_data());
+        // Saved with settings before the migration existed
+        let saved = serde_json::json!({"period": 30});
+        assert_eq!(sma.migrate(saved.clone(), 0).unwrap(), saved);
+    }
+
+    #[test]
+    fn migrate_keeps_pairing_by_position() {
+        let compare = *registry().get("CompareNode").unwrap();
+        let arguments = compare
+            .migrate(serde_json::json!({"mode": "LessThan"}), 0)
+            .unwrap();
+        assert_eq!(arguments["join"], "Strict");
+        let arguments = compare
+            .migrate(serde_json::json!({"mode": "LessThan", "join": "Left"}), 0)
+            .unwrap();
+        assert_eq!(arguments["join"], "Left");
+    }
+
+    #[test]
+    fn migrate_runs_the_migrations_after_the_saved_version() {
+        fn add_a(mut arguments: serde_json::Value) -> Result<serde_json::Value> {
+            arguments["steps"].as_array_mut().unwrap().push("a".into());
+            Ok(arguments)
+        }
+        fn add_b(mut arguments: serde_json::Value) -> Result<serde_json::Value> {
+            arguments["steps"].as_array_mut().unwrap().push("b".into());
+            Ok(arguments)
+        }
+        let registration =
+            NodeRegistration::new::<SMANode>("Test", "Test", "").with_migrations(&[add_a, add_b]);
+        let saved = serde_json::json!({"steps": []});
+        let steps =
+            |version| registration.migrate(saved.clone(), version).unwrap()["steps"].clone();
+        assert_eq!(steps(0), serde_json::json!(["a", "b"]));
+        assert_eq!(steps(1), serde_json::json!(["b"]));
+        assert_eq!(steps(2), serde_json::json!([]));
+    }
+
+    #[test]
+    fn migrate_rejects_newer_versions() {
+        let sma = *registry().get("SMANode").unwrap();
+        let error = sma.migrate(serde_json::Value::Null, 2).unwrap_err();
+        assert!(error.to_string().contains("newer version"), "{}", error);
+    }
+}
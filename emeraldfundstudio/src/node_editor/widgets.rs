@@ -0,0 +1,38 @@
+use egui::{ComboBox, Ui};
+use egui_snarl::NodeId;
+use strum::IntoEnumIterator;
+
+use crate::{node_editor::node_trait::NodeDataType, types::time_index::JoinMode};
+
+/// How a node with two sequence inputs lines them up, returns whether it changed
+pub fn join_mode_combo_box(ui: &mut Ui, node_id: NodeId, join: &mut JoinMode) -> bool {
+    let mut changed = false;
+    ComboBox::from_id_salt(("join_mode", node_id))
+        .selected_text(join.to_string())
+        .show_ui(ui, |ui| {
+            for mode in JoinMode::iter() {
+                changed |= ui.selectable_value(join, mode, mode.to_string()).changed();
+            }
+        });
+    changed
+}
+
+/// Picks the type of a pin, returns whether it changed
+pub fn data_type_combo_box(
+    ui: &mut Ui,
+    id_salt: impl std::hash::Hash,
+    data_type: &mut NodeDataType,
+    types: &[NodeDataType],
+) -> bool {
+    let mut changed = false;
+    ComboBox::from_id_salt(id_salt)
+        .selected_text(format!("{:?}", data_type))
+        .show_ui(ui, |ui| {
+            for option in types {
+                changed |= ui
+                    .selectable_value(data_type, *option, format!("{:?}", option))
+                    .changed();
+            }
+        });
+    changed
+}
@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use egui_snarl::{InPinId, OutPinId, Snarl};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::node_editor::node_trait::{CheapCloneNodeDataTypeWithValue, EFNodeFNSerialized};
+
+/// Results of a graph run, keyed by node id, same layout as `NODE_COMPUTE_CACHE`.
+pub type ComputeCache = HashMap<usize, Vec<CheapCloneNodeDataTypeWithValue>>;
+
+pub struct GraphNode {
+    pub node: EFNodeFNSerialized<'static>,
+    /// The output pin connected to each input, `None` if the input is not connected
+    pub inputs: Vec<Option<OutPinId>>,
+}
+
+/// A detached copy of the node graph. Unlike the snarl it does not belong to the UI,
+/// so it can be modified and executed many times in parallel (sweeps, optimisers, etc.)
+pub struct GraphSnapshot {
+    pub nodes: BTreeMap<usize, GraphNode>,
+}
+
+impl GraphSnapshot {
+    /// Placeholders of nodes that can't be loaded are left out, so they only stop the nodes
+    /// after them from running instead of the whole graph
+    pub fn from_snarl(snarl: &Snarl<EFNodeFNSerialized<'_>>) -> Result<Self> {
+        let mut nodes = BTreeMap::new();
+        for (id, node) in snarl.node_ids() {
+            if node.is_missing() {
+                continue;
+            }
+            let inner_node = node.get_node();
+            let inputs = (0..inner_node.get_inputs().len())
+                .map(|input| {
+                    snarl
+                        .in_pin(InPinId { node: id, input })
+                        .remotes
+                        .first()
+                        .copied()
+                })
+                .collect();
+            let mut node = EFNodeFNSerialized {
+                node_name: node.node_name.to_string().into(),
+                arguments: inner_node.export_data(),
+                version: node.version,
+                loaded_node: None,
+            };
+            node.load_node()?;
+            nodes.insert(id.0, GraphNode { node, inputs });
+        }
+        Ok(Self { nodes })
+    }
+
+    pub fn try_clone(&self) -> Result<Self> {
+        let mut nodes = BTreeMap::new();
+        for (id, graph_node) in self.nodes.iter() {
+            let mut node = EFNodeFNSerialized {
+                node_name: graph_node.node.node_name.clone(),
+                arguments: graph_node.node.arguments.clone(),
+                version: graph_node.node.version,
+                loaded_node: None,
+            };
+            node.load_node()?;
+            nodes.insert(
+                *id,
+                GraphNode {
+                    node,
+                    inputs: graph_node.inputs.clone(),
+                },
+            );
+        }
+        Ok(Self { nodes })
+    }
+
+    pub fn find_nodes<'a>(&'a self, node_name: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.nodes
+            .iter()
+            .filter(move |(_, graph_node)| graph_node.node.node_name == node_name)
+            .map(|(id, _)| *id)
+    }
+
+    /// Changes a single top-level argument of a node and reloads it
+    pub fn set_argument(
+        &mut self,
+        node_id: usize,
+        key: &str,
+        value: serde_json::Value,
+    ) -> Result<()> {
+        let graph_node = self
+            .nodes
+            .get_mut(&node_id)
+            .ok_or_else(|| anyhow!("Node #{} not found", node_id))?;
+        let arguments = graph_node
+            .node
+            .arguments
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Node #{} has no arguments", node_id))?;
+        arguments.insert(key.to_owned(), value);
+        graph_node.node.load_node()
+    }
+
+    /// All nodes that (in)directly consume the outputs of `roots`, including the roots themselves
+    pub fn downstream_of(&self, roots: &[usize]) -> HashSet<usize> {
+        let mut result: HashSet<usize> = roots.iter().copied().collect();
+        let mut stack: Vec<usize> = roots.to_vec();
+        while let Some(node_id) = stack.pop() {
+            for (id, graph_node) in self.nodes.iter() {
+                let is_consumer = graph_node
+                    .inputs
+                    .iter()
+                    .flatten()
+                    .any(|remote| remote.node.0 == node_id);
+                if is_consumer && result.insert(*id) {
+                    stack.push(*id);
+                }
+            }
+        }
+        result
+    }
+
+    /// The value flowing into the given input, if the upstream node has been computed
+    pub fn input_value(
+        &self,
+        cache: &ComputeCache,
+        node_id: usize,
+        input: usize,
+    ) -> Option<CheapCloneNodeDataTypeWithValue> {
+        let remote = self.nodes.get(&node_id)?.inputs.get(input)?.as_ref()?;
+        cache.get(&remote.node.0)?.get(remote.output).cloned()
+    }
+
+    fn is_executable(&self, cache: &ComputeCache, node_id: usize) -> bool {
+        if cache.contains_key(&node_id) {
+            return false;
+        }
+        self.nodes[&node_id]
+            .inputs
+            .iter()
+            .all(|remote| match remote {
+                Some(remote) => cache.contains_key(&remote.node.0),
+                None => false,
+            })
+    }
+
+    /// Executes every node that is not in `cache` yet and has all its inputs available.
+    /// Entries already in `cache` are reused as-is, which is how callers share upstream results.
+    pub fn run(&self, cache: &mut ComputeCache) -> Result<()> {
+        loop {
+            let computed: &ComputeCache = cache;
+            let executable_nodes: Vec<usize> = self
+                .nodes
+                .keys()
+                .copied()
+                .filter(|id| self.is_executable(computed, *id))
+                .collect();
+            if executable_nodes.is_empty() {
+                return Ok(());
+            }
+
+            let results = executable_nodes
+                .par_iter()
+                .map(|id| {
+                    let graph_node = &self.nodes[id];
+                    let input_args = (0..graph_node.inputs.len())
+                        .map(|input| self.input_value(computed, *id, input).unwrap())
+                        .collect::<Vec<CheapCloneNodeDataTypeWithValue>>();
+                    let results = graph_node
+                        .node
+                        .get_node()
+                        .process_data(&input_args)
+                        .map_err(|e| {
+                            anyhow!("{} #{} failed: {}", graph_node.node.node_name, id, e)
+                        })?;
+                    Ok((*id, results))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            cache.extend(results);
+        }
+    }
+}
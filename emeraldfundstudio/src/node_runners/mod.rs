@@ -1 +1,4 @@
+pub mod graph;
+pub mod profiler;
 pub mod realtime;
+pub mod streaming;
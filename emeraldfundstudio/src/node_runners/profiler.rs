@@ -0,0 +1,172 @@
+use std::{sync::Arc, time::Duration};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+
+use crate::node_editor::node_trait::{CheapCloneNodeDataTypeWithValue, NodeDataTypeWithValue};
+
+/// What running a node took, or that its result was still cached
+#[derive(Clone)]
+pub struct NodeTiming {
+    pub node_id: usize,
+    pub node_name: String,
+    /// Since the run started
+    pub start: Duration,
+    pub duration: Duration,
+    /// Rows of the longest output
+    pub rows: usize,
+    /// Approximate size of the outputs, without their time index
+    pub bytes: usize,
+    /// The result was there from an earlier run, nothing was executed
+    pub cache_hit: bool,
+    pub failed: bool,
+    /// The rayon thread the node ran on, 0 outside of the pool
+    pub thread: usize,
+}
+
+impl NodeTiming {
+    pub fn new(node_id: usize, node_name: String, start: Duration, duration: Duration) -> Self {
+        Self {
+            node_id,
+            node_name,
+            start,
+            duration,
+            rows: 0,
+            bytes: 0,
+            cache_hit: false,
+            failed: false,
+            thread: rayon::current_thread_index().map_or(0, |index| index + 1),
+        }
+    }
+
+    pub fn with_results(mut self, results: &[CheapCloneNodeDataTypeWithValue]) -> Self {
+        self.rows = results.iter().map(|value| rows(value)).max().unwrap_or(0);
+        self.bytes = results.iter().map(|value| bytes(value)).sum();
+        self
+    }
+}
+
+fn rows(value: &NodeDataTypeWithValue) -> usize {
+    match value {
+        NodeDataTypeWithValue::DecimalSequence(seq) => seq.values.len(),
+        NodeDataTypeWithValue::Mask(mask) => mask.values.len(),
+        NodeDataTypeWithValue::Signal(signal) => signal.values.len(),
+        NodeDataTypeWithValue::Candles(candles) => candles.df.height(),
+    }
+}
+
+fn bytes(value: &NodeDataTypeWithValue) -> usize {
+    match value {
+        NodeDataTypeWithValue::DecimalSequence(seq) => seq.values.len() * size_of::<f64>(),
+        NodeDataTypeWithValue::Mask(mask) => mask.values.len(),
+        NodeDataTypeWithValue::Signal(signal) => signal.values.len(),
+        NodeDataTypeWithValue::Candles(candles) => candles.df.estimated_size(),
+    }
+}
+
+/// Every node of one `run_nodes`, those executed and those that were cached
+pub struct RunProfile {
+    pub incremental: bool,
+    pub duration: Duration,
+    pub nodes: Vec<NodeTiming>,
+}
+
+impl RunProfile {
+    pub fn executed(&self) -> usize {
+        self.nodes.iter().filter(|node| !node.cache_hit).count()
+    }
+
+    /// The run in the Chrome trace event format, for chrome://tracing or Perfetto
+    pub fn chrome_trace(&self) -> Value {
+        let run = json!({
+            "name": if self.incremental { "incremental run" } else { "run" },
+            "cat": "run",
+            "ph": "X",
+            "ts": 0,
+            "dur": self.duration.as_micros() as u64,
+            "pid": 1,
+            "tid": 0,
+        });
+        let nodes = self
+            .nodes
+            .iter()
+            .filter(|node| !node.cache_hit)
+            .map(|node| {
+                json!({
+                    "name": node.node_name,
+                    "cat": "node",
+                    "ph": "X",
+                    "ts": node.start.as_micros() as u64,
+                    "dur": node.duration.as_micros() as u64,
+                    "pid": 1,
+                    "tid": node.thread,
+                    "args": {
+                        "node_id": node.node_id,
+                        "rows": node.rows,
+                        "bytes": node.bytes,
+                        "failed": node.failed,
+                    },
+                })
+            });
+        json!({
+            "traceEvents": std::iter::once(run).chain(nodes).collect::<Vec<_>>(),
+            "displayTimeUnit": "ms",
+        })
+    }
+}
+
+/// The last run that executed something
+pub static LAST_RUN: Lazy<Mutex<Option<Arc<RunProfile>>>> = Lazy::new(Default::default);
+
+/// The last time each node was executed, kept across runs since most only execute a few nodes
+pub static NODE_TIMINGS: Lazy<DashMap<usize, NodeTiming>> = Lazy::new(Default::default);
+
+pub fn last_run() -> Option<Arc<RunProfile>> {
+    LAST_RUN.lock().clone()
+}
+
+pub fn record_run(profile: RunProfile) {
+    for node in profile.nodes.iter().filter(|node| !node.cache_hit) {
+        NODE_TIMINGS.insert(node.node_id, node.clone());
+    }
+    *LAST_RUN.lock() = Some(Arc::new(profile));
+}
+
+/// Like "1.2 ms · 5000 rows", for the header of the node `id`
+pub fn node_summary(id: usize) -> Option<String> {
+    let timing = NODE_TIMINGS.get(&id)?;
+    let cached = LAST_RUN.lock().as_ref().is_some_and(|run| {
+        run.nodes
+            .iter()
+            .any(|node| node.node_id == id && node.cache_hit)
+    });
+    Some(format!(
+        "{} · {} rows{}",
+        format_duration(timing.duration),
+        timing.rows,
+        if cached { " · cached" } else { "" }
+    ))
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    if micros < 1000 {
+        format!("{} µs", micros)
+    } else if micros < 1_000_000 {
+        format!("{:.1} ms", micros as f64 / 1000.0)
+    } else {
+        format!("{:.2} s", duration.as_secs_f64())
+    }
+}
+
+pub fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
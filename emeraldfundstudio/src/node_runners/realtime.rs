@@ -1,21 +1,113 @@
-use dashmap::DashMap;
-use egui_snarl::{InPinId, NodeId, Snarl};
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{self, Sender};
+
+use dashmap::{DashMap, DashSet};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
 use itertools::Itertools;
 use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use web_time::Instant;
+
+use super::profiler::{record_run, NodeTiming, RunProfile, NODE_TIMINGS};
 
-use crate::node_editor::node_trait::{CheapCloneNodeDataTypeWithValue, EFNodeFNSerialized};
+use crate::node_editor::node_trait::{
+    CheapCloneNodeDataTypeWithValue, EFNodeFNSerialized, EFNodeFn, NodeState,
+};
 
 pub static NODE_COMPUTE_CACHE: Lazy<DashMap<usize, Vec<CheapCloneNodeDataTypeWithValue>>> =
     Lazy::new(|| Default::default());
 
-pub fn filter_already_executed(nodes: &mut Vec<NodeId>) {
-    nodes.retain(|n| !NODE_COMPUTE_CACHE.contains_key(&n.0));
+/// State of nodes that were run incrementally, see `EFNodeFn::process_update`
+pub static NODE_STATE_CACHE: Lazy<DashMap<usize, NodeState>> = Lazy::new(Default::default);
+
+/// Bumped whenever results are dropped or a new run starts. A run belongs to the generation it
+/// started in and stops once that is over, without keeping what it computed since.
+static GENERATION: Lazy<RwLock<u64>> = Lazy::new(Default::default);
+
+/// Nodes being executed, with the generation of their run
+static RUNNING_NODES: Lazy<DashMap<usize, u64>> = Lazy::new(Default::default);
+
+/// Runs handed to the worker that haven't finished, including cancelled ones
+static BACKGROUND_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Ends the current generation, which cancels the runs in progress
+fn next_generation() -> u64 {
+    let mut generation = GENERATION.write();
+    *generation += 1;
+    *generation
 }
 
-pub fn clear_cache_from_node_onward(snarl: &Snarl<EFNodeFNSerialized<'_>>, id: &NodeId) {
-    // TODO: Need to remove only it and dependencies
+fn is_current(generation: u64) -> bool {
+    *GENERATION.read() == generation
+}
+
+/// Whether the worker has runs left, the UI repaints until it hasn't
+pub fn is_running() -> bool {
+    BACKGROUND_RUNS.load(Ordering::Relaxed) > 0
+}
+
+/// Whether the node `id` is being executed by a run that is still current
+pub fn is_node_running(id: &NodeId) -> bool {
+    RUNNING_NODES
+        .get(&id.0)
+        .is_some_and(|generation| is_current(*generation))
+}
+
+/// `id` and every node that depends on it
+fn downstream_nodes(snarl: &Snarl<EFNodeFNSerialized<'_>>, id: &NodeId) -> HashSet<NodeId> {
+    let mut pending = vec![*id];
+    let mut visited = HashSet::new();
+    while let Some(id) = pending.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let Some(node) = snarl.get_node(id) else {
+            continue;
+        };
+        for output in 0..node.get_node().get_outputs().len() {
+            let out_pin = snarl.out_pin(OutPinId { node: id, output });
+            pending.extend(out_pin.remotes.iter().map(|remote| remote.node));
+        }
+    }
+    visited
+}
+
+/// Forgets the results of `id` and of every node that depends on it, other results stay valid
+pub fn clear_cache_downstream(snarl: &Snarl<EFNodeFNSerialized<'_>>, id: &NodeId) {
+    next_generation();
+    for id in downstream_nodes(snarl, id) {
+        NODE_COMPUTE_CACHE.remove(&id.0);
+    }
+}
+
+/// Drops everything kept for a node, for nodes that are removed or replaced
+pub fn forget_node(id: &NodeId) {
+    next_generation();
+    NODE_COMPUTE_CACHE.remove(&id.0);
+    NODE_STATE_CACHE.remove(&id.0);
+    NODE_TIMINGS.remove(&id.0);
+}
+
+/// Forgets the results of every node, for when something they all may read changed
+pub fn clear_cache() {
+    next_generation();
     NODE_COMPUTE_CACHE.clear();
+}
+
+/// Like `clear_cache_downstream`, but for an edited node, so the incremental state of the nodes
+/// can't be continued either, and runs the graph again
+pub fn clear_cache_from_node_onward(snarl: &Snarl<EFNodeFNSerialized<'_>>, id: &NodeId) {
+    next_generation();
+    for id in downstream_nodes(snarl, id) {
+        NODE_COMPUTE_CACHE.remove(&id.0);
+        NODE_STATE_CACHE.remove(&id.0);
+    }
     run_nodes(snarl);
 }
 
@@ -48,44 +140,254 @@ pub fn is_node_realtime_executable(
     has_all_inputs_connected
 }
 
-fn get_executable_nodes(snarl: &Snarl<EFNodeFNSerialized<'_>>) -> Vec<NodeId> {
+/// A node without a result, with the outputs its inputs are connected to
+struct PendingNode<'g> {
+    id: NodeId,
+    node: &'g dyn EFNodeFn,
+    inputs: Vec<Option<OutPinId>>,
+}
+
+impl PendingNode<'_> {
+    fn is_executable(&self) -> bool {
+        !NODE_COMPUTE_CACHE.contains_key(&self.id.0)
+            && self.inputs.iter().all(|remote| {
+                remote.is_some_and(|remote| NODE_COMPUTE_CACHE.contains_key(&remote.node.0))
+            })
+    }
+
+    /// The values of the inputs, `None` once one of them was dropped
+    fn input_args(&self) -> Option<Vec<CheapCloneNodeDataTypeWithValue>> {
+        self.inputs
+            .iter()
+            .map(|remote| {
+                let remote = (*remote)?;
+                let output_values = NODE_COMPUTE_CACHE.get(&remote.node.0)?;
+                output_values.get(remote.output).cloned()
+            })
+            .collect()
+    }
+}
+
+fn node_inputs(snarl: &Snarl<EFNodeFNSerialized<'_>>, id: NodeId) -> Vec<Option<OutPinId>> {
+    let n_inputs = snarl[id].get_node().get_inputs().len();
+    (0..n_inputs)
+        .map(|input| {
+            snarl
+                .in_pin(InPinId { node: id, input })
+                .remotes
+                .first()
+                .copied()
+        })
+        .collect()
+}
+
+/// The nodes with a result already, by name, for the profile of a run
+fn cached_nodes(snarl: &Snarl<EFNodeFNSerialized<'_>>) -> Vec<(NodeId, &'static str)> {
     snarl
         .node_ids()
-        .filter_map(|(id, node)| {
-            if is_node_realtime_executable(snarl, id, node) {
-                Some(id)
-            } else {
-                None
-            }
-        })
+        .filter(|(id, _)| NODE_COMPUTE_CACHE.contains_key(&id.0))
+        .map(|(id, node)| (id, node.get_node().get_name()))
         .collect()
 }
 
+/// A run for the worker: copies of the nodes without a result, the others only need to be
+/// looked up
+struct Job {
+    nodes: Vec<(NodeId, EFNodeFNSerialized<'static>, Vec<Option<OutPinId>>)>,
+    cached: Vec<(NodeId, &'static str)>,
+    incremental: bool,
+    generation: u64,
+}
+
+impl Job {
+    /// Starts a new generation, which cancels the runs in progress and those still queued
+    fn new(snarl: &Snarl<EFNodeFNSerialized<'_>>, incremental: bool) -> Self {
+        let generation = next_generation();
+        let nodes = snarl
+            .node_ids()
+            .filter(|(id, node)| !NODE_COMPUTE_CACHE.contains_key(&id.0) && !node.is_missing())
+            .filter_map(|(id, node)| {
+                let mut copy = EFNodeFNSerialized {
+                    node_name: node.node_name.to_string().into(),
+                    arguments: node.get_node().export_data(),
+                    version: node.version,
+                    loaded_node: None,
+                };
+                if let Err(e) = copy.load_node() {
+                    log::error!("{} #{} can't be run: {}", node.node_name, id.0, e);
+                    return None;
+                }
+                Some((id, copy, node_inputs(snarl, id)))
+            })
+            .collect();
+        Self {
+            nodes,
+            cached: cached_nodes(snarl),
+            incremental,
+            generation,
+        }
+    }
+
+    fn run(self) {
+        let nodes: Vec<PendingNode<'_>> = self
+            .nodes
+            .iter()
+            .map(|(id, node, inputs)| PendingNode {
+                id: *id,
+                node: node.get_node().as_ref(),
+                inputs: inputs.clone(),
+            })
+            .collect();
+        run_pending(&nodes, self.cached, self.incremental, self.generation);
+    }
+
+    /// Hands the run to the worker, or runs it right away on the web where there are no
+    /// threads and the UI waits for the results
+    fn submit(self) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            BACKGROUND_RUNS.fetch_add(1, Ordering::Relaxed);
+            if WORKER.send(self).is_err() {
+                BACKGROUND_RUNS.fetch_sub(1, Ordering::Relaxed);
+                log::error!("The graph runner thread is gone, the graph can't run");
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        self.run();
+    }
+}
+
+/// The thread that executes every run, one after the other. Runs that were cancelled while
+/// they waited are skipped, one that is cancelled while it runs stops after the nodes it was
+/// executing, so at most one run uses the CPU.
+#[cfg(not(target_arch = "wasm32"))]
+static WORKER: Lazy<Sender<Job>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    std::thread::Builder::new()
+        .name("graph runner".to_owned())
+        .spawn(move || {
+            for job in receiver {
+                if is_current(job.generation) {
+                    job.run();
+                }
+                BACKGROUND_RUNS.fetch_sub(1, Ordering::Relaxed);
+            }
+        })
+        .expect("Can't start the graph runner thread");
+    sender
+});
+
+/// Evaluates the graph on the worker thread, the results appear in `NODE_COMPUTE_CACHE` as
+/// nodes finish. Runs started before are cancelled, the nodes they were executing run again
+/// in this one.
 pub fn run_nodes(snarl: &Snarl<EFNodeFNSerialized<'_>>) {
-    let mut executable_nodes = get_executable_nodes(snarl);
-    filter_already_executed(&mut executable_nodes);
-    while executable_nodes.len() > 0 {
-        executable_nodes.par_iter().for_each(|id| {
-            let node = snarl.get_node(*id).unwrap();
-            let inner_node = node.get_node();
-            let n_inputs = node.get_node().get_inputs().len();
-            let input_args = (0..n_inputs)
-                .into_par_iter()
-                .map(|pin_id| {
-                    let in_pin = snarl.in_pin(InPinId {
-                        node: *id,
-                        input: pin_id,
-                    });
+    Job::new(snarl, false).submit();
+}
 
-                    let remote_output_pin = in_pin.remotes.first().unwrap();
-                    let output_values = NODE_COMPUTE_CACHE.get(&remote_output_pin.node.0).unwrap();
-                    output_values.get(remote_output_pin.output).unwrap().clone()
-                })
-                .collect::<Vec<CheapCloneNodeDataTypeWithValue>>();
-            let results = inner_node.process_data(&input_args).unwrap();
-            NODE_COMPUTE_CACHE.insert(id.0, results);
+/// Like `run_nodes`, but nodes continue from their state of the last incremental run. Only
+/// valid when the inputs of the nodes without a result only got bars appended since then.
+pub fn run_nodes_incremental(snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+    Job::new(snarl, true).submit();
+}
+
+/// Executes `nodes` as their inputs get a result, until none is left that can run or
+/// `generation` is over
+fn run_pending(
+    nodes: &[PendingNode<'_>],
+    cached: Vec<(NodeId, &'static str)>,
+    incremental: bool,
+    generation: u64,
+) {
+    // Nodes that returned an error, they and everything after them stay without a result
+    let failed: DashSet<NodeId> = DashSet::new();
+    let timings: Mutex<Vec<NodeTiming>> = Mutex::new(Vec::new());
+    let run_start = Instant::now();
+    loop {
+        let executable_nodes: Vec<&PendingNode<'_>> = nodes
+            .iter()
+            .filter(|node| !failed.contains(&node.id) && node.is_executable())
+            .collect();
+        if executable_nodes.is_empty() || !is_current(generation) {
+            break;
+        }
+        executable_nodes.par_iter().for_each(|pending| {
+            let id = pending.id;
+            let inner_node = pending.node;
+            if !is_current(generation) {
+                return;
+            }
+            let Some(input_args) = pending.input_args() else {
+                return;
+            };
+            RUNNING_NODES.insert(id.0, generation);
+            let started = Instant::now();
+            let mut state = None;
+            let result = if incremental {
+                state = NODE_STATE_CACHE.remove(&id.0).map(|(_, state)| state);
+                inner_node.process_update(&input_args, &mut state)
+            } else {
+                // The inputs may have changed anywhere, an old state can't be continued
+                NODE_STATE_CACHE.remove(&id.0);
+                inner_node.process_data(&input_args)
+            };
+            let timing = NodeTiming::new(
+                id.0,
+                inner_node.get_name().to_string(),
+                started - run_start,
+                started.elapsed(),
+            );
+            RUNNING_NODES.remove_if(&id.0, |_, running| *running == generation);
+            // Held while the result is stored, so the generation can't end in between
+            let current = GENERATION.read();
+            if *current != generation {
+                // The state may be from inputs that are gone, the next run starts over
+                return;
+            }
+            if let Some(state) = state {
+                NODE_STATE_CACHE.insert(id.0, state);
+            }
+            match result {
+                Ok(results) => {
+                    timings.lock().push(timing.with_results(&results));
+                    NODE_COMPUTE_CACHE.insert(id.0, results);
+                }
+                Err(e) => {
+                    log::error!("{} failed: {}", inner_node.get_name(), e);
+                    timings.lock().push(NodeTiming {
+                        failed: true,
+                        ..timing
+                    });
+                    failed.insert(id);
+                }
+            }
         });
-        executable_nodes = get_executable_nodes(snarl);
-        filter_already_executed(&mut executable_nodes);
     }
+
+    let mut nodes = timings.into_inner();
+    if nodes.is_empty() || !is_current(generation) {
+        // Keep the profile of the last run that did something
+        return;
+    }
+    for (id, name) in cached {
+        let Some(results) = NODE_COMPUTE_CACHE.get(&id.0) else {
+            continue;
+        };
+        let mut timing = NodeTiming::new(
+            id.0,
+            name.to_string(),
+            Default::default(),
+            Default::default(),
+        )
+        .with_results(&results);
+        timing.cache_hit = true;
+        nodes.push(timing);
+    }
+    record_run(RunProfile {
+        incremental,
+        duration: run_start.elapsed(),
+        nodes,
+    });
 }
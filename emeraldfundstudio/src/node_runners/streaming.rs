@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use dashmap::{mapref::entry::Entry, DashMap};
+use egui_snarl::Snarl;
+use once_cell::sync::Lazy;
+use polars::df;
+use serde::{Deserialize, Serialize};
+
+use super::realtime::{clear_cache_downstream, run_nodes_incremental};
+use crate::{
+    node_editor::{
+        node_trait::EFNodeFNSerialized,
+        nodes::market_data::{DataSource, MarketDataNode},
+    },
+    types::{
+        candles::{Candles, MarketInfo},
+        timestamp::TimeStamp,
+    },
+};
+
+/// Candles received from a live feed so far, keyed by exchange and pair
+pub static LIVE_CANDLES: Lazy<DashMap<(String, String), Candles>> = Lazy::new(Default::default);
+
+/// One candle as it arrives from a feed
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LiveBar {
+    pub timestamp: TimeStamp,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+/// Adds a bar to the live candles of `info`. A bar with the timestamp of the last bar replaces
+/// it, like the updates of a candle that hasn't closed yet.
+pub fn append_bar(info: &MarketInfo, bar: &LiveBar) -> Result<()> {
+    let row = df![
+        "open" => [bar.open],
+        "high" => [bar.high],
+        "low" => [bar.low],
+        "close" => [bar.close],
+        "volume" => [bar.volume],
+        "timestamp" => [bar.timestamp],
+    ]?;
+    match LIVE_CANDLES.entry((info.exchange.clone(), info.pair.clone())) {
+        Entry::Occupied(mut entry) => {
+            let candles = entry.get_mut();
+            let height = candles.df.height();
+            match candles.timestamps()?.last() {
+                Some(last) if bar.timestamp < *last => {
+                    return Err(anyhow!(
+                        "Bar of {} at {} is older than the last bar at {}",
+                        info,
+                        bar.timestamp,
+                        last
+                    ));
+                }
+                Some(last) if bar.timestamp == *last => {
+                    candles.df = candles.df.slice(0, height - 1);
+                }
+                _ => {}
+            }
+            candles.df.vstack_mut(&row)?;
+            candles.df.as_single_chunk();
+            candles.info = info.clone();
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(Candles::new(info.clone(), row));
+        }
+    }
+    Ok(())
+}
+
+/// Every candle as a bar, missing values become NaN
+pub fn candle_bars(candles: &Candles) -> Result<Vec<LiveBar>> {
+    let df = &candles.df;
+    let column = |name: &str| -> Result<Vec<f64>> {
+        Ok(df
+            .column(name)?
+            .f64()?
+            .into_iter()
+            .map(|x| x.unwrap_or(f64::NAN))
+            .collect())
+    };
+    let (opens, highs, lows) = (column("open")?, column("high")?, column("low")?);
+    let (closes, volumes) = (column("close")?, column("volume")?);
+    Ok(candles
+        .timestamps()?
+        .into_iter()
+        .enumerate()
+        .map(|(idx, timestamp)| LiveBar {
+            timestamp,
+            open: opens[idx],
+            high: highs[idx],
+            low: lows[idx],
+            close: closes[idx],
+            volume: volumes[idx],
+        })
+        .collect())
+}
+
+pub fn clear_live_candles(exchange: &str, pair: &str) {
+    LIVE_CANDLES.remove(&(exchange.to_owned(), pair.to_owned()));
+}
+
+/// Live candles of a market, cloning is cheap
+pub fn live_candles(exchange: &str, pair: &str) -> Option<Candles> {
+    LIVE_CANDLES
+        .get(&(exchange.to_owned(), pair.to_owned()))
+        .map(|candles| candles.clone())
+}
+
+/// Re-evaluates the market data nodes that follow the feed of `exchange`/`pair` and the nodes
+/// after them. The rest of the graph keeps its results and nodes that support it only
+/// compute the new bars.
+pub fn run_streaming_update(snarl: &Snarl<EFNodeFNSerialized<'_>>, exchange: &str, pair: &str) {
+    for (id, node) in snarl.node_ids() {
+        if node.get_node().get_name() != "MarketDataNode" {
+            continue;
+        }
+        let Some(market_data) = node.get_node().as_any().downcast_ref::<MarketDataNode>() else {
+            continue;
+        };
+        let follows_feed = market_data.source == DataSource::Replay
+            && market_data
+                .symbols
+                .iter()
+                .any(|symbol| symbol.exchange == exchange && symbol.pair == pair);
+        if follows_feed {
+            clear_cache_downstream(snarl, &id);
+        }
+    }
+    run_nodes_incremental(snarl);
+}
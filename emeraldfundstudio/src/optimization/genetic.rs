@@ -0,0 +1,86 @@
+use rand::{seq::SliceRandom, Rng};
+use rand_chacha::ChaCha8Rng;
+
+use super::optimizer::{Evaluator, OptimizerConfig, Trial};
+
+const TOURNAMENT_SIZE: usize = 3;
+const ELITES: usize = 2;
+
+fn tournament<'a>(population: &'a [Trial], rng: &mut ChaCha8Rng) -> &'a Trial {
+    population
+        .choose_multiple(rng, TOURNAMENT_SIZE.min(population.len()))
+        .max_by(|a, b| a.score.total_cmp(&b.score))
+        .unwrap()
+}
+
+fn crossover(a: &[usize], b: &[usize], rng: &mut ChaCha8Rng) -> Vec<usize> {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| if rng.gen_bool(0.5) { *a } else { *b })
+        .collect()
+}
+
+/// Either nudges a gene to a neighbouring value (keeps ordered ranges smooth)
+/// or replaces it with a random one
+fn mutate(
+    indices: &mut [usize],
+    evaluator: &Evaluator<'_>,
+    mutation_rate: f64,
+    rng: &mut ChaCha8Rng,
+) {
+    for (idx, range) in indices.iter_mut().zip(evaluator.ranges) {
+        if !rng.gen_bool(mutation_rate.clamp(0.0, 1.0)) {
+            continue;
+        }
+        let len = range.values.len();
+        *idx = if rng.gen_bool(0.5) {
+            if rng.gen_bool(0.5) {
+                (*idx + 1).min(len - 1)
+            } else {
+                idx.saturating_sub(1)
+            }
+        } else {
+            rng.gen_range(0..len)
+        };
+    }
+}
+
+pub fn run_genetic(
+    evaluator: &mut Evaluator<'_>,
+    rng: &mut ChaCha8Rng,
+    config: &OptimizerConfig,
+    should_continue: &mut dyn FnMut(&Evaluator<'_>) -> bool,
+) {
+    let population_size = config.population.max(ELITES + 1);
+    let mut population: Vec<Vec<usize>> = (0..population_size)
+        .map(|_| evaluator.random_indices(rng))
+        .collect();
+
+    // Small search spaces get exhausted before the budget is, so the generations are capped too
+    let max_generations = config.budget / population_size + 1;
+    for _ in 0..max_generations {
+        let mut trials = evaluator.evaluate(&population);
+        if !should_continue(evaluator) || trials.is_empty() {
+            return;
+        }
+        trials.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        let mut next_population: Vec<Vec<usize>> = trials
+            .iter()
+            .take(ELITES)
+            .map(|trial| trial.indices.clone())
+            .collect();
+        while next_population.len() < population_size {
+            let a = tournament(&trials, rng);
+            let b = tournament(&trials, rng);
+            let mut child = crossover(&a.indices, &b.indices, rng);
+            mutate(&mut child, evaluator, config.mutation_rate, rng);
+            if evaluator.is_evaluated(&child) {
+                // Duplicates don't teach us anything, explore instead
+                mutate(&mut child, evaluator, 1.0, rng);
+            }
+            next_population.push(child);
+        }
+        population = next_population;
+    }
+}
@@ -0,0 +1,8 @@
+pub mod genetic;
+pub mod optimizer;
+pub mod optimizer_panel;
+pub mod sweep;
+pub mod sweep_panel;
+pub mod tpe;
+pub mod walk_forward;
+pub mod walk_forward_panel;
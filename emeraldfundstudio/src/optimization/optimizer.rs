@@ -0,0 +1,384 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Result;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum::{Display, EnumIter};
+
+use super::{
+    genetic::run_genetic,
+    sweep::{evaluate_parameters, shared_cache, validate_ranges, ParameterRange},
+    tpe::run_tpe,
+};
+use crate::{
+    backtest::{
+        engine::BacktestConfig,
+        metrics::{BacktestMetrics, Metric},
+    },
+    node_runners::graph::{ComputeCache, GraphSnapshot},
+};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum OptimizerKind {
+    #[default]
+    #[strum(serialize = "Genetic")]
+    Genetic,
+    #[strum(serialize = "Bayesian (TPE)")]
+    Tpe,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OptimizerConfig {
+    pub kind: OptimizerKind,
+    /// Same seed, graph and ranges always give the same search
+    pub seed: u64,
+    /// Maximum number of distinct parameter combinations to backtest
+    pub budget: usize,
+    /// Individuals per generation for the genetic search, candidates per step for TPE
+    pub population: usize,
+    pub mutation_rate: f64,
+    pub metric: Metric,
+    /// Combinations with fewer trades than this are rejected
+    pub min_trades: usize,
+    /// Combinations with a bigger drawdown than this are rejected, 1.0 disables the check
+    pub max_drawdown: f64,
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        Self {
+            kind: OptimizerKind::Genetic,
+            seed: 21,
+            budget: 200,
+            population: 16,
+            mutation_rate: 0.2,
+            metric: Metric::Sharpe,
+            min_trades: 5,
+            max_drawdown: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Trial {
+    /// Index into the values of each `ParameterRange`
+    pub indices: Vec<usize>,
+    pub values: Vec<Value>,
+    pub metrics: Option<BacktestMetrics>,
+    /// Objective to maximise, `f64::NEG_INFINITY` when the backtest failed or broke a constraint
+    pub score: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OptimizerProgress {
+    pub evaluations: usize,
+    pub budget: usize,
+    /// The best trial with a finite score, `None` while every backtest failed or broke a
+    /// constraint
+    pub best: Option<Trial>,
+    /// Best score after each evaluation, for plotting convergence
+    pub best_scores: Vec<f64>,
+    pub finished: bool,
+}
+
+/// Backtests index combinations, remembering every result so a search never pays twice
+pub struct Evaluator<'a> {
+    graph: &'a GraphSnapshot,
+    base_cache: ComputeCache,
+    pub ranges: &'a [ParameterRange],
+    backtest_config: &'a BacktestConfig,
+    config: &'a OptimizerConfig,
+    trials: HashMap<Vec<usize>, Trial>,
+    pub progress: OptimizerProgress,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(
+        graph: &'a GraphSnapshot,
+        ranges: &'a [ParameterRange],
+        backtest_config: &'a BacktestConfig,
+        config: &'a OptimizerConfig,
+    ) -> Result<Self> {
+        validate_ranges(ranges)?;
+        let swept_nodes: Vec<usize> = ranges.iter().map(|range| range.node_id).collect();
+        let base_cache = shared_cache(graph, ComputeCache::new(), &swept_nodes)?;
+        Ok(Self {
+            graph,
+            base_cache,
+            ranges,
+            backtest_config,
+            config,
+            trials: HashMap::new(),
+            progress: OptimizerProgress {
+                budget: config.budget.min(search_space_size(ranges)),
+                ..Default::default()
+            },
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress.evaluations >= self.progress.budget
+    }
+
+    pub fn trials(&self) -> impl Iterator<Item = &Trial> {
+        self.trials.values()
+    }
+
+    pub fn is_evaluated(&self, indices: &[usize]) -> bool {
+        self.trials.contains_key(indices)
+    }
+
+    pub fn random_indices(&self, rng: &mut ChaCha8Rng) -> Vec<usize> {
+        self.ranges
+            .iter()
+            .map(|range| rng.gen_range(0..range.values.len()))
+            .collect()
+    }
+
+    fn score(&self, metrics: &BacktestMetrics) -> f64 {
+        if metrics.trade_count < self.config.min_trades
+            || metrics.max_drawdown > self.config.max_drawdown
+        {
+            return f64::NEG_INFINITY;
+        }
+        self.config.metric.score(metrics)
+    }
+
+    /// Evaluates the combinations that have not been seen yet in parallel (up to the budget)
+    /// and returns the trials for all of them, in the same order.
+    pub fn evaluate(&mut self, batch: &[Vec<usize>]) -> Vec<Trial> {
+        let mut new_indices: Vec<Vec<usize>> = vec![];
+        for indices in batch {
+            if !self.trials.contains_key(indices) && !new_indices.contains(indices) {
+                new_indices.push(indices.clone());
+            }
+        }
+        new_indices.truncate(self.progress.budget - self.progress.evaluations);
+
+        let new_trials: Vec<Trial> = new_indices
+            .par_iter()
+            .map(|indices| {
+                let values: Vec<Value> = indices
+                    .iter()
+                    .zip(self.ranges)
+                    .map(|(idx, range)| range.values[*idx].clone())
+                    .collect();
+                let metrics = evaluate_parameters(
+                    self.graph,
+                    &self.base_cache,
+                    self.ranges,
+                    &values,
+                    self.backtest_config,
+                )
+                .map(|result| result.metrics)
+                .ok();
+                let score = metrics
+                    .as_ref()
+                    .map_or(f64::NEG_INFINITY, |metrics| self.score(metrics));
+                Trial {
+                    indices: indices.clone(),
+                    values,
+                    metrics,
+                    score,
+                }
+            })
+            .collect();
+
+        for trial in new_trials {
+            self.progress.evaluations += 1;
+            let is_best = trial.score.is_finite()
+                && self
+                    .progress
+                    .best
+                    .as_ref()
+                    .is_none_or(|best| trial.score > best.score);
+            if is_best {
+                self.progress.best = Some(trial.clone());
+            }
+            let best_score = self
+                .progress
+                .best
+                .as_ref()
+                .map_or(f64::NEG_INFINITY, |best| best.score);
+            self.progress.best_scores.push(best_score);
+            self.trials.insert(trial.indices.clone(), trial);
+        }
+
+        batch
+            .iter()
+            .filter_map(|indices| self.trials.get(indices).cloned())
+            .collect()
+    }
+}
+
+pub fn search_space_size(ranges: &[ParameterRange]) -> usize {
+    ranges
+        .iter()
+        .map(|range| range.values.len())
+        .fold(1, usize::saturating_mul)
+}
+
+/// Searches the ranges for the combination that maximises `config.metric`.
+/// Does not touch any UI state, so it can run headless or on a worker thread;
+/// `on_progress` is called after every batch and `stop` aborts the search early.
+pub fn run_optimizer(
+    graph: &GraphSnapshot,
+    ranges: &[ParameterRange],
+    backtest_config: &BacktestConfig,
+    config: &OptimizerConfig,
+    stop: &AtomicBool,
+    mut on_progress: impl FnMut(&OptimizerProgress),
+) -> Result<OptimizerProgress> {
+    let mut evaluator = Evaluator::new(graph, ranges, backtest_config, config)?;
+    let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+    let mut should_continue = |evaluator: &Evaluator<'_>| {
+        on_progress(&evaluator.progress);
+        !evaluator.is_done() && !stop.load(Ordering::Relaxed)
+    };
+    match config.kind {
+        OptimizerKind::Genetic => {
+            run_genetic(&mut evaluator, &mut rng, config, &mut should_continue)
+        }
+        OptimizerKind::Tpe => run_tpe(&mut evaluator, &mut rng, config, &mut should_continue),
+    }
+    evaluator.progress.finished = true;
+    on_progress(&evaluator.progress);
+    Ok(evaluator.progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use egui_snarl::{NodeId, OutPinId};
+    use serde_json::json;
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::{
+        node_editor::node_trait::EFNodeFNSerialized, node_runners::graph::GraphNode,
+        optimization::sweep::numeric_values,
+    };
+
+    /// Synthetic candles, with a long position while the fast SMA of the close is above the
+    /// slow one
+    fn crossover_graph() -> GraphSnapshot {
+        let nodes = [
+            (1, "MarketDataNode", json!({}), vec![]),
+            (2, "SplitCandlesNode", Value::Null, vec![(1, 0)]),
+            (3, "SMANode", json!({"period": 5}), vec![(2, 3)]),
+            (4, "SMANode", json!({"period": 20}), vec![(2, 3)]),
+            (
+                5,
+                "CompareNode",
+                json!({"mode": "BiggerThan"}),
+                vec![(3, 0), (4, 0)],
+            ),
+            (6, "ToSignalNode", json!({}), vec![(5, 0)]),
+            (7, "ExecutePositionNode", Value::Null, vec![(6, 0)]),
+        ];
+        let nodes = nodes
+            .into_iter()
+            .map(|(id, node_name, arguments, inputs)| {
+                let mut node = EFNodeFNSerialized {
+                    node_name: node_name.into(),
+                    arguments,
+                    version: 0,
+                    loaded_node: None,
+                };
+                node.load_node().unwrap();
+                let inputs = inputs
+                    .into_iter()
+                    .map(|(node, output)| {
+                        Some(OutPinId {
+                            node: NodeId(node),
+                            output,
+                        })
+                    })
+                    .collect();
+                (id, GraphNode { node, inputs })
+            })
+            .collect::<BTreeMap<_, _>>();
+        GraphSnapshot { nodes }
+    }
+
+    fn ranges() -> Vec<ParameterRange> {
+        vec![
+            ParameterRange {
+                node_id: 3,
+                key: "period".to_owned(),
+                values: numeric_values(2.0, 10.0, 1.0, true),
+            },
+            ParameterRange {
+                node_id: 4,
+                key: "period".to_owned(),
+                values: numeric_values(15.0, 40.0, 5.0, true),
+            },
+        ]
+    }
+
+    /// Every trial as (indices, score bits), the best indices and the best score after every
+    /// evaluation
+    type Search = (Vec<(Vec<usize>, u64)>, Option<Vec<usize>>, Vec<u64>);
+
+    fn search(graph: &GraphSnapshot, kind: OptimizerKind, seed: u64) -> Search {
+        let ranges = ranges();
+        let backtest_config = BacktestConfig::default();
+        let config = OptimizerConfig {
+            kind,
+            seed,
+            budget: 20,
+            population: 6,
+            min_trades: 0,
+            ..Default::default()
+        };
+        let mut evaluator = Evaluator::new(graph, &ranges, &backtest_config, &config).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut should_continue = |evaluator: &Evaluator<'_>| !evaluator.is_done();
+        match kind {
+            OptimizerKind::Genetic => {
+                run_genetic(&mut evaluator, &mut rng, &config, &mut should_continue)
+            }
+            OptimizerKind::Tpe => run_tpe(&mut evaluator, &mut rng, &config, &mut should_continue),
+        }
+        let mut trials: Vec<(Vec<usize>, u64)> = evaluator
+            .trials()
+            .map(|trial| (trial.indices.clone(), trial.score.to_bits()))
+            .collect();
+        trials.sort();
+        let progress = evaluator.progress;
+        assert!(progress.evaluations > 0);
+        (
+            trials,
+            progress.best.map(|best| best.indices),
+            progress
+                .best_scores
+                .iter()
+                .map(|score| score.to_bits())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn searches_are_deterministic_by_seed() {
+        let graph = crossover_graph();
+        for kind in OptimizerKind::iter() {
+            let first = search(&graph, kind, 7);
+            assert!(first.1.is_some(), "{} found nothing", kind);
+            assert_eq!(first, search(&graph, kind, 7), "{}", kind);
+            let other = search(&graph, kind, 8);
+            assert_ne!(
+                first.0, other.0,
+                "{} explored the same with another seed",
+                kind
+            );
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use egui::{Color32, ComboBox, DragValue, Grid, ProgressBar, RichText, ScrollArea, Ui};
+use egui_plot::{Line, Plot, PlotPoints};
+use egui_snarl::{NodeId, Snarl};
+use itertools::Itertools;
+use parking_lot::Mutex;
+use strum::IntoEnumIterator;
+
+use super::{
+    optimizer::{run_optimizer, OptimizerConfig, OptimizerKind, OptimizerProgress},
+    sweep::{value_label, ParameterRange},
+};
+use crate::{
+    backtest::{engine::BacktestConfig, metrics::Metric},
+    node_editor::{history::History, node_trait::EFNodeFNSerialized},
+    node_runners::{graph::GraphSnapshot, realtime::clear_cache_from_node_onward},
+};
+
+const SCORE_COLOR: Color32 = Color32::from_rgb(0x00, 0xb0, 0x00);
+
+#[derive(Default)]
+struct SharedState {
+    progress: OptimizerProgress,
+    error: Option<String>,
+    running: bool,
+}
+
+/// Genetic / TPE search over the parameters selected in the sweep panel, on a worker thread
+#[derive(Default)]
+pub struct OptimizerPanel {
+    pub open: bool,
+    config: OptimizerConfig,
+    backtest_config: BacktestConfig,
+    ranges: Vec<ParameterRange>,
+    state: Arc<Mutex<SharedState>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl OptimizerPanel {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+        ranges: Vec<ParameterRange>,
+        history: &mut History,
+    ) {
+        let mut open = self.open;
+        egui::Window::new("Optimiser")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    self.show_controls(ui, snarl, ranges);
+                    ui.separator();
+                    self.show_progress(ui, snarl, history);
+                });
+            });
+        self.open = open;
+    }
+
+    fn show_controls(
+        &mut self,
+        ui: &mut Ui,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        ranges: Vec<ParameterRange>,
+    ) {
+        if ranges.is_empty() {
+            ui.label("Select the parameters to optimise in the Parameter Sweep window.");
+        } else {
+            ui.label(format!(
+                "Optimising: {}",
+                ranges.iter().map(|range| range.label()).join(", ")
+            ));
+        }
+
+        let running = self.state.lock().running;
+        ui.add_enabled_ui(!running, |ui| {
+            Grid::new("optimizer_config").show(ui, |ui| {
+                ui.label("Algorithm");
+                ComboBox::from_id_salt("optimizer_kind")
+                    .selected_text(self.config.kind.to_string())
+                    .show_ui(ui, |ui| {
+                        for kind in OptimizerKind::iter() {
+                            ui.selectable_value(&mut self.config.kind, kind, kind.to_string());
+                        }
+                    });
+                ui.end_row();
+                ui.label("Seed");
+                ui.add(DragValue::new(&mut self.config.seed));
+                ui.end_row();
+                ui.label("Budget");
+                ui.add(DragValue::new(&mut self.config.budget).range(1..=100_000));
+                ui.end_row();
+                ui.label("Population / batch");
+                ui.add(DragValue::new(&mut self.config.population).range(1..=1000));
+                ui.end_row();
+                if self.config.kind == OptimizerKind::Genetic {
+                    ui.label("Mutation rate");
+                    ui.add(
+                        DragValue::new(&mut self.config.mutation_rate)
+                            .speed(0.01)
+                            .range(0.0..=1.0),
+                    );
+                    ui.end_row();
+                }
+                ui.label("Maximise");
+                ComboBox::from_id_salt("optimizer_metric")
+                    .selected_text(self.config.metric.to_string())
+                    .show_ui(ui, |ui| {
+                        for metric in Metric::iter() {
+                            ui.selectable_value(
+                                &mut self.config.metric,
+                                metric,
+                                metric.to_string(),
+                            );
+                        }
+                    });
+                ui.end_row();
+                ui.label("Min trades");
+                ui.add(DragValue::new(&mut self.config.min_trades));
+                ui.end_row();
+                ui.label("Max drawdown");
+                ui.add(
+                    DragValue::new(&mut self.config.max_drawdown)
+                        .speed(0.01)
+                        .range(0.0..=1.0),
+                );
+                ui.end_row();
+                ui.label("Fee");
+                ui.add(
+                    DragValue::new(&mut self.backtest_config.fee)
+                        .speed(0.0001)
+                        .range(0.0..=0.1),
+                );
+                ui.end_row();
+            });
+        });
+
+        ui.horizontal(|ui| {
+            if running {
+                if ui.button("Stop").clicked() {
+                    self.stop.store(true, Ordering::Relaxed);
+                }
+            } else if ui.button("Start").clicked() {
+                self.start(ui.ctx().clone(), snarl, ranges);
+            }
+        });
+        if let Some(error) = &self.state.lock().error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    fn start(
+        &mut self,
+        ctx: egui::Context,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        ranges: Vec<ParameterRange>,
+    ) {
+        let graph = match GraphSnapshot::from_snarl(snarl) {
+            Ok(graph) => graph,
+            Err(e) => {
+                self.state.lock().error = Some(e.to_string());
+                return;
+            }
+        };
+        *self.state.lock() = SharedState {
+            running: true,
+            ..Default::default()
+        };
+        self.stop.store(false, Ordering::Relaxed);
+        self.ranges = ranges.clone();
+
+        let state = self.state.clone();
+        let stop = self.stop.clone();
+        let config = self.config.clone();
+        let backtest_config = self.backtest_config.clone();
+        let optimize = move || {
+            let result = run_optimizer(
+                &graph,
+                &ranges,
+                &backtest_config,
+                &config,
+                &stop,
+                |progress| {
+                    state.lock().progress = progress.clone();
+                    ctx.request_repaint();
+                },
+            );
+            let mut state = state.lock();
+            state.running = false;
+            if let Err(e) = result {
+                state.error = Some(e.to_string());
+            }
+            ctx.request_repaint();
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(optimize);
+        // There are no threads on the web, the search runs before the next frame
+        #[cfg(target_arch = "wasm32")]
+        optimize();
+    }
+
+    fn show_progress(
+        &mut self,
+        ui: &mut Ui,
+        snarl: &mut Snarl<EFNodeFNSerialized<'_>>,
+        history: &mut History,
+    ) {
+        let progress = self.state.lock().progress.clone();
+        if progress.budget == 0 {
+            return;
+        }
+        ui.add(
+            ProgressBar::new(progress.evaluations as f32 / progress.budget as f32).text(format!(
+                "{} / {} backtests",
+                progress.evaluations, progress.budget
+            )),
+        );
+
+        let scores = PlotPoints::from_iter(
+            progress
+                .best_scores
+                .iter()
+                .enumerate()
+                .filter(|(_, score)| score.is_finite())
+                .map(|(idx, score)| [idx as f64, *score]),
+        );
+        Plot::new("optimizer_convergence")
+            .height(120.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(scores).name("Best score").color(SCORE_COLOR));
+            });
+
+        let Some(best) = &progress.best else {
+            if progress.evaluations > 0 {
+                ui.colored_label(
+                    Color32::YELLOW,
+                    "No combination satisfied the constraints yet",
+                );
+            }
+            return;
+        };
+        ui.heading("Best");
+        Grid::new("optimizer_best").striped(true).show(ui, |ui| {
+            for (range, value) in self.ranges.iter().zip(best.values.iter()) {
+                ui.label(RichText::new(range.label()).strong());
+                ui.label(value_label(value));
+                ui.end_row();
+            }
+            // Failed backtests never have a finite score, so the best one has metrics
+            if let Some(metrics) = &best.metrics {
+                for metric in Metric::iter() {
+                    ui.label(RichText::new(metric.to_string()).strong());
+                    ui.label(format!("{:.3}", metric.value(metrics)));
+                    ui.end_row();
+                }
+            }
+        });
+        if ui.button("Apply to graph").clicked() {
+            history.record(snarl, |snarl| {
+                for (range, value) in self.ranges.iter().zip(best.values.iter()) {
+                    let node_id = NodeId(range.node_id);
+                    let Some(node) = snarl.get_node_mut(node_id) else {
+                        continue;
+                    };
+                    node.save_node();
+                    if let Some(arguments) = node.arguments.as_object_mut() {
+                        arguments.insert(range.key.clone(), value.clone());
+                    }
+                    if let Err(e) = node.load_node() {
+                        self.state.lock().error = Some(e.to_string());
+                    }
+                    clear_cache_from_node_onward(snarl, &node_id);
+                }
+            });
+        }
+    }
+}
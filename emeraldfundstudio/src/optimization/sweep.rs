@@ -0,0 +1,187 @@
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde_json::Value;
+
+use crate::{
+    backtest::{
+        engine::{backtest_graph, BacktestConfig, BacktestResult},
+        metrics::{BacktestMetrics, Metric},
+    },
+    node_runners::graph::{ComputeCache, GraphSnapshot},
+};
+
+/// All values a single node argument takes during a sweep
+#[derive(Clone, Debug)]
+pub struct ParameterRange {
+    pub node_id: usize,
+    pub key: String,
+    pub values: Vec<Value>,
+}
+
+impl ParameterRange {
+    pub fn label(&self) -> String {
+        format!("{} #{}", self.key, self.node_id)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SweepRun {
+    /// One value per `ParameterRange`, in the same order
+    pub values: Vec<Value>,
+    pub result: Result<BacktestMetrics, String>,
+}
+
+pub fn value_label(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if n.is_f64() => format!("{:.4}", f),
+            _ => n.to_string(),
+        },
+        v => v.to_string(),
+    }
+}
+
+/// Values from `start` to `end` (inclusive) in steps of `step`
+pub fn numeric_values(start: f64, end: f64, step: f64, integer: bool) -> Vec<Value> {
+    if step <= 0.0 || end < start {
+        return vec![];
+    }
+    let n_steps = ((end - start) / step + 1e-9).floor() as usize;
+    let values = (0..=n_steps).map(|i| start + step * i as f64);
+    if integer {
+        values
+            .map(|v| v.round() as i64)
+            .dedup()
+            .map(Value::from)
+            .collect()
+    } else {
+        values.map(Value::from).collect()
+    }
+}
+
+/// Runs the graph once and keeps only the results that none of `modified_nodes` can influence,
+/// so they can be reused by every variation of those nodes.
+/// Entries already in `cache` (e.g. a slice of candles) are used as inputs for that run.
+pub fn shared_cache(
+    graph: &GraphSnapshot,
+    mut cache: ComputeCache,
+    modified_nodes: &[usize],
+) -> Result<ComputeCache> {
+    let dirty = graph.downstream_of(modified_nodes);
+    graph.run(&mut cache)?;
+    cache.retain(|id, _| !dirty.contains(id));
+    Ok(cache)
+}
+
+/// Runs a copy of the graph with the given values applied to its ranges
+pub fn run_with_parameters(
+    graph: &GraphSnapshot,
+    base_cache: &ComputeCache,
+    ranges: &[ParameterRange],
+    values: &[Value],
+) -> Result<(GraphSnapshot, ComputeCache)> {
+    let mut graph = graph.try_clone()?;
+    for (range, value) in ranges.iter().zip(values) {
+        graph.set_argument(range.node_id, &range.key, value.clone())?;
+    }
+    let mut cache = base_cache.clone();
+    graph.run(&mut cache)?;
+    Ok((graph, cache))
+}
+
+pub fn evaluate_parameters(
+    graph: &GraphSnapshot,
+    base_cache: &ComputeCache,
+    ranges: &[ParameterRange],
+    values: &[Value],
+    config: &BacktestConfig,
+) -> Result<BacktestResult> {
+    let (graph, cache) = run_with_parameters(graph, base_cache, ranges, values)?;
+    backtest_graph(&graph, &cache, config)
+}
+
+pub fn combinations(ranges: &[ParameterRange]) -> Vec<Vec<Value>> {
+    ranges
+        .iter()
+        .map(|range| range.values.clone())
+        .multi_cartesian_product()
+        .collect()
+}
+
+pub fn validate_ranges(ranges: &[ParameterRange]) -> Result<()> {
+    if ranges.is_empty() {
+        return Err(anyhow!("Select at least one parameter to sweep"));
+    }
+    if let Some(range) = ranges.iter().find(|range| range.values.is_empty()) {
+        return Err(anyhow!("{} has no values to sweep", range.label()));
+    }
+    Ok(())
+}
+
+/// Backtests the graph for every combination of the given ranges, in parallel
+pub fn run_sweep(
+    graph: &GraphSnapshot,
+    ranges: &[ParameterRange],
+    config: &BacktestConfig,
+) -> Result<Vec<SweepRun>> {
+    validate_ranges(ranges)?;
+    let swept_nodes: Vec<usize> = ranges.iter().map(|range| range.node_id).collect();
+    let base_cache = shared_cache(graph, ComputeCache::new(), &swept_nodes)?;
+
+    Ok(combinations(ranges)
+        .into_par_iter()
+        .map(|values| {
+            let result = evaluate_parameters(graph, &base_cache, ranges, &values, config)
+                .map(|result| result.metrics)
+                .map_err(|e| e.to_string());
+            SweepRun { values, result }
+        })
+        .collect())
+}
+
+/// Best metric score for every (x, y) value pair of two swept parameters.
+/// Indexed as `cells[y][x]`, `None` where no run succeeded.
+pub fn heatmap(
+    ranges: &[ParameterRange],
+    runs: &[SweepRun],
+    x_axis: usize,
+    y_axis: Option<usize>,
+    metric: Metric,
+) -> Vec<Vec<Option<f64>>> {
+    let width = ranges[x_axis].values.len();
+    let height = y_axis.map_or(1, |y_axis| ranges[y_axis].values.len());
+    let mut best: Vec<Vec<Option<&BacktestMetrics>>> = vec![vec![None; width]; height];
+
+    for run in runs {
+        let Ok(metrics) = &run.result else {
+            continue;
+        };
+        let x = ranges[x_axis]
+            .values
+            .iter()
+            .position(|v| *v == run.values[x_axis]);
+        let y = match y_axis {
+            Some(y_axis) => ranges[y_axis]
+                .values
+                .iter()
+                .position(|v| *v == run.values[y_axis]),
+            None => Some(0),
+        };
+        if let (Some(x), Some(y)) = (x, y) {
+            let cell = &mut best[y][x];
+            if cell.is_none_or(|current| metric.score(metrics) > metric.score(current)) {
+                *cell = Some(metrics);
+            }
+        }
+    }
+
+    best.into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|metrics| metrics.map(|metrics| metric.value(metrics)))
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,404 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use egui::{Button, Color32, ComboBox, DragValue, Grid, RichText, ScrollArea, Sense, Spinner, Ui};
+use egui_snarl::Snarl;
+use parking_lot::Mutex;
+use strum::IntoEnumIterator;
+
+use super::sweep::{heatmap, numeric_values, run_sweep, value_label, ParameterRange, SweepRun};
+use crate::{
+    backtest::{engine::BacktestConfig, metrics::Metric},
+    node_editor::node_trait::{EFNodeFNSerialized, ParameterKind},
+    node_runners::graph::GraphSnapshot,
+};
+
+const HEATMAP_CELL_SIZE: f32 = 36.0;
+
+struct SweepSpec {
+    enabled: bool,
+    start: f64,
+    end: f64,
+    step: f64,
+    selected: Vec<bool>,
+}
+
+impl SweepSpec {
+    fn new(kind: &ParameterKind) -> Self {
+        match kind {
+            ParameterKind::Integer { min, max } => Self {
+                enabled: false,
+                start: *min as f64,
+                end: (*max as f64).min(*min as f64 + 10.0),
+                step: 1.0,
+                selected: vec![],
+            },
+            ParameterKind::Decimal { min, max } => Self {
+                enabled: false,
+                start: *min,
+                end: *max,
+                step: (max - min) / 10.0,
+                selected: vec![],
+            },
+            ParameterKind::Choice(choices) => Self {
+                enabled: false,
+                start: 0.0,
+                end: 0.0,
+                step: 0.0,
+                selected: vec![true; choices.len()],
+            },
+        }
+    }
+
+    fn values(&self, kind: &ParameterKind) -> Vec<serde_json::Value> {
+        match kind {
+            ParameterKind::Integer { .. } => numeric_values(self.start, self.end, self.step, true),
+            ParameterKind::Decimal { .. } => numeric_values(self.start, self.end, self.step, false),
+            ParameterKind::Choice(choices) => choices
+                .iter()
+                .zip(self.selected.iter())
+                .filter(|(_, selected)| **selected)
+                .map(|(choice, _)| choice.clone())
+                .collect(),
+        }
+    }
+}
+
+/// The ranges of a sweep and its runs, or why it couldn't run
+type FinishedSweep = (Vec<ParameterRange>, Result<Vec<SweepRun>, String>);
+
+#[derive(Default)]
+struct SharedState {
+    /// A sweep that finished, until the panel takes it
+    finished: Option<FinishedSweep>,
+    running: bool,
+}
+
+/// Grid search over node arguments, shown as a window next to the graph. Sweeps run on a
+/// worker thread.
+#[derive(Default)]
+pub struct SweepPanel {
+    pub open: bool,
+    specs: BTreeMap<(usize, &'static str), SweepSpec>,
+    metric: Metric,
+    config: BacktestConfig,
+    ranges: Vec<ParameterRange>,
+    runs: Vec<SweepRun>,
+    x_axis: usize,
+    y_axis: Option<usize>,
+    error: Option<String>,
+    state: Arc<Mutex<SharedState>>,
+}
+
+fn heat_color(t: f64) -> Color32 {
+    let t = t.clamp(0.0, 1.0) as f32;
+    Color32::from_rgb((220.0 * (1.0 - t)) as u8, (200.0 * t) as u8, 48)
+}
+
+impl SweepPanel {
+    pub fn show(&mut self, ctx: &egui::Context, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+        self.take_finished();
+        let mut open = self.open;
+        egui::Window::new("Parameter Sweep")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    self.show_parameters(ui, snarl);
+                    ui.separator();
+                    self.show_controls(ui, snarl);
+                    if !self.runs.is_empty() {
+                        ui.separator();
+                        self.show_results(ui);
+                        ui.separator();
+                        self.show_heatmap(ui);
+                    }
+                });
+            });
+        self.open = open;
+    }
+
+    fn show_parameters(&mut self, ui: &mut Ui, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+        ui.heading("Parameters");
+        for (id, node) in snarl.node_ids() {
+            let parameters = node.get_node().get_parameters();
+            if parameters.is_empty() {
+                continue;
+            }
+            ui.label(RichText::new(format!("{} #{}", node.get_node().get_name(), id.0)).strong());
+            for parameter in parameters {
+                let spec = self
+                    .specs
+                    .entry((id.0, parameter.key))
+                    .or_insert_with(|| SweepSpec::new(&parameter.kind));
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut spec.enabled, parameter.key);
+                    if !spec.enabled {
+                        return;
+                    }
+                    match &parameter.kind {
+                        ParameterKind::Integer { min, max } => {
+                            let range = (*min as f64)..=(*max as f64);
+                            ui.add(DragValue::new(&mut spec.start).range(range.clone()));
+                            ui.label("to");
+                            ui.add(DragValue::new(&mut spec.end).range(range));
+                            ui.label("step");
+                            ui.add(DragValue::new(&mut spec.step).range(1.0..=f64::MAX));
+                        }
+                        ParameterKind::Decimal { min, max } => {
+                            let speed = (max - min) / 100.0;
+                            ui.add(DragValue::new(&mut spec.start).speed(speed));
+                            ui.label("to");
+                            ui.add(DragValue::new(&mut spec.end).speed(speed));
+                            ui.label("step");
+                            ui.add(DragValue::new(&mut spec.step).speed(speed / 10.0));
+                        }
+                        ParameterKind::Choice(choices) => {
+                            for (choice, selected) in choices.iter().zip(spec.selected.iter_mut()) {
+                                ui.checkbox(selected, value_label(choice));
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    /// The ranges currently enabled in the panel, for the nodes still in the graph
+    pub fn collect_ranges(&self, snarl: &Snarl<EFNodeFNSerialized<'_>>) -> Vec<ParameterRange> {
+        snarl
+            .node_ids()
+            .flat_map(|(id, node)| {
+                node.get_node()
+                    .get_parameters()
+                    .into_iter()
+                    .filter_map(move |parameter| {
+                        let spec = self.specs.get(&(id.0, parameter.key))?;
+                        if !spec.enabled {
+                            return None;
+                        }
+                        Some(ParameterRange {
+                            node_id: id.0,
+                            key: parameter.key.to_owned(),
+                            values: spec.values(&parameter.kind),
+                        })
+                    })
+            })
+            .collect()
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+        ui.horizontal(|ui| {
+            ui.label("Fee");
+            ui.add(
+                DragValue::new(&mut self.config.fee)
+                    .speed(0.0001)
+                    .range(0.0..=0.1),
+            );
+            ui.label("Metric");
+            ComboBox::from_id_salt("sweep_metric")
+                .selected_text(self.metric.to_string())
+                .show_ui(ui, |ui| {
+                    for metric in Metric::iter() {
+                        ui.selectable_value(&mut self.metric, metric, metric.to_string());
+                    }
+                });
+        });
+
+        let ranges = self.collect_ranges(snarl);
+        let n_combinations: usize = ranges.iter().map(|range| range.values.len()).product();
+        ui.horizontal(|ui| {
+            let running = self.state.lock().running;
+            if ui
+                .add_enabled(
+                    !running,
+                    Button::new(format!("Run {} combinations", n_combinations)),
+                )
+                .clicked()
+            {
+                self.start(ui.ctx().clone(), snarl, ranges);
+            }
+            if running {
+                ui.add(Spinner::new());
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    fn start(
+        &mut self,
+        ctx: egui::Context,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        ranges: Vec<ParameterRange>,
+    ) {
+        let graph = match GraphSnapshot::from_snarl(snarl) {
+            Ok(graph) => graph,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        self.state.lock().running = true;
+        let state = self.state.clone();
+        let config = self.config.clone();
+        let sweep = move || {
+            let runs = run_sweep(&graph, &ranges, &config).map_err(|e| e.to_string());
+            let mut state = state.lock();
+            state.finished = Some((ranges, runs));
+            state.running = false;
+            ctx.request_repaint();
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(sweep);
+        // There are no threads on the web, the sweep runs before the next frame
+        #[cfg(target_arch = "wasm32")]
+        sweep();
+    }
+
+    /// Shows the runs of the sweep that finished since the last frame
+    fn take_finished(&mut self) {
+        let Some((ranges, runs)) = self.state.lock().finished.take() else {
+            return;
+        };
+        match runs {
+            Ok(runs) => {
+                self.error = None;
+                self.runs = runs;
+                self.x_axis = 0;
+                self.y_axis = if ranges.len() > 1 { Some(1) } else { None };
+                self.ranges = ranges;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn show_results(&mut self, ui: &mut Ui) {
+        ui.heading("Results");
+        let mut order: Vec<usize> = (0..self.runs.len()).collect();
+        order.sort_by(|a, b| {
+            let score = |idx: usize| match &self.runs[idx].result {
+                Ok(metrics) => self.metric.score(metrics),
+                Err(_) => f64::NEG_INFINITY,
+            };
+            score(*b).total_cmp(&score(*a))
+        });
+
+        ScrollArea::both()
+            .id_salt("sweep_results")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                Grid::new("sweep_results_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for range in self.ranges.iter() {
+                            ui.label(RichText::new(range.label()).strong());
+                        }
+                        for metric in Metric::iter() {
+                            ui.label(RichText::new(metric.to_string()).strong());
+                        }
+                        ui.end_row();
+
+                        for idx in order {
+                            let run = &self.runs[idx];
+                            for value in run.values.iter() {
+                                ui.label(value_label(value));
+                            }
+                            match &run.result {
+                                Ok(metrics) => {
+                                    for metric in Metric::iter() {
+                                        ui.label(format!("{:.3}", metric.value(metrics)));
+                                    }
+                                }
+                                Err(e) => {
+                                    ui.colored_label(Color32::RED, e);
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    fn show_heatmap(&mut self, ui: &mut Ui) {
+        ui.heading("Heatmap");
+        ui.horizontal(|ui| {
+            ComboBox::from_label("X")
+                .selected_text(self.ranges[self.x_axis].label())
+                .show_ui(ui, |ui| {
+                    for (idx, range) in self.ranges.iter().enumerate() {
+                        ui.selectable_value(&mut self.x_axis, idx, range.label());
+                    }
+                });
+            ComboBox::from_label("Y")
+                .selected_text(
+                    self.y_axis
+                        .map_or("None".to_owned(), |y_axis| self.ranges[y_axis].label()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.y_axis, None, "None");
+                    for (idx, range) in self.ranges.iter().enumerate() {
+                        ui.selectable_value(&mut self.y_axis, Some(idx), range.label());
+                    }
+                });
+        });
+
+        let cells = heatmap(
+            &self.ranges,
+            &self.runs,
+            self.x_axis,
+            self.y_axis,
+            self.metric,
+        );
+        let (min, max) = cells
+            .iter()
+            .flatten()
+            .flatten()
+            .fold((f64::MAX, f64::MIN), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            });
+        let flip = self.metric == Metric::MaxDrawdown;
+
+        let x_values = &self.ranges[self.x_axis].values;
+        let y_values = self.y_axis.map(|y_axis| &self.ranges[y_axis].values);
+        ScrollArea::horizontal()
+            .id_salt("sweep_heatmap")
+            .show(ui, |ui| {
+                Grid::new("sweep_heatmap_grid")
+                    .spacing([2.0, 2.0])
+                    .show(ui, |ui| {
+                        for (y, row) in cells.iter().enumerate().rev() {
+                            ui.label(
+                                y_values.map_or(String::new(), |values| value_label(&values[y])),
+                            );
+                            for cell in row {
+                                let (rect, response) = ui.allocate_exact_size(
+                                    egui::vec2(HEATMAP_CELL_SIZE, HEATMAP_CELL_SIZE * 0.6),
+                                    Sense::hover(),
+                                );
+                                let Some(value) = cell else {
+                                    ui.painter().rect_filled(rect, 2.0, Color32::DARK_GRAY);
+                                    continue;
+                                };
+                                let mut t = if max > min {
+                                    (value - min) / (max - min)
+                                } else {
+                                    1.0
+                                };
+                                if flip {
+                                    t = 1.0 - t;
+                                }
+                                ui.painter().rect_filled(rect, 2.0, heat_color(t));
+                                response.on_hover_text(format!("{}: {:.4}", self.metric, value));
+                            }
+                            ui.end_row();
+                        }
+                        ui.label("");
+                        for value in x_values {
+                            ui.label(value_label(value));
+                        }
+                        ui.end_row();
+                    });
+            });
+    }
+}
@@ -0,0 +1,105 @@
+use rand::{distributions::WeightedIndex, prelude::Distribution};
+use rand_chacha::ChaCha8Rng;
+
+use super::optimizer::{Evaluator, OptimizerConfig, Trial};
+
+/// Fraction of the trials that count as "good"
+const GAMMA: f64 = 0.25;
+/// Weight of the uniform prior mixed into every density, keeps unexplored values reachable
+const PRIOR_WEIGHT: f64 = 1.0;
+const CANDIDATES_PER_SUGGESTION: usize = 24;
+
+/// Parzen estimate over the value indices of a single parameter
+fn density(observations: &[usize], n_values: usize) -> Vec<f64> {
+    let bandwidth = (n_values as f64 / 10.0).max(1.0);
+    let mut weights = vec![PRIOR_WEIGHT / n_values as f64; n_values];
+    for observation in observations {
+        for (idx, weight) in weights.iter_mut().enumerate() {
+            let distance = (idx as f64 - *observation as f64) / bandwidth;
+            *weight += (-0.5 * distance * distance).exp();
+        }
+    }
+    let total: f64 = weights.iter().sum();
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+/// Proposes `n` combinations that are likely under the good trials and unlikely under the bad
+/// ones (Tree-structured Parzen Estimator, with every parameter modelled independently)
+fn suggest(evaluator: &Evaluator<'_>, rng: &mut ChaCha8Rng, n: usize) -> Vec<Vec<usize>> {
+    let mut trials: Vec<&Trial> = evaluator.trials().collect();
+    // HashMap order is random, sort fully so the search stays deterministic for a seed
+    trials.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.indices.cmp(&b.indices)));
+    let n_good = ((trials.len() as f64 * GAMMA).ceil() as usize).max(1);
+    let (good, bad) = trials.split_at(n_good.min(trials.len()));
+
+    let densities: Vec<(Vec<f64>, Vec<f64>)> = evaluator
+        .ranges
+        .iter()
+        .enumerate()
+        .map(|(dim, range)| {
+            let good: Vec<usize> = good.iter().map(|trial| trial.indices[dim]).collect();
+            let bad: Vec<usize> = bad.iter().map(|trial| trial.indices[dim]).collect();
+            (
+                density(&good, range.values.len()),
+                density(&bad, range.values.len()),
+            )
+        })
+        .collect();
+    let samplers: Vec<WeightedIndex<f64>> = densities
+        .iter()
+        .map(|(good, _)| WeightedIndex::new(good).unwrap())
+        .collect();
+
+    let mut suggestions: Vec<Vec<usize>> = vec![];
+    for _ in 0..n {
+        let best_candidate = (0..CANDIDATES_PER_SUGGESTION)
+            .map(|_| {
+                samplers
+                    .iter()
+                    .map(|sampler| sampler.sample(rng))
+                    .collect::<Vec<usize>>()
+            })
+            .filter(|candidate| {
+                !evaluator.is_evaluated(candidate) && !suggestions.contains(candidate)
+            })
+            .map(|candidate| {
+                let expected_improvement: f64 = candidate
+                    .iter()
+                    .zip(densities.iter())
+                    .map(|(idx, (good, bad))| (good[*idx] / bad[*idx]).ln())
+                    .sum();
+                (candidate, expected_improvement)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(candidate, _)| candidate);
+        match best_candidate {
+            Some(candidate) => suggestions.push(candidate),
+            // The good region is fully explored, fall back to random search
+            None => suggestions.push(evaluator.random_indices(rng)),
+        }
+    }
+    suggestions
+}
+
+pub fn run_tpe(
+    evaluator: &mut Evaluator<'_>,
+    rng: &mut ChaCha8Rng,
+    config: &OptimizerConfig,
+    should_continue: &mut dyn FnMut(&Evaluator<'_>) -> bool,
+) {
+    let batch_size = config.population.max(1);
+    let n_startup = (config.budget / 5).max(10);
+    let startup: Vec<Vec<usize>> = (0..n_startup)
+        .map(|_| evaluator.random_indices(rng))
+        .collect();
+    evaluator.evaluate(&startup);
+
+    // Every step makes at least one new evaluation unless the space is exhausted
+    for _ in 0..config.budget {
+        if !should_continue(evaluator) {
+            return;
+        }
+        let suggestions = suggest(evaluator, rng, batch_size);
+        evaluator.evaluate(&suggestions);
+    }
+}
@@ -0,0 +1,276 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum::{Display, EnumIter};
+
+use super::sweep::{
+    combinations, run_with_parameters, shared_cache, validate_ranges, ParameterRange,
+};
+use crate::{
+    backtest::{
+        engine::{
+            backtest_candles, graph_candles_and_signal, market_data_node, BacktestConfig,
+            BacktestResult, Trade,
+        },
+        metrics::{BacktestMetrics, Metric},
+    },
+    node_editor::node_trait::{CheapCloneNodeDataTypeWithValue, NodeDataTypeWithValue},
+    node_runners::graph::{ComputeCache, GraphSnapshot},
+    traits::IntoArc,
+};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum WindowMode {
+    /// The in-sample window slides along with the out-of-sample window
+    #[default]
+    Rolling,
+    /// The in-sample window always starts at the first candle and grows
+    Anchored,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalkForwardConfig {
+    pub mode: WindowMode,
+    /// Number of candles to optimise on
+    pub in_sample: usize,
+    /// Number of candles to evaluate the optimised parameters on
+    pub out_of_sample: usize,
+    /// Candles before a window that are fed to the graph so indicators can warm up,
+    /// but are not traded
+    pub warmup: usize,
+    pub metric: Metric,
+}
+
+impl Default for WalkForwardConfig {
+    fn default() -> Self {
+        Self {
+            mode: WindowMode::Rolling,
+            in_sample: 200,
+            out_of_sample: 50,
+            warmup: 50,
+            metric: Metric::Sharpe,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Window {
+    pub in_sample: Range<usize>,
+    pub out_of_sample: Range<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct WindowResult {
+    pub window: Window,
+    /// The best values found in-sample, one per `ParameterRange`
+    pub values: Vec<Value>,
+    pub in_sample: BacktestMetrics,
+    pub out_of_sample: BacktestMetrics,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WalkForwardResult {
+    pub windows: Vec<WindowResult>,
+    /// Index of the first candle of the stitched equity curve
+    pub start: usize,
+    /// Out-of-sample equity curves chained together
+    pub equity: Vec<f64>,
+    /// Out-of-sample trades, indexed on the full candle range
+    pub trades: Vec<Trade>,
+    pub metrics: BacktestMetrics,
+}
+
+pub fn split_windows(len: usize, config: &WalkForwardConfig) -> Vec<Window> {
+    let mut windows = vec![];
+    if config.in_sample == 0 || config.out_of_sample == 0 {
+        return windows;
+    }
+    let mut oos_start = config.in_sample;
+    while oos_start < len {
+        let oos_end = (oos_start + config.out_of_sample).min(len);
+        let is_start = match config.mode {
+            WindowMode::Rolling => oos_start - config.in_sample,
+            WindowMode::Anchored => 0,
+        };
+        windows.push(Window {
+            in_sample: is_start..oos_start,
+            out_of_sample: oos_start..oos_end,
+        });
+        oos_start = oos_end;
+    }
+    windows
+}
+
+struct WindowRunner<'a> {
+    graph: &'a GraphSnapshot,
+    market_data_id: usize,
+    /// Every output of the market data node, so all of its symbols get sliced
+    market_data: Vec<CheapCloneNodeDataTypeWithValue>,
+    ranges: &'a [ParameterRange],
+    warmup: usize,
+    config: &'a BacktestConfig,
+}
+
+impl WindowRunner<'_> {
+    /// Backtests only `range` of the candles, while the graph sees `warmup` extra candles before
+    /// it. Like `run_sweep`, a combination that fails doesn't stop the others.
+    fn backtest(
+        &self,
+        range: &Range<usize>,
+        values_to_try: &[Vec<Value>],
+    ) -> Result<Vec<Result<BacktestResult>>> {
+        let fed_start = range.start.saturating_sub(self.warmup);
+        let skip = range.start - fed_start;
+        let mut initial_cache = ComputeCache::new();
+        let fed_market_data = self
+            .market_data
+            .iter()
+            .map(|output| match &**output {
+                NodeDataTypeWithValue::Candles(candles) => NodeDataTypeWithValue::Candles(
+                    candles.slice(fed_start as i64, range.end - fed_start),
+                )
+                .into_arc(),
+                _ => output.clone(),
+            })
+            .collect();
+        initial_cache.insert(self.market_data_id, fed_market_data);
+        let swept_nodes: Vec<usize> = self.ranges.iter().map(|range| range.node_id).collect();
+        let base_cache = shared_cache(self.graph, initial_cache, &swept_nodes)?;
+
+        Ok(values_to_try
+            .into_par_iter()
+            .map(|values| {
+                let (graph, cache) =
+                    run_with_parameters(self.graph, &base_cache, self.ranges, values)?;
+                let (fed_candles, signal) = graph_candles_and_signal(&graph, &cache)?;
+                if signal.len() < skip {
+                    return Err(anyhow!("Signal is shorter than the warmup period"));
+                }
+                backtest_candles(
+                    &fed_candles.df.slice(skip as i64, range.len()),
+                    &signal[skip..],
+                    self.config,
+                )
+            })
+            .collect())
+    }
+}
+
+pub fn run_walk_forward(
+    graph: &GraphSnapshot,
+    ranges: &[ParameterRange],
+    config: &BacktestConfig,
+    walk_forward_config: &WalkForwardConfig,
+) -> Result<WalkForwardResult> {
+    validate_ranges(ranges)?;
+    let market_data_id = market_data_node(graph)?;
+    // Each window feeds its own slice of the candles, a swept node that changes them would
+    // regenerate all of them instead
+    let swept_nodes: Vec<usize> = ranges.iter().map(|range| range.node_id).collect();
+    if graph.downstream_of(&swept_nodes).contains(&market_data_id) {
+        return Err(anyhow!(
+            "Walk-forward can't sweep the MarketDataNode or nodes before it, the candles must \
+             stay the same in every window"
+        ));
+    }
+    let mut cache = ComputeCache::new();
+    graph.run(&mut cache)?;
+    let market_data = cache
+        .get(&market_data_id)
+        .cloned()
+        .ok_or_else(|| anyhow!("MarketDataNode has no computed candles"))?;
+    let candles = match market_data.first().map(|output| &**output) {
+        Some(NodeDataTypeWithValue::Candles(candles)) => candles.df.clone(),
+        _ => return Err(anyhow!("MarketDataNode did not output candles")),
+    };
+
+    let windows = split_windows(candles.height(), walk_forward_config);
+    if windows.is_empty() {
+        return Err(anyhow!(
+            "Not enough candles ({}) for an in-sample window of {}",
+            candles.height(),
+            walk_forward_config.in_sample
+        ));
+    }
+
+    let runner = WindowRunner {
+        graph,
+        market_data_id,
+        market_data,
+        ranges,
+        warmup: walk_forward_config.warmup,
+        config,
+    };
+    let all_values = combinations(ranges);
+    let metric = walk_forward_config.metric;
+    let mut result = WalkForwardResult {
+        start: windows[0].out_of_sample.start,
+        ..Default::default()
+    };
+    let mut balance = config.initial_balance;
+    for window in windows {
+        let in_sample_results = runner.backtest(&window.in_sample, &all_values)?;
+        // NaN would rank above every real score, so only finite scores can win
+        let best = in_sample_results
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, result)| {
+                let result = result.as_ref().ok()?;
+                let score = metric.score(&result.metrics);
+                score.is_finite().then_some((idx, result, score))
+            })
+            .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b));
+        let Some((best_idx, best, _)) = best else {
+            let reason = match in_sample_results
+                .iter()
+                .find_map(|result| result.as_ref().err())
+            {
+                Some(e) => e.to_string(),
+                None => format!("none had a finite {}", metric),
+            };
+            return Err(anyhow!(
+                "No parameter combination worked in the in-sample window {}..{}: {}",
+                window.in_sample.start,
+                window.in_sample.end,
+                reason
+            ));
+        };
+        let values = all_values[best_idx].clone();
+
+        let out_of_sample = runner
+            .backtest(&window.out_of_sample, std::slice::from_ref(&values))?
+            .remove(0)?;
+
+        // Chain the window onto the previous one, each window starts at `initial_balance`
+        let scale = balance / config.initial_balance;
+        result
+            .equity
+            .extend(out_of_sample.equity.iter().map(|value| value * scale));
+        balance = result.equity.last().copied().unwrap_or(balance);
+        result
+            .trades
+            .extend(out_of_sample.trades.iter().map(|trade| Trade {
+                entry_index: trade.entry_index + window.out_of_sample.start,
+                exit_index: trade.exit_index + window.out_of_sample.start,
+                ..trade.clone()
+            }));
+        result.windows.push(WindowResult {
+            window,
+            values,
+            in_sample: best.metrics.clone(),
+            out_of_sample: out_of_sample.metrics,
+        });
+    }
+
+    let timestamps = candles.column("timestamp")?.u64()?;
+    let bar_seconds = match (timestamps.get(0), timestamps.get(1)) {
+        (Some(first), Some(second)) => second.saturating_sub(first),
+        _ => 0,
+    };
+    result.metrics = BacktestMetrics::new(&result.equity, &result.trades, bar_seconds);
+    Ok(result)
+}
@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use egui::{Button, Color32, ComboBox, DragValue, Grid, RichText, ScrollArea, Spinner, Ui};
+use egui_plot::{Legend, Line, Plot, PlotPoints, VLine};
+use egui_snarl::Snarl;
+use itertools::Itertools;
+use parking_lot::Mutex;
+use strum::IntoEnumIterator;
+
+use super::{
+    sweep::{value_label, ParameterRange},
+    walk_forward::{run_walk_forward, WalkForwardConfig, WalkForwardResult, WindowMode},
+};
+use crate::{
+    backtest::{
+        engine::BacktestConfig,
+        metrics::{BacktestMetrics, Metric},
+    },
+    node_editor::node_trait::EFNodeFNSerialized,
+    node_runners::graph::GraphSnapshot,
+};
+
+const EQUITY_COLOR: Color32 = Color32::from_rgb(0x00, 0xb0, 0x00);
+const WINDOW_COLOR: Color32 = Color32::from_rgb(0x60, 0x60, 0x60);
+
+fn metrics_summary(metrics: &BacktestMetrics) -> String {
+    format!(
+        "{:+.2}% | DD {:.2}% | Sharpe {:.2} | {} trades",
+        metrics.total_return * 100.0,
+        metrics.max_drawdown * 100.0,
+        metrics.sharpe,
+        metrics.trade_count
+    )
+}
+
+/// The ranges of a walk-forward and its result, or why it failed
+type FinishedWalkForward = (Vec<ParameterRange>, Result<WalkForwardResult, String>);
+
+#[derive(Default)]
+struct SharedState {
+    /// A walk-forward that finished, until the panel takes it
+    finished: Option<FinishedWalkForward>,
+    running: bool,
+}
+
+/// Walk-forward analysis of the parameters selected in the sweep panel, on a worker thread
+#[derive(Default)]
+pub struct WalkForwardPanel {
+    pub open: bool,
+    config: WalkForwardConfig,
+    backtest_config: BacktestConfig,
+    ranges: Vec<ParameterRange>,
+    result: Option<WalkForwardResult>,
+    error: Option<String>,
+    state: Arc<Mutex<SharedState>>,
+}
+
+impl WalkForwardPanel {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        ranges: Vec<ParameterRange>,
+    ) {
+        self.take_finished();
+        let mut open = self.open;
+        egui::Window::new("Walk-Forward")
+            .open(&mut open)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    self.show_controls(ui, snarl, ranges);
+                    if self.result.is_some() {
+                        ui.separator();
+                        self.show_result(ui);
+                    }
+                });
+            });
+        self.open = open;
+    }
+
+    fn show_controls(
+        &mut self,
+        ui: &mut Ui,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        ranges: Vec<ParameterRange>,
+    ) {
+        if ranges.is_empty() {
+            ui.label("Select the parameters to optimise in the Parameter Sweep window.");
+        } else {
+            ui.label(format!(
+                "Optimising: {}",
+                ranges.iter().map(|range| range.label()).join(", ")
+            ));
+        }
+
+        Grid::new("walk_forward_config").show(ui, |ui| {
+            ui.label("Windows");
+            ComboBox::from_id_salt("walk_forward_mode")
+                .selected_text(self.config.mode.to_string())
+                .show_ui(ui, |ui| {
+                    for mode in WindowMode::iter() {
+                        ui.selectable_value(&mut self.config.mode, mode, mode.to_string());
+                    }
+                });
+            ui.end_row();
+            ui.label("In-sample candles");
+            ui.add(DragValue::new(&mut self.config.in_sample).range(1..=usize::MAX));
+            ui.end_row();
+            ui.label("Out-of-sample candles");
+            ui.add(DragValue::new(&mut self.config.out_of_sample).range(1..=usize::MAX));
+            ui.end_row();
+            ui.label("Warmup candles");
+            ui.add(DragValue::new(&mut self.config.warmup));
+            ui.end_row();
+            ui.label("Optimise for");
+            ComboBox::from_id_salt("walk_forward_metric")
+                .selected_text(self.config.metric.to_string())
+                .show_ui(ui, |ui| {
+                    for metric in Metric::iter() {
+                        ui.selectable_value(&mut self.config.metric, metric, metric.to_string());
+                    }
+                });
+            ui.end_row();
+            ui.label("Fee");
+            ui.add(
+                DragValue::new(&mut self.backtest_config.fee)
+                    .speed(0.0001)
+                    .range(0.0..=0.1),
+            );
+            ui.end_row();
+        });
+
+        ui.horizontal(|ui| {
+            let running = self.state.lock().running;
+            if ui
+                .add_enabled(!running, Button::new("Run walk-forward"))
+                .clicked()
+            {
+                self.start(ui.ctx().clone(), snarl, ranges);
+            }
+            if running {
+                ui.add(Spinner::new());
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    fn start(
+        &mut self,
+        ctx: egui::Context,
+        snarl: &Snarl<EFNodeFNSerialized<'_>>,
+        ranges: Vec<ParameterRange>,
+    ) {
+        let graph = match GraphSnapshot::from_snarl(snarl) {
+            Ok(graph) => graph,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        self.state.lock().running = true;
+        let state = self.state.clone();
+        let backtest_config = self.backtest_config.clone();
+        let config = self.config.clone();
+        let walk_forward = move || {
+            let result = run_walk_forward(&graph, &ranges, &backtest_config, &config)
+                .map_err(|e| e.to_string());
+            let mut state = state.lock();
+            state.finished = Some((ranges, result));
+            state.running = false;
+            ctx.request_repaint();
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(walk_forward);
+        // There are no threads on the web, the walk-forward runs before the next frame
+        #[cfg(target_arch = "wasm32")]
+        walk_forward();
+    }
+
+    /// Shows the result of the walk-forward that finished since the last frame
+    fn take_finished(&mut self) {
+        let Some((ranges, result)) = self.state.lock().finished.take() else {
+            return;
+        };
+        match result {
+            Ok(result) => {
+                self.error = None;
+                self.result = Some(result);
+                self.ranges = ranges;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn show_result(&self, ui: &mut Ui) {
+        let Some(result) = &self.result else {
+            return;
+        };
+        ui.heading("Out-of-sample");
+        ui.label(metrics_summary(&result.metrics));
+
+        let start = result.start;
+        let equity = PlotPoints::from_iter(
+            result
+                .equity
+                .iter()
+                .enumerate()
+                .map(|(idx, value)| [(start + idx) as f64, *value]),
+        );
+        let window_starts: Vec<f64> = result
+            .windows
+            .iter()
+            .map(|window| window.window.out_of_sample.start as f64)
+            .collect();
+        Plot::new("walk_forward_equity")
+            .height(160.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                for window_start in window_starts {
+                    plot_ui.vline(VLine::new(window_start).color(WINDOW_COLOR));
+                }
+                plot_ui.line(Line::new(equity).name("OOS equity").color(EQUITY_COLOR));
+            });
+
+        ui.heading("Windows");
+        ScrollArea::horizontal()
+            .id_salt("walk_forward_windows")
+            .show(ui, |ui| {
+                Grid::new("walk_forward_windows_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label(RichText::new("In-sample").strong());
+                        ui.label(RichText::new("Out-of-sample").strong());
+                        for range in self.ranges.iter() {
+                            ui.label(RichText::new(range.label()).strong());
+                        }
+                        ui.label(RichText::new("IS metrics").strong());
+                        ui.label(RichText::new("OOS metrics").strong());
+                        ui.end_row();
+
+                        for window in result.windows.iter() {
+                            ui.label(format!(
+                                "{}..{}",
+                                window.window.in_sample.start, window.window.in_sample.end
+                            ));
+                            ui.label(format!(
+                                "{}..{}",
+                                window.window.out_of_sample.start, window.window.out_of_sample.end
+                            ));
+                            for value in window.values.iter() {
+                                ui.label(value_label(value));
+                            }
+                            ui.label(metrics_summary(&window.in_sample));
+                            ui.label(metrics_summary(&window.out_of_sample));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
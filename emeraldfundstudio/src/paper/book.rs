@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{node_runners::streaming::LiveBar, types::timestamp::TimeStamp};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Level {
+    pub price: f64,
+    pub amount: f64,
+}
+
+/// How a book is made up from a candle when there are no snapshots
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookConfig {
+    /// Levels on each side
+    pub levels: usize,
+    /// Distance between the best bid and the best ask, as a fraction of the close
+    pub spread: f64,
+    /// Distance between two levels, as a fraction of the close
+    pub tick: f64,
+    /// Part of the candle's volume resting on each side of the book
+    pub depth: f64,
+}
+
+impl Default for BookConfig {
+    fn default() -> Self {
+        Self {
+            levels: 10,
+            spread: 0.0005,
+            tick: 0.0002,
+            depth: 0.1,
+        }
+    }
+}
+
+/// Level 2 order book, bids from the highest price down, asks from the lowest price up
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct OrderBook {
+    pub timestamp: TimeStamp,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+impl OrderBook {
+    /// Sorts the levels and drops the empty ones, snapshots don't have to be in order
+    pub fn new(timestamp: TimeStamp, mut bids: Vec<Level>, mut asks: Vec<Level>) -> Self {
+        bids.retain(|level| level.amount > 0.0);
+        asks.retain(|level| level.amount > 0.0);
+        bids.sort_by(|a, b| b.price.total_cmp(&a.price));
+        asks.sort_by(|a, b| a.price.total_cmp(&b.price));
+        Self {
+            timestamp,
+            bids,
+            asks,
+        }
+    }
+
+    /// A book around the close of `bar`, with the depth spread evenly over the levels
+    pub fn from_candle(bar: &LiveBar, config: &BookConfig) -> Self {
+        let levels = config.levels.max(1);
+        let amount = bar.volume.max(0.0) * config.depth / levels as f64;
+        let half_spread = bar.close * config.spread / 2.0;
+        let tick = bar.close * config.tick;
+        let side = |sign: f64| -> Vec<Level> {
+            (0..levels)
+                .map(|level| Level {
+                    price: bar.close + sign * (half_spread + tick * level as f64),
+                    amount,
+                })
+                .collect()
+        };
+        Self::new(bar.timestamp, side(-1.0), side(1.0))
+    }
+
+    pub fn best_bid(&self) -> Option<f64> {
+        self.bids.first().map(|level| level.price)
+    }
+
+    pub fn best_ask(&self) -> Option<f64> {
+        self.asks.first().map(|level| level.price)
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.best_bid()? + self.best_ask()?) / 2.0)
+    }
+}
+
+/// One line of a snapshots file
+#[derive(Deserialize)]
+struct Snapshot {
+    timestamp: TimeStamp,
+    bids: Vec<(f64, f64)>,
+    asks: Vec<(f64, f64)>,
+}
+
+/// Reads order book snapshots from JSON lines like
+/// `{"timestamp": 1700000000, "bids": [[price, amount], ...], "asks": [[price, amount], ...]}`,
+/// oldest first
+pub fn parse_snapshots(text: &str) -> Result<Vec<OrderBook>> {
+    let to_levels = |levels: Vec<(f64, f64)>| {
+        levels
+            .into_iter()
+            .map(|(price, amount)| Level { price, amount })
+            .collect()
+    };
+    let mut books = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            let snapshot: Snapshot = serde_json::from_str(line)
+                .map_err(|e| anyhow!("Snapshot on line {}: {}", number + 1, e))?;
+            Ok(OrderBook::new(
+                snapshot.timestamp,
+                to_levels(snapshot.bids),
+                to_levels(snapshot.asks),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    books.sort_by_key(|book| book.timestamp);
+    Ok(books)
+}
@@ -0,0 +1,614 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use super::book::{BookConfig, Level, OrderBook};
+use crate::{
+    node_editor::{OrderDirection, OrderType},
+    node_runners::streaming::LiveBar,
+    types::timestamp::TimeStamp,
+};
+
+/// Amounts below this count as nothing, so rounding doesn't leave dust orders open
+const EPSILON: f64 = 1e-12;
+
+pub type OrderId = u64;
+
+/// Fees that apply from a traded volume on, like the VIP levels of an exchange
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FeeTier {
+    /// Quote volume traded on the account
+    pub min_volume: f64,
+    /// Fraction of the traded value for fills of resting orders
+    pub maker: f64,
+    /// Fraction of the traded value for fills that take liquidity
+    pub taker: f64,
+}
+
+pub fn default_fee_tiers() -> Vec<FeeTier> {
+    vec![
+        FeeTier {
+            min_volume: 0.0,
+            maker: 0.001,
+            taker: 0.001,
+        },
+        FeeTier {
+            min_volume: 1_000_000.0,
+            maker: 0.0009,
+            taker: 0.001,
+        },
+        FeeTier {
+            min_volume: 5_000_000.0,
+            maker: 0.0008,
+            taker: 0.0009,
+        },
+        FeeTier {
+            min_volume: 20_000_000.0,
+            maker: 0.0006,
+            taker: 0.0008,
+        },
+    ]
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaperConfig {
+    pub base_balance: f64,
+    pub quote_balance: f64,
+    pub fee_tiers: Vec<FeeTier>,
+    pub book: BookConfig,
+    /// Part of a candle's volume that resting orders traded through can be filled with
+    pub volume_share: f64,
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        Self {
+            base_balance: 0.0,
+            quote_balance: 1000.0,
+            fee_tiers: default_fee_tiers(),
+            book: BookConfig::default(),
+            volume_share: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Balance {
+    pub free: f64,
+    /// Reserved for open orders
+    pub locked: f64,
+}
+
+impl Balance {
+    pub fn total(&self) -> f64 {
+        self.free + self.locked
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+pub enum OrderStatus {
+    Open,
+    #[strum(serialize = "Partially filled")]
+    PartiallyFilled,
+    Filled,
+    Canceled,
+}
+
+#[derive(Clone, Debug)]
+pub struct Order {
+    pub id: OrderId,
+    pub direction: OrderDirection,
+    pub order_type: OrderType,
+    /// `None` for market orders
+    pub price: Option<f64>,
+    /// In the base asset
+    pub amount: f64,
+    pub filled: f64,
+    pub status: OrderStatus,
+    pub timestamp: TimeStamp,
+    /// Balance still reserved for the order, quote for buys and base for sells
+    locked: f64,
+}
+
+impl Order {
+    pub fn remaining(&self) -> f64 {
+        self.amount - self.filled
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.status,
+            OrderStatus::Open | OrderStatus::PartiallyFilled
+        )
+    }
+
+    /// Whether a resting order at `price` on the other side would trade with this order
+    fn crosses(&self, price: f64) -> bool {
+        match (self.price, self.direction) {
+            (None, _) => true,
+            (Some(limit), OrderDirection::Buy) => price <= limit,
+            (Some(limit), OrderDirection::Sell) => price >= limit,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Fill {
+    pub order_id: OrderId,
+    pub timestamp: TimeStamp,
+    pub direction: OrderDirection,
+    pub price: f64,
+    pub amount: f64,
+    /// Paid in the quote asset
+    pub fee: f64,
+    /// The order was resting in the book
+    pub maker: bool,
+}
+
+/// In-process exchange for one market. Orders match against an order book that is either
+/// made up from each candle or taken from snapshots, resting orders also fill when a candle
+/// trades through their price.
+pub struct PaperExchange {
+    pub pair: String,
+    pub config: PaperConfig,
+    pub base: Balance,
+    pub quote: Balance,
+    /// Quote volume traded so far, picks the fee tier
+    pub volume: f64,
+    pub book: OrderBook,
+    snapshots: Vec<OrderBook>,
+    /// Every order placed, open or not
+    pub orders: BTreeMap<OrderId, Order>,
+    pub fills: Vec<Fill>,
+    next_id: OrderId,
+    /// Bars up to this timestamp were matched already
+    pub last_bar: Option<TimeStamp>,
+}
+
+impl PaperExchange {
+    pub fn new(pair: &str, config: PaperConfig) -> Self {
+        Self {
+            pair: pair.to_owned(),
+            base: Balance {
+                free: config.base_balance,
+                locked: 0.0,
+            },
+            quote: Balance {
+                free: config.quote_balance,
+                locked: 0.0,
+            },
+            config,
+            volume: 0.0,
+            book: OrderBook::default(),
+            snapshots: vec![],
+            orders: BTreeMap::new(),
+            fills: vec![],
+            next_id: 1,
+            last_bar: None,
+        }
+    }
+
+    /// Base and quote asset names, for messages
+    pub fn assets(&self) -> (&str, &str) {
+        self.pair
+            .split_once(['-', '/', '_'])
+            .unwrap_or((self.pair.as_str(), "quote"))
+    }
+
+    /// Snapshots are used instead of made up books from their timestamp on
+    pub fn set_snapshots(&mut self, snapshots: Vec<OrderBook>) {
+        self.snapshots = snapshots;
+    }
+
+    pub fn snapshot_count(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// The tier with the highest volume the account reached, no fees without tiers
+    pub fn fee_tier(&self) -> FeeTier {
+        self.config
+            .fee_tiers
+            .iter()
+            .filter(|tier| tier.min_volume <= self.volume)
+            .max_by(|a, b| a.min_volume.total_cmp(&b.min_volume))
+            .cloned()
+            .unwrap_or(FeeTier {
+                min_volume: 0.0,
+                maker: 0.0,
+                taker: 0.0,
+            })
+    }
+
+    /// Account value in the quote asset at the middle of the book
+    pub fn equity(&self) -> f64 {
+        self.quote.total() + self.base.total() * self.book.mid().unwrap_or(0.0)
+    }
+
+    pub fn open_orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values().filter(|order| order.is_open())
+    }
+
+    /// Places an order. Market orders fill immediately as far as the book and the balance
+    /// allow, the rest is canceled. Limit orders take what crosses the book and rest with the
+    /// remaining amount. Limit maker orders are rejected if they would take liquidity.
+    pub fn place_order(
+        &mut self,
+        direction: OrderDirection,
+        order_type: OrderType,
+        amount: f64,
+        price: Option<f64>,
+    ) -> Result<OrderId> {
+        if !amount.is_finite() || amount <= EPSILON {
+            return Err(anyhow!("Order amount must be positive, got {}", amount));
+        }
+        let price = match (order_type, price) {
+            (OrderType::Market, _) => None,
+            (_, Some(price)) if price.is_finite() && price > 0.0 => Some(price),
+            (_, _) => return Err(anyhow!("{} orders need a positive price", order_type)),
+        };
+        let (base_asset, quote_asset) = self.assets();
+        let locked = match (direction, price) {
+            (OrderDirection::Buy, None) => 0.0,
+            (OrderDirection::Buy, Some(price)) => {
+                price * amount * (1.0 + self.fee_tier().taker.max(self.fee_tier().maker))
+            }
+            (OrderDirection::Sell, _) => amount,
+        };
+        let available = match direction {
+            OrderDirection::Buy => self.quote.free,
+            OrderDirection::Sell => self.base.free,
+        };
+        if locked > available + EPSILON {
+            let asset = match direction {
+                OrderDirection::Buy => quote_asset,
+                OrderDirection::Sell => base_asset,
+            };
+            return Err(anyhow!(
+                "Not enough {}: {} needed, {} available",
+                asset,
+                locked,
+                available
+            ));
+        }
+        if order_type == OrderType::LimitMaker {
+            let takes = match direction {
+                OrderDirection::Buy => self.book.best_ask(),
+                OrderDirection::Sell => self.book.best_bid(),
+            };
+            if let (Some(limit), Some(best)) = (price, takes) {
+                let crosses = match direction {
+                    OrderDirection::Buy => best <= limit,
+                    OrderDirection::Sell => best >= limit,
+                };
+                if crosses {
+                    return Err(anyhow!(
+                        "Limit maker order at {} would take liquidity at {}",
+                        limit,
+                        best
+                    ));
+                }
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        match direction {
+            OrderDirection::Buy => {
+                self.quote.free -= locked;
+                self.quote.locked += locked;
+            }
+            OrderDirection::Sell => {
+                self.base.free -= locked;
+                self.base.locked += locked;
+            }
+        }
+        self.orders.insert(
+            id,
+            Order {
+                id,
+                direction,
+                order_type,
+                price,
+                amount,
+                filled: 0.0,
+                status: OrderStatus::Open,
+                timestamp: self.book.timestamp,
+                locked,
+            },
+        );
+        if order_type != OrderType::LimitMaker {
+            self.take(id, false);
+        }
+        if order_type == OrderType::Market && self.orders[&id].is_open() {
+            // Immediate or cancel, there is nothing to rest at
+            self.release(id, OrderStatus::Canceled);
+        }
+        Ok(id)
+    }
+
+    pub fn cancel(&mut self, id: OrderId) -> Result<()> {
+        match self.orders.get(&id) {
+            Some(order) if order.is_open() => {
+                self.release(id, OrderStatus::Canceled);
+                Ok(())
+            }
+            Some(order) => Err(anyhow!("Order {} is {} already", id, order.status)),
+            None => Err(anyhow!("There is no order {}", id)),
+        }
+    }
+
+    pub fn cancel_all(&mut self) {
+        let open: Vec<OrderId> = self.open_orders().map(|order| order.id).collect();
+        for id in open {
+            self.release(id, OrderStatus::Canceled);
+        }
+    }
+
+    /// Matches the resting orders against a candle, then moves the book to it. Bars that are
+    /// not newer than the last one are ignored.
+    pub fn on_candle(&mut self, bar: &LiveBar) {
+        if self.last_bar.is_some_and(|last| bar.timestamp <= last) {
+            return;
+        }
+        self.last_bar = Some(bar.timestamp);
+
+        // Orders the candle traded through fill as maker at their price, from its volume
+        let mut volume = bar.volume.max(0.0) * self.config.volume_share;
+        let resting: Vec<OrderId> = self.open_orders().map(|order| order.id).collect();
+        for id in resting {
+            let order = &self.orders[&id];
+            let Some(price) = order.price else {
+                continue;
+            };
+            let traded_through = match order.direction {
+                OrderDirection::Buy => bar.low < price,
+                OrderDirection::Sell => bar.high > price,
+            };
+            let amount = order.remaining().min(volume);
+            if traded_through && amount > EPSILON {
+                self.fill(id, price, amount, true, bar.timestamp);
+                volume -= amount;
+            }
+        }
+
+        let position = self
+            .snapshots
+            .partition_point(|book| book.timestamp <= bar.timestamp);
+        let book = match position.checked_sub(1) {
+            Some(position) => {
+                let mut book = self.snapshots[position].clone();
+                book.timestamp = bar.timestamp;
+                book
+            }
+            None => OrderBook::from_candle(bar, &self.config.book),
+        };
+        self.on_book(book);
+    }
+
+    /// Moves to a new book, resting orders it crosses fill as maker at their price
+    pub fn on_book(&mut self, book: OrderBook) {
+        self.book = book;
+        let resting: Vec<OrderId> = self.open_orders().map(|order| order.id).collect();
+        for id in resting {
+            self.take(id, true);
+        }
+    }
+
+    /// Fills `id` against the other side of the book for as long as the prices cross
+    fn take(&mut self, id: OrderId, maker: bool) {
+        let fee = self.fee_tier();
+        loop {
+            let order = &self.orders[&id];
+            let levels = match order.direction {
+                OrderDirection::Buy => &self.book.asks,
+                OrderDirection::Sell => &self.book.bids,
+            };
+            let Some(Level { price, amount }) = levels.first().copied() else {
+                break;
+            };
+            if !order.is_open() || !order.crosses(price) {
+                break;
+            }
+            let fill_price = match (maker, order.price) {
+                (true, Some(limit)) => limit,
+                _ => price,
+            };
+            let mut fill_amount = order.remaining().min(amount);
+            if order.direction == OrderDirection::Buy && order.price.is_none() {
+                // Market buys spend the free balance, nothing was locked for them
+                let fee_rate = if maker { fee.maker } else { fee.taker };
+                fill_amount = fill_amount.min(self.quote.free / (fill_price * (1.0 + fee_rate)));
+            }
+            if fill_amount <= EPSILON {
+                break;
+            }
+            let timestamp = self.book.timestamp;
+            self.fill(id, fill_price, fill_amount, maker, timestamp);
+            let levels = match self.orders[&id].direction {
+                OrderDirection::Buy => &mut self.book.asks,
+                OrderDirection::Sell => &mut self.book.bids,
+            };
+            levels[0].amount -= fill_amount;
+            if levels[0].amount <= EPSILON {
+                levels.remove(0);
+            }
+        }
+    }
+
+    fn fill(&mut self, id: OrderId, price: f64, amount: f64, maker: bool, timestamp: TimeStamp) {
+        let tier = self.fee_tier();
+        let value = price * amount;
+        let fee = value * if maker { tier.maker } else { tier.taker };
+        let order = self.orders.get_mut(&id).unwrap();
+        // Unlock the share of the reservation that belongs to the filled amount
+        let unlocked = order.locked * (amount / order.remaining()).min(1.0);
+        order.locked -= unlocked;
+        order.filled += amount;
+        order.status = if order.remaining() <= EPSILON {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        let direction = order.direction;
+        match direction {
+            OrderDirection::Buy => {
+                self.quote.locked -= unlocked;
+                self.quote.free += unlocked - value - fee;
+                self.base.free += amount;
+            }
+            OrderDirection::Sell => {
+                self.base.locked -= unlocked;
+                self.base.free += unlocked - amount;
+                self.quote.free += value - fee;
+            }
+        }
+        self.volume += value;
+        self.fills.push(Fill {
+            order_id: id,
+            timestamp,
+            direction,
+            price,
+            amount,
+            fee,
+            maker,
+        });
+        if !self.orders[&id].is_open() {
+            self.release(id, OrderStatus::Filled);
+        }
+    }
+
+    /// Closes an order and gives back what's still reserved for it
+    fn release(&mut self, id: OrderId, status: OrderStatus) {
+        let order = self.orders.get_mut(&id).unwrap();
+        let locked = std::mem::take(&mut order.locked);
+        order.status = status;
+        let balance = match order.direction {
+            OrderDirection::Buy => &mut self.quote,
+            OrderDirection::Sell => &mut self.base,
+        };
+        balance.locked -= locked;
+        balance.free += locked;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} isn't {}",
+            actual,
+            expected
+        );
+    }
+
+    /// 10 base and 10000 quote, with a book of 1 at 99 and 98 to buy and 101 and 102 to sell
+    fn exchange() -> PaperExchange {
+        let mut exchange = PaperExchange::new(
+            "BTC-USDT",
+            PaperConfig {
+                base_balance: 10.0,
+                quote_balance: 10_000.0,
+                ..Default::default()
+            },
+        );
+        let level = |price| Level { price, amount: 1.0 };
+        exchange.on_book(OrderBook::new(
+            60,
+            vec![level(99.0), level(98.0)],
+            vec![level(101.0), level(102.0)],
+        ));
+        exchange
+    }
+
+    #[test]
+    fn cancel_after_partial_fill_gives_back_the_rest() {
+        let mut exchange = exchange();
+        let id = exchange
+            .place_order(OrderDirection::Buy, OrderType::Limit, 2.0, Some(101.5))
+            .unwrap();
+        // Takes the 1 at 101, the other 1 rests at 101.5
+        let order = &exchange.orders[&id];
+        assert_eq!(order.status, OrderStatus::PartiallyFilled);
+        assert_close(order.filled, 1.0);
+        assert_eq!(exchange.fills.len(), 1);
+        assert!(!exchange.fills[0].maker);
+        let fee = 101.0 * exchange.fee_tier().taker;
+        assert_close(exchange.fills[0].fee, fee);
+        assert!(exchange.quote.locked > 101.5);
+
+        exchange.cancel(id).unwrap();
+        assert_eq!(exchange.orders[&id].status, OrderStatus::Canceled);
+        assert_close(exchange.quote.locked, 0.0);
+        assert_close(exchange.quote.free, 10_000.0 - 101.0 - fee);
+        assert_close(exchange.base.free, 11.0);
+        assert_close(exchange.base.locked, 0.0);
+        assert!(exchange.cancel(id).is_err());
+    }
+
+    #[test]
+    fn limit_maker_orders_that_would_take_are_rejected() {
+        let mut exchange = exchange();
+        assert!(exchange
+            .place_order(OrderDirection::Buy, OrderType::LimitMaker, 1.0, Some(101.0))
+            .is_err());
+        assert!(exchange
+            .place_order(OrderDirection::Sell, OrderType::LimitMaker, 1.0, Some(98.5))
+            .is_err());
+        assert!(exchange.orders.is_empty());
+        assert_eq!(
+            exchange.quote,
+            Balance {
+                free: 10_000.0,
+                locked: 0.0
+            }
+        );
+        assert_eq!(
+            exchange.base,
+            Balance {
+                free: 10.0,
+                locked: 0.0
+            }
+        );
+
+        // Inside the spread it rests
+        let id = exchange
+            .place_order(
+                OrderDirection::Sell,
+                OrderType::LimitMaker,
+                1.0,
+                Some(100.0),
+            )
+            .unwrap();
+        assert_eq!(exchange.orders[&id].status, OrderStatus::Open);
+        assert!(exchange.fills.is_empty());
+        assert_close(exchange.base.locked, 1.0);
+    }
+
+    #[test]
+    fn fee_tier_follows_the_traded_volume() {
+        let mut exchange = exchange();
+        assert_close(exchange.fee_tier().maker, 0.001);
+        exchange.volume = 1_500_000.0;
+        assert_close(exchange.fee_tier().maker, 0.0009);
+        exchange.volume = 5_000_000.0;
+        assert_close(exchange.fee_tier().taker, 0.0009);
+        exchange.volume = 25_000_000.0;
+        assert_close(exchange.fee_tier().maker, 0.0006);
+
+        // Fills pay the fees of the tier the account is in
+        exchange.volume = 5_000_000.0;
+        exchange
+            .place_order(OrderDirection::Buy, OrderType::Market, 1.0, None)
+            .unwrap();
+        assert_close(exchange.fills[0].fee, 101.0 * 0.0009);
+
+        exchange.config.fee_tiers.clear();
+        assert_eq!(exchange.fee_tier().taker, 0.0);
+    }
+}
@@ -0,0 +1,5 @@
+pub mod book;
+pub mod exchange;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod panel;
+pub mod trading;
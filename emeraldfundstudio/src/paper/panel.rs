@@ -0,0 +1,387 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use egui::{Color32, ComboBox, DragValue, Grid, ScrollArea, TextEdit, Ui};
+use strum::IntoEnumIterator;
+
+use super::{
+    book::parse_snapshots,
+    exchange::{FeeTier, OrderId, PaperConfig, PaperExchange},
+    trading::{PaperTrading, PAPER_TRADING},
+};
+use crate::{
+    data_store::panel::format_date,
+    node_editor::{OrderDirection, OrderType},
+};
+
+/// Levels of each side of the book that are shown
+const BOOK_DEPTH: usize = 5;
+/// Latest fills that are shown
+const FILLS_SHOWN: usize = 20;
+
+/// Accounts of the paper exchanges the execution nodes trade on in replay mode
+pub struct PaperTradingPanel {
+    pub open: bool,
+    snapshots_path: String,
+    exchange: String,
+    pair: String,
+    direction: OrderDirection,
+    order_type: OrderType,
+    amount: f64,
+    price: f64,
+    message: Option<Result<String, String>>,
+}
+
+impl Default for PaperTradingPanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            snapshots_path: String::new(),
+            exchange: String::new(),
+            pair: String::new(),
+            direction: OrderDirection::Buy,
+            order_type: OrderType::Limit,
+            amount: 0.0,
+            price: 0.0,
+            message: None,
+        }
+    }
+}
+
+impl PaperTradingPanel {
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Paper Trading")
+            .open(&mut open)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    let mut trading = PAPER_TRADING.lock();
+                    self.show_settings(ui, &mut trading);
+                    ui.separator();
+                    self.show_snapshots(ui, &mut trading);
+                    for ((exchange, pair), paper) in trading.exchanges.iter_mut() {
+                        ui.separator();
+                        ui.push_id((exchange, pair), |ui| {
+                            ui.heading(format!("{} {}", exchange, pair));
+                            self.show_exchange(ui, paper);
+                        });
+                    }
+                    if trading.exchanges.is_empty() {
+                        ui.label(
+                            "Set an execution node to \"Paper exchange\" and start a replay, \
+                             its market shows up here.",
+                        );
+                    }
+                    if let Some(error) = &trading.last_error {
+                        ui.colored_label(Color32::RED, format!("Last order failed: {}", error));
+                    }
+                    if let Some(message) = &self.message {
+                        match message {
+                            Ok(message) => ui.label(message),
+                            Err(error) => ui.colored_label(Color32::RED, error),
+                        };
+                    }
+                });
+            });
+        self.open = open;
+    }
+
+    fn show_settings(&mut self, ui: &mut Ui, trading: &mut PaperTrading) {
+        ui.collapsing("Account settings", |ui| {
+            let config = &mut trading.config;
+            Grid::new("paper_settings").show(ui, |ui| {
+                ui.label("Base balance");
+                ui.add(DragValue::new(&mut config.base_balance).range(0.0..=f64::MAX));
+                ui.end_row();
+                ui.label("Quote balance");
+                ui.add(DragValue::new(&mut config.quote_balance).range(0.0..=f64::MAX));
+                ui.end_row();
+                ui.label("Fill share of candle volume");
+                ui.add(
+                    DragValue::new(&mut config.volume_share)
+                        .range(0.0..=1.0)
+                        .speed(0.01),
+                );
+                ui.end_row();
+                ui.label("Book levels");
+                ui.add(DragValue::new(&mut config.book.levels).range(1..=100));
+                ui.end_row();
+                ui.label("Book spread");
+                ui.add(
+                    DragValue::new(&mut config.book.spread)
+                        .range(0.0..=0.1)
+                        .speed(0.0001),
+                );
+                ui.end_row();
+                ui.label("Book tick");
+                ui.add(
+                    DragValue::new(&mut config.book.tick)
+                        .range(0.0..=0.1)
+                        .speed(0.0001),
+                );
+                ui.end_row();
+                ui.label("Book depth share of candle volume");
+                ui.add(
+                    DragValue::new(&mut config.book.depth)
+                        .range(0.0..=1.0)
+                        .speed(0.01),
+                );
+                ui.end_row();
+            });
+            show_fee_tiers(ui, &mut config.fee_tiers);
+            ui.horizontal(|ui| {
+                if ui.button("Reset accounts").clicked() {
+                    trading.reset();
+                }
+                if ui.button("Default settings").clicked() {
+                    trading.config = PaperConfig::default();
+                }
+            });
+            ui.label("Settings apply to accounts created after a reset.");
+        });
+    }
+
+    fn load_snapshots(&self, trading: &mut PaperTrading) -> Result<String> {
+        let path = PathBuf::from(self.snapshots_path.trim());
+        let snapshots = parse_snapshots(&std::fs::read_to_string(&path)?)?;
+        let count = snapshots.len();
+        trading
+            .exchange_mut(self.exchange.trim(), self.pair.trim())
+            .set_snapshots(snapshots);
+        Ok(format!(
+            "Loaded {} snapshots for {} {}",
+            count,
+            self.exchange.trim(),
+            self.pair.trim()
+        ))
+    }
+
+    fn show_snapshots(&mut self, ui: &mut Ui, trading: &mut PaperTrading) {
+        ui.collapsing("Order book snapshots", |ui| {
+            Grid::new("paper_snapshots").show(ui, |ui| {
+                ui.label("File");
+                ui.add(
+                    TextEdit::singleline(&mut self.snapshots_path)
+                        .hint_text("/path/to/snapshots.jsonl")
+                        .desired_width(280.0),
+                );
+                ui.end_row();
+                ui.label("Exchange");
+                ui.text_edit_singleline(&mut self.exchange);
+                ui.end_row();
+                ui.label("Pair");
+                ui.text_edit_singleline(&mut self.pair);
+                ui.end_row();
+            });
+            let ready = !self.snapshots_path.trim().is_empty()
+                && !self.exchange.trim().is_empty()
+                && !self.pair.trim().is_empty();
+            if ui.add_enabled(ready, egui::Button::new("Load")).clicked() {
+                self.message = Some(self.load_snapshots(trading).map_err(|e| e.to_string()));
+            }
+            ui.label(
+                "One JSON object per line with timestamp, bids and asks as [price, amount] \
+                 pairs. Without snapshots the book is made up from each candle.",
+            );
+        });
+    }
+
+    fn show_exchange(&mut self, ui: &mut Ui, paper: &mut PaperExchange) {
+        let (base_asset, quote_asset) = paper.assets();
+        let (base_asset, quote_asset) = (base_asset.to_owned(), quote_asset.to_owned());
+        let tier = paper.fee_tier();
+        Grid::new("paper_balances").show(ui, |ui| {
+            ui.label("");
+            ui.label("Free");
+            ui.label("Locked");
+            ui.end_row();
+            for (asset, balance) in [(&base_asset, paper.base), (&quote_asset, paper.quote)] {
+                ui.label(asset);
+                ui.label(format!("{:.6}", balance.free));
+                ui.label(format!("{:.6}", balance.locked));
+                ui.end_row();
+            }
+        });
+        ui.label(format!(
+            "Equity {:.2} {}, volume {:.2}, fees {:.3}% maker / {:.3}% taker{}",
+            paper.equity(),
+            quote_asset,
+            paper.volume,
+            tier.maker * 100.0,
+            tier.taker * 100.0,
+            match paper.snapshot_count() {
+                0 => String::new(),
+                count => format!(", {} snapshots", count),
+            }
+        ));
+
+        ui.collapsing("Order book", |ui| {
+            Grid::new("paper_book").striped(true).show(ui, |ui| {
+                ui.label("Bid amount");
+                ui.label("Bid");
+                ui.label("Ask");
+                ui.label("Ask amount");
+                ui.end_row();
+                for level in 0..BOOK_DEPTH {
+                    for (levels, price_first) in
+                        [(&paper.book.bids, false), (&paper.book.asks, true)]
+                    {
+                        let (price, amount) = match levels.get(level) {
+                            Some(level) => (
+                                format!("{:.4}", level.price),
+                                format!("{:.4}", level.amount),
+                            ),
+                            None => (String::new(), String::new()),
+                        };
+                        if price_first {
+                            ui.label(price);
+                            ui.label(amount);
+                        } else {
+                            ui.label(amount);
+                            ui.label(price);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        let mut cancel: Option<OrderId> = None;
+        ui.collapsing("Open orders", |ui| {
+            Grid::new("paper_orders").striped(true).show(ui, |ui| {
+                for order in paper.open_orders() {
+                    ui.label(format!("#{}", order.id));
+                    ui.label(format_date(order.timestamp));
+                    ui.label(format!("{} {}", order.order_type, order.direction));
+                    ui.label(format!(
+                        "{:.6} / {:.6} @ {}",
+                        order.filled,
+                        order.amount,
+                        order
+                            .price
+                            .map(|price| format!("{:.4}", price))
+                            .unwrap_or_else(|| "market".to_owned())
+                    ));
+                    ui.label(order.status.to_string());
+                    if ui.button("Cancel").clicked() {
+                        cancel = Some(order.id);
+                    }
+                    ui.end_row();
+                }
+            });
+            if ui.button("Cancel all").clicked() {
+                paper.cancel_all();
+            }
+        });
+        if let Some(id) = cancel {
+            self.message = paper.cancel(id).err().map(|e| Err(e.to_string()));
+        }
+
+        ui.collapsing("Place order", |ui| {
+            ui.horizontal(|ui| {
+                ComboBox::from_id_salt("paper_direction")
+                    .selected_text(self.direction.to_string())
+                    .show_ui(ui, |ui| {
+                        for direction in OrderDirection::iter() {
+                            ui.selectable_value(
+                                &mut self.direction,
+                                direction,
+                                direction.to_string(),
+                            );
+                        }
+                    });
+                ComboBox::from_id_salt("paper_order_type")
+                    .selected_text(self.order_type.to_string())
+                    .show_ui(ui, |ui| {
+                        for order_type in OrderType::iter() {
+                            ui.selectable_value(
+                                &mut self.order_type,
+                                order_type,
+                                order_type.to_string(),
+                            );
+                        }
+                    });
+                ui.add(
+                    DragValue::new(&mut self.amount)
+                        .range(0.0..=f64::MAX)
+                        .speed(0.01)
+                        .suffix(format!(" {}", base_asset)),
+                );
+                if self.order_type != OrderType::Market {
+                    ui.add(
+                        DragValue::new(&mut self.price)
+                            .range(0.0..=f64::MAX)
+                            .prefix("@ "),
+                    );
+                }
+                if ui.button("Place").clicked() {
+                    let price = (self.order_type != OrderType::Market).then_some(self.price);
+                    self.message = Some(
+                        paper
+                            .place_order(self.direction, self.order_type, self.amount, price)
+                            .map(|id| format!("Placed order #{}", id))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+            });
+        });
+
+        ui.collapsing("Fills", |ui| {
+            Grid::new("paper_fills").striped(true).show(ui, |ui| {
+                for fill in paper.fills.iter().rev().take(FILLS_SHOWN) {
+                    ui.label(format!("#{}", fill.order_id));
+                    ui.label(format_date(fill.timestamp));
+                    ui.label(fill.direction.to_string());
+                    ui.label(format!("{:.6} @ {:.4}", fill.amount, fill.price));
+                    ui.label(format!(
+                        "fee {:.4} {}",
+                        fill.fee,
+                        if fill.maker { "maker" } else { "taker" }
+                    ));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+fn show_fee_tiers(ui: &mut Ui, tiers: &mut Vec<FeeTier>) {
+    let mut remove = None;
+    Grid::new("paper_fee_tiers").show(ui, |ui| {
+        ui.label("From volume");
+        ui.label("Maker %");
+        ui.label("Taker %");
+        ui.end_row();
+        for (position, tier) in tiers.iter_mut().enumerate() {
+            ui.add(DragValue::new(&mut tier.min_volume).range(0.0..=f64::MAX));
+            for fee in [&mut tier.maker, &mut tier.taker] {
+                let mut percent = *fee * 100.0;
+                if ui
+                    .add(DragValue::new(&mut percent).range(-1.0..=5.0).speed(0.001))
+                    .changed()
+                {
+                    *fee = percent / 100.0;
+                }
+            }
+            if ui.button("Remove").clicked() {
+                remove = Some(position);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(position) = remove {
+        tiers.remove(position);
+    }
+    if ui.button("Add fee tier").clicked() {
+        let min_volume = tiers
+            .last()
+            .map(|tier| (tier.min_volume * 2.0).max(1.0))
+            .unwrap_or(0.0);
+        tiers.push(FeeTier {
+            min_volume,
+            maker: 0.001,
+            taker: 0.001,
+        });
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use egui_snarl::{InPinId, Snarl};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::exchange::{PaperConfig, PaperExchange};
+use crate::{
+    node_editor::{
+        node_trait::{EFNodeFNSerialized, NodeDataTypeWithValue},
+        nodes::execute_position::{ExecutePositionNode, ExecutionTarget},
+        OrderDirection, OrderType,
+    },
+    node_runners::{
+        realtime::NODE_COMPUTE_CACHE,
+        streaming::{candle_bars, live_candles},
+    },
+    types::timestamp::TimeStamp,
+};
+
+/// Amounts below this are treated as no position
+const DUST: f64 = 1e-9;
+
+pub static PAPER_TRADING: Lazy<Mutex<PaperTrading>> = Lazy::new(Default::default);
+
+/// Paper exchanges of the markets the replay feed sent bars of
+#[derive(Default)]
+pub struct PaperTrading {
+    /// Used for exchanges created from now on
+    pub config: PaperConfig,
+    /// Keyed by exchange and pair
+    pub exchanges: BTreeMap<(String, String), PaperExchange>,
+    /// Close time of the last bar each execution node traded on, by node id
+    traded: HashMap<usize, TimeStamp>,
+    pub last_error: Option<String>,
+}
+
+impl PaperTrading {
+    pub fn exchange_mut(&mut self, exchange: &str, pair: &str) -> &mut PaperExchange {
+        self.exchanges
+            .entry((exchange.to_owned(), pair.to_owned()))
+            .or_insert_with(|| PaperExchange::new(pair, self.config.clone()))
+    }
+
+    /// Starts over with fresh accounts
+    pub fn reset(&mut self) {
+        self.exchanges.clear();
+        self.traded.clear();
+        self.last_error = None;
+    }
+}
+
+/// Turns the signal of a node into orders: a buy signal spends `order_size` when there is no
+/// position, a sell signal sells the whole position. Orders of the other direction that are
+/// still open are canceled first.
+fn execute_signal(
+    node: &ExecutePositionNode,
+    exchange: &mut PaperExchange,
+    signal: i8,
+) -> Result<()> {
+    let direction = match signal {
+        1 => OrderDirection::Buy,
+        -1 => OrderDirection::Sell,
+        _ => return Ok(()),
+    };
+    let stale: Vec<_> = exchange
+        .open_orders()
+        .filter(|order| order.direction != direction)
+        .map(|order| order.id)
+        .collect();
+    for id in stale {
+        exchange.cancel(id)?;
+    }
+    let mid = exchange
+        .book
+        .mid()
+        .ok_or_else(|| anyhow!("The order book of {} is empty", exchange.pair))?;
+    let pending = exchange
+        .open_orders()
+        .any(|order| order.direction == direction);
+    let (amount, limit) = match direction {
+        OrderDirection::Buy if exchange.base.total() > DUST || pending => return Ok(()),
+        OrderDirection::Buy => (node.order_size / mid, mid * (1.0 - node.limit_offset)),
+        OrderDirection::Sell if exchange.base.free <= DUST => return Ok(()),
+        OrderDirection::Sell => (exchange.base.free, mid * (1.0 + node.limit_offset)),
+    };
+    let price = (node.order_type != OrderType::Market).then_some(limit);
+    exchange.place_order(direction, node.order_type, amount, price)?;
+    Ok(())
+}
+
+/// Matches the new bars of `exchange`/`pair` on its paper exchange and trades the signals of
+/// the execution nodes that target it, bar by bar. Called after the graph ran on the bars.
+/// An exchange that saw no bars yet starts with the last one, the bars before are history.
+pub fn run_paper_trading(snarl: &Snarl<EFNodeFNSerialized<'_>>, exchange: &str, pair: &str) {
+    let Some(candles) = live_candles(exchange, pair) else {
+        return;
+    };
+    let (Ok(bars), Ok(index)) = (candle_bars(&candles), candles.time_index()) else {
+        return;
+    };
+    // Execution nodes whose signal was computed on these candles
+    let nodes: Vec<_> = snarl
+        .node_ids()
+        .filter(|(_, node)| node.get_node().get_name() == "ExecutePositionNode")
+        .filter_map(|(id, node)| {
+            let node = node
+                .get_node()
+                .as_any()
+                .downcast_ref::<ExecutePositionNode>()?;
+            if node.target != ExecutionTarget::Paper {
+                return None;
+            }
+            let remote = *snarl
+                .in_pin(InPinId { node: id, input: 0 })
+                .remotes
+                .first()?;
+            let signal = NODE_COMPUTE_CACHE
+                .get(&remote.node.0)?
+                .get(remote.output)?
+                .clone();
+            let on_candles = matches!(
+                &*signal,
+                NodeDataTypeWithValue::Signal(values)
+                    if values.index.len() == index.len() && values.index.last() == index.last()
+            );
+            on_candles.then_some((id.0, node, signal))
+        })
+        .collect();
+
+    let mut trading = PAPER_TRADING.lock();
+    let PaperTrading {
+        config,
+        exchanges,
+        traded,
+        last_error,
+    } = &mut *trading;
+    let paper = exchanges
+        .entry((exchange.to_owned(), pair.to_owned()))
+        .or_insert_with(|| PaperExchange::new(pair, config.clone()));
+    let start = match paper.last_bar {
+        Some(last) => bars.partition_point(|bar| bar.timestamp <= last),
+        None => bars.len().saturating_sub(1),
+    };
+    for position in start..bars.len() {
+        paper.on_candle(&bars[position]);
+        for (id, node, signal) in &nodes {
+            let NodeDataTypeWithValue::Signal(signal) = &**signal else {
+                continue;
+            };
+            if traded.get(id).is_some_and(|last| *last >= index[position]) {
+                continue;
+            }
+            traded.insert(*id, index[position]);
+            if let Err(e) = execute_signal(node, paper, signal[position]) {
+                log::warn!("Paper order on {} {} failed: {}", exchange, pair, e);
+                *last_error = Some(e.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use tungstenite::{stream::MaybeTlsStream, Message};
+
+use super::server::ReplayMessage;
+
+/// How long a read waits before the thread looks whether the feed was dropped
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Connection to a replay feed. Messages are read on a background thread and picked up by the
+/// UI with `try_iter`, the thread ends when the server closes the connection or the feed is
+/// dropped.
+pub struct ReplayFeed {
+    pub url: String,
+    receiver: Receiver<ReplayMessage>,
+    connected: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+}
+
+impl ReplayFeed {
+    pub fn connect(url: &str) -> Result<Self> {
+        let (mut socket, _) = tungstenite::connect(url)?;
+        if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+            stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let connected = Arc::new(AtomicBool::new(true));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_connected = connected.clone();
+        let thread_stop = stop.clone();
+        thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                match socket.read() {
+                    Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                        Ok(message) => {
+                            if sender.send(message).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => log::warn!("Ignoring replay message: {}", e),
+                    },
+                    Ok(Message::Close(_)) => break,
+                    Err(tungstenite::Error::Io(e))
+                        if matches!(
+                            e.kind(),
+                            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                        ) => {}
+                    Err(_) => break,
+                    Ok(_) => {}
+                }
+            }
+            let _ = socket.close(None);
+            thread_connected.store(false, Ordering::Relaxed);
+        });
+        Ok(Self {
+            url: url.to_owned(),
+            receiver,
+            connected,
+            stop,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Messages that arrived since the last call
+    pub fn try_iter(&self) -> impl Iterator<Item = ReplayMessage> + '_ {
+        self.receiver.try_iter()
+    }
+}
+
+impl Drop for ReplayFeed {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,277 @@
+use std::{collections::BTreeSet, path::PathBuf, sync::atomic::Ordering, time::Duration};
+
+use anyhow::Result;
+use egui::{Color32, ComboBox, DragValue, Grid, ProgressBar, TextEdit, Ui};
+use egui_snarl::Snarl;
+
+use super::{
+    feed::ReplayFeed,
+    server::{ReplayConfig, ReplayMessage, ReplayServer},
+};
+use crate::{
+    data_store::{hummingbot::read_candles, store::CANDLE_STORE},
+    node_editor::node_trait::EFNodeFNSerialized,
+    node_runners::{
+        realtime::is_running,
+        streaming::{append_bar, clear_live_candles, run_streaming_update},
+    },
+    paper::trading::{run_paper_trading, PAPER_TRADING},
+    types::candles::{Candles, MarketInfo},
+};
+
+/// How often the UI checks the feed for new bars while it's connected
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Replays candles through a local feed and follows a feed with the graph
+pub struct ReplayPanel {
+    pub open: bool,
+    /// Stored candles to replay, `None` for a Hummingbot candles CSV
+    source: Option<MarketInfo>,
+    csv_path: String,
+    config: ReplayConfig,
+    server: Option<ReplayServer>,
+    feed_url: String,
+    feed: Option<ReplayFeed>,
+    received: usize,
+    ended: bool,
+    error: Option<String>,
+    /// Markets that got bars, paper trading waits for the graph run that computes their signals
+    to_trade: BTreeSet<(String, String)>,
+}
+
+impl Default for ReplayPanel {
+    fn default() -> Self {
+        let config = ReplayConfig::default();
+        Self {
+            open: false,
+            source: None,
+            csv_path: String::new(),
+            feed_url: format!("ws://127.0.0.1:{}", config.port),
+            config,
+            server: None,
+            feed: None,
+            received: 0,
+            ended: false,
+            error: None,
+            to_trade: BTreeSet::new(),
+        }
+    }
+}
+
+impl ReplayPanel {
+    /// Moves the bars that arrived since the last frame into the graph, called every frame
+    pub fn poll(&mut self, ctx: &egui::Context, snarl: &Snarl<EFNodeFNSerialized<'_>>) {
+        if !is_running() {
+            for (exchange, pair) in std::mem::take(&mut self.to_trade) {
+                run_paper_trading(snarl, &exchange, &pair);
+            }
+        }
+        let Some(feed) = &self.feed else {
+            return;
+        };
+        let mut markets = BTreeSet::new();
+        for message in feed.try_iter() {
+            match message {
+                ReplayMessage::Candle {
+                    exchange,
+                    pair,
+                    timeframe_seconds,
+                    bar,
+                } => {
+                    let info = MarketInfo {
+                        exchange,
+                        pair,
+                        timeframe_seconds,
+                    };
+                    if let Err(e) = append_bar(&info, &bar) {
+                        self.error = Some(e.to_string());
+                        continue;
+                    }
+                    self.received += 1;
+                    markets.insert((info.exchange, info.pair));
+                }
+                ReplayMessage::End => self.ended = true,
+            }
+        }
+        for (exchange, pair) in &markets {
+            run_streaming_update(snarl, exchange, pair);
+        }
+        self.to_trade.extend(markets);
+        if feed.is_connected() {
+            ctx.request_repaint_after(POLL_INTERVAL);
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Replay")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                self.show_server(ui);
+                ui.separator();
+                self.show_feed(ui);
+                if let Some(error) = &self.error {
+                    ui.colored_label(Color32::RED, error);
+                }
+            });
+        self.open = open;
+    }
+
+    fn load_candles(&self) -> Result<Candles> {
+        match &self.source {
+            Some(info) => CANDLE_STORE.lock().query(info, None, None),
+            None => read_candles(&PathBuf::from(self.csv_path.trim()), None),
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        // Stop the old server first, a new one might want the same port
+        self.feed = None;
+        self.server = None;
+        let candles = self.load_candles()?;
+        let server = ReplayServer::start(&candles, &self.config)?;
+        clear_live_candles(&candles.info.exchange, &candles.info.pair);
+        // The bars start over, so do the paper accounts trading them
+        PAPER_TRADING.lock().reset();
+        self.feed_url = server.url();
+        self.server = Some(server);
+        self.connect()
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        self.feed = None;
+        self.received = 0;
+        self.ended = false;
+        self.feed = Some(ReplayFeed::connect(self.feed_url.trim())?);
+        Ok(())
+    }
+
+    fn show_server(&mut self, ui: &mut Ui) {
+        ui.heading("Replay server");
+        let running = self.server.is_some();
+        ui.add_enabled_ui(!running, |ui| {
+            Grid::new("replay_source").show(ui, |ui| {
+                ui.label("Candles");
+                let selected_text = match &self.source {
+                    Some(info) => info.to_string(),
+                    None => "Hummingbot candles CSV".to_owned(),
+                };
+                ComboBox::from_id_salt("replay_source")
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        for entry in CANDLE_STORE.lock().entries() {
+                            ui.selectable_value(
+                                &mut self.source,
+                                Some(entry.info.clone()),
+                                entry.info.to_string(),
+                            );
+                        }
+                        ui.selectable_value(&mut self.source, None, "Hummingbot candles CSV");
+                    });
+                ui.end_row();
+                if self.source.is_none() {
+                    ui.label("File");
+                    ui.add(
+                        TextEdit::singleline(&mut self.csv_path)
+                            .hint_text("candles_binance_BTC-USDT_1m.csv")
+                            .desired_width(260.0),
+                    );
+                    ui.end_row();
+                }
+                ui.label("Warmup bars");
+                ui.add(DragValue::new(&mut self.config.warmup_bars).range(0..=100_000));
+                ui.end_row();
+                ui.label("Port");
+                ui.add(DragValue::new(&mut self.config.port));
+                ui.end_row();
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Bars per second");
+            let speed = ui.add(
+                DragValue::new(&mut self.config.bars_per_second)
+                    .speed(0.1)
+                    .range(0.1..=1000.0),
+            );
+            if let (true, Some(server)) = (speed.changed(), &self.server) {
+                *server.status.bars_per_second.lock() = self.config.bars_per_second;
+            }
+        });
+
+        let Some(server) = &self.server else {
+            if ui.button("Start").clicked() {
+                if let Err(e) = self.start() {
+                    self.error = Some(e.to_string());
+                } else {
+                    self.error = None;
+                }
+            }
+            return;
+        };
+        let status = &server.status;
+        let position = status.position.load(Ordering::Relaxed);
+        ui.label(format!(
+            "Serving {} on {} to {} clients",
+            server.market,
+            server.url(),
+            status.clients.load(Ordering::Relaxed)
+        ));
+        ui.add(
+            ProgressBar::new(position as f32 / server.total.max(1) as f32)
+                .text(format!("{} / {} bars", position, server.total)),
+        );
+        let mut stop = false;
+        ui.horizontal(|ui| {
+            let paused = status.paused.load(Ordering::Relaxed);
+            if !server.is_finished() && ui.button(if paused { "Resume" } else { "Pause" }).clicked()
+            {
+                status.paused.store(!paused, Ordering::Relaxed);
+            }
+            stop = ui.button("Stop").clicked();
+        });
+        if stop {
+            self.server = None;
+        } else {
+            ui.ctx().request_repaint_after(POLL_INTERVAL);
+        }
+    }
+
+    fn show_feed(&mut self, ui: &mut Ui) {
+        ui.heading("Feed");
+        ui.horizontal(|ui| {
+            ui.label("URL");
+            ui.add_enabled(
+                self.feed.is_none(),
+                TextEdit::singleline(&mut self.feed_url).desired_width(220.0),
+            );
+            match &self.feed {
+                Some(_) => {
+                    if ui.button("Disconnect").clicked() {
+                        self.feed = None;
+                    }
+                }
+                None => {
+                    if ui.button("Connect").clicked() {
+                        self.error = self.connect().err().map(|e| e.to_string());
+                    }
+                }
+            }
+        });
+        if let Some(feed) = &self.feed {
+            let state = match (feed.is_connected(), self.ended) {
+                (_, true) => "replay finished",
+                (true, false) => "connected",
+                (false, false) => "disconnected",
+            };
+            ui.label(format!(
+                "{} bars received from {}, {}",
+                self.received, feed.url, state
+            ));
+        }
+        ui.label(
+            "Market data nodes with the \"Replay feed\" source follow the bars of their symbols, \
+             execution nodes set to \"Paper exchange\" trade them.",
+        );
+    }
+}
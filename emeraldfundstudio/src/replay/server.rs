@@ -0,0 +1,205 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    node_runners::streaming::{candle_bars, LiveBar},
+    types::candles::{Candles, MarketInfo},
+};
+
+/// Longest the server sleeps, so pausing, speed changes and new clients are picked up quickly
+const TICK: Duration = Duration::from_millis(10);
+
+/// A message of a replay feed, sent as a JSON text frame
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReplayMessage {
+    Candle {
+        exchange: String,
+        pair: String,
+        timeframe_seconds: u64,
+        #[serde(flatten)]
+        bar: LiveBar,
+    },
+    /// Every bar was sent
+    End,
+}
+
+impl ReplayMessage {
+    fn candles(candles: &Candles) -> Result<Vec<Self>> {
+        let MarketInfo {
+            exchange,
+            pair,
+            timeframe_seconds,
+        } = &candles.info;
+        Ok(candle_bars(candles)?
+            .into_iter()
+            .map(|bar| ReplayMessage::Candle {
+                exchange: exchange.clone(),
+                pair: pair.clone(),
+                timeframe_seconds: *timeframe_seconds,
+                bar,
+            })
+            .collect())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplayConfig {
+    pub bars_per_second: f64,
+    /// Bars that are sent at once when a client connects, so indicators have history
+    pub warmup_bars: usize,
+    /// 0 picks a free port
+    pub port: u16,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            bars_per_second: 2.0,
+            warmup_bars: 200,
+            port: 8765,
+        }
+    }
+}
+
+/// Shared between the server thread and the UI
+#[derive(Default)]
+pub struct ReplayStatus {
+    /// Bars sent so far
+    pub position: AtomicUsize,
+    pub clients: AtomicUsize,
+    pub paused: AtomicBool,
+    stop: AtomicBool,
+    pub bars_per_second: Mutex<f64>,
+}
+
+/// Streams candles bar by bar to websocket clients on localhost, like an exchange's candle
+/// feed. Clients that connect late get every bar sent so far first.
+pub struct ReplayServer {
+    pub address: SocketAddr,
+    pub market: MarketInfo,
+    pub total: usize,
+    pub status: Arc<ReplayStatus>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ReplayServer {
+    pub fn start(candles: &Candles, config: &ReplayConfig) -> Result<Self> {
+        let messages: Vec<String> = ReplayMessage::candles(candles)?
+            .iter()
+            .map(|message| serde_json::to_string(message).unwrap())
+            .collect();
+        let listener = TcpListener::bind(("127.0.0.1", config.port))?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let status = Arc::new(ReplayStatus {
+            position: AtomicUsize::new(config.warmup_bars.min(messages.len())),
+            bars_per_second: Mutex::new(config.bars_per_second),
+            ..Default::default()
+        });
+        let total = messages.len();
+        let thread_status = status.clone();
+        let handle = thread::spawn(move || serve(listener, messages, thread_status));
+        Ok(Self {
+            address,
+            market: candles.info.clone(),
+            total,
+            status,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status.position.load(Ordering::Relaxed) >= self.total
+    }
+}
+
+impl Drop for ReplayServer {
+    fn drop(&mut self) {
+        self.status.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn accept_client(stream: TcpStream, history: &[String]) -> Result<WebSocket<TcpStream>> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+    let mut socket = tungstenite::accept(stream)?;
+    for message in history {
+        socket.send(Message::text(message.clone()))?;
+    }
+    Ok(socket)
+}
+
+fn broadcast(clients: &mut Vec<WebSocket<TcpStream>>, message: &str) {
+    clients.retain_mut(|socket| socket.send(Message::text(message.to_owned())).is_ok());
+}
+
+fn serve(listener: TcpListener, messages: Vec<String>, status: Arc<ReplayStatus>) {
+    let end = serde_json::to_string(&ReplayMessage::End).unwrap();
+    let mut clients: Vec<WebSocket<TcpStream>> = vec![];
+    let mut next_bar = Instant::now();
+    while !status.stop.load(Ordering::Relaxed) {
+        let position = status.position.load(Ordering::Relaxed);
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => match accept_client(stream, &messages[..position]) {
+                    Ok(mut socket) => {
+                        if position == messages.len() && socket.send(Message::text(&end)).is_err() {
+                            continue;
+                        }
+                        clients.push(socket);
+                    }
+                    Err(e) => log::warn!("Replay client couldn't connect: {}", e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Replay server stopped accepting clients: {}", e);
+                    break;
+                }
+            }
+        }
+        status.clients.store(clients.len(), Ordering::Relaxed);
+
+        let now = Instant::now();
+        if status.paused.load(Ordering::Relaxed) || position >= messages.len() {
+            next_bar = now;
+            thread::sleep(TICK);
+            continue;
+        }
+        let interval = Duration::from_secs_f64(1.0 / status.bars_per_second.lock().max(0.001));
+        if now >= next_bar {
+            broadcast(&mut clients, &messages[position]);
+            status.position.store(position + 1, Ordering::Relaxed);
+            if position + 1 == messages.len() {
+                broadcast(&mut clients, &end);
+            }
+            // Don't catch up on bars that were due while the interval was longer
+            next_bar = (next_bar + interval).max(now);
+        }
+        thread::sleep(next_bar.saturating_duration_since(Instant::now()).min(TICK));
+    }
+    for mut socket in clients {
+        let _ = socket.close(None);
+        let _ = socket.flush();
+    }
+}
@@ -0,0 +1,190 @@
+pub mod run;
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use rhai::{module_resolvers::DummyModuleResolver, Dynamic, Engine, EvalAltResult, AST};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+use web_time::Instant;
+
+use crate::{node_editor::node_trait::NodeDataType, types::time_index::JoinMode};
+
+/// Names every script has in scope besides its pins
+pub const RESERVED_NAMES: [&str; 3] = ["bar", "time", "state"];
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum ScriptMode {
+    /// Runs once for every row, the inputs hold the values of that row
+    #[default]
+    #[strum(serialize = "Per bar")]
+    PerBar,
+    /// Runs once, the inputs and `time` are arrays with a value for every row
+    #[strum(serialize = "Per series")]
+    PerSeries,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ScriptPin {
+    pub name: String,
+    pub data_type: NodeDataType,
+}
+
+/// Keeps a runaway script from hanging the app or eating all memory
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ScriptLimits {
+    /// Operations per bar, a series run gets this many for every bar it covers
+    pub max_operations: u64,
+    /// Wall clock time for all runs of one execution
+    pub max_millis: u64,
+    /// Most elements of any one array or map, and most characters of any one string, the
+    /// script builds. This bounds each collection on its own, not the memory of the whole
+    /// script, and series runs can always build arrays with a value for every bar.
+    pub max_collection_size: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: 100_000,
+            max_millis: 2_000,
+            max_collection_size: 1_000_000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ScriptSettings {
+    pub code: String,
+    pub mode: ScriptMode,
+    pub inputs: Vec<ScriptPin>,
+    pub outputs: Vec<ScriptPin>,
+    pub limits: ScriptLimits,
+    /// How several inputs are lined up
+    pub join: JoinMode,
+}
+
+impl Default for ScriptSettings {
+    fn default() -> Self {
+        Self {
+            code: "// Missing values are NaN. `state` is kept from bar to bar,\n\
+                   // `bar` is the row and `time` its close time.\n\
+                   let previous = state.previous ?? close;\n\
+                   change = close - previous;\n\
+                   state.previous = close;\n"
+                .to_owned(),
+            mode: ScriptMode::PerBar,
+            inputs: vec![ScriptPin {
+                name: "close".to_owned(),
+                data_type: NodeDataType::DecimalSequence,
+            }],
+            outputs: vec![ScriptPin {
+                name: "change".to_owned(),
+                data_type: NodeDataType::DecimalSequence,
+            }],
+            limits: ScriptLimits::default(),
+            join: JoinMode::Strict,
+        }
+    }
+}
+
+/// A sandboxed engine: no modules, no `eval`, no printing, and the limits enforced for runs
+/// covering `bars` bars. Runs stop once `deadline` has passed.
+fn engine(limits: &ScriptLimits, bars: usize, deadline: Option<Instant>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine.set_max_operations(operations(limits, bars));
+    engine.set_max_array_size(limits.max_collection_size.max(bars));
+    engine.set_max_map_size(limits.max_collection_size);
+    engine.set_max_string_size(limits.max_collection_size);
+    engine.set_max_call_levels(64);
+    engine.set_max_expr_depths(64, 32);
+    if let Some(deadline) = deadline {
+        // Looking at the clock on every operation would slow scripts down a lot
+        engine.on_progress(move |operations| {
+            if operations % 1024 == 0 && Instant::now() > deadline {
+                return Some(Dynamic::UNIT);
+            }
+            None
+        });
+    }
+    engine
+}
+
+/// The operations of a run covering `bars` bars
+fn operations(limits: &ScriptLimits, bars: usize) -> u64 {
+    limits.max_operations.saturating_mul(bars.max(1) as u64)
+}
+
+fn check_name(name: &str, seen: &mut HashSet<String>) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow!("'{}' is not a valid variable name", name));
+    }
+    if RESERVED_NAMES.contains(&name) {
+        return Err(anyhow!("{} is reserved, pick another name", name));
+    }
+    if !seen.insert(name.to_owned()) {
+        return Err(anyhow!("There are two pins named {}", name));
+    }
+    Ok(())
+}
+
+/// A script that compiled, with the settings it compiled from
+pub struct Script {
+    pub settings: ScriptSettings,
+    ast: AST,
+}
+
+impl Script {
+    pub fn compile(settings: &ScriptSettings) -> Result<Self> {
+        if settings.inputs.is_empty() || settings.outputs.is_empty() {
+            return Err(anyhow!("A script needs at least one input and one output"));
+        }
+        let mut seen = HashSet::new();
+        for pin in settings.inputs.iter().chain(&settings.outputs) {
+            check_name(&pin.name, &mut seen)?;
+        }
+        if let Some(pin) = settings
+            .outputs
+            .iter()
+            .find(|pin| pin.data_type == NodeDataType::Candles)
+        {
+            return Err(anyhow!(
+                "{} can't be candles, scripts can't output those",
+                pin.name
+            ));
+        }
+        let ast = engine(&settings.limits, 1, None)
+            .compile(&settings.code)
+            .map_err(|e| anyhow!("{}", e))?;
+        Ok(Self {
+            settings: settings.clone(),
+            ast,
+        })
+    }
+
+    /// Describes an error of a run covering `bars` bars
+    fn error(&self, error: EvalAltResult, bars: usize) -> anyhow::Error {
+        match error {
+            EvalAltResult::ErrorTooManyOperations(_) => anyhow!(
+                "Script ran more than {} operations",
+                operations(&self.settings.limits, bars)
+            ),
+            EvalAltResult::ErrorTerminated(..) => anyhow!(
+                "Script took longer than {} ms",
+                self.settings.limits.max_millis
+            ),
+            error => anyhow!("{}", error),
+        }
+    }
+}
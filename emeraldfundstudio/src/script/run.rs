@@ -0,0 +1,422 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use polars::prelude::DataFrame;
+use rhai::{Array, Dynamic, Map, Scope, INT};
+use web_time::Instant;
+
+use super::{engine, Script, ScriptMode, ScriptPin};
+use crate::{
+    node_editor::node_trait::{
+        CheapCloneNodeDataTypeWithValue, NodeDataType, NodeDataTypeWithValue,
+    },
+    traits::IntoArc,
+    types::{
+        decimal_sequence::DecimalSequence,
+        time_index::{join, same_index, take, Indexed, JoinMode, TimeIndex},
+    },
+};
+
+const CANDLE_FIELDS: [&str; 5] = ["open", "high", "low", "close", "volume"];
+
+/// An input with a value for every row of the result
+enum Column {
+    Values(Vec<Dynamic>),
+    Candles(Vec<(&'static str, Vec<Dynamic>)>),
+}
+
+impl Column {
+    fn row(&self, row: usize) -> Dynamic {
+        match self {
+            Column::Values(values) => values[row].clone(),
+            Column::Candles(fields) => {
+                let map: Map = fields
+                    .iter()
+                    .map(|(field, values)| ((*field).into(), values[row].clone()))
+                    .collect();
+                map.into()
+            }
+        }
+    }
+
+    fn series(&self) -> Dynamic {
+        match self {
+            Column::Values(values) => values.clone().into(),
+            Column::Candles(fields) => {
+                let map: Map = fields
+                    .iter()
+                    .map(|(field, values)| ((*field).into(), values.clone().into()))
+                    .collect();
+                map.into()
+            }
+        }
+    }
+}
+
+/// Missing numbers are NaN so scripts can do math without checking every value
+fn number(value: Option<f64>) -> Dynamic {
+    Dynamic::from_float(value.unwrap_or(f64::NAN))
+}
+
+/// What a row without a value of `data_type` holds
+fn missing(data_type: NodeDataType) -> Dynamic {
+    match data_type {
+        NodeDataType::Mask => Dynamic::FALSE,
+        NodeDataType::Signal => Dynamic::from_int(0),
+        NodeDataType::DecimalSequence | NodeDataType::Candles => number(None),
+    }
+}
+
+fn candle_fields(df: &DataFrame) -> Result<Vec<(&'static str, Vec<Dynamic>)>> {
+    CANDLE_FIELDS
+        .iter()
+        .map(|field| {
+            let values = df.column(field)?.f64()?.into_iter().map(number).collect();
+            Ok((*field, values))
+        })
+        .collect()
+}
+
+fn column(input: &NodeDataTypeWithValue, pin: &ScriptPin) -> Result<Column> {
+    Ok(match (input, pin.data_type) {
+        (NodeDataTypeWithValue::DecimalSequence(seq), NodeDataType::DecimalSequence) => {
+            Column::Values(seq.values.iter().map(number).collect())
+        }
+        (NodeDataTypeWithValue::Mask(mask), NodeDataType::Mask) => Column::Values(
+            mask.values
+                .iter()
+                .map(|value| Dynamic::from_bool(*value))
+                .collect(),
+        ),
+        (NodeDataTypeWithValue::Signal(signal), NodeDataType::Signal) => Column::Values(
+            signal
+                .values
+                .iter()
+                .map(|value| Dynamic::from_int(*value as INT))
+                .collect(),
+        ),
+        (NodeDataTypeWithValue::Candles(candles), NodeDataType::Candles) => {
+            Column::Candles(candle_fields(&candles.df)?)
+        }
+        _ => return Err(anyhow!("{} should be {:?}", pin.name, pin.data_type)),
+    })
+}
+
+/// Lines an input with timestamps `input` up with the rows of `index`
+fn line_up(
+    column: Column,
+    data_type: NodeDataType,
+    index: &TimeIndex,
+    input: &TimeIndex,
+) -> Result<Column> {
+    if same_index(index, input) {
+        return Ok(column);
+    }
+    let positions = join(index, input, JoinMode::Left)?.right;
+    let fill = missing(data_type);
+    Ok(match column {
+        Column::Values(values) => Column::Values(take(&values, &positions, fill)),
+        Column::Candles(fields) => Column::Candles(
+            fields
+                .into_iter()
+                .map(|(field, values)| (field, take(&values, &positions, fill.clone())))
+                .collect(),
+        ),
+    })
+}
+
+fn output(
+    pin: &ScriptPin,
+    values: Vec<Dynamic>,
+    index: TimeIndex,
+) -> Result<CheapCloneNodeDataTypeWithValue> {
+    let wrong = |row: usize, value: &Dynamic, wanted: &str| {
+        anyhow!(
+            "{} should be {} but is {} at bar {}",
+            pin.name,
+            wanted,
+            value.type_name(),
+            row
+        )
+    };
+    let result = match pin.data_type {
+        NodeDataType::DecimalSequence => {
+            let values = values
+                .iter()
+                .enumerate()
+                .map(|(row, value)| {
+                    if value.is_unit() {
+                        return Ok(None);
+                    }
+                    let number = value
+                        .as_float()
+                        .or_else(|_| value.as_int().map(|value| value as f64))
+                        .map_err(|_| wrong(row, value, "a number"))?;
+                    // Like the other nodes, values that can't be computed are missing
+                    Ok(Some(number).filter(|number| number.is_finite()))
+                })
+                .collect::<Result<Vec<Option<f64>>>>()?;
+            let seq: DecimalSequence = values.into_iter().collect();
+            NodeDataTypeWithValue::DecimalSequence(Indexed::new(
+                index,
+                seq.with_name(pin.name.as_str().into()),
+            ))
+        }
+        NodeDataType::Mask => {
+            let mask = values
+                .iter()
+                .enumerate()
+                .map(|(row, value)| {
+                    if value.is_unit() {
+                        return Ok(false);
+                    }
+                    value.as_bool().map_err(|_| wrong(row, value, "a bool"))
+                })
+                .collect::<Result<Vec<bool>>>()?;
+            NodeDataTypeWithValue::Mask(Indexed::new(index, mask))
+        }
+        NodeDataType::Signal => {
+            let signal = values
+                .iter()
+                .enumerate()
+                .map(|(row, value)| {
+                    if value.is_unit() {
+                        return Ok(0);
+                    }
+                    let direction = value
+                        .as_float()
+                        .or_else(|_| value.as_int().map(|value| value as f64))
+                        .map_err(|_| wrong(row, value, "1, 0 or -1"))?;
+                    Ok(match direction {
+                        direction if direction > 0.0 => 1,
+                        direction if direction < 0.0 => -1,
+                        _ => 0,
+                    })
+                })
+                .collect::<Result<Vec<i8>>>()?;
+            NodeDataTypeWithValue::Signal(Indexed::new(index, signal))
+        }
+        NodeDataType::Candles => return Err(anyhow!("Scripts can't output candles")),
+    };
+    Ok(result.into_arc())
+}
+
+impl Script {
+    /// Runs the script over the inputs. With several inputs the rows are those of the inputs
+    /// joined one after the other.
+    pub fn run(
+        &self,
+        input_args: &[CheapCloneNodeDataTypeWithValue],
+    ) -> Result<Vec<CheapCloneNodeDataTypeWithValue>> {
+        let inputs = &self.settings.inputs;
+        if input_args.len() != inputs.len() {
+            return Err(anyhow!("should have {} inputs!", inputs.len()));
+        }
+        let indexes = input_args
+            .iter()
+            .map(|input| input.time_index())
+            .collect::<Result<Vec<TimeIndex>>>()?;
+        let mut index = indexes[0].clone();
+        for other in &indexes[1..] {
+            index = join(&index, other, self.settings.join)?.index;
+        }
+        let columns = input_args
+            .iter()
+            .zip(&indexes)
+            .zip(inputs)
+            .map(|((input, input_index), pin)| {
+                line_up(column(input, pin)?, pin.data_type, &index, input_index)
+            })
+            .collect::<Result<Vec<Column>>>()?;
+
+        let results = match self.settings.mode {
+            ScriptMode::PerBar => self.per_bar(&columns, &index)?,
+            ScriptMode::PerSeries => self.per_series(&columns, &index)?,
+        };
+        self.settings
+            .outputs
+            .iter()
+            .zip(results)
+            .map(|(pin, values)| output(pin, values, index.clone()))
+            .collect()
+    }
+
+    fn deadline(&self) -> Instant {
+        Instant::now() + Duration::from_millis(self.settings.limits.max_millis)
+    }
+
+    fn per_bar(&self, columns: &[Column], index: &TimeIndex) -> Result<Vec<Vec<Dynamic>>> {
+        let deadline = self.deadline();
+        let engine = engine(&self.settings.limits, 1, Some(deadline));
+        let mut scope = Scope::new();
+        scope.push("state", Map::new());
+        scope.push("bar", 0 as INT);
+        scope.push("time", 0 as INT);
+        for pin in self.settings.inputs.iter().chain(&self.settings.outputs) {
+            scope.push_dynamic(pin.name.as_str(), Dynamic::UNIT);
+        }
+        // Variables the script declares only live for one bar, `state` is for keeping things
+        let declared = scope.len();
+
+        let mut results = vec![Vec::with_capacity(index.len()); self.settings.outputs.len()];
+        for (row, timestamp) in index.iter().enumerate() {
+            if Instant::now() > deadline {
+                return Err(anyhow!(
+                    "Script took longer than {} ms",
+                    self.settings.limits.max_millis
+                ));
+            }
+            scope.set_value("bar", row as INT);
+            scope.set_value("time", *timestamp as INT);
+            for (pin, column) in self.settings.inputs.iter().zip(columns) {
+                scope.set_value(pin.name.as_str(), column.row(row));
+            }
+            for pin in &self.settings.outputs {
+                scope.set_value(pin.name.as_str(), Dynamic::UNIT);
+            }
+            engine
+                .run_ast_with_scope(&mut scope, &self.ast)
+                .map_err(|e| anyhow!("Bar {}: {}", row, self.error(*e, 1)))?;
+            for (pin, values) in self.settings.outputs.iter().zip(&mut results) {
+                values.push(scope.get_value::<Dynamic>(&pin.name).unwrap_or_default());
+            }
+            scope.rewind(declared);
+        }
+        Ok(results)
+    }
+
+    fn per_series(&self, columns: &[Column], index: &TimeIndex) -> Result<Vec<Vec<Dynamic>>> {
+        let engine = engine(&self.settings.limits, index.len(), Some(self.deadline()));
+        let mut scope = Scope::new();
+        let time: Array = index
+            .iter()
+            .map(|timestamp| Dynamic::from_int(*timestamp as INT))
+            .collect();
+        scope.push("time", time);
+        for (pin, column) in self.settings.inputs.iter().zip(columns) {
+            scope.push_dynamic(pin.name.as_str(), column.series());
+        }
+        for pin in &self.settings.outputs {
+            scope.push_dynamic(pin.name.as_str(), Dynamic::UNIT);
+        }
+        engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| self.error(*e, index.len()))?;
+
+        self.settings
+            .outputs
+            .iter()
+            .map(|pin| {
+                let values = scope
+                    .get_value::<Dynamic>(&pin.name)
+                    .unwrap_or_default()
+                    .into_array()
+                    .map_err(|type_name| {
+                        anyhow!("{} should be an array but is {}", pin.name, type_name)
+                    })?;
+                if values.len() != index.len() {
+                    return Err(anyhow!(
+                        "{} has {} values but there are {} bars",
+                        pin.name,
+                        values.len(),
+                        index.len()
+                    ));
+                }
+                Ok(values)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        script::{ScriptLimits, ScriptSettings},
+        types::timestamp::TimeStamp,
+    };
+
+    fn input(bars: usize) -> Vec<CheapCloneNodeDataTypeWithValue> {
+        let index: Vec<TimeStamp> = (0..bars as TimeStamp).map(|i| 60 + i * 60).collect();
+        let seq: DecimalSequence = (0..bars).map(|i| Some(i as f64)).collect();
+        vec![NodeDataTypeWithValue::DecimalSequence(Indexed::new(Arc::new(index), seq)).into_arc()]
+    }
+
+    fn script(mode: ScriptMode, code: &str, limits: ScriptLimits) -> Script {
+        Script::compile(&ScriptSettings {
+            code: code.to_owned(),
+            mode,
+            limits,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn error(script: &Script, bars: usize) -> String {
+        script.run(&input(bars)).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn runaway_scripts_hit_the_operation_limit() {
+        let limits = ScriptLimits {
+            max_operations: 1_000,
+            ..Default::default()
+        };
+        let per_bar = script(ScriptMode::PerBar, "loop {}", limits.clone());
+        assert_eq!(
+            error(&per_bar, 10),
+            "Bar 0: Script ran more than 1000 operations"
+        );
+        let per_series = script(ScriptMode::PerSeries, "loop {}", limits);
+        assert_eq!(
+            error(&per_series, 10),
+            "Script ran more than 10000 operations"
+        );
+    }
+
+    #[test]
+    fn series_runs_get_operations_for_every_bar() {
+        let limits = ScriptLimits {
+            max_operations: 1_000,
+            ..Default::default()
+        };
+        // A few operations per bar, far more than 1000 for the whole series
+        let code = "change = []; for value in close { change.push(value * 2.0); }";
+        let result = script(ScriptMode::PerSeries, code, limits)
+            .run(&input(5_000))
+            .unwrap();
+        match &*result[0] {
+            NodeDataTypeWithValue::DecimalSequence(seq) => {
+                assert_eq!(seq.values.len(), 5_000);
+                assert_eq!(seq.values.get(4_999), Some(9_998.0));
+            }
+            _ => panic!("expected a decimal sequence"),
+        }
+    }
+
+    #[test]
+    fn slow_scripts_hit_the_time_limit() {
+        let limits = ScriptLimits {
+            max_operations: u64::MAX,
+            max_millis: 10,
+            ..Default::default()
+        };
+        let per_bar = script(ScriptMode::PerBar, "loop {}", limits.clone());
+        assert_eq!(error(&per_bar, 10), "Bar 0: Script took longer than 10 ms");
+        let per_series = script(ScriptMode::PerSeries, "loop {}", limits);
+        assert_eq!(error(&per_series, 10), "Script took longer than 10 ms");
+    }
+
+    #[test]
+    fn collections_are_limited() {
+        let limits = ScriptLimits {
+            max_collection_size: 1_000,
+            ..Default::default()
+        };
+        let code = "let a = []; for i in 0..2000 { a.push(i); } change = 0;";
+        let per_bar = script(ScriptMode::PerBar, code, limits);
+        assert!(error(&per_bar, 1).contains("Size of array"));
+    }
+}
@@ -1,12 +1,199 @@
-use anyhow::Result;
-use polars::{df, frame::DataFrame};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use polars::{df, frame::DataFrame, prelude::Column};
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    time_index::TimeIndex,
+    timeframe::{timeframe_label, Timeframe},
+    timestamp::{get_unix_time, SECONDS_PER_YEAR},
+};
+
+const BASE_VOLUME: f64 = 100.0;
+
+/// Where a candle stream comes from
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct MarketInfo {
+    pub exchange: String,
+    /// e.g. "BTC-USDT"
+    pub pair: String,
+    /// Length of one candle
+    pub timeframe_seconds: u64,
+}
+
+impl std::fmt::Display for MarketInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.exchange,
+            self.pair,
+            timeframe_label(self.timeframe_seconds)
+        )
+    }
+}
+
+/// OHLCV DataFrame (`open`, `high`, `low`, `close`, `volume` and `timestamp` columns)
+/// together with the market it belongs to
+#[derive(Clone, Debug)]
+pub struct Candles {
+    pub info: MarketInfo,
+    pub df: DataFrame,
+}
+
+impl Candles {
+    pub fn new(info: MarketInfo, df: DataFrame) -> Self {
+        Self { info, df }
+    }
+
+    pub fn closes(&self) -> Result<Vec<Option<f64>>> {
+        Ok(self.df.column("close")?.f64()?.into_iter().collect())
+    }
+
+    pub fn timestamps(&self) -> Result<Vec<u64>> {
+        Ok(self
+            .df
+            .column("timestamp")?
+            .u64()?
+            .into_no_null_iter()
+            .collect())
+    }
+
+    /// Close time of every candle, see `TimeIndex`
+    pub fn time_index(&self) -> Result<TimeIndex> {
+        let timestamps = self.timestamps()?;
+        let timeframe_seconds = match (self.info.timeframe_seconds, timestamps.as_slice()) {
+            (0, [first, second, ..]) => second.saturating_sub(*first),
+            (timeframe_seconds, _) => timeframe_seconds,
+        };
+        Ok(Arc::new(
+            timestamps
+                .into_iter()
+                .map(|timestamp| timestamp + timeframe_seconds)
+                .collect(),
+        ))
+    }
+
+    /// Candles `offset..offset + len`, with the same market info
+    pub fn slice(&self, offset: i64, len: usize) -> Self {
+        Self {
+            info: self.info.clone(),
+            df: self.df.slice(offset, len),
+        }
+    }
+}
+
+/// Settings of the synthetic market. Every feature can be switched off by setting its
+/// parameters to 0, with all of them off it is a plain geometric Brownian motion.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CandleGenerator {
+    pub seed: u64,
+    pub len: usize,
+    pub timeframe: Timeframe,
+    pub start_price: f64,
+    /// Annualized drift of the log price
+    pub drift: f64,
+    /// Annualized volatility, also the long-run volatility when GARCH is enabled
+    pub volatility: f64,
+    /// GARCH(1,1) weight of the previous bar's shock
+    pub garch_alpha: f64,
+    /// GARCH(1,1) weight of the previous bar's variance, `garch_alpha + garch_beta` must be below 1
+    pub garch_beta: f64,
+    /// Chance per bar to switch between the trend and the range regime
+    pub regime_switch: f64,
+    /// Extra drift per bar in the trend regime, as a multiple of the bar's volatility
+    pub trend_strength: f64,
+    /// Fraction of the distance to the range's anchor price that is pulled back per bar
+    pub range_reversion: f64,
+    /// Chance per bar of a jump
+    pub jump_probability: f64,
+    /// Mean log size of a jump
+    pub jump_mean: f64,
+    /// Standard deviation of the log size of a jump
+    pub jump_volatility: f64,
+}
+
+impl Default for CandleGenerator {
+    fn default() -> Self {
+        Self {
+            seed: 21,
+            len: 500,
+            timeframe: Timeframe::M1,
+            start_price: 100.0,
+            drift: 0.0,
+            volatility: 0.8,
+            garch_alpha: 0.1,
+            garch_beta: 0.85,
+            regime_switch: 0.01,
+            trend_strength: 0.1,
+            range_reversion: 0.05,
+            jump_probability: 0.002,
+            jump_mean: 0.0,
+            jump_volatility: 0.02,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Regime {
+    /// Drifts up (1.0) or down (-1.0)
+    Trend(f64),
+    /// Reverts to the anchor price
+    Range(f64),
+}
+
+/// Box-Muller, rand 0.8 has no normal distribution without `rand_distr`
+fn standard_normal(rng: &mut ChaCha8Rng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
 
-use super::timestamp::get_unix_time;
+fn random_regime(rng: &mut ChaCha8Rng, price: f64) -> Regime {
+    if rng.gen_bool(0.5) {
+        Regime::Trend(if rng.gen_bool(0.5) { 1.0 } else { -1.0 })
+    } else {
+        Regime::Range(price)
+    }
+}
+
+impl CandleGenerator {
+    fn validate(&self) -> Result<()> {
+        if self.start_price <= 0.0 {
+            return Err(anyhow!("Start price must be positive"));
+        }
+        if self.volatility < 0.0 || self.jump_volatility < 0.0 {
+            return Err(anyhow!("Volatility can't be negative"));
+        }
+        if self.garch_alpha < 0.0
+            || self.garch_beta < 0.0
+            || self.garch_alpha + self.garch_beta >= 1.0
+        {
+            return Err(anyhow!(
+                "GARCH alpha and beta can't be negative and must sum to less than 1"
+            ));
+        }
+        for (name, probability) in [
+            ("Regime switch", self.regime_switch),
+            ("Jump probability", self.jump_probability),
+        ] {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(anyhow!("{} must be between 0 and 1", name));
+            }
+        }
+        Ok(())
+    }
+}
 
-pub fn generate_candles(seed: u64, len: usize) -> Result<DataFrame> {
-    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+pub fn generate_candles(generator: &CandleGenerator) -> Result<DataFrame> {
+    generator.validate()?;
+    let len = generator.len;
+    let mut rng = ChaCha8Rng::seed_from_u64(generator.seed);
 
     let mut opens = Vec::with_capacity(len);
     let mut highs = Vec::with_capacity(len);
@@ -19,16 +206,47 @@ pub fn generate_candles(seed: u64, len: usize) -> Result<DataFrame> {
         return Ok(DataFrame::new(vec![])?);
     }
 
-    // Start with a random price for the first candle
-    let mut prev_close = rng.gen_range(0.0..1.0);
-    let mut timestamp = get_unix_time(); // Starting timestamp
+    let bar_seconds = generator.timeframe.seconds();
+    let dt = bar_seconds as f64 / SECONDS_PER_YEAR;
+    // Per-bar variance the GARCH process reverts to
+    let long_run_variance = generator.volatility.powi(2) * dt;
+    let omega = long_run_variance * (1.0 - generator.garch_alpha - generator.garch_beta);
+
+    let mut prev_close = generator.start_price;
+    let mut variance = long_run_variance;
+    let mut prev_shock: f64 = 0.0;
+    let mut regime = random_regime(&mut rng, prev_close);
+    // Starting timestamp, aligned to the timeframe
+    let mut timestamp = get_unix_time() / bar_seconds * bar_seconds;
 
     for _ in 0..len {
+        variance =
+            omega + generator.garch_alpha * prev_shock.powi(2) + generator.garch_beta * variance;
+        let bar_volatility = variance.sqrt();
+
+        let regime_drift = match regime {
+            Regime::Trend(direction) => direction * generator.trend_strength * bar_volatility,
+            Regime::Range(anchor) => -generator.range_reversion * (prev_close / anchor).ln(),
+        };
+        let shock = bar_volatility * standard_normal(&mut rng);
+        let jump = if rng.gen_bool(generator.jump_probability) {
+            generator.jump_mean + generator.jump_volatility * standard_normal(&mut rng)
+        } else {
+            0.0
+        };
+        let log_return = generator.drift * dt - 0.5 * variance + regime_drift + shock + jump;
+
         let open: f64 = prev_close;
-        let close = (open + rng.gen_range(-0.05..0.05)).max(0.0); // Ensure price doesn't go negative
-        let high = close + rng.gen_range(0.00..0.02);
-        let low = close - rng.gen_range(0.00..0.02);
-        let volume = rng.gen_range(50.0..150.0); // Random volume between 50 and 150
+        let close = open * log_return.exp();
+        let high = open.max(close) * (0.5 * bar_volatility * standard_normal(&mut rng).abs()).exp();
+        let low = open.min(close) * (-0.5 * bar_volatility * standard_normal(&mut rng).abs()).exp();
+        // Busier bars trade more
+        let activity = if long_run_variance > 0.0 {
+            log_return.abs() / long_run_variance.sqrt()
+        } else {
+            0.0
+        };
+        let volume = BASE_VOLUME * (0.25 * standard_normal(&mut rng)).exp() * (1.0 + activity);
 
         opens.push(open);
         highs.push(high);
@@ -37,8 +255,15 @@ pub fn generate_candles(seed: u64, len: usize) -> Result<DataFrame> {
         volumes.push(volume);
         timestamps.push(timestamp);
 
+        if rng.gen_bool(generator.regime_switch) {
+            regime = match regime {
+                Regime::Trend(_) => Regime::Range(close),
+                Regime::Range(_) => Regime::Trend(if rng.gen_bool(0.5) { 1.0 } else { -1.0 }),
+            };
+        }
+        prev_shock = shock;
         prev_close = close;
-        timestamp += 60; // Increase timestamp by 1 minute
+        timestamp += bar_seconds;
     }
 
     let df = df![
@@ -52,3 +277,65 @@ pub fn generate_candles(seed: u64, len: usize) -> Result<DataFrame> {
 
     Ok(df)
 }
+
+fn f64_column(df: &DataFrame, column: &str) -> Result<Vec<f64>> {
+    Ok(df
+        .column(column)?
+        .f64()?
+        .into_iter()
+        .map(|x| x.unwrap_or(f64::NAN))
+        .collect())
+}
+
+/// Mixes the close-to-close log returns of `leader` into `own`, so the result moves with the
+/// leader by `correlation` (0 = independent, 1 = the same returns). The wicks and volume of
+/// `own` are kept relative to its candle bodies, the timestamps are taken from the leader.
+pub fn correlate_candles(
+    leader: &DataFrame,
+    own: &DataFrame,
+    correlation: f64,
+) -> Result<DataFrame> {
+    if leader.height() != own.height() {
+        return Err(anyhow!("Both markets must have the same number of candles"));
+    }
+    let correlation = correlation.clamp(0.0, 1.0);
+    let own_weight = (1.0 - correlation.powi(2)).sqrt();
+    let leader_closes = f64_column(leader, "close")?;
+    let opens = f64_column(own, "open")?;
+    let highs = f64_column(own, "high")?;
+    let lows = f64_column(own, "low")?;
+    let closes = f64_column(own, "close")?;
+
+    let len = own.height();
+    let mut new_opens = Vec::with_capacity(len);
+    let mut new_highs = Vec::with_capacity(len);
+    let mut new_lows = Vec::with_capacity(len);
+    let mut new_closes = Vec::with_capacity(len);
+    let mut prev_close = opens.first().copied().unwrap_or_default();
+    for idx in 0..len {
+        let (leader_return, own_return) = match idx {
+            0 => (0.0, (closes[0] / opens[0]).ln()),
+            _ => (
+                (leader_closes[idx] / leader_closes[idx - 1]).ln(),
+                (closes[idx] / closes[idx - 1]).ln(),
+            ),
+        };
+        let open = prev_close;
+        let close = open * (correlation * leader_return + own_weight * own_return).exp();
+        new_opens.push(open);
+        new_highs.push(open.max(close) * highs[idx] / opens[idx].max(closes[idx]));
+        new_lows.push(open.min(close) * lows[idx] / opens[idx].min(closes[idx]));
+        new_closes.push(close);
+        prev_close = close;
+    }
+
+    let df = DataFrame::new(vec![
+        Column::new("open".into(), new_opens),
+        Column::new("high".into(), new_highs),
+        Column::new("low".into(), new_lows),
+        Column::new("close".into(), new_closes),
+        own.column("volume")?.clone(),
+        leader.column("timestamp")?.clone(),
+    ])?;
+    Ok(df)
+}
@@ -0,0 +1,26 @@
+use crate::node_editor::OrderDirection;
+
+use super::timestamp::TimeStamp;
+
+/// A fill of a live bot, to compare against the backtest of the same period
+#[derive(Clone, Debug)]
+pub struct LiveTrade {
+    pub timestamp: TimeStamp,
+    pub exchange: String,
+    /// e.g. "BTC-USDT"
+    pub pair: String,
+    pub direction: OrderDirection,
+    pub price: f64,
+    pub amount: f64,
+}
+
+/// Pairs are written as "BTC-USDT", "BTC/USDT" or "btc_usdt" depending on the source
+fn normalize_pair(pair: &str) -> String {
+    pair.trim().to_uppercase().replace(['/', '_'], "-")
+}
+
+impl LiveTrade {
+    pub fn is_pair(&self, pair: &str) -> bool {
+        normalize_pair(&self.pair) == normalize_pair(pair)
+    }
+}
@@ -1,5 +1,9 @@
 pub mod candles;
 pub mod decimal_sequence;
+pub mod live_trade;
 pub mod mask;
+pub mod rolling;
 pub mod signal;
+pub mod time_index;
+pub mod timeframe;
 pub mod timestamp;
@@ -0,0 +1,334 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+
+use super::{
+    decimal_sequence::DecimalSequence,
+    time_index::{check_length, Indexed, TimeIndex},
+};
+use crate::node_editor::node_trait::NodeState;
+
+/// An indicator computed one value at a time. Batch and incremental execution both step
+/// through the same `next` calls, so their results are identical.
+pub trait Rolling: Clone + Send + Sync + 'static {
+    fn next(&mut self, value: Option<f64>) -> Option<f64>;
+
+    /// Whether a state built with `other`'s settings can be continued by `self`
+    fn same_settings(&self, other: &Self) -> bool;
+}
+
+/// Simple moving average, missing while a missing value is in the window like polars'
+/// `rolling_mean`
+#[derive(Clone, Debug)]
+pub struct RollingSma {
+    period: usize,
+    window: VecDeque<Option<f64>>,
+    sum: f64,
+    /// Missing values in the window
+    missing: usize,
+}
+
+impl RollingSma {
+    pub fn new(period: usize) -> Self {
+        let period = period.max(1);
+        Self {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+            sum: 0.0,
+            missing: 0,
+        }
+    }
+}
+
+impl Rolling for RollingSma {
+    fn next(&mut self, value: Option<f64>) -> Option<f64> {
+        let value = value.filter(|value| !value.is_nan());
+        match value {
+            Some(value) => self.sum += value,
+            None => self.missing += 1,
+        }
+        self.window.push_back(value);
+        if self.window.len() > self.period {
+            match self.window.pop_front().flatten() {
+                Some(value) => self.sum -= value,
+                None => self.missing -= 1,
+            }
+        }
+        if self.window.len() == self.period && self.missing == 0 {
+            Some(self.sum / self.period as f64)
+        } else {
+            None
+        }
+    }
+
+    fn same_settings(&self, other: &Self) -> bool {
+        self.period == other.period
+    }
+}
+
+/// Exponential moving average seeded with the SMA of the first `period` values. Missing
+/// values are skipped and repeat the last average.
+#[derive(Clone, Debug)]
+pub struct RollingEma {
+    period: usize,
+    seed_sum: f64,
+    seen: usize,
+    value: Option<f64>,
+}
+
+impl RollingEma {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            seed_sum: 0.0,
+            seen: 0,
+            value: None,
+        }
+    }
+}
+
+impl Rolling for RollingEma {
+    fn next(&mut self, value: Option<f64>) -> Option<f64> {
+        let Some(value) = value.filter(|value| !value.is_nan()) else {
+            return self.value;
+        };
+        self.value = match self.value {
+            Some(previous) => {
+                let alpha = 2.0 / (self.period as f64 + 1.0);
+                Some(previous + alpha * (value - previous))
+            }
+            None => {
+                self.seed_sum += value;
+                self.seen += 1;
+                (self.seen == self.period).then(|| self.seed_sum / self.period as f64)
+            }
+        };
+        self.value
+    }
+
+    fn same_settings(&self, other: &Self) -> bool {
+        self.period == other.period
+    }
+}
+
+/// Runs `rolling` over every value of `seq`
+pub fn rolling_batch<R: Rolling>(seq: &DecimalSequence, mut rolling: R) -> DecimalSequence {
+    let result: DecimalSequence = seq.iter().map(|value| rolling.next(value)).collect();
+    result.with_name(seq.name().clone())
+}
+
+/// What an incremental node keeps between updates
+struct RollingState<R> {
+    /// The indicator before it saw the last value, the last bar can still change
+    before_last: R,
+    index: TimeIndex,
+    /// The output so far, see `append_piece`
+    pieces: Vec<DecimalSequence>,
+}
+
+impl<R> RollingState<R> {
+    /// Whether `index` is the index of the last call with bars appended or the last bar updated
+    fn continues(&self, index: &TimeIndex) -> bool {
+        let len = self.index.len();
+        len > 0
+            && index.len() >= len
+            && index[0] == self.index[0]
+            && index[len - 1] == self.index[len - 1]
+    }
+}
+
+/// Appends `values` to the output kept in `pieces`. Pieces are merged until each is at most
+/// half as long as the one before it, so there are O(log bars) of them and each value is
+/// copied O(log bars) times in all.
+fn append_piece(pieces: &mut Vec<DecimalSequence>, values: DecimalSequence) -> Result<()> {
+    pieces.push(values);
+    while let [.., before, last] = &pieces[..] {
+        if last.len() * 2 <= before.len() {
+            break;
+        }
+        let last = pieces.pop().unwrap();
+        let before = pieces.last_mut().unwrap();
+        before.append(&last)?;
+        *before = before.rechunk();
+    }
+    Ok(())
+}
+
+/// Drops the last value of the output kept in `pieces`, without copying
+fn drop_last(pieces: &mut Vec<DecimalSequence>) {
+    if let Some(last) = pieces.pop() {
+        if last.len() > 1 {
+            pieces.push(last.slice(0, last.len() - 1));
+        }
+    }
+}
+
+/// Like `rolling_batch`, but continues from `state` when `seq` only got new bars at the end
+/// since the last call. The output is shared with the state in chunks instead of copied, so
+/// that costs O(new bars + log bars) amortized. Anything else starts over.
+pub fn rolling_update<R: Rolling>(
+    seq: &Indexed<DecimalSequence>,
+    rolling: R,
+    state: &mut Option<NodeState>,
+) -> Result<Indexed<DecimalSequence>> {
+    check_length(&seq.index, seq.len())?;
+    let previous = state
+        .take()
+        .and_then(|state| state.downcast::<RollingState<R>>().ok())
+        .filter(|previous| {
+            previous.continues(&seq.index) && previous.before_last.same_settings(&rolling)
+        });
+    let (mut rolling, mut pieces, start) = match previous {
+        Some(previous) => {
+            let mut pieces = previous.pieces;
+            drop_last(&mut pieces);
+            (previous.before_last, pieces, previous.index.len() - 1)
+        }
+        None => (rolling, Vec::new(), 0),
+    };
+    let mut before_last = rolling.clone();
+    let mut values = Vec::with_capacity(seq.len() - start);
+    for idx in start..seq.len() {
+        if idx + 1 == seq.len() {
+            before_last = rolling.clone();
+        }
+        values.push(rolling.next(seq.get(idx)));
+    }
+    append_piece(&mut pieces, values.into_iter().collect())?;
+    let mut result = pieces[0].clone();
+    for piece in &pieces[1..] {
+        result.append(piece)?;
+    }
+    *state = Some(Box::new(RollingState {
+        before_last,
+        index: seq.index.clone(),
+        pieces,
+    }));
+    Ok(seq.with_values(result.with_name(seq.name().clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        node_editor::{
+            node_trait::{CheapCloneNodeDataTypeWithValue, EFNodeFn, NodeDataTypeWithValue},
+            nodes::{ema::EMANode, sma::SMANode},
+        },
+        traits::IntoArc,
+        types::timestamp::TimeStamp,
+    };
+
+    fn input(values: &[Option<f64>]) -> Vec<CheapCloneNodeDataTypeWithValue> {
+        let index: Vec<TimeStamp> = (0..values.len() as TimeStamp)
+            .map(|i| 60 + i * 60)
+            .collect();
+        let seq: DecimalSequence = values.iter().copied().collect();
+        vec![NodeDataTypeWithValue::DecimalSequence(Indexed::new(Arc::new(index), seq)).into_arc()]
+    }
+
+    fn output(result: &[CheapCloneNodeDataTypeWithValue]) -> Vec<Option<f64>> {
+        match &*result[0] {
+            NodeDataTypeWithValue::DecimalSequence(seq) => seq.iter().collect(),
+            _ => panic!("expected a decimal sequence"),
+        }
+    }
+
+    fn prices() -> Vec<Option<f64>> {
+        (0..300)
+            .map(|i| match i {
+                // Missing values must be handled the same way by both paths
+                40 | 41 | 150 => None,
+                i => Some(100.0 + (i as f64 * 0.37).sin() * 5.0 + i as f64 * 0.01),
+            })
+            .collect()
+    }
+
+    /// Feeds the prices bar by bar, updating every bar once like an unclosed candle, and
+    /// checks every incremental result against a full recompute
+    fn assert_incremental_matches_batch(node: &dyn EFNodeFn) {
+        let prices = prices();
+        let mut state = None;
+        for len in 1..=prices.len() {
+            let bars = prices[..len].to_vec();
+            if let Some(close) = bars[len - 1] {
+                let mut unclosed = bars.clone();
+                unclosed[len - 1] = Some(close * 1.01);
+                let updated = node.process_update(&input(&unclosed), &mut state).unwrap();
+                let batch = node.process_data(&input(&unclosed)).unwrap();
+                assert_eq!(output(&updated), output(&batch), "unclosed bar {}", len);
+            }
+            let incremental = node.process_update(&input(&bars), &mut state).unwrap();
+            let batch = node.process_data(&input(&bars)).unwrap();
+            assert_eq!(output(&incremental), output(&batch), "after {} bars", len);
+        }
+        assert!(state.is_some());
+    }
+
+    #[test]
+    fn sma_incremental_matches_batch() {
+        for period in [1, 2, 14, 50] {
+            assert_incremental_matches_batch(&SMANode { period });
+        }
+    }
+
+    #[test]
+    fn sma_is_missing_while_the_window_has_a_missing_value() {
+        let node = SMANode { period: 2 };
+        let bars = [Some(1.0), Some(3.0), None, Some(5.0), Some(7.0), Some(f64::NAN)];
+        let result = node.process_data(&input(&bars)).unwrap();
+        assert_eq!(
+            output(&result),
+            vec![None, Some(2.0), None, None, Some(6.0), None]
+        );
+    }
+
+    #[test]
+    fn ema_incremental_matches_batch() {
+        for period in [1, 2, 14, 50] {
+            assert_incremental_matches_batch(&EMANode { period });
+        }
+    }
+
+    #[test]
+    fn incremental_starts_over_on_other_candles() {
+        let node = SMANode { period: 3 };
+        let mut state = None;
+        let prices = prices();
+        node.process_update(&input(&prices[..100]), &mut state)
+            .unwrap();
+        // Fewer bars than last time, like a restarted feed
+        let restarted = node
+            .process_update(&input(&prices[..20]), &mut state)
+            .unwrap();
+        let batch = node.process_data(&input(&prices[..20])).unwrap();
+        assert_eq!(output(&restarted), output(&batch));
+        // A different period can't continue the old state
+        let other = SMANode { period: 5 };
+        let result = other
+            .process_update(&input(&prices[..21]), &mut state)
+            .unwrap();
+        assert_eq!(
+            output(&result),
+            output(&other.process_data(&input(&prices[..21])).unwrap())
+        );
+    }
+
+    #[test]
+    fn incremental_output_stays_in_few_chunks() {
+        let node = SMANode { period: 14 };
+        let prices = prices();
+        let mut state = None;
+        for len in 1..=prices.len() {
+            node.process_update(&input(&prices[..len]), &mut state)
+                .unwrap();
+        }
+        let result = node.process_update(&input(&prices), &mut state).unwrap();
+        match &*result[0] {
+            NodeDataTypeWithValue::DecimalSequence(seq) => assert!(seq.chunks().len() <= 9),
+            _ => panic!("expected a decimal sequence"),
+        }
+    }
+}
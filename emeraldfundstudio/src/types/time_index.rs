@@ -0,0 +1,252 @@
+use std::{ops::Deref, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+
+use super::timestamp::TimeStamp;
+
+/// Close times of the candles a sequence was computed from, ascending. A value is known
+/// from its close time on, which is what makes joins across timeframes free of look-ahead.
+/// Shared between all sequences derived from the same candles, so comparing them is
+/// usually a pointer check.
+pub type TimeIndex = Arc<Vec<TimeStamp>>;
+
+/// Values with the timestamp of every value
+#[derive(Clone, Debug)]
+pub struct Indexed<T> {
+    pub index: TimeIndex,
+    pub values: T,
+}
+
+impl<T> Indexed<T> {
+    pub fn new(index: TimeIndex, values: T) -> Self {
+        Self { index, values }
+    }
+
+    /// Same index, different values
+    pub fn with_values<U>(&self, values: U) -> Indexed<U> {
+        Indexed {
+            index: self.index.clone(),
+            values,
+        }
+    }
+}
+
+impl<T> Deref for Indexed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.values
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum JoinMode {
+    /// Both inputs must come from the same candles
+    #[default]
+    #[strum(serialize = "Same index")]
+    Strict,
+    /// Only timestamps both inputs have
+    #[strum(serialize = "Inner join")]
+    Inner,
+    /// Every timestamp of the first input, the second input is forward-filled
+    #[strum(serialize = "Left join")]
+    Left,
+}
+
+/// Rows of two joined inputs: the resulting index and, for every row, the position of the
+/// value in each input (`None` where an input has no value yet)
+pub struct Join {
+    pub index: TimeIndex,
+    pub left: Vec<Option<usize>>,
+    pub right: Vec<Option<usize>>,
+}
+
+impl Join {
+    /// Right positions without the forward fill, for values that are events (like signals)
+    /// rather than states
+    pub fn right_exact(&self, right: &TimeIndex) -> Vec<Option<usize>> {
+        self.right
+            .iter()
+            .zip(self.index.iter())
+            .map(|(position, timestamp)| position.filter(|position| right[*position] == *timestamp))
+            .collect()
+    }
+}
+
+fn describe(index: &[TimeStamp]) -> String {
+    let format = |timestamp: &TimeStamp| {
+        DateTime::from_timestamp(*timestamp as i64, 0)
+            .map(|datetime| datetime.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| timestamp.to_string())
+    };
+    match (index.first(), index.last()) {
+        (Some(first), Some(last)) => {
+            format!(
+                "{} values from {} to {}",
+                index.len(),
+                format(first),
+                format(last)
+            )
+        }
+        _ => "no values".to_owned(),
+    }
+}
+
+pub fn check_length(index: &TimeIndex, len: usize) -> Result<()> {
+    if index.len() != len {
+        return Err(anyhow!(
+            "Sequence has {} values but its index has {} timestamps",
+            len,
+            index.len()
+        ));
+    }
+    Ok(())
+}
+
+pub fn same_index(a: &TimeIndex, b: &TimeIndex) -> bool {
+    Arc::ptr_eq(a, b) || a == b
+}
+
+/// Lines up two indexes. The right side is never looked up past the timestamp of a row,
+/// so a left join doesn't leak future values.
+pub fn join(left: &TimeIndex, right: &TimeIndex, mode: JoinMode) -> Result<Join> {
+    if same_index(left, right) {
+        let positions: Vec<Option<usize>> = (0..left.len()).map(Some).collect();
+        return Ok(Join {
+            index: left.clone(),
+            left: positions.clone(),
+            right: positions,
+        });
+    }
+
+    match mode {
+        JoinMode::Strict => Err(anyhow!(
+            "Inputs come from different candles ({} vs {}), pick an inner or left join",
+            describe(left),
+            describe(right)
+        )),
+        JoinMode::Inner => {
+            let mut index = vec![];
+            let mut left_positions = vec![];
+            let mut right_positions = vec![];
+            let (mut l, mut r) = (0, 0);
+            while l < left.len() && r < right.len() {
+                match left[l].cmp(&right[r]) {
+                    std::cmp::Ordering::Less => l += 1,
+                    std::cmp::Ordering::Greater => r += 1,
+                    std::cmp::Ordering::Equal => {
+                        index.push(left[l]);
+                        left_positions.push(Some(l));
+                        right_positions.push(Some(r));
+                        l += 1;
+                        r += 1;
+                    }
+                }
+            }
+            Ok(Join {
+                index: Arc::new(index),
+                left: left_positions,
+                right: right_positions,
+            })
+        }
+        JoinMode::Left => {
+            let mut seen = 0;
+            let right_positions = left
+                .iter()
+                .map(|timestamp| {
+                    while seen < right.len() && right[seen] <= *timestamp {
+                        seen += 1;
+                    }
+                    seen.checked_sub(1)
+                })
+                .collect();
+            Ok(Join {
+                index: left.clone(),
+                left: (0..left.len()).map(Some).collect(),
+                right: right_positions,
+            })
+        }
+    }
+}
+
+/// Values at `positions`, `fill` where there is no position
+pub fn take<T: Clone>(values: &[T], positions: &[Option<usize>], fill: T) -> Vec<T> {
+    positions
+        .iter()
+        .map(|position| match position {
+            Some(position) => values[*position].clone(),
+            None => fill.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(timestamps: &[TimeStamp]) -> TimeIndex {
+        Arc::new(timestamps.to_vec())
+    }
+
+    #[test]
+    fn strict_join_needs_the_same_index() {
+        let left = index(&[60, 120, 180]);
+        assert!(join(&left, &index(&[60, 120, 180]), JoinMode::Strict).is_ok());
+        let error = join(&left, &index(&[60, 120, 240]), JoinMode::Strict)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("different candles"), "{}", error);
+    }
+
+    #[test]
+    fn inner_join_keeps_shared_timestamps() {
+        let left = index(&[60, 120, 180, 240, 300]);
+        let right = index(&[120, 240, 270, 300, 360]);
+        let joined = join(&left, &right, JoinMode::Inner).unwrap();
+        assert_eq!(*joined.index, vec![120, 240, 300]);
+        assert_eq!(joined.left, vec![Some(1), Some(3), Some(4)]);
+        assert_eq!(joined.right, vec![Some(0), Some(1), Some(3)]);
+    }
+
+    #[test]
+    fn left_join_never_takes_a_later_value() {
+        // Minutes on the left, 5 minutes closing every 300 seconds on the right
+        let left = index(&(1..=12).map(|i| i * 60).collect::<Vec<_>>());
+        let right = index(&[300, 600, 900]);
+        let joined = join(&left, &right, JoinMode::Left).unwrap();
+        assert_eq!(joined.index, left);
+        assert_eq!(joined.left, (0..12).map(Some).collect::<Vec<_>>());
+        for (timestamp, position) in joined.index.iter().zip(&joined.right) {
+            match position {
+                Some(position) => {
+                    assert!(right[*position] <= *timestamp);
+                    // The newest value known at that time
+                    assert!(right.get(position + 1).is_none_or(|next| next > timestamp));
+                }
+                None => assert!(right[0] > *timestamp),
+            }
+        }
+        let mut expected = vec![None; 4];
+        expected.extend([Some(0); 5]);
+        expected.extend([Some(1); 3]);
+        assert_eq!(joined.right, expected);
+    }
+
+    #[test]
+    fn right_exact_drops_forward_filled_values() {
+        let left = index(&[60, 120, 180, 240, 300, 360]);
+        let right = index(&[120, 300]);
+        let joined = join(&left, &right, JoinMode::Left).unwrap();
+        assert_eq!(
+            joined.right,
+            vec![None, Some(0), Some(0), Some(0), Some(1), Some(1)]
+        );
+        assert_eq!(
+            joined.right_exact(&right),
+            vec![None, Some(0), None, None, Some(1), None]
+        );
+    }
+}
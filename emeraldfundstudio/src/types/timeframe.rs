@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, EnumIter, Display)]
+pub enum Timeframe {
+    #[default]
+    #[strum(serialize = "1m")]
+    M1,
+    #[strum(serialize = "5m")]
+    M5,
+    #[strum(serialize = "15m")]
+    M15,
+    #[strum(serialize = "1h")]
+    H1,
+    #[strum(serialize = "4h")]
+    H4,
+    #[strum(serialize = "1d")]
+    D1,
+}
+
+impl Timeframe {
+    pub fn seconds(&self) -> u64 {
+        match self {
+            Timeframe::M1 => 60,
+            Timeframe::M5 => 5 * 60,
+            Timeframe::M15 => 15 * 60,
+            Timeframe::H1 => 60 * 60,
+            Timeframe::H4 => 4 * 60 * 60,
+            Timeframe::D1 => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Short label of a bar length, e.g. "15m" or "4h"
+pub fn timeframe_label(seconds: u64) -> String {
+    match seconds {
+        0 => "?".to_owned(),
+        s if s % (24 * 60 * 60) == 0 => format!("{}d", s / (24 * 60 * 60)),
+        s if s % (60 * 60) == 0 => format!("{}h", s / (60 * 60)),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// Bar length of a label like "15m" or "4h", the inverse of `timeframe_label`
+pub fn parse_timeframe_label(label: &str) -> Option<u64> {
+    let unit = label.chars().last()?;
+    let count: u64 = label[..label.len() - unit.len_utf8()].parse().ok()?;
+    let unit_seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    count.checked_mul(unit_seconds)
+}
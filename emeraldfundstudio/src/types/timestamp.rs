@@ -6,3 +6,5 @@ pub fn get_unix_time() -> TimeStamp {
     let now = Utc::now();
     now.timestamp().try_into().unwrap()
 }
+
+pub const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;